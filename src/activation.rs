@@ -0,0 +1,144 @@
+//! Keeping background applications from taking focus away from whatever is being worked on.
+use crate::{
+    client::{pid, wm_class},
+    focus::FocusHistory,
+    urgency::demand_attention,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, ManageHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result, Xid,
+};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How long after a key or mouse binding a new window is assumed to be something that was
+/// just launched and so is allowed to take focus.
+const LAUNCH_GRACE: Duration = Duration::from_secs(5);
+
+// The source indication in a _NET_ACTIVE_WINDOW message: pagers and task bars act on behalf
+// of the user so their requests are always honoured.
+const SOURCE_PAGER: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivationPolicy {
+    /// Give the client focus straight away, switching tags if needed.
+    Focus,
+    /// Give the client focus if it is on the current tag, otherwise mark it as urgent.
+    FocusOnCurrentTag,
+    /// Leave focus alone and mark the client as urgent.
+    MarkUrgent,
+}
+
+/// Applies an [ActivationPolicy] to clients asking for focus via _NET_ACTIVE_WINDOW, with
+/// per WM_CLASS overrides. New windows that weren't plausibly just launched only get focus
+/// if their policy is `Focus`, and are marked as urgent otherwise. Dialogs from the focused
+/// application are always let through.
+///
+/// This needs to run ahead of the EWMH event hook so register it before calling
+/// `add_ewmh_hooks`. Clones share the time of the last input so the hook can be registered
+/// for both events and manage.
+#[derive(Debug, Clone)]
+pub struct FocusStealingHook {
+    policy: ActivationPolicy,
+    overrides: HashMap<String, ActivationPolicy>,
+    last_input: Rc<Cell<Option<Instant>>>,
+}
+
+impl FocusStealingHook {
+    pub fn new(policy: ActivationPolicy, overrides: &[(&str, ActivationPolicy)]) -> Self {
+        Self {
+            policy,
+            overrides: overrides
+                .iter()
+                .map(|&(class, p)| (class.to_string(), p))
+                .collect(),
+            last_input: Rc::default(),
+        }
+    }
+
+    fn policy_for<X: XConn>(&self, id: Xid, focused: Option<Xid>, x: &X) -> ActivationPolicy {
+        if let (Some(a), Some(b)) = (pid(x, id), focused.and_then(|f| pid(x, f))) {
+            if a == b {
+                return ActivationPolicy::Focus;
+            }
+        }
+
+        wm_class(x, id)
+            .and_then(|c| self.overrides.get(&c).copied())
+            .unwrap_or(self.policy)
+    }
+
+    fn recent_input(&self) -> bool {
+        self.last_input
+            .get()
+            .is_some_and(|t| t.elapsed() < LAUNCH_GRACE)
+    }
+}
+
+fn focused_before<X: XConn>(state: &mut State<X>) -> Option<Xid> {
+    state
+        .extension_or_default::<FocusHistory>()
+        .borrow()
+        .most_recent()
+        .filter(|id| state.client_set.contains(id))
+}
+
+impl<X: XConn> EventHook<X> for FocusStealingHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::KeyPress(_) | XEvent::MouseEvent(_) => {
+                self.last_input.set(Some(Instant::now()));
+                return Ok(true);
+            }
+            XEvent::ClientMessage(msg) if msg.dtype == "_NET_ACTIVE_WINDOW" => msg,
+            _ => return Ok(true),
+        };
+
+        let id = msg.id;
+        let focused = state.client_set.current_client().copied();
+        if !state.client_set.contains(&id)
+            || focused == Some(id)
+            || msg.data.as_usize()[0] == SOURCE_PAGER
+        {
+            return Ok(true);
+        }
+
+        let allowed = match self.policy_for(id, focused, x) {
+            ActivationPolicy::Focus => true,
+            ActivationPolicy::FocusOnCurrentTag => {
+                state.client_set.tag_for_client(&id) == Some(state.client_set.current_tag())
+            }
+            ActivationPolicy::MarkUrgent => false,
+        };
+        if !allowed {
+            demand_attention(x, id)?;
+        }
+
+        Ok(allowed)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for FocusStealingHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let previous = match focused_before(state) {
+            Some(id) if id != client => id,
+            _ => return Ok(()),
+        };
+        if self.recent_input()
+            || self.policy_for(client, Some(previous), x) == ActivationPolicy::Focus
+        {
+            return Ok(());
+        }
+
+        state.client_set.focus_client(&previous);
+        demand_attention(x, client)
+    }
+}
@@ -0,0 +1,251 @@
+//! Volume control and output switching through PulseAudio (or PipeWire's PulseAudio server).
+//!
+//! The connection to the server is kept between key presses so that changes are quick, and is
+//! made again if the server has gone away. Every change acts on whatever the default sink is
+//! at the time, and the resulting level is published to the bar as "volume" and flashed up
+//! on the [osd](crate::osd). The microphone (the default source) can be muted in the same way,
+//! with the bar showing "mic" as "muted" for as long as it is.
+use crate::{bar, menu, osd};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::StateHook, State},
+    x::XConn,
+    Error, Result,
+};
+use pulsectl::controllers::{
+    errors::ControllerError, types::DeviceInfo, AppControl, DeviceControl, SinkController,
+    SourceController,
+};
+use std::fmt::Debug;
+use tracing::warn;
+
+/// The volume PulseAudio treats as 100%.
+const VOLUME_NORM: u32 = 0x10000;
+
+fn pulse_err(e: impl Debug) -> Error {
+    Error::Custom(format!("pulseaudio: {e:?}"))
+}
+
+fn percent(device: &DeviceInfo) -> u8 {
+    let volume = device.volume.avg().0 as u64;
+
+    ((volume * 100 + VOLUME_NORM as u64 / 2) / VOLUME_NORM as u64).min(u8::MAX as u64) as u8
+}
+
+/// How far to move the volume from `current` percent when asked to move it by `delta`,
+/// stopping at 100% on the way up.
+fn capped_step(current: u8, delta: i8) -> i32 {
+    if delta > 0 {
+        (delta as i32).min(100 - (current as i32).min(100))
+    } else {
+        delta as i32
+    }
+}
+
+type PulseResult<T> = std::result::Result<T, ControllerError>;
+
+/// Run `f` against the controller in `slot`, connecting (again) first if need be.
+fn with_controller<C, T>(
+    slot: &mut Option<C>,
+    create: fn() -> PulseResult<C>,
+    mut f: impl FnMut(&mut C) -> PulseResult<T>,
+) -> Result<T> {
+    if let Some(controller) = slot.as_mut() {
+        if let Ok(t) = f(controller) {
+            return Ok(t);
+        }
+    }
+
+    // The server may have restarted since we last connected.
+    let controller = slot.insert(create().map_err(pulse_err)?);
+
+    f(controller).map_err(pulse_err)
+}
+
+#[derive(Default)]
+struct Pulse {
+    sinks: Option<SinkController>,
+    sources: Option<SourceController>,
+    /// Whether the default source was muted as of the last time we looked.
+    mic_muted: Option<bool>,
+}
+
+impl Pulse {
+    fn sinks<T>(&mut self, f: impl FnMut(&mut SinkController) -> PulseResult<T>) -> Result<T> {
+        with_controller(&mut self.sinks, SinkController::create, f)
+    }
+
+    fn sources<T>(&mut self, f: impl FnMut(&mut SourceController) -> PulseResult<T>) -> Result<T> {
+        with_controller(&mut self.sources, SourceController::create, f)
+    }
+}
+
+fn show_volume<X: XConn>(state: &mut State<X>, sink: &DeviceInfo) -> Result<()> {
+    let percent = percent(sink);
+    if sink.mute {
+        bar::publish(state, "volume", "muted");
+        osd::show(state, "Volume muted", Some(0))
+    } else {
+        bar::publish(state, "volume", format!("{percent}%"));
+        osd::show(state, &format!("Volume {percent}%"), Some(percent))
+    }
+}
+
+/// Change the volume of the default sink by `delta` percent, unmuting it.
+pub fn adjust_volume<X: XConn>(delta: i8) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let ext = state.extension_or_default::<Pulse>();
+        let sink = ext.borrow_mut().sinks(|sinks| {
+            let sink = sinks.get_default_device()?;
+            let step = capped_step(percent(&sink), delta);
+            if step > 0 {
+                sinks.increase_device_volume_by_percent(sink.index, step as f64 / 100.0);
+            } else if step < 0 {
+                sinks.decrease_device_volume_by_percent(sink.index, -step as f64 / 100.0);
+            }
+            sinks.set_device_mute_by_index(sink.index, false);
+
+            sinks.get_device_by_index(sink.index)
+        })?;
+
+        show_volume(state, &sink)
+    })
+}
+
+/// Mute or unmute the default sink.
+pub fn toggle_mute<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let ext = state.extension_or_default::<Pulse>();
+        let sink = ext.borrow_mut().sinks(|sinks| {
+            let sink = sinks.get_default_device()?;
+            sinks.set_device_mute_by_index(sink.index, !sink.mute);
+
+            sinks.get_device_by_index(sink.index)
+        })?;
+
+        show_volume(state, &sink)
+    })
+}
+
+fn publish_mic<X: XConn>(state: &mut State<X>, muted: bool) {
+    let ext = state.extension_or_default::<Pulse>();
+    if ext.borrow_mut().mic_muted.replace(muted) != Some(muted) {
+        bar::publish(state, "mic", if muted { "muted" } else { "" });
+    }
+}
+
+/// Mute or unmute the default source (the microphone).
+pub fn toggle_mic_mute<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let ext = state.extension_or_default::<Pulse>();
+        let muted = ext.borrow_mut().sources(|sources| {
+            let source = sources.get_default_device()?;
+            sources.set_device_mute_by_index(source.index, !source.mute);
+
+            Ok(sources.get_device_by_index(source.index)?.mute)
+        })?;
+        publish_mic(state, muted);
+
+        let label = if muted {
+            "Microphone muted"
+        } else {
+            "Microphone on"
+        };
+        osd::show(state, label, None)
+    })
+}
+
+/// Publishes whether the microphone is muted to the bar at startup.
+#[derive(Debug, Clone, Copy)]
+pub struct MicMuteHook;
+
+impl<X: XConn> StateHook<X> for MicMuteHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Pulse>();
+        let muted = ext
+            .borrow_mut()
+            .sources(|sources| Ok(sources.get_default_device()?.mute));
+
+        // PulseAudio often isn't up yet this early on, in which case the bar is left without
+        // a state for the microphone until it is next toggled.
+        match muted {
+            Ok(muted) => publish_mic(state, muted),
+            Err(e) => warn!("unable to read the microphone mute state: {e}"),
+        }
+
+        Ok(())
+    }
+}
+
+/// The entry to show in the menu for the sink called `name`, marking the current default.
+fn sink_label(name: &str, description: Option<&str>, default: &str) -> String {
+    let description = description.unwrap_or(name);
+    if name == default {
+        format!("* {description}")
+    } else {
+        format!("  {description}")
+    }
+}
+
+/// Pick an output device from a menu, making it the default and moving everything currently
+/// playing over to it.
+pub fn select_sink<X: XConn + 'static>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let ext = state.extension_or_default::<Pulse>();
+        let (sinks, default) = ext.borrow_mut().sinks(|sinks| {
+            let default = sinks.get_default_device()?;
+            Ok((sinks.list_devices()?, default.name.unwrap_or_default()))
+        })?;
+        let labels: Vec<String> = sinks
+            .iter()
+            .map(|s| {
+                let name = s.name.as_deref().unwrap_or_default();
+                sink_label(name, s.description.as_deref(), &default)
+            })
+            .collect();
+
+        menu::select("output", labels, state, move |ix, state, _| {
+            let sink = &sinks[ix];
+            let name = sink.name.clone().unwrap_or_default();
+            let ext = state.extension_or_default::<Pulse>();
+            ext.borrow_mut().sinks(|sinks| {
+                sinks.set_default_device(&name)?;
+                for stream in sinks.list_applications()? {
+                    sinks.move_app_by_index(stream.index, sink.index)?;
+                }
+                Ok(())
+            })?;
+
+            let description = sink.description.as_deref().unwrap_or(&name);
+            osd::show(state, description, None)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raising_the_volume_stops_at_full() {
+        assert_eq!(capped_step(50, 5), 5);
+        assert_eq!(capped_step(98, 5), 2);
+        assert_eq!(capped_step(120, 5), 0);
+        assert_eq!(capped_step(120, -5), -5);
+    }
+
+    #[test]
+    fn the_default_sink_is_marked_in_the_menu() {
+        let hdmi = ("alsa_output.hdmi", Some("HDMI Audio"));
+
+        assert_eq!(
+            sink_label(hdmi.0, hdmi.1, "alsa_output.hdmi"),
+            "* HDMI Audio"
+        );
+        assert_eq!(
+            sink_label(hdmi.0, hdmi.1, "alsa_output.analog"),
+            "  HDMI Audio"
+        );
+        assert_eq!(sink_label("bluez_sink", None, ""), "  bluez_sink");
+    }
+}
@@ -0,0 +1,231 @@
+//! Launching the XDG autostart entries at the start of the session.
+//!
+//! Each `.desktop` file in the `autostart` directory under `~/.config` (and the system config
+//! directories such as `/etc/xdg`) is run once on startup, so applications that install an
+//! autostart entry, such as nm-applet or a clipboard manager, work without a separate
+//! xprofile script. An entry in `~/.config/autostart` replaces a system one with the same
+//! file name, which is how a system entry is turned off: by overriding it with `Hidden=true`.
+//! Entries are skipped when restarting in place, as the ones already running are still
+//! running, and when embedded, as they belong to the outer session.
+use crate::{embed::embedded, paths::config_dirs, process::run_with_args, restart::restarted};
+use penrose::{
+    core::{hooks::StateHook, State},
+    x::XConn,
+    Result,
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+/// The parts of an autostart entry that decide whether and how it is run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Entry {
+    exec: Option<String>,
+    try_exec: Option<String>,
+    hidden: bool,
+    only_show_in: Option<Vec<String>>,
+    not_show_in: Vec<String>,
+}
+
+/// Undo the escapes allowed in desktop entry values.
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => (),
+        }
+    }
+
+    out
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parse the `[Desktop Entry]` group of a desktop file.
+fn parse_entry(contents: &str) -> Entry {
+    let mut entry = Entry::default();
+    let mut in_group = false;
+    let mut enabled = true;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if in_group && !line.starts_with('#') => (key.trim(), value.trim()),
+            _ => continue,
+        };
+        match key {
+            "Exec" => entry.exec = Some(unescape(value)),
+            "TryExec" => entry.try_exec = Some(unescape(value)),
+            "Hidden" => entry.hidden = value == "true",
+            "OnlyShowIn" => entry.only_show_in = Some(list(value)),
+            "NotShowIn" => entry.not_show_in = list(value),
+            // Not in the spec, but GNOME's settings turn entries off this way.
+            "X-GNOME-Autostart-enabled" => enabled = value != "false",
+            _ => (),
+        }
+    }
+    entry.hidden |= !enabled;
+
+    entry
+}
+
+impl Entry {
+    /// Whether this should run on any of `desktops`, from `$XDG_CURRENT_DESKTOP`.
+    fn shown_in(&self, desktops: &[&str]) -> bool {
+        let listed = |names: &[String]| names.iter().any(|n| desktops.contains(&n.as_str()));
+        let only = self.only_show_in.as_deref().is_none_or(listed);
+
+        !self.hidden && only && !listed(&self.not_show_in)
+    }
+}
+
+/// Split `exec` into arguments, following the quoting rules for the Exec key and dropping the
+/// field codes, which expand to nothing when launching without files or URLs.
+fn exec_args(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_with(String::new);
+            }
+            '\\' if quoted => arg.get_or_insert_with(String::new).extend(chars.next()),
+            ' ' if !quoted => args.extend(arg.take()),
+            '%' if !quoted => {
+                if chars.next() == Some('%') {
+                    arg.get_or_insert_with(String::new).push('%');
+                }
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+
+    args
+}
+
+fn on_path(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|d| d.join(program).is_file()))
+}
+
+/// The autostart entries to consider, keyed by file name so that more important directories
+/// replace entries with the same name from less important ones.
+fn entries() -> BTreeMap<String, PathBuf> {
+    let mut found = BTreeMap::new();
+    for dir in config_dirs().into_iter().rev() {
+        let files = fs::read_dir(dir.join("autostart"))
+            .into_iter()
+            .flatten()
+            .flatten();
+        for file in files {
+            let name = file.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".desktop") {
+                found.insert(name, file.path());
+            }
+        }
+    }
+
+    found
+}
+
+/// Runs the XDG autostart entries. This needs registering as a startup hook after the
+/// [ReaperHook](crate::process::ReaperHook), to pick up entries that fail to start.
+#[derive(Debug, Clone, Copy)]
+pub struct AutostartHook;
+
+impl<X: XConn> StateHook<X> for AutostartHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        if restarted() || embedded() {
+            return Ok(());
+        }
+        let current = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        let desktops: Vec<&str> = current.split(':').filter(|d| !d.is_empty()).collect();
+
+        let mut started = 0;
+        for (name, path) in entries() {
+            let entry = match fs::read_to_string(&path) {
+                Ok(contents) => parse_entry(&contents),
+                Err(e) => {
+                    warn!("unable to read {}: {e}", path.display());
+                    continue;
+                }
+            };
+            if !entry.shown_in(&desktops) || !entry.try_exec.as_deref().is_none_or(on_path) {
+                continue;
+            }
+            let args = entry.exec.as_deref().map(exec_args).unwrap_or_default();
+            let (program, args) = match args.split_first() {
+                Some(split) => split,
+                None => {
+                    warn!("{} has nothing to run", path.display());
+                    continue;
+                }
+            };
+
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            if let Err(e) = run_with_args(program, &args) {
+                warn!("unable to autostart {name}: {e}");
+                continue;
+            }
+            started += 1;
+        }
+        info!("autostarted {started} entries");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_shown_according_to_the_desktop() {
+        let entry = parse_entry(
+            "# comment\n[Desktop Entry]\nExec=nm-applet\nOnlyShowIn=GNOME;XFCE;\n\
+             [Desktop Action New]\nHidden=true\n",
+        );
+
+        assert_eq!(entry.exec.as_deref(), Some("nm-applet"));
+        assert!(entry.shown_in(&["XFCE"]));
+        assert!(!entry.shown_in(&["KDE"]));
+        assert!(!parse_entry("[Desktop Entry]\nHidden=true\n").shown_in(&[]));
+        assert!(!parse_entry("[Desktop Entry]\nNotShowIn=KDE;\n").shown_in(&["KDE"]));
+    }
+
+    #[test]
+    fn exec_lines_are_split_and_field_codes_dropped() {
+        assert_eq!(
+            exec_args(r#"copyq --start-server %U "a \"quoted\" arg" 100%%"#),
+            vec!["copyq", "--start-server", r#"a "quoted" arg"#, "100%"]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Screen brightness through `/sys/class/backlight`.
+//!
+//! Every backlight device (or those named in the config) is set to the same percentage, each
+//! in terms of its own maximum. Changes are faded in over a few steps on a background thread,
+//! and a newer change cuts short any fade still running. Writing the brightness needs the
+//! usual udev rule giving the `video` group access to the devices.
+use crate::{bar, osd};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::StateHook, State},
+    x::XConn,
+    Error, Result,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    thread,
+    time::Duration,
+};
+use tracing::warn;
+
+const BACKLIGHTS: &str = "/sys/class/backlight";
+const FADE_STEPS: u32 = 8;
+
+/// Bumped for every change so that older fades know to stop.
+static FADE: AtomicU64 = AtomicU64::new(0);
+/// The percentage being faded to, or [NOT_FADING]. Changes made mid fade start from here
+/// rather than from wherever the fade has got to.
+static FADING_TO: AtomicU8 = AtomicU8::new(NOT_FADING);
+const NOT_FADING: u8 = u8::MAX;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backlight {
+    /// The devices to control by name (e.g. "intel_backlight"), or `None` for all of them.
+    pub devices: Option<&'static [&'static str]>,
+    /// The lowest percentage to go down to, to keep the screen from going completely dark.
+    pub min_percent: u8,
+    /// How long to fade between levels over, or zero to change straight away.
+    pub fade: Duration,
+}
+
+pub fn read_u32(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// Percentages round down and raw levels round up, so that a level converted one way and then
+// back again comes out where it started whichever of the two is the finer grained.
+pub fn to_percent(raw: u32, max: u32) -> u8 {
+    if max == 0 {
+        return 0;
+    }
+
+    (raw as u64 * 100 / max as u64).min(100) as u8
+}
+
+fn to_raw(percent: u8, max: u32) -> u32 {
+    (max as u64 * percent.min(100) as u64).div_ceil(100) as u32
+}
+
+/// The raw level to move a device with the given maximum to when going from `current` percent
+/// to `percent`, moving at least one raw level in the direction of `delta` (but not below
+/// `min_percent`) so that devices with only a few levels don't get stuck.
+fn target_raw(current: u8, percent: u8, delta: i32, max: u32, min_percent: u8) -> u32 {
+    let (from, to) = (to_raw(current, max), to_raw(percent, max));
+    if to != from || delta == 0 {
+        return to;
+    }
+    let lowest = to_raw(min_percent, max);
+
+    (from as i64 + delta.signum() as i64).clamp(lowest as i64, max as i64) as u32
+}
+
+/// The brightness to write at each step of fading from `from` to `to`, ending on `to`.
+fn fade_steps(from: u32, to: u32, steps: u32) -> Vec<u32> {
+    let steps = steps.max(1) as i64;
+    let (from, to) = (from as i64, to as i64);
+
+    (1..=steps)
+        .map(|i| (from + (to - from) * i / steps) as u32)
+        .collect()
+}
+
+struct Device {
+    path: PathBuf,
+    max: u32,
+}
+
+impl Device {
+    fn brightness(&self) -> Option<u32> {
+        read_u32(&self.path.join("brightness"))
+    }
+
+    fn set(&self, raw: u32) -> std::io::Result<()> {
+        fs::write(self.path.join("brightness"), raw.to_string())
+    }
+}
+
+impl Backlight {
+    fn devices(&self) -> Vec<Device> {
+        let entries = match fs::read_dir(BACKLIGHTS) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut devices: Vec<Device> = entries
+            .flatten()
+            .filter(|e| match self.devices {
+                Some(names) => names.iter().any(|n| e.file_name() == *n),
+                None => true,
+            })
+            .filter_map(|e| {
+                let path = e.path();
+                let max = read_u32(&path.join("max_brightness")).filter(|&m| m > 0)?;
+                Some(Device { path, max })
+            })
+            .collect();
+        devices.sort_by(|a, b| a.path.cmp(&b.path));
+
+        devices
+    }
+
+    /// The current brightness as a percentage, going by the first device.
+    fn percent(&self) -> Option<u8> {
+        let fading_to = FADING_TO.load(Ordering::SeqCst);
+        if fading_to != NOT_FADING {
+            return Some(fading_to);
+        }
+        let device = self.devices().into_iter().next()?;
+
+        Some(to_percent(device.brightness()?, device.max))
+    }
+
+    /// Move every device by `delta` percent, clamped to the configured minimum, returning the
+    /// level actually set.
+    fn step(&self, delta: i32) -> Result<u8> {
+        let devices = self.devices();
+        if devices.is_empty() {
+            return Err(Error::Custom("no backlight devices found".into()));
+        }
+        let current = self.percent().unwrap_or(0);
+        let percent = (current as i32 + delta).clamp(self.min_percent as i32, 100) as u8;
+        let targets: Vec<u32> = devices
+            .iter()
+            .map(|d| target_raw(current, percent, delta, d.max, self.min_percent))
+            .collect();
+        let percent = to_percent(targets[0], devices[0].max);
+
+        let fade = FADE.fetch_add(1, Ordering::SeqCst) + 1;
+        FADING_TO.store(percent, Ordering::SeqCst);
+        let steps = if self.fade.is_zero() { 1 } else { FADE_STEPS };
+        let delay = self.fade / steps;
+
+        thread::spawn(move || {
+            let fades: Vec<(Device, Vec<u32>)> = devices
+                .into_iter()
+                .zip(targets)
+                .map(|(d, to)| {
+                    let from = d.brightness().unwrap_or(0);
+                    let steps = fade_steps(from, to, steps);
+                    (d, steps)
+                })
+                .collect();
+
+            for i in 0..steps as usize {
+                if FADE.load(Ordering::SeqCst) != fade {
+                    return;
+                }
+                for (device, steps) in fades.iter() {
+                    if let Err(e) = device.set(steps[i]) {
+                        warn!("unable to set {}: {e}", device.path.display());
+                    }
+                }
+                thread::sleep(delay);
+            }
+            let _ =
+                FADING_TO.compare_exchange(percent, NOT_FADING, Ordering::SeqCst, Ordering::SeqCst);
+        });
+
+        Ok(percent)
+    }
+
+    fn show<X: XConn>(state: &mut State<X>, percent: u8) -> Result<()> {
+        bar::publish(state, "backlight", format!("{percent}%"));
+
+        osd::show(state, &format!("Brightness {percent}%"), Some(percent))
+    }
+}
+
+/// Publishes the brightness to the bar at startup.
+impl<X: XConn> StateHook<X> for Backlight {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        if let Some(percent) = self.percent() {
+            bar::publish(state, "backlight", format!("{percent}%"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Change the brightness by `delta` percent.
+pub fn adjust_backlight<X: XConn>(config: Backlight, delta: i8) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let percent = config.step(delta as i32)?;
+
+        Backlight::show(state, percent)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_convert_between_raw_and_percent() {
+        assert_eq!(to_percent(96000, 96000), 100);
+        assert_eq!(to_percent(4800, 96000), 5);
+        assert_eq!(to_raw(5, 96000), 4800);
+        assert_eq!(to_percent(to_raw(37, 255), 255), 37);
+    }
+
+    #[test]
+    fn devices_with_few_levels_still_step() {
+        // 5% of 15 levels is less than one level.
+        assert_eq!(to_raw(5, 15), 1);
+        assert_eq!(to_raw(0, 15), 0);
+        assert_eq!(to_raw(to_percent(7, 15), 15), 7);
+
+        assert_eq!(target_raw(46, 51, 5, 15, 5), 8);
+        assert_eq!(target_raw(46, 41, -5, 15, 5), 6);
+        assert_eq!(target_raw(6, 5, -5, 15, 5), 1);
+        assert_eq!(target_raw(100, 100, 5, 15, 5), 15);
+    }
+
+    #[test]
+    fn fades_end_on_the_target() {
+        assert_eq!(fade_steps(0, 100, 4), vec![25, 50, 75, 100]);
+        assert_eq!(fade_steps(100, 20, 4), vec![80, 60, 40, 20]);
+        assert_eq!(fade_steps(7, 9, 1), vec![9]);
+    }
+}
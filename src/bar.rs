@@ -0,0 +1,49 @@
+//! Window manager state published for an external status bar.
+//!
+//! Each value is written as a `key: value` line to `$XDG_RUNTIME_DIR/wm/status`, which a bar
+//! can poll or watch (e.g. a polybar custom/script module using `inotifywait`).
+use crate::paths::runtime_file;
+use penrose::{core::State, x::XConn};
+use std::{collections::BTreeMap, fs};
+use tracing::warn;
+
+#[derive(Debug, Default, Clone)]
+pub struct BarStatus {
+    values: BTreeMap<String, String>,
+}
+
+impl BarStatus {
+    fn write(&self) -> std::io::Result<()> {
+        let path = runtime_file("status");
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let lines: Vec<String> = self
+            .values
+            .iter()
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect();
+
+        fs::write(path, lines.join("\n") + "\n")
+    }
+}
+
+/// Set the value shown for `key`, rewriting the status file if it changed. An empty value
+/// removes the key.
+pub fn publish<X: XConn>(state: &mut State<X>, key: &str, value: impl Into<String>) {
+    let ext = state.extension_or_default::<BarStatus>();
+    let mut status = ext.borrow_mut();
+    let value = value.into();
+
+    let changed = if value.is_empty() {
+        status.values.remove(key).is_some()
+    } else {
+        status.values.insert(key.to_string(), value.clone()) != Some(value)
+    };
+
+    if changed {
+        if let Err(e) = status.write() {
+            warn!("unable to write bar status: {e}");
+        }
+    }
+}
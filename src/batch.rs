@@ -0,0 +1,151 @@
+//! Batching the X requests made by the refresh hooks.
+//!
+//! Every refresh runs all of the refresh hooks in turn, and several of them look at each
+//! visible client: switching to a tag with a dozen clients on it used to fetch each client's
+//! _NET_WM_STATE once per hook to see whether it was fullscreen, waiting on a reply every
+//! time, and send border and stacking changes one client and one hook at a time. While a
+//! refresh is running, hooks go through the [Batch] instead: fullscreen states are fetched
+//! once per client, and client config changes are gathered up and sent once the last hook
+//! has run, a single request per client followed by a single flush.
+//!
+//! This only covers the hooks here: the layout itself is applied by penrose before the
+//! refresh hooks run.
+use penrose::{
+    core::{hooks::StateHook, Config, State},
+    x::{ClientConfig, Prop, XConn},
+    Result, Xid,
+};
+use std::collections::HashMap;
+
+const NET_WM_STATE_FULLSCREEN: &str = "_NET_WM_STATE_FULLSCREEN";
+
+/// Client state fetched and client changes queued during the current refresh.
+#[derive(Debug, Default, Clone)]
+pub struct Batch {
+    /// How many refreshes are running, as refresh hooks can trigger a refresh of their own.
+    depth: usize,
+    fullscreen: HashMap<Xid, bool>,
+    configs: Vec<(Xid, ClientConfig)>,
+}
+
+impl Batch {
+    /// Whether `id` is fullscreen. Nothing run from a refresh hook changes this, so while
+    /// refreshing it is only fetched the first time it is asked for.
+    pub fn fullscreen<X: XConn>(&mut self, x: &X, id: Xid) -> Result<bool> {
+        if let Some(&fullscreen) = self.fullscreen.get(&id) {
+            return Ok(fullscreen);
+        }
+        let fullscreen = match x.get_prop(id, "_NET_WM_STATE")? {
+            Some(Prop::Atom(atoms)) => atoms.iter().any(|a| a == NET_WM_STATE_FULLSCREEN),
+            _ => false,
+        };
+        if self.depth > 0 {
+            self.fullscreen.insert(id, fullscreen);
+        }
+
+        Ok(fullscreen)
+    }
+
+    /// Apply `config` to `id`, at the end of the refresh if one is running.
+    pub fn configure<X: XConn>(&mut self, x: &X, id: Xid, config: ClientConfig) -> Result<()> {
+        if self.depth == 0 {
+            return x.set_client_config(id, &[config]);
+        }
+        self.configs.push((id, config));
+
+        Ok(())
+    }
+
+    fn begin(&mut self) {
+        if self.depth == 0 {
+            self.fullscreen.clear();
+        }
+        self.depth += 1;
+    }
+
+    /// Finish a refresh, sending everything queued if it was the outermost one.
+    fn finish<X: XConn>(&mut self, x: &X) -> Result<()> {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth > 0 {
+            return Ok(());
+        }
+        self.fullscreen.clear();
+        let configs = grouped(std::mem::take(&mut self.configs));
+        if configs.is_empty() {
+            return Ok(());
+        }
+        for (id, configs) in configs {
+            x.set_client_config(id, &configs)?;
+        }
+        x.flush();
+
+        Ok(())
+    }
+}
+
+/// `configs` gathered up per client, keeping the order they were made in.
+fn grouped(configs: Vec<(Xid, ClientConfig)>) -> Vec<(Xid, Vec<ClientConfig>)> {
+    let mut grouped: Vec<(Xid, Vec<ClientConfig>)> = Vec::new();
+    for (id, config) in configs {
+        match grouped.iter_mut().find(|(c, _)| *c == id) {
+            Some((_, configs)) => configs.push(config),
+            None => grouped.push((id, vec![config])),
+        }
+    }
+
+    grouped
+}
+
+/// Runs the refresh hooks with a [Batch] in progress.
+struct Batched<X: XConn> {
+    inner: Box<dyn StateHook<X>>,
+}
+
+impl<X: XConn> StateHook<X> for Batched<X> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let batch = state.extension_or_default::<Batch>();
+        batch.borrow_mut().begin();
+        let res = self.inner.call(state, x);
+        // Whatever was queued before an error still needs sending.
+        let finished = batch.borrow_mut().finish(x);
+
+        res.and(finished)
+    }
+}
+
+/// Batch the X requests made by the refresh hooks set on `config`. This needs to come after
+/// all of the refresh hooks have been set, and outside of [catch_hook_panics] so that a panic
+/// can't leave a batch unfinished.
+///
+/// [catch_hook_panics]: crate::panics::catch_hook_panics
+pub fn batch_refresh_hooks<X: XConn + 'static>(mut config: Config<X>) -> Config<X> {
+    config.refresh_hook = config
+        .refresh_hook
+        .take()
+        .map(|inner| Box::new(Batched { inner }) as _);
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_are_sent_once_per_client_in_order() {
+        let (a, b) = (Xid::from(1), Xid::from(2));
+        let configs = vec![
+            (a, ClientConfig::BorderPx(2)),
+            (b, ClientConfig::StackTop),
+            (a, ClientConfig::StackTop),
+        ];
+
+        assert_eq!(
+            grouped(configs),
+            vec![
+                (a, vec![ClientConfig::BorderPx(2), ClientConfig::StackTop]),
+                (b, vec![ClientConfig::StackTop]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Watching the battery and acting as it runs down.
+//!
+//! The battery is read from sysfs once a minute and its level is published to the bar as
+//! "battery". Each threshold fires once as the battery discharges past it, and is re-armed
+//! once the battery starts charging again.
+use crate::{
+    bar,
+    embed::embedded,
+    logind::suspend,
+    process::{run, run_with_args},
+    timer::notify_after,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{cell::RefCell, collections::BTreeSet, fs, path::Path, rc::Rc, time::Duration};
+use tracing::info;
+
+const POWER_SUPPLIES: &str = "/sys/class/power_supply";
+const BATTERY_TICK: &str = "_WM_BATTERY_TICK";
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The charge left in a battery and whether it is charging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Level {
+    percent: u8,
+    charging: bool,
+}
+
+impl Level {
+    /// The combined level of every battery, weighted by how much each can hold.
+    fn read() -> Option<Self> {
+        let (mut now, mut full, mut charging) = (0u64, 0u64, false);
+        for supply in fs::read_dir(POWER_SUPPLIES).ok()?.flatten() {
+            let path = supply.path();
+            if read(&path.join("type")) != "Battery" {
+                continue;
+            }
+            let value = |names: [&str; 2]| {
+                names
+                    .iter()
+                    .find_map(|n| read(&path.join(n)).parse::<u64>().ok())
+            };
+            match (
+                value(["energy_now", "charge_now"]),
+                value(["energy_full", "charge_full"]),
+            ) {
+                (Some(n), Some(f)) => (now, full) = (now + n, full + f),
+                _ => continue,
+            }
+            charging |= matches!(read(&path.join("status")).as_str(), "Charging" | "Full");
+        }
+
+        if full == 0 {
+            return None;
+        }
+
+        Some(Self {
+            percent: (now * 100 / full).min(100) as u8,
+            charging,
+        })
+    }
+
+    fn summary(&self) -> String {
+        if self.charging {
+            format!("{}% charging", self.percent)
+        } else {
+            format!("{}%", self.percent)
+        }
+    }
+}
+
+/// Whether the machine is running from a battery: it has one and no mains supply is online.
+pub fn on_battery() -> bool {
+    let supplies = match fs::read_dir(POWER_SUPPLIES) {
+        Ok(dir) => dir,
+        Err(_) => return false,
+    };
+    let (mut battery, mut mains) = (false, false);
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read(&path.join("type")).as_str() {
+            "Battery" => battery = true,
+            "Mains" | "USB" => mains |= read(&path.join("online")) == "1",
+            _ => (),
+        }
+    }
+
+    battery && !mains
+}
+
+/// Something to do once the battery runs down to a threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryAction {
+    /// Show a desktop notification.
+    Notify,
+    /// Run a command, such as one to dim the screen.
+    Run(&'static str),
+    /// Suspend the machine.
+    Suspend,
+}
+
+/// The action due at `level`, if any, given the thresholds in `fired` that have already gone
+/// off since the battery last charged.
+fn due(
+    thresholds: &[(u8, BatteryAction)],
+    fired: &mut BTreeSet<u8>,
+    level: Level,
+) -> Option<(u8, BatteryAction)> {
+    if level.charging {
+        fired.clear();
+        return None;
+    }
+
+    // Only the most urgent action is taken when several thresholds are passed at once.
+    let mut crossed: Vec<(u8, BatteryAction)> = thresholds
+        .iter()
+        .copied()
+        .filter(|&(t, _)| level.percent <= t && fired.insert(t))
+        .collect();
+    crossed.sort_by_key(|&(t, _)| t);
+
+    crossed.first().copied()
+}
+
+/// Polls the battery, publishing its level and firing `thresholds` as (percent, action) pairs.
+#[derive(Debug, Clone)]
+pub struct BatteryHook {
+    thresholds: &'static [(u8, BatteryAction)],
+    fired: Rc<RefCell<BTreeSet<u8>>>,
+}
+
+impl BatteryHook {
+    pub fn new(thresholds: &'static [(u8, BatteryAction)]) -> Self {
+        Self {
+            thresholds,
+            fired: Rc::default(),
+        }
+    }
+
+    fn check<X: XConn>(&self, state: &mut State<X>) -> Result<()> {
+        let level = Level::read();
+        bar::publish(
+            state,
+            "battery",
+            level.map(|l| l.summary()).unwrap_or_default(),
+        );
+
+        let level = match level {
+            Some(level) => level,
+            None => return Ok(()),
+        };
+        let (percent, action) = match due(self.thresholds, &mut self.fired.borrow_mut(), level) {
+            Some(due) => due,
+            None => return Ok(()),
+        };
+        info!("battery at {}%: {action:?}", level.percent);
+
+        match action {
+            BatteryAction::Notify => run_with_args(
+                "notify-send",
+                &[
+                    "-u",
+                    "critical",
+                    "Battery low",
+                    &format!("{}% remaining (below {percent}%)", level.percent),
+                ],
+            ),
+            BatteryAction::Run(command) => run(command),
+            // Suspending is left to the outer session.
+            BatteryAction::Suspend if embedded() => Ok(()),
+            BatteryAction::Suspend => suspend(),
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for BatteryHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == BATTERY_TICK => {
+                notify_after(POLL_INTERVAL, BATTERY_TICK, [0; 5]);
+                self.check(state)?;
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for BatteryHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        notify_after(POLL_INTERVAL, BATTERY_TICK, [0; 5]);
+        self.check(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_fire_once_per_discharge() {
+        const THRESHOLDS: [(u8, BatteryAction); 3] = [
+            (20, BatteryAction::Notify),
+            (10, BatteryAction::Run("light -S 10")),
+            (5, BatteryAction::Suspend),
+        ];
+        let mut fired = BTreeSet::new();
+        let level = |percent, charging| Level { percent, charging };
+
+        assert_eq!(due(&THRESHOLDS, &mut fired, level(50, false)), None);
+        assert_eq!(
+            due(&THRESHOLDS, &mut fired, level(19, false)),
+            Some((20, BatteryAction::Notify))
+        );
+        assert_eq!(due(&THRESHOLDS, &mut fired, level(18, false)), None);
+        assert_eq!(
+            due(&THRESHOLDS, &mut fired, level(4, false)),
+            Some((5, BatteryAction::Suspend))
+        );
+
+        assert_eq!(due(&THRESHOLDS, &mut fired, level(4, true)), None);
+        assert!(fired.is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+//! Send a command to the running window manager, e.g. `wmcli output save desk`.
+use std::{
+    env,
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    process::ExitCode,
+};
+use wm::paths;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("usage: wmcli <command> [args...]");
+        return ExitCode::FAILURE;
+    }
+
+    match send(&args) {
+        Ok((true, output)) => {
+            print!("{output}");
+            ExitCode::SUCCESS
+        }
+        Ok((false, output)) => {
+            eprint!("{output}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("wmcli: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Send `args` and return whether the command succeeded along with its output.
+fn send(args: &[String]) -> std::io::Result<(bool, String)> {
+    let mut stream = UnixStream::connect(paths::runtime_file("ipc.sock"))?;
+    stream.write_all((args.join("\n") + "\n").as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    let (status, output) = reply.split_once('\n').unwrap_or((reply.as_str(), ""));
+    let output = if output.is_empty() || output.ends_with('\n') {
+        output.to_string()
+    } else {
+        format!("{output}\n")
+    };
+
+    Ok((status == "ok", output))
+}
@@ -0,0 +1,250 @@
+//! The key and mouse bindings.
+use crate::{
+    audio::{adjust_volume, select_sink, toggle_mic_mute, toggle_mute},
+    backlight::adjust_backlight,
+    bluetooth::{select_bluetooth_device, toggle_bluetooth},
+    compositor::toggle_compositor,
+    config::{
+        BACKLIGHT, CAFFEINE_EXPIRE, CENTERED_FLOAT_SIZE, FLOAT_MODE_STEP, FOCUS_OR_SPAWN, GAMING,
+        GAMING_KEY, KILL_TIMEOUT, LOCKER, NIGHT_LIGHT, OPACITY_STEP, PASSTHROUGH_KEY,
+    },
+    conflicts::{check_key_bindings, drop_unresolved},
+    direction::{focus_direction, focus_screen_direction, swap_direction, Direction},
+    dump::{self, dump_state},
+    embed::embedded,
+    floating::{
+        center_floating, float_mode, toggle_float_all, toggle_floating_remembered,
+        ConstrainedMouseHandler,
+    },
+    focus::{
+        cycle_or_spawn, focus_from_menu, focus_previous, focus_tag, key_driven_bindings, toggle_tag,
+    },
+    gaming::toggle_gaming_mode,
+    hidden::{minimize_focused, restore_minimized},
+    idle::{blank_screens, toggle_blanking, toggle_caffeine},
+    kbd_backlight::adjust_kbd_backlight,
+    kill::{kill_focused, kill_mode},
+    logging::toggle_debug_logging,
+    marks::{jump_to_mark, set_mark},
+    mouse::{RetagOnDropHandler, SnapDragHandler, SwapDragHandler},
+    mpris::media,
+    nightlight::toggle_night_light,
+    opacity::adjust_opacity,
+    panics::{catch_key_panics, catch_mouse_panics},
+    passthrough::Passthrough,
+    power::cycle_power_profile,
+    process::{spawn, spawn_terminal_here},
+    restart::{quit, restart},
+    screens::{cycle_screen, focus_primary, rotate_focused_output, send_to_screen, toggle_mirror},
+    spans::trace_key_bindings,
+    stacking::toggle_above,
+    startup::launch,
+    urgency::focus_urgent,
+};
+use penrose::{
+    builtin::{
+        actions::{
+            floating::{sink_focused, MouseDragHandler, MouseResizeHandler},
+            modify_with, send_layout_message,
+        },
+        layout::messages::{ExpandMain, IncMain, ShrinkMain},
+    },
+    core::bindings::{
+        click_handler, parse_keybindings_with_xmodmap, KeyBindings, KeyEventHandler, MouseBindings,
+        MouseState,
+    },
+    extensions::actions::toggle_fullscreen,
+    map,
+    x11rb::RustConn,
+    Result,
+};
+use std::collections::HashMap;
+use x11rb::protocol::randr::Rotation;
+
+/// Like `map!`, but keeping every binding in order so that conflicts can be reported.
+macro_rules! bindings {
+    ($($k:expr => $v:expr),+ $(,)?) => {
+        vec![$(($k.to_string(), $v)),+]
+    };
+}
+
+fn raw_key_bindings() -> HashMap<String, Box<dyn KeyEventHandler<RustConn>>> {
+    let mut raw_bindings: Vec<(String, Box<dyn KeyEventHandler<RustConn>>)> = bindings! {
+        "M-Left" => focus_direction(Direction::Left),
+        "M-Up" => focus_direction(Direction::Up),
+        "M-Right" => focus_direction(Direction::Right),
+        "M-Down" => focus_direction(Direction::Down),
+        "M-C-Left" => swap_direction(Direction::Left),
+        "M-C-Up" => swap_direction(Direction::Up),
+        "M-C-Right" => swap_direction(Direction::Right),
+        "M-C-Down" => swap_direction(Direction::Down),
+        "M-A-Left" => focus_screen_direction(Direction::Left),
+        "M-A-Up" => focus_screen_direction(Direction::Up),
+        "M-A-Right" => focus_screen_direction(Direction::Right),
+        "M-A-Down" => focus_screen_direction(Direction::Down),
+        "M-j" => modify_with(|cs| cs.focus_up()),
+        "M-k" => modify_with(|cs| cs.focus_down()),
+        "M-S-k" => modify_with(|cs| cs.swap_down()),
+        "M-S-j" => modify_with(|cs| cs.swap_up()),
+        "M-q" => kill_focused(KILL_TIMEOUT),
+        "M-C-q" => kill_mode(),
+        "M-Tab" => toggle_tag(),
+        "M-bracketright" => cycle_screen(1),
+        "M-bracketleft" => cycle_screen(-1),
+        "M-S-bracketright" => send_to_screen(1, false),
+        "M-S-bracketleft" => send_to_screen(-1, false),
+        "M-C-bracketright" => send_to_screen(1, true),
+        "M-C-bracketleft" => send_to_screen(-1, true),
+        "M-A-p" => focus_primary(),
+        "M-A-m" => toggle_mirror(),
+        "M-A-n" => toggle_night_light(NIGHT_LIGHT),
+        "M-A-S-Up" => rotate_focused_output(Rotation::ROTATE0),
+        "M-A-S-Left" => rotate_focused_output(Rotation::ROTATE90),
+        "M-A-S-Down" => rotate_focused_output(Rotation::ROTATE180),
+        "M-A-S-Right" => rotate_focused_output(Rotation::ROTATE270),
+        "M-S-Tab" => modify_with(|cs| {
+            let focussed_screen_index = cs.current_screen().index();
+            let unfocussed_screens = cs.screens().filter(|s| s.index() != focussed_screen_index).collect::<Vec<_>>();
+            if let Some(unfocussed_screen) = unfocussed_screens.first() {
+               cs.pull_tag_to_screen(cs.tag_for_screen(unfocussed_screen.index()).unwrap().to_string());
+            }
+        }),
+        "M-grave" => modify_with(|cs| cs.next_layout()),
+        "M-S-grave" => modify_with(|cs| cs.previous_layout()),
+        "M-S-Up" => send_layout_message(|| IncMain(1)),
+        "M-S-Down" => send_layout_message(|| IncMain(-1)),
+        "M-S-Right" => send_layout_message(|| ExpandMain),
+        "M-S-Left" => send_layout_message(|| ShrinkMain),
+        "M-f" =>   toggle_fullscreen(),
+        "M-space" => toggle_floating_remembered(),
+        "M-S-space" => center_floating(None),
+        "M-C-space" => center_floating(Some(CENTERED_FLOAT_SIZE)),
+        "M-S-f" => float_mode(FLOAT_MODE_STEP),
+        "M-C-f" => toggle_float_all(),
+        "M-a" => toggle_above(),
+        "M-n" => minimize_focused(),
+        "M-S-n" => restore_minimized(),
+        "M-m" => set_mark(),
+        "M-apostrophe" => jump_to_mark(),
+        "M-Escape" => focus_previous(),
+        "M-u" => focus_urgent(),
+        "M-w" => focus_from_menu(),
+        "M-minus" => adjust_opacity(-OPACITY_STEP),
+        "M-equal" => adjust_opacity(OPACITY_STEP),
+        "M-S-q" => quit(),
+        "M-C-r" => restart(),
+        "M-A-d" => toggle_debug_logging(),
+        "M-A-S-d" => dump_state(),
+
+        "M-p" => spawn("dmenu_run"),
+        "M-Return" => spawn_terminal_here("alacritty", "--working-directory"),
+        "M-d" => launch("startdired"),
+        "M-b" => launch("thorium"),
+        "M-v" => launch("code"),
+        "M-l" => spawn(LOCKER),
+        "M-A-b" => blank_screens(),
+        "M-A-i" => toggle_blanking(),
+        "M-A-k" => toggle_caffeine(CAFFEINE_EXPIRE),
+        "M-A-c" => toggle_compositor(),
+        "M-S-s" => spawn("flameshot gui"),
+        "Print" => spawn("flameshot screen"),
+        "M-S-c" => spawn("xcolor -s clipboard"),
+
+        "XF86AudioRaiseVolume" => adjust_volume(5),
+        "XF86AudioLowerVolume" => adjust_volume(-5),
+        "XF86AudioMute" => toggle_mute(),
+        "XF86AudioMicMute" => toggle_mic_mute(),
+        "M-S-m" => toggle_mic_mute(),
+        "M-A-o" => select_sink(),
+        "M-A-h" => select_bluetooth_device(),
+        "M-A-S-h" => toggle_bluetooth(),
+        "XF86AudioPlay" => media("PlayPause"),
+        "XF86AudioNext" => media("Next"),
+        "XF86AudioPrev" => media("Previous"),
+    };
+
+    // The hardware belongs to the outer session when embedded.
+    if !embedded() {
+        raw_bindings.extend(bindings! {
+            "M-A-w" => cycle_power_profile(),
+            "XF86MonBrightnessUp" => adjust_backlight(BACKLIGHT, 5),
+            "XF86MonBrightnessDown" => adjust_backlight(BACKLIGHT, -5),
+            "XF86KbdBrightnessUp" => adjust_kbd_backlight(1),
+            "XF86KbdBrightnessDown" => adjust_kbd_backlight(-1),
+        });
+    }
+
+    for (key, class, command) in FOCUS_OR_SPAWN {
+        raw_bindings.push((key.to_string(), cycle_or_spawn(class, command)));
+    }
+
+    for tag in &["1", "2", "3", "4", "5", "6", "7", "8", "9"] {
+        raw_bindings.extend([
+            (format!("M-{tag}"), focus_tag(tag)),
+            (
+                format!("M-S-{tag}"),
+                modify_with(move |client_set| client_set.move_focused_to_tag(tag)),
+            ),
+        ]);
+    }
+
+    check_key_bindings(raw_bindings)
+}
+
+/// The key bindings, checked for conflicts and parsed with xmodmap, along with the bindings
+/// for passthrough and gaming mode that stay bound while passthrough is on.
+pub fn key_bindings() -> Result<KeyBindings<RustConn>> {
+    let mut raw_bindings = raw_key_bindings();
+    drop_unresolved(&mut raw_bindings);
+    let mut bound: Vec<String> = raw_bindings.keys().cloned().collect();
+    bound.extend([PASSTHROUGH_KEY, GAMING_KEY].map(String::from));
+    dump::set_key_bindings(bound);
+    let mut key_bindings = parse_keybindings_with_xmodmap(catch_key_panics(trace_key_bindings(
+        key_driven_bindings(raw_bindings),
+    )))?;
+    let passthrough = Passthrough::default();
+    passthrough.release(key_bindings.keys().copied());
+    key_bindings.extend(parse_keybindings_with_xmodmap(catch_key_panics(
+        trace_key_bindings(map! {
+            map_keys: |k: &str| k.to_string();
+            PASSTHROUGH_KEY => passthrough.handler(),
+            GAMING_KEY => toggle_gaming_mode(GAMING, &passthrough),
+        }),
+    ))?);
+
+    Ok(key_bindings)
+}
+
+pub fn mouse_bindings() -> MouseBindings<RustConn> {
+    use penrose::core::bindings::{
+        ModifierKey::{Ctrl, Meta, Shift},
+        MouseButton::{Left, Middle, Right},
+    };
+
+    let drag = ConstrainedMouseHandler::wrap(RetagOnDropHandler::wrap(SnapDragHandler::wrap(
+        MouseDragHandler::boxed_default(),
+    )));
+
+    catch_mouse_panics(map! {
+        map_keys: |(button, modifiers)| MouseState { button, modifiers };
+
+        (Left, vec![Shift, Meta]) => drag,
+        (Right, vec![Shift, Meta]) => ConstrainedMouseHandler::wrap(MouseResizeHandler::boxed_default()),
+        (Middle, vec![Shift, Meta]) => click_handler(sink_focused()),
+        (Left, vec![Ctrl, Meta]) => SwapDragHandler::boxed_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_parse_correctly_with_xmodmap() {
+        let res = parse_keybindings_with_xmodmap(raw_key_bindings());
+
+        if let Err(e) = res {
+            panic!("{e}");
+        }
+    }
+}
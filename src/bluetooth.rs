@@ -0,0 +1,145 @@
+//! Connecting Bluetooth devices through BlueZ on the system bus.
+//!
+//! Paired devices are listed in a menu and picking one connects it, or disconnects it if it
+//! is already connected. Connecting can take several seconds, so it happens on a thread of
+//! its own rather than holding up the event loop. Whether the adapter is powered is
+//! published to the bar as "bluetooth".
+use crate::{bar, menu, modal::x_err, osd};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, State},
+    x::XConn,
+    Error,
+};
+use std::{collections::HashMap, thread};
+use tracing::{error, info};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
+
+const BLUEZ: &str = "org.bluez";
+const ADAPTER: &str = "org.bluez.Adapter1";
+const DEVICE: &str = "org.bluez.Device1";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+
+type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Device {
+    path: String,
+    alias: String,
+    connected: bool,
+}
+
+/// The entry to show in the menu for a device.
+fn device_label(alias: &str, connected: bool) -> String {
+    if connected {
+        format!("{alias} (connected)")
+    } else {
+        alias.to_string()
+    }
+}
+
+/// The paths of every object BlueZ has with `interface`, in order.
+fn objects_with(conn: &Connection, interface: &str) -> zbus::Result<Vec<String>> {
+    let manager = Proxy::new(conn, BLUEZ, "/", OBJECT_MANAGER)?;
+    let objects: ManagedObjects = manager.call("GetManagedObjects", &())?;
+    let mut paths: Vec<String> = objects
+        .into_iter()
+        .filter(|(_, interfaces)| interfaces.contains_key(interface))
+        .map(|(path, _)| path.as_str().to_string())
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn paired_devices(conn: &Connection) -> zbus::Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    for path in objects_with(conn, DEVICE)? {
+        let device = Proxy::new(conn, BLUEZ, &path, DEVICE)?;
+        if !device.get_property::<bool>("Paired")? {
+            continue;
+        }
+        devices.push(Device {
+            alias: device.get_property("Alias")?,
+            connected: device.get_property("Connected")?,
+            path,
+        });
+    }
+    devices.sort_by(|a, b| a.alias.cmp(&b.alias));
+
+    Ok(devices)
+}
+
+/// Pick a paired device from a menu, connecting it or disconnecting it if already connected.
+pub fn select_bluetooth_device<X: XConn + 'static>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let conn = Connection::system().map_err(x_err)?;
+        let devices = paired_devices(&conn).map_err(x_err)?;
+        let labels: Vec<String> = devices
+            .iter()
+            .map(|d| device_label(&d.alias, d.connected))
+            .collect();
+
+        menu::select("bluetooth", labels, state, move |ix, state, _| {
+            let device = devices[ix].clone();
+            let (method, doing) = if device.connected {
+                ("Disconnect", "Disconnecting")
+            } else {
+                ("Connect", "Connecting")
+            };
+            osd::show(state, &format!("{doing} {}", device.alias), None)?;
+
+            thread::spawn(move || {
+                info!("{doing} {}", device.alias);
+                let res = Proxy::new(&conn, BLUEZ, &device.path, DEVICE)
+                    .and_then(|d| d.call::<_, ()>(method, &()));
+                if let Err(e) = res {
+                    error!("unable to {method} {}: {e}", device.alias);
+                }
+            });
+
+            Ok(())
+        })
+    })
+}
+
+/// Power the Bluetooth adapter on or off.
+pub fn toggle_bluetooth<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let conn = Connection::system().map_err(x_err)?;
+        let path = match objects_with(&conn, ADAPTER)
+            .map_err(x_err)?
+            .into_iter()
+            .next()
+        {
+            Some(path) => path,
+            None => return Err(Error::Custom("no bluetooth adapter found".into())),
+        };
+        let adapter = Proxy::new(&conn, BLUEZ, &path, ADAPTER).map_err(x_err)?;
+        let powered = !adapter.get_property::<bool>("Powered").map_err(x_err)?;
+        adapter.set_property("Powered", powered).map_err(x_err)?;
+
+        bar::publish(state, "bluetooth", if powered { "on" } else { "" });
+        let label = if powered {
+            "Bluetooth on"
+        } else {
+            "Bluetooth off"
+        };
+
+        osd::show(state, label, None)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connected_devices_are_marked_in_the_menu() {
+        assert_eq!(device_label("WH-1000XM4", true), "WH-1000XM4 (connected)");
+        assert_eq!(device_label("MX Keys", false), "MX Keys");
+    }
+}
@@ -0,0 +1,227 @@
+//! Per client border handling on top of the focused / unfocused colours penrose applies.
+use crate::{
+    batch::Batch,
+    client::wm_class,
+    dpi::{scale_for, scaled},
+    marks::Marks,
+    sticky::Sticky,
+    urgency::Urgent,
+};
+use penrose::{
+    core::{
+        hooks::{ManageHook, StateHook},
+        ClientSet, State,
+    },
+    x::{ClientConfig, Prop, XConn, XConnExt},
+    Color, Result, Xid,
+};
+use std::collections::HashSet;
+
+// _MOTIF_WM_HINTS is five cardinals: flags, functions, decorations, input mode and status.
+// Decorations are only meaningful when the corresponding flag is set.
+const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+
+/// Clients that are drawn without a border.
+#[derive(Debug, Default, Clone)]
+pub struct Borderless {
+    clients: HashSet<Xid>,
+}
+
+impl Borderless {
+    pub fn contains(&self, id: &Xid) -> bool {
+        self.clients.contains(id)
+    }
+}
+
+/// The border width `id` is drawn with given the base width from the config: scaled for the
+/// screen it is on, or none at all if it is borderless. Fullscreen clients aren't accounted
+/// for.
+pub fn border_width(base: u32, borderless: &Borderless, cs: &ClientSet, id: &Xid) -> u32 {
+    if borderless.contains(id) {
+        return 0;
+    }
+    let scale = cs
+        .screen_for_client(id)
+        .map_or(1.0, |s| scale_for(s.geometry()));
+
+    scaled(base, scale)
+}
+
+fn wants_no_decorations(hints: &[u32]) -> bool {
+    match hints {
+        [flags, _, decorations, ..] => flags & MWM_HINTS_DECORATIONS != 0 && *decorations == 0,
+        _ => false,
+    }
+}
+
+fn motif_hints<X: XConn>(x: &X, id: Xid) -> Vec<u32> {
+    match x.get_prop(id, "_MOTIF_WM_HINTS") {
+        Ok(Some(Prop::Cardinal(vals))) | Ok(Some(Prop::Bytes(vals))) => vals,
+        _ => vec![],
+    }
+}
+
+/// Removes the border from clients that ask for no decorations via _MOTIF_WM_HINTS and from
+/// any client with one of the given WM_CLASS values.
+#[derive(Debug, Clone)]
+pub struct BorderlessHook {
+    classes: Vec<String>,
+}
+
+impl BorderlessHook {
+    pub fn new(classes: &[&str]) -> Self {
+        Self {
+            classes: classes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl<X: XConn> ManageHook<X> for BorderlessHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let by_class = wm_class(x, client).is_some_and(|c| self.classes.contains(&c));
+        if !by_class && !wants_no_decorations(&motif_hints(x, client)) {
+            return Ok(());
+        }
+
+        state
+            .extension_or_default::<Borderless>()
+            .borrow_mut()
+            .clients
+            .insert(client);
+
+        x.set_client_config(client, &[ClientConfig::BorderPx(0)])
+    }
+}
+
+impl<X: XConn> StateHook<X> for BorderlessHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Borderless>();
+        let mut borderless = ext.borrow_mut();
+        borderless
+            .clients
+            .retain(|id| state.client_set.contains(id));
+        let batch = state.extension_or_default::<Batch>();
+        let mut batch = batch.borrow_mut();
+
+        // Penrose reapplies the configured border width in places (e.g. leaving fullscreen)
+        // so this is reasserted on every refresh rather than only at manage time.
+        for &id in borderless.clients.iter() {
+            batch.configure(x, id, ClientConfig::BorderPx(0))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Scales the border width of each client by the scale factor of the screen it is on, starting
+/// from the border width in the config held by the window manager state. Like
+/// [BorderlessHook] this is reasserted on every refresh, and it skips borderless and
+/// fullscreen clients.
+#[derive(Debug, Default, Clone)]
+pub struct ScaledBordersHook {
+    /// Clients currently drawn with something other than the base width.
+    scaled: HashSet<Xid>,
+}
+
+impl<X: XConn> StateHook<X> for ScaledBordersHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let base = state.config.border_width;
+        let borderless = state.extension_or_default::<Borderless>();
+        let borderless = borderless.borrow();
+        let batch = state.extension_or_default::<Batch>();
+        let mut batch = batch.borrow_mut();
+        let cs = &state.client_set;
+        self.scaled.retain(|id| cs.contains(id));
+
+        for &id in cs.on_screen_workspace_clients() {
+            if borderless.contains(&id) || batch.fullscreen(x, id)? {
+                self.scaled.remove(&id);
+                continue;
+            }
+
+            let px = border_width(base, &borderless, cs, &id);
+            if px != base {
+                self.scaled.insert(id);
+            } else if !self.scaled.remove(&id) {
+                continue;
+            }
+
+            batch.configure(x, id, ClientConfig::BorderPx(px))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Colours the borders of unfocused clients according to their state so that it is visible
+/// at a glance. Where a client is in more than one state the first of urgent, marked, sticky
+/// and floating wins. Register this after the hooks that track those states.
+#[derive(Debug, Clone, Copy)]
+pub struct BorderColorHook {
+    pub urgent: Color,
+    pub marked: Color,
+    pub sticky: Color,
+    pub floating: Color,
+}
+
+impl<X: XConn> StateHook<X> for BorderColorHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let urgent = state.extension_or_default::<Urgent>();
+        let marks = state.extension_or_default::<Marks>();
+        let sticky = state.extension_or_default::<Sticky>();
+        let (urgent, marks, sticky) = (urgent.borrow(), marks.borrow(), sticky.borrow());
+        let focused = state.client_set.current_client();
+
+        for id in state.client_set.on_screen_workspace_clients() {
+            if Some(id) == focused {
+                continue;
+            }
+
+            let color = if urgent.contains(id) {
+                self.urgent
+            } else if marks.mark_for(id).is_some() {
+                self.marked
+            } else if sticky.contains(id) {
+                self.sticky
+            } else if state.client_set.is_floating(id) {
+                self.floating
+            } else {
+                continue;
+            };
+
+            x.set_client_border_color(*id, color)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockXConn;
+
+    #[test]
+    fn decorations_are_only_read_when_flagged() {
+        assert!(wants_no_decorations(&[MWM_HINTS_DECORATIONS, 0, 0, 0, 0]));
+        assert!(!wants_no_decorations(&[MWM_HINTS_DECORATIONS, 0, 1, 0, 0]));
+        assert!(!wants_no_decorations(&[0, 0, 0, 0, 0]));
+        assert!(!wants_no_decorations(&[]));
+    }
+
+    #[test]
+    fn clients_asking_for_no_decorations_lose_their_border() {
+        let (x, mut state, id) = MockXConn::with_client();
+        let hints = vec![MWM_HINTS_DECORATIONS, 0, 0, 0, 0];
+        x.set_prop(id, "_MOTIF_WM_HINTS", Prop::Cardinal(hints))
+            .unwrap();
+
+        ManageHook::call(&mut BorderlessHook::new(&[]), id, &mut state, &x).unwrap();
+
+        assert_eq!(x.configs(id), vec![ClientConfig::BorderPx(0)]);
+        assert!(state
+            .extension_or_default::<Borderless>()
+            .borrow()
+            .contains(&id));
+    }
+}
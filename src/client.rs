@@ -0,0 +1,101 @@
+//! Helpers for reading properties off of client windows.
+use penrose::{
+    core::State,
+    x::{ClientMessage, Prop, XConn},
+    Result, Xid,
+};
+use std::collections::HashMap;
+
+// Actions for _NET_WM_STATE client messages as laid out in the EWMH spec.
+const STATE_REMOVE: usize = 0;
+const STATE_ADD: usize = 1;
+const STATE_TOGGLE: usize = 2;
+
+/// The class component of a client's WM_CLASS property, falling back to the instance name
+/// for clients that only set one of the two.
+pub fn wm_class<X: XConn>(x: &X, id: Xid) -> Option<String> {
+    match x.get_prop(id, "WM_CLASS") {
+        Ok(Some(Prop::UTF8String(mut strs))) => strs.pop().filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+/// The atoms currently set in a client's _NET_WM_STATE property.
+pub fn net_wm_state<X: XConn>(x: &X, id: Xid) -> Result<Vec<String>> {
+    match x.get_prop(id, "_NET_WM_STATE")? {
+        Some(Prop::Atom(atoms)) => Ok(atoms),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Add or remove `atom` from a client's _NET_WM_STATE property, leaving other states intact.
+pub fn set_net_wm_state<X: XConn>(x: &X, id: Xid, atom: &str, enabled: bool) -> Result<()> {
+    let mut atoms = net_wm_state(x, id)?;
+    if atoms.iter().any(|a| a == atom) == enabled {
+        return Ok(());
+    }
+    atoms.retain(|a| a != atom);
+    if enabled {
+        atoms.push(atom.to_string());
+    }
+
+    x.set_prop(id, "_NET_WM_STATE", Prop::Atom(atoms))
+}
+
+/// The _NET_WM_STATE atoms checked for in client messages, interned the first time each is
+/// asked about.
+#[derive(Debug, Default, Clone)]
+struct StateAtoms {
+    atoms: HashMap<&'static str, usize>,
+}
+
+/// Whether `atom` should be set once the _NET_WM_STATE request `msg` has been carried out,
+/// given whether it is set now, or `None` if `msg` isn't a request to change `atom`.
+pub fn requested_state<X: XConn>(
+    state: &mut State<X>,
+    x: &X,
+    msg: &ClientMessage,
+    atom: &'static str,
+    current: bool,
+) -> Result<Option<bool>> {
+    if msg.dtype != "_NET_WM_STATE" {
+        return Ok(None);
+    }
+    let data = msg.data.as_usize();
+    let ext = state.extension_or_default::<StateAtoms>();
+    let cached = ext.borrow().atoms.get(atom).copied();
+    let atom = match cached {
+        Some(atom) => atom,
+        None => {
+            let interned = *x.intern_atom(atom)? as usize;
+            ext.borrow_mut().atoms.insert(atom, interned);
+            interned
+        }
+    };
+    if data[1] != atom && data[2] != atom {
+        return Ok(None);
+    }
+
+    Ok(match data[0] {
+        STATE_REMOVE => Some(false),
+        STATE_ADD => Some(true),
+        STATE_TOGGLE => Some(!current),
+        _ => None,
+    })
+}
+
+/// The process id a client has advertised via _NET_WM_PID.
+pub fn pid<X: XConn>(x: &X, id: Xid) -> Option<u32> {
+    match x.get_prop(id, "_NET_WM_PID") {
+        Ok(Some(Prop::Cardinal(vals))) => vals.first().copied(),
+        _ => None,
+    }
+}
+
+/// The atoms set in a client's _NET_WM_WINDOW_TYPE property, most preferred first.
+pub fn window_types<X: XConn>(x: &X, id: Xid) -> Vec<String> {
+    match x.get_prop(id, "_NET_WM_WINDOW_TYPE") {
+        Ok(Some(Prop::Atom(atoms))) => atoms,
+        _ => vec![],
+    }
+}
@@ -0,0 +1,261 @@
+//! Running the compositor as a child of the window manager.
+//!
+//! The compositor is started along with the window manager and started again if it dies,
+//! unless it keeps dying straight away (usually because another compositor is already
+//! running). Anything may pause it for a reason of its own, such as a fullscreen game wanting
+//! every frame, and it is started again once the last reason is gone. The command must keep
+//! the compositor in the foreground (so no `picom -b`) for it to be watched.
+use crate::{bar, batch::Batch, modal::x_err, timer::notify_after};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{
+    collections::BTreeSet,
+    process::{Child, Command},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+const COMPOSITOR_CHECK: &str = "_WM_COMPOSITOR_CHECK";
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// A compositor exiting sooner than this after starting counts as failing to start.
+const MIN_UPTIME: Duration = Duration::from_secs(5);
+const MAX_FAILED_STARTS: u32 = 3;
+const MANUAL_REASON: &str = "manual";
+const FULLSCREEN_REASON: &str = "fullscreen";
+
+/// Counts a child that is kept running failing to start, to know when to stop trying.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Restarts {
+    failed: u32,
+}
+
+impl Restarts {
+    /// Record the child exiting after `uptime`, returning whether to start it again.
+    pub fn exited(&mut self, uptime: Duration) -> bool {
+        if uptime < MIN_UPTIME {
+            self.failed += 1;
+        } else {
+            self.failed = 0;
+        }
+
+        self.failed < MAX_FAILED_STARTS
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Compositor {
+    command: Option<&'static str>,
+    child: Option<(Child, Instant)>,
+    restarts: Restarts,
+    paused: BTreeSet<String>,
+}
+
+impl Compositor {
+    /// Start the compositor unless it is running or paused. A compositor that can't be
+    /// started at all (e.g. because it isn't installed) is given up on.
+    fn start(&mut self) {
+        let command = match self.command {
+            Some(command) if self.child.is_none() && self.paused.is_empty() => command,
+            _ => return,
+        };
+        let mut parts = command.split_whitespace();
+        let program = match parts.next() {
+            Some(program) => program,
+            None => return,
+        };
+
+        info!("starting {command}");
+        match Command::new(program).args(parts).spawn() {
+            Ok(child) => self.child = Some((child, Instant::now())),
+            Err(e) => {
+                warn!("unable to start {command}: {e}: giving up on it");
+                self.command = None;
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some((mut child, _)) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    /// Start the compositor again if it has exited, unless it keeps failing to start.
+    fn check(&mut self) -> Result<()> {
+        let uptime = match self.child.as_mut() {
+            Some((child, started)) => match child.try_wait().map_err(x_err)? {
+                Some(status) => {
+                    warn!("compositor exited: {status}");
+                    started.elapsed()
+                }
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        self.child = None;
+
+        if self.restarts.exited(uptime) {
+            self.start();
+        } else {
+            warn!("compositor keeps exiting on startup: giving up on it");
+            self.command = None;
+        }
+
+        Ok(())
+    }
+}
+
+fn publish_paused<X: XConn>(state: &mut State<X>) {
+    let paused = state
+        .extension_or_default::<Compositor>()
+        .borrow()
+        .paused
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(",");
+    bar::publish(state, "compositor", paused);
+}
+
+/// Stop the compositor until [resume] is called with the same `reason`. Active reasons are
+/// published to the bar as "compositor".
+pub fn pause<X: XConn>(state: &mut State<X>, reason: &str) -> Result<()> {
+    {
+        let ext = state.extension_or_default::<Compositor>();
+        let mut compositor = ext.borrow_mut();
+        if !compositor.paused.insert(reason.to_string()) {
+            return Ok(());
+        }
+        compositor.stop();
+    }
+    publish_paused(state);
+
+    Ok(())
+}
+
+/// Release a pause taken with [pause], starting the compositor again if nothing else holds
+/// it paused.
+pub fn resume<X: XConn>(state: &mut State<X>, reason: &str) -> Result<()> {
+    {
+        let ext = state.extension_or_default::<Compositor>();
+        let mut compositor = ext.borrow_mut();
+        if !compositor.paused.remove(reason) {
+            return Ok(());
+        }
+        compositor.start();
+    }
+    publish_paused(state);
+
+    Ok(())
+}
+
+/// Stop the compositor for good, ahead of the window manager exiting or restarting.
+pub fn shut_down<X: XConn>(state: &mut State<X>) {
+    let ext = state.extension_or_default::<Compositor>();
+    let mut compositor = ext.borrow_mut();
+    compositor.command = None;
+    compositor.stop();
+}
+
+fn is_paused_by<X: XConn>(state: &mut State<X>, reason: &str) -> bool {
+    state
+        .extension_or_default::<Compositor>()
+        .borrow()
+        .paused
+        .contains(reason)
+}
+
+/// Pause the compositor until this is run again.
+pub fn toggle_compositor<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        if is_paused_by(state, MANUAL_REASON) {
+            resume(state, MANUAL_REASON)
+        } else {
+            pause(state, MANUAL_REASON)
+        }
+    })
+}
+
+/// Starts the compositor with `command` at startup and keeps it running.
+#[derive(Debug, Clone, Copy)]
+pub struct CompositorHook {
+    pub command: &'static str,
+}
+
+impl<X: XConn> EventHook<X> for CompositorHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == COMPOSITOR_CHECK => (),
+            _ => return Ok(true),
+        }
+
+        notify_after(CHECK_INTERVAL, COMPOSITOR_CHECK, [0; 5]);
+        state
+            .extension_or_default::<Compositor>()
+            .borrow_mut()
+            .check()?;
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for CompositorHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Compositor>();
+        let mut compositor = ext.borrow_mut();
+        compositor.command = Some(self.command);
+        notify_after(CHECK_INTERVAL, COMPOSITOR_CHECK, [0; 5]);
+        compositor.start();
+
+        Ok(())
+    }
+}
+
+/// Pauses the compositor while the focused client is fullscreen.
+#[derive(Debug, Clone, Copy)]
+pub struct FullscreenPauseHook;
+
+impl<X: XConn> StateHook<X> for FullscreenPauseHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let fullscreen = match state.client_set.current_client() {
+            Some(&id) => state
+                .extension_or_default::<Batch>()
+                .borrow_mut()
+                .fullscreen(x, id)?,
+            None => false,
+        };
+
+        if fullscreen {
+            pause(state, FULLSCREEN_REASON)
+        } else {
+            resume(state, FULLSCREEN_REASON)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarting_stops_after_repeated_failed_starts() {
+        let mut restarts = Restarts::default();
+        let quick = Duration::from_secs(1);
+
+        assert!(restarts.exited(quick));
+        assert!(restarts.exited(quick));
+        assert!(restarts.exited(Duration::from_secs(600)));
+        assert!(restarts.exited(quick));
+        assert!(restarts.exited(quick));
+        assert!(!restarts.exited(quick));
+    }
+}
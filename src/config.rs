@@ -0,0 +1,439 @@
+//! The settings the window manager runs with, and the penrose config built from them.
+use crate::{
+    activation::{ActivationPolicy, FocusStealingHook},
+    audio::MicMuteHook,
+    autostart::AutostartHook,
+    backlight::Backlight,
+    batch::batch_refresh_hooks,
+    battery::{BatteryAction, BatteryHook},
+    borders::{BorderColorHook, BorderlessHook, ScaledBordersHook},
+    compositor::{CompositorHook, FullscreenPauseHook},
+    corners::RoundedCornersHook,
+    daemons::{Daemon, DaemonsHook},
+    dpi::{DpiHook, ScaledGaps},
+    dump::dump_command,
+    embed::embedded,
+    ewmh::{EwmhHook, EwmhSupportedHook, WmDesktopHook},
+    floating::{ConstrainFloatingHook, FloatModeHook, FloatingMemoryHook, OffscreenRescueHook},
+    focus::{FocusHistoryHook, FocusMode, TakeFocusHook, WarpPointerHook},
+    gaming::GamingMode,
+    hidden::MinimizeHook,
+    idle::{CaffeineHook, Dpms, FullscreenInhibitHook, IdleLockHook},
+    ipc::{IpcCommand, IpcHook},
+    kbd_backlight::{KbdBacklight, KbdBacklightHook},
+    kill::{ForceKillHook, KillModeHook},
+    layouts::OutputLayoutsHook,
+    lid::{LidAction, LidHook, LidSwitch},
+    logging::log_level_command,
+    marks::MarksHook,
+    maximize::MaximizeHook,
+    menu::MenuHook,
+    migration::WorkspaceMigrationHook,
+    monitor::{MonitorHook, OutputScriptHook},
+    network::{NetworkHook, NetworkScriptHook},
+    nightlight::NightLight,
+    opacity::OpacityHook,
+    osd::OsdHook,
+    panics::{catch_hook_panics, non_essential},
+    pip::{Corner, PictureInPictureHook},
+    power::PowerProfiles,
+    presentation::{Presentation, PresentationHook},
+    process::{spawn, ReaperHook},
+    profiles::{output_command, Profile, ProfileHook, ProfileOutput},
+    restart::RestoreHook,
+    schedule::{Location, ScheduleHook, When},
+    screens::PrimaryHook,
+    screensaver::ScreenSaverHook,
+    selection::ReplacedHook,
+    spans::{traced, TracedLayout},
+    stacking::AlwaysOnTopHook,
+    startup::StartupNotificationHook,
+    sticky::StickyHook,
+    struts::{Reserve, Reserved, ReservedSpaceHook},
+    swallow::SwallowHook,
+    systemd::SystemdHook,
+    titlebar::{TitlebarHook, Titled},
+    urgency::UrgencyHook,
+    wallpaper::Scaling,
+};
+use penrose::{
+    builtin::layout::{CenteredMain, MainAndStack, Monocle},
+    core::{layout::LayoutStack, Config},
+    extensions::hooks::add_ewmh_hooks,
+    stack,
+    x11rb::RustConn,
+    Result,
+};
+use std::{collections::HashMap, time::Duration};
+use x11rb::protocol::randr::Rotation;
+
+pub const WHITE: u32 = 0xffffffff;
+pub const RED: u32 = 0xcc241dff;
+pub const BLUE: u32 = 0x458588ff;
+pub const GREY: u32 = 0x3c3836ff;
+pub const YELLOW: u32 = 0xd79921ff;
+pub const GREEN: u32 = 0x98971aff;
+pub const PURPLE: u32 = 0xb16286ff;
+pub const CENTERED_FLOAT_SIZE: (f64, f64) = (0.6, 0.7);
+pub const FLOAT_MODE_STEP: u32 = 20;
+pub const KILL_TIMEOUT: Duration = Duration::from_secs(3);
+pub const FOCUS_MODE: FocusMode = FocusMode::FollowMouse;
+pub const WARP_POINTER: bool = true;
+pub const ACTIVATION_POLICY: ActivationPolicy = ActivationPolicy::MarkUrgent;
+pub const OPACITY_STEP: f64 = 0.05;
+pub const CORNER_RADIUS: u16 = 8;
+/// Border width at a scale factor of 1.
+pub const BORDER_WIDTH: u32 = 2;
+/// Scale factors for outputs whose reported physical size gives the wrong one.
+pub const DPI_OVERRIDES: [(&str, f64); 0] = [];
+/// Bindings that focus (or cycle through) the clients with a WM_CLASS, running the given
+/// command if there are none.
+pub const FOCUS_OR_SPAWN: [(&str, &str, &str); 2] =
+    [("M-c", "emacs", "emacs"), ("M-s", "spotify", "spotify")];
+pub const TITLEBAR_TAGS: [&str; 2] = ["8", "9"];
+/// Wallpapers for specific outputs, by their xrandr name.
+pub const WALLPAPERS: [(&str, &str); 1] = [("HDMI-1", "/home/praneeth/Pictures/wall6.jpg")];
+pub const DEFAULT_WALLPAPER: &str = "/home/praneeth/Pictures/wall5.jpg";
+/// Run with the details of each output that is connected, disconnected or changed.
+pub const OUTPUT_SCRIPT: Option<&str> = None;
+/// Run with the kind of change and the connection name each time the network changes, e.g.
+/// `script disconnected HomeWifi`.
+pub const NETWORK_SCRIPT: Option<&str> = None;
+/// What to do when a projector is plugged in.
+pub const PRESENTATION: Presentation = Presentation {
+    projectors: &["DP-2", "HDMI-2"],
+    mirror: true,
+    tag: Some("5"),
+};
+/// Monitor arrangements applied as soon as exactly their outputs are connected.
+pub const MONITOR_PROFILES: [Profile; 2] = [
+    Profile {
+        name: "laptop",
+        outputs: &[ProfileOutput {
+            name: "eDP-1",
+            edid: None,
+            mode: Some((1920, 1080)),
+            pos: (0, 0),
+            rotation: Rotation::ROTATE0,
+            primary: true,
+        }],
+        tags: &[],
+        docked: false,
+    },
+    Profile {
+        name: "desk",
+        outputs: &[
+            ProfileOutput {
+                name: "eDP-1",
+                edid: None,
+                mode: Some((1920, 1080)),
+                pos: (0, 360),
+                rotation: Rotation::ROTATE0,
+                primary: false,
+            },
+            ProfileOutput {
+                name: "HDMI-1",
+                edid: None,
+                mode: Some((2560, 1440)),
+                pos: (1920, 0),
+                rotation: Rotation::ROTATE0,
+                primary: true,
+            },
+        ],
+        tags: &[("1", "HDMI-1"), ("9", "eDP-1")],
+        docked: true,
+    },
+];
+/// The laptop panel, to turn off while a docked profile applies, or `None` to keep using it.
+pub const DOCKED_PANEL: Option<&str> = None;
+/// Space to keep free along the edges of particular outputs, for docks and widgets that
+/// don't reserve it themselves.
+pub const RESERVED_SPACE: [(&str, Reserve); 0] = [];
+/// Seconds of inactivity before the monitors go into standby, suspend and off.
+pub const DPMS: Dpms = Dpms {
+    standby: 600,
+    suspend: 900,
+    off: 1200,
+};
+/// Lock the screen after this long without any input.
+pub const IDLE_LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+/// Dim the screens for this long before the idle lock as a warning.
+pub const IDLE_DIM: Duration = Duration::from_secs(15);
+pub const LOCKER: &str = "xsecurelock";
+/// How long caffeine mode keeps the screens on for, or `None` until it is turned off.
+pub const CAFFEINE_EXPIRE: Option<Duration> = Some(Duration::from_secs(2 * 60 * 60));
+/// Tags and WM_CLASSes that hold off the idle lock while focused.
+pub const IDLE_LOCK_TAGS: [&str; 0] = [];
+pub const IDLE_LOCK_CLASSES: [&str; 0] = [];
+pub const BACKLIGHT: Backlight = Backlight {
+    devices: None,
+    min_percent: 1,
+    fade: Duration::from_millis(150),
+};
+pub const KBD_BACKLIGHT: KbdBacklight = KbdBacklight {
+    idle_timeout: Duration::from_secs(30),
+};
+/// Battery levels to act at as it runs down.
+pub const BATTERY_THRESHOLDS: [(u8, BatteryAction); 3] = [
+    (20, BatteryAction::Notify),
+    (10, BatteryAction::Run("light -S 10")),
+    (5, BatteryAction::Suspend),
+];
+/// Closing the lid only turns off the panel while other monitors are in use.
+pub const LID_SWITCH: LidSwitch = LidSwitch {
+    panel: "eDP-1",
+    locker: LOCKER,
+    docked: LidAction::TurnOffPanel,
+    on_ac: LidAction::Lock,
+    on_battery: LidAction::LockAndSuspend,
+};
+pub const NIGHT_LIGHT: NightLight = NightLight {
+    day: 6600,
+    night: 3400,
+    schedule: Some(((21, 0), (7, 0))),
+};
+/// Where to work out sunrise and sunset for the schedule.
+pub const LOCATION: Location = Location {
+    latitude: 17.4,
+    longitude: 78.5,
+};
+/// Tags to run in the performance power profile while plugged in.
+pub const POWER_PROFILES: PowerProfiles = PowerProfiles {
+    performance_tags: &["9"],
+};
+/// Toggles passing every other key binding through to the focused application.
+pub const PASSTHROUGH_KEY: &str = "M-Pause";
+/// Toggles gaming mode, which also turns passthrough on.
+pub const GAMING_KEY: &str = "M-S-Pause";
+pub const GAMING: GamingMode = GamingMode {
+    pause_compositor: true,
+};
+/// Run in the foreground so that it can be restarted if it dies.
+pub const COMPOSITOR: Option<&str> = Some("picom");
+/// Started in this order, and kept running in the foreground.
+pub const DAEMONS: &[Daemon] = &[
+    Daemon {
+        name: "dunst",
+        command: "dunst",
+    },
+    Daemon {
+        name: "polkit agent",
+        command: "/usr/lib/polkit-gnome/polkit-gnome-authentication-agent-1",
+    },
+];
+/// Started inside Xephyr with `--embed <WxH> --with-clients`.
+pub const EMBED_CLIENTS: [&str; 3] = ["alacritty", "alacritty", "xclock"];
+
+fn with_gaps(layouts: LayoutStack) -> LayoutStack {
+    layouts.map(|layout| {
+        TracedLayout::wrap(Reserved::wrap(ScaledGaps::wrap(
+            layout,
+            10,
+            10,
+            BORDER_WIDTH,
+        )))
+    })
+}
+
+fn layouts() -> LayoutStack {
+    with_gaps(stack!(MainAndStack::boxed_default(), Monocle::boxed()))
+}
+
+/// The layouts for a tag when it is shown on the given output.
+fn output_layouts(output: &str, tag: &str) -> LayoutStack {
+    let layouts = match output {
+        "HDMI-1" => with_gaps(stack!(
+            CenteredMain::boxed_default(),
+            MainAndStack::boxed_default(),
+            Monocle::boxed()
+        )),
+        "eDP-1" => with_gaps(stack!(Monocle::boxed(), MainAndStack::boxed_default())),
+        _ => layouts(),
+    };
+
+    if TITLEBAR_TAGS.contains(&tag) {
+        layouts.map(Titled::wrap)
+    } else {
+        layouts
+    }
+}
+
+/// The penrose config: the layouts, and every hook wired up with the settings above.
+pub fn config() -> Result<Config<RustConn>> {
+    let mut ipc_commands: HashMap<&str, Box<dyn IpcCommand<RustConn>>> = HashMap::new();
+    ipc_commands.insert("output", Box::new(output_command));
+    ipc_commands.insert("log-level", Box::new(log_level_command));
+    ipc_commands.insert("dump", Box::new(dump_command));
+    let mut config = Config {
+        default_layouts: layouts(),
+        focused_border: WHITE.into(),
+        border_width: BORDER_WIDTH,
+        ..Config::default()
+    };
+    // These come first so that nothing failing later in startup can leave systemd waiting to
+    // hear the window manager is ready, or SIGCHLD ignored with no children being reaped.
+    config.compose_or_set_startup_hook(traced(SystemdHook::default()));
+    config.compose_or_set_startup_hook(non_essential(traced(ReaperHook)));
+    let stealing =
+        FocusStealingHook::new(ACTIVATION_POLICY, &[("Alacritty", ActivationPolicy::Focus)]);
+    config.compose_or_set_event_hook(traced(stealing.clone()));
+    config.compose_or_set_manage_hook(traced(stealing));
+    let mut config = add_ewmh_hooks(config);
+    config.compose_or_set_startup_hook(non_essential(traced(EwmhSupportedHook)));
+    config.compose_or_set_manage_hook(traced(EwmhHook::default()));
+    config.compose_or_set_refresh_hook(traced(EwmhHook::default()));
+    config.compose_or_set_event_hook(traced(WmDesktopHook::default()));
+    config.compose_or_set_refresh_hook(traced(WmDesktopHook::default()));
+    config.compose_or_set_event_hook(traced(IpcHook::new(ipc_commands)));
+    FOCUS_MODE.apply(&mut config);
+    let monitor = MonitorHook::new(&WALLPAPERS, DEFAULT_WALLPAPER, Scaling::Fit);
+    config.compose_or_set_event_hook(traced(monitor.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(monitor)));
+    if let Some(script) = OUTPUT_SCRIPT {
+        config.compose_or_set_event_hook(traced(OutputScriptHook { script }));
+    }
+    config.compose_or_set_event_hook(traced(NetworkHook::default()));
+    if let Some(script) = NETWORK_SCRIPT {
+        config.compose_or_set_event_hook(traced(NetworkScriptHook { script }));
+    }
+    let migration = WorkspaceMigrationHook::default();
+    config.compose_or_set_event_hook(traced(migration.clone()));
+    config.compose_or_set_refresh_hook(traced(migration));
+    let output_layouts = OutputLayoutsHook::new(output_layouts);
+    config.compose_or_set_event_hook(traced(output_layouts.clone()));
+    config.compose_or_set_refresh_hook(traced(output_layouts));
+    let primary = PrimaryHook {
+        output: None,
+        dialogs_on_primary: true,
+    };
+    config.compose_or_set_event_hook(traced(primary));
+    config.compose_or_set_startup_hook(non_essential(traced(primary)));
+    config.compose_or_set_manage_hook(traced(primary));
+    let dpi = DpiHook {
+        overrides: &DPI_OVERRIDES,
+    };
+    config.compose_or_set_event_hook(traced(dpi));
+    config.compose_or_set_startup_hook(non_essential(traced(dpi)));
+    let reserved = ReservedSpaceHook {
+        outputs: &RESERVED_SPACE,
+    };
+    config.compose_or_set_event_hook(traced(reserved));
+    config.compose_or_set_startup_hook(non_essential(traced(reserved)));
+    let profiles = ProfileHook::new(&MONITOR_PROFILES, DOCKED_PANEL);
+    config.compose_or_set_event_hook(traced(profiles.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(profiles)));
+    let presentation = PresentationHook::new(PRESENTATION);
+    config.compose_or_set_event_hook(traced(presentation.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(presentation)));
+    config.compose_or_set_event_hook(traced(NIGHT_LIGHT));
+    let schedule = ScheduleHook::new(
+        LOCATION,
+        vec![
+            (When::Sunset(0), spawn("darkman set dark")),
+            (When::Sunrise(0), spawn("darkman set light")),
+            (When::At(0, 0), spawn("dunstctl set-paused true")),
+            (When::At(8, 0), spawn("dunstctl set-paused false")),
+        ],
+    );
+    config.compose_or_set_event_hook(traced(schedule.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(schedule)));
+    config.compose_or_set_startup_hook(non_essential(traced(NIGHT_LIGHT)));
+    config.compose_or_set_event_hook(traced(DPMS));
+    config.compose_or_set_startup_hook(non_essential(traced(DPMS)));
+    config.compose_or_set_refresh_hook(traced(FullscreenInhibitHook { classes: None }));
+    let idle_lock = IdleLockHook::new(
+        IDLE_LOCK_TIMEOUT,
+        IDLE_DIM,
+        LOCKER,
+        &IDLE_LOCK_TAGS,
+        &IDLE_LOCK_CLASSES,
+    );
+    config.compose_or_set_event_hook(traced(idle_lock.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(idle_lock)));
+    config.compose_or_set_event_hook(traced(CaffeineHook));
+    config.compose_or_set_startup_hook(non_essential(traced(MicMuteHook)));
+    let battery = BatteryHook::new(&BATTERY_THRESHOLDS);
+    config.compose_or_set_event_hook(traced(battery.clone()));
+    config.compose_or_set_startup_hook(non_essential(traced(battery)));
+    // The hardware belongs to the outer session when embedded.
+    if !embedded() {
+        config.compose_or_set_startup_hook(non_essential(traced(BACKLIGHT)));
+        let kbd_backlight = KbdBacklightHook::new(KBD_BACKLIGHT);
+        config.compose_or_set_event_hook(traced(kbd_backlight));
+        config.compose_or_set_startup_hook(non_essential(traced(kbd_backlight)));
+        config.compose_or_set_event_hook(traced(POWER_PROFILES));
+        config.compose_or_set_refresh_hook(traced(POWER_PROFILES));
+        let lid = LidHook::new(LID_SWITCH);
+        config.compose_or_set_event_hook(traced(lid.clone()));
+        config.compose_or_set_startup_hook(non_essential(traced(lid)));
+        config.compose_or_set_event_hook(traced(ScreenSaverHook::default()));
+    }
+    if let Some(command) = COMPOSITOR {
+        let compositor = CompositorHook { command };
+        config.compose_or_set_event_hook(traced(compositor));
+        config.compose_or_set_startup_hook(non_essential(traced(compositor)));
+        config.compose_or_set_refresh_hook(traced(FullscreenPauseHook));
+    }
+    config.compose_or_set_event_hook(traced(ConstrainFloatingHook));
+    config.compose_or_set_event_hook(traced(FloatModeHook));
+    config.compose_or_set_event_hook(traced(ForceKillHook));
+    config.compose_or_set_event_hook(traced(KillModeHook));
+    config.compose_or_set_event_hook(traced(OffscreenRescueHook));
+    config.compose_or_set_refresh_hook(traced(RestoreHook::load()));
+    config.compose_or_set_manage_hook(traced(FloatingMemoryHook));
+    config.compose_or_set_event_hook(traced(FloatingMemoryHook));
+    config.compose_or_set_event_hook(traced(AlwaysOnTopHook));
+    config.compose_or_set_manage_hook(traced(AlwaysOnTopHook));
+    config.compose_or_set_refresh_hook(traced(AlwaysOnTopHook));
+    config.compose_or_set_manage_hook(traced(PictureInPictureHook {
+        corner: Corner::BottomRight,
+        max_size: 0.3,
+    }));
+    config.compose_or_set_event_hook(traced(StickyHook::default()));
+    config.compose_or_set_manage_hook(traced(StickyHook::default()));
+    config.compose_or_set_refresh_hook(traced(StickyHook::default()));
+    config.compose_or_set_event_hook(traced(MinimizeHook));
+    config.compose_or_set_event_hook(traced(MaximizeHook));
+    config.compose_or_set_manage_hook(traced(MaximizeHook));
+    config.compose_or_set_refresh_hook(traced(MaximizeHook));
+    config.compose_or_set_event_hook(traced(MarksHook));
+    config.compose_or_set_refresh_hook(traced(MarksHook));
+    config.compose_or_set_event_hook(traced(MenuHook));
+    config.compose_or_set_refresh_hook(traced(FocusHistoryHook));
+    config.compose_or_set_refresh_hook(traced(TakeFocusHook::default()));
+    let urgency = UrgencyHook;
+    config.compose_or_set_event_hook(traced(urgency));
+    config.compose_or_set_manage_hook(traced(urgency));
+    config.compose_or_set_refresh_hook(traced(urgency));
+    if WARP_POINTER {
+        config.compose_or_set_refresh_hook(traced(WarpPointerHook::default()));
+    }
+    let swallow = SwallowHook::new(&["Alacritty"]);
+    config.compose_or_set_manage_hook(traced(swallow.clone()));
+    config.compose_or_set_refresh_hook(traced(swallow));
+    config.compose_or_set_refresh_hook(traced(OpacityHook::new(&[("Alacritty", 0.9)])));
+    let borderless = BorderlessHook::new(&["Conky", "mpv"]);
+    config.compose_or_set_manage_hook(traced(borderless.clone()));
+    config.compose_or_set_refresh_hook(traced(borderless));
+    config.compose_or_set_refresh_hook(traced(ScaledBordersHook::default()));
+    config.compose_or_set_refresh_hook(traced(BorderColorHook {
+        urgent: RED.into(),
+        marked: YELLOW.into(),
+        sticky: GREEN.into(),
+        floating: PURPLE.into(),
+    }));
+    config.compose_or_set_refresh_hook(traced(RoundedCornersHook::new(CORNER_RADIUS)));
+    let titlebars = TitlebarHook::new(BLUE.into(), GREY.into(), KILL_TIMEOUT)?;
+    config.compose_or_set_event_hook(traced(titlebars.clone()));
+    config.compose_or_set_refresh_hook(traced(titlebars));
+    config.compose_or_set_event_hook(traced(OsdHook));
+    config.compose_or_set_event_hook(traced(SystemdHook::default()));
+    config.compose_or_set_startup_hook(non_essential(traced(DaemonsHook { daemons: DAEMONS })));
+    config.compose_or_set_event_hook(traced(DaemonsHook { daemons: DAEMONS }));
+    config.compose_or_set_startup_hook(non_essential(traced(AutostartHook)));
+    config.compose_or_set_event_hook(traced(ReplacedHook));
+    config.compose_or_set_event_hook(traced(StartupNotificationHook::default()));
+    config.compose_or_set_manage_hook(traced(StartupNotificationHook::default()));
+
+    Ok(batch_refresh_hooks(catch_hook_panics(config)))
+}
@@ -0,0 +1,123 @@
+//! Reporting mistakes in the key bindings.
+//!
+//! The bindings are a long list, so it is easy to bind the same keys twice (or the same keys
+//! spelled differently, `M-S-q` and `S-M-q`) and have one binding silently replace another, or
+//! to use a key name that xmodmap doesn't know, which would stop the window manager from
+//! starting at all. These are all logged as warnings at startup, and bindings for keys that
+//! can't be resolved are dropped so that the rest still work.
+use penrose::{
+    core::bindings::{keycodes_from_xmodmap, KeyEventHandler},
+    x::XConn,
+};
+use std::collections::{BTreeSet, HashMap};
+use tracing::warn;
+
+const MODIFIERS: [&str; 4] = ["A", "C", "M", "S"];
+
+/// A problem with a binding, found by [conflicts].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Conflict {
+    /// The same keys are bound twice.
+    Duplicate(String),
+    /// The same keys are bound twice with different spellings (earlier, later).
+    Respelled(String, String),
+    /// A modifier other than those penrose understands.
+    UnknownModifier(String),
+}
+
+/// The modifiers and key for `keys`, e.g. `({"M", "S"}, "q")` for `M-S-q`.
+fn combo(keys: &str) -> Option<(BTreeSet<&str>, &str)> {
+    let mut parts: Vec<&str> = keys.split('-').collect();
+    let key = parts.pop().filter(|key| !key.is_empty())?;
+    if parts.iter().any(|m| !MODIFIERS.contains(m)) {
+        return None;
+    }
+
+    Some((parts.into_iter().collect(), key))
+}
+
+/// Every problem with `bound`, the keys bound in the order they were bound.
+fn conflicts(bound: &[&str]) -> Vec<Conflict> {
+    let mut seen: HashMap<(BTreeSet<&str>, &str), &str> = HashMap::new();
+    let mut found = Vec::new();
+    for &keys in bound {
+        let c = match combo(keys) {
+            Some(c) => c,
+            None => {
+                found.push(Conflict::UnknownModifier(keys.to_string()));
+                continue;
+            }
+        };
+        match seen.insert(c, keys) {
+            Some(earlier) if earlier == keys => found.push(Conflict::Duplicate(keys.to_string())),
+            Some(earlier) => found.push(Conflict::Respelled(earlier.to_string(), keys.to_string())),
+            None => (),
+        }
+    }
+
+    found
+}
+
+/// Warn about any conflicts in `bindings`, returning them with later bindings replacing
+/// earlier ones for the same keys.
+pub fn check_key_bindings<X: XConn>(
+    bindings: Vec<(String, Box<dyn KeyEventHandler<X>>)>,
+) -> HashMap<String, Box<dyn KeyEventHandler<X>>> {
+    let bound: Vec<&str> = bindings.iter().map(|(keys, _)| keys.as_str()).collect();
+    for conflict in conflicts(&bound) {
+        match conflict {
+            Conflict::Duplicate(keys) => warn!("{keys} is bound more than once"),
+            Conflict::Respelled(earlier, keys) => {
+                warn!("{keys} replaces the binding for {earlier}")
+            }
+            Conflict::UnknownModifier(keys) => warn!("{keys} has an unknown modifier"),
+        }
+    }
+
+    let mut checked = HashMap::new();
+    for (keys, handler) in bindings {
+        // Respelled keys would otherwise both be bound, and penrose would pick one at random.
+        checked.retain(|k: &String, _| combo(k) != combo(&keys) || combo(k).is_none());
+        checked.insert(keys, handler);
+    }
+
+    checked
+}
+
+/// Drop any bindings for keys that xmodmap doesn't know about, with a warning for each.
+pub fn drop_unresolved<X: XConn>(bindings: &mut HashMap<String, Box<dyn KeyEventHandler<X>>>) {
+    let keycodes = match keycodes_from_xmodmap() {
+        Ok(keycodes) => keycodes,
+        Err(e) => {
+            warn!("unable to check key bindings against xmodmap: {e}");
+            return;
+        }
+    };
+
+    bindings.retain(|keys, _| {
+        let resolved = combo(keys).is_some_and(|(_, key)| keycodes.contains_key(key));
+        if !resolved {
+            warn!("dropping the binding for {keys}: unable to resolve it");
+        }
+        resolved
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_keys_spelled_differently_conflict() {
+        let found = conflicts(&["M-S-q", "M-q", "S-M-q", "M-q", "M-X-q"]);
+
+        assert_eq!(
+            found,
+            vec![
+                Conflict::Respelled("M-S-q".into(), "S-M-q".into()),
+                Conflict::Duplicate("M-q".into()),
+                Conflict::UnknownModifier("M-X-q".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Rounded window corners using the X Shape extension rather than relying on the compositor.
+use crate::{
+    batch::Batch,
+    borders::{border_width, Borderless},
+    modal::x_err,
+};
+use penrose::{
+    core::{hooks::StateHook, State},
+    x::XConn,
+    Result, Xid,
+};
+use std::collections::HashMap;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        shape::{ClipOrdering, ConnectionExt as _, SK, SO},
+        xproto::Rectangle,
+    },
+    rust_connection::RustConnection,
+    NONE,
+};
+
+/// One rectangle per row across the rounded top and bottom of a `w` x `h` window, plus a
+/// single rectangle for the straight sided middle.
+fn rounded_rects(w: u16, h: u16, radius: u16) -> Vec<Rectangle> {
+    let r = radius.min(w / 2).min(h / 2);
+    let rf = r as f64;
+    let mut rects = Vec::with_capacity(2 * r as usize + 1);
+
+    for row in 0..r {
+        let dy = rf - row as f64 - 0.5;
+        let inset = (rf - (rf * rf - dy * dy).sqrt()).round() as u16;
+        let width = w - 2 * inset;
+        rects.push(Rectangle {
+            x: inset as i16,
+            y: row as i16,
+            width,
+            height: 1,
+        });
+        rects.push(Rectangle {
+            x: inset as i16,
+            y: (h - row - 1) as i16,
+            width,
+            height: 1,
+        });
+    }
+    rects.push(Rectangle {
+        x: 0,
+        y: r as i16,
+        width: w,
+        height: h - 2 * r,
+    });
+
+    rects
+}
+
+/// Shapes visible clients to have rounded corners of the given radius after each refresh,
+/// leaving fullscreen clients square. Shapes are only recomputed when a client's size or
+/// border width changes. Register this after [BorderlessHook](crate::borders::BorderlessHook)
+/// and [ScaledBordersHook](crate::borders::ScaledBordersHook), whose border widths it follows.
+#[derive(Debug)]
+pub struct RoundedCornersHook {
+    radius: u16,
+    conn: Option<RustConnection>,
+    /// The size including the border and the border width each client was last shaped for.
+    shaped: HashMap<Xid, ((u16, u16), u16)>,
+}
+
+impl RoundedCornersHook {
+    pub fn new(radius: u16) -> Self {
+        Self {
+            radius,
+            conn: None,
+            shaped: HashMap::new(),
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for RoundedCornersHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        if self.conn.is_none() {
+            self.conn = Some(x11rb::connect(None).map_err(x_err)?.0);
+        }
+        let conn = match &self.conn {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+
+        self.shaped.retain(|id, _| state.client_set.contains(id));
+        let base = state.config.border_width;
+        let borderless = state.extension_or_default::<Borderless>();
+        let borderless = borderless.borrow();
+        let batch = state.extension_or_default::<Batch>();
+        let mut batch = batch.borrow_mut();
+
+        for &id in state.client_set.on_screen_workspace_clients() {
+            if batch.fullscreen(x, id)? {
+                if self.shaped.remove(&id).is_some() {
+                    conn.shape_mask(SO::SET, SK::BOUNDING, *id, 0, 0, NONE)
+                        .map_err(x_err)?;
+                }
+                continue;
+            }
+
+            // The bounding shape covers the border as well, which sits at negative offsets
+            // from the window's origin. Border changes from this refresh haven't been sent
+            // yet, so the width is worked out the same way the border hooks do it.
+            let bw = border_width(base, &borderless, &state.client_set, &id) as u16;
+            let r = x.client_geometry(id)?;
+            let size = (r.w as u16 + 2 * bw, r.h as u16 + 2 * bw);
+            if self.shaped.get(&id) == Some(&(size, bw)) {
+                continue;
+            }
+
+            let rects = rounded_rects(size.0, size.1, self.radius);
+            conn.shape_rectangles(
+                SO::SET,
+                SK::BOUNDING,
+                ClipOrdering::UNSORTED,
+                *id,
+                -(bw as i16),
+                -(bw as i16),
+                &rects,
+            )
+            .map_err(x_err)?;
+            self.shaped.insert(id, (size, bw));
+        }
+
+        conn.flush().map_err(x_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_are_cut_and_the_middle_is_left_whole() {
+        let rects = rounded_rects(100, 50, 4);
+
+        assert_eq!(rects.len(), 9);
+        assert_eq!(rects[0].y, 0);
+        assert!(rects[0].x > 0);
+        assert_eq!(rects[1].y, 49);
+        assert_eq!(rects[0].width, 100 - 2 * rects[0].x as u16);
+        assert_eq!(
+            rects[8],
+            Rectangle {
+                x: 0,
+                y: 4,
+                width: 100,
+                height: 42
+            }
+        );
+    }
+
+    #[test]
+    fn radius_is_capped_for_small_windows() {
+        assert_eq!(rounded_rects(6, 100, 10).len(), 7);
+    }
+}
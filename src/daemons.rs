@@ -0,0 +1,207 @@
+//! Daemons run as children of the window manager for the length of the session.
+//!
+//! The daemons in the config (a notification daemon, a polkit agent...) are started in the
+//! order they are listed once the window manager is up, and any that crash are started
+//! again, unless they keep crashing straight away in the same way as the
+//! [compositor](crate::compositor). One that exits successfully is taken to have meant it and
+//! is left alone. When the window manager exits or restarts they are asked to stop with
+//! SIGTERM, last started first, and killed if they haven't stopped a moment later. As with
+//! autostart entries, they aren't started when embedded. Each command must keep its daemon
+//! in the foreground for it to be watched.
+use crate::{
+    compositor::Restarts, embed::embedded, modal::x_err, process::run_with_args,
+    timer::notify_after,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+const DAEMON_CHECK: &str = "_WM_DAEMON_CHECK";
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// How long daemons are given to exit after SIGTERM before being killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A program to keep running for the length of the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Daemon {
+    pub name: &'static str,
+    pub command: &'static str,
+}
+
+#[derive(Debug)]
+struct Supervised {
+    daemon: Daemon,
+    child: Option<(Child, Instant)>,
+    restarts: Restarts,
+    /// Cleared once the daemon exits successfully, or keeps failing to start.
+    wanted: bool,
+}
+
+impl Supervised {
+    fn start(&mut self) -> Result<()> {
+        let mut parts = self.daemon.command.split_whitespace();
+        let program = match parts.next() {
+            Some(program) if self.wanted && self.child.is_none() => program,
+            _ => return Ok(()),
+        };
+
+        info!("starting {}", self.daemon.name);
+        let child = Command::new(program).args(parts).spawn().map_err(x_err)?;
+        self.child = Some((child, Instant::now()));
+
+        Ok(())
+    }
+
+    /// Start the daemon again if it has crashed, unless it keeps crashing.
+    fn check(&mut self) -> Result<()> {
+        let (status, uptime) = match self.child.as_mut() {
+            Some((child, started)) => match child.try_wait().map_err(x_err)? {
+                Some(status) => (status, started.elapsed()),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        self.child = None;
+
+        let name = self.daemon.name;
+        if status.success() {
+            info!("{name} exited");
+            self.wanted = false;
+            return Ok(());
+        }
+        warn!("{name} exited: {status}");
+        if self.restarts.exited(uptime) {
+            return self.start();
+        }
+
+        warn!("{name} keeps exiting on startup: giving up on it");
+        self.wanted = false;
+        let message = format!("{name} keeps exiting on startup");
+        run_with_args("notify-send", &["Window manager", &message])
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Daemons {
+    supervised: Vec<Supervised>,
+}
+
+/// Stop every daemon for good, ahead of the window manager exiting or restarting.
+pub fn shut_down<X: XConn>(state: &mut State<X>) {
+    let ext = state.extension_or_default::<Daemons>();
+    let mut daemons = ext.borrow_mut();
+    let mut children: Vec<(Daemon, Child)> = daemons
+        .supervised
+        .drain(..)
+        .rev()
+        .filter_map(|s| Some((s.daemon, s.child?.0)))
+        .collect();
+
+    for (daemon, child) in &children {
+        let terminated = Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status();
+        if !terminated.is_ok_and(|status| status.success()) {
+            warn!("unable to send SIGTERM to {}", daemon.name);
+        }
+    }
+
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while !children.is_empty() && Instant::now() < deadline {
+        children.retain_mut(|(_, child)| matches!(child.try_wait(), Ok(None)));
+        thread::sleep(POLL_INTERVAL);
+    }
+    for (daemon, mut child) in children {
+        warn!("{} didn't stop: killing it", daemon.name);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Starts `daemons` at startup, in order, and keeps them running.
+#[derive(Debug, Clone, Copy)]
+pub struct DaemonsHook {
+    pub daemons: &'static [Daemon],
+}
+
+impl<X: XConn> StateHook<X> for DaemonsHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        if embedded() {
+            return Ok(());
+        }
+        let ext = state.extension_or_default::<Daemons>();
+        let mut daemons = ext.borrow_mut();
+        for &daemon in self.daemons {
+            let mut supervised = Supervised {
+                daemon,
+                child: None,
+                restarts: Restarts::default(),
+                wanted: true,
+            };
+            if let Err(e) = supervised.start() {
+                warn!("unable to start {}: {e}", daemon.name);
+            }
+            daemons.supervised.push(supervised);
+        }
+        notify_after(CHECK_INTERVAL, DAEMON_CHECK, [0; 5]);
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for DaemonsHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == DAEMON_CHECK => (),
+            _ => return Ok(true),
+        }
+
+        notify_after(CHECK_INTERVAL, DAEMON_CHECK, [0; 5]);
+        let ext = state.extension_or_default::<Daemons>();
+        for supervised in ext.borrow_mut().supervised.iter_mut() {
+            if let Err(e) = supervised.check() {
+                warn!("unable to restart {}: {e}", supervised.daemon.name);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daemons_that_exit_successfully_are_left_alone() {
+        let mut supervised = Supervised {
+            daemon: Daemon {
+                name: "true",
+                command: "true",
+            },
+            child: None,
+            restarts: Restarts::default(),
+            wanted: true,
+        };
+        supervised.start().unwrap();
+        if let Some((child, _)) = supervised.child.as_mut() {
+            child.wait().unwrap();
+        }
+
+        supervised.check().unwrap();
+        assert!(supervised.child.is_none());
+        assert!(!supervised.wanted);
+    }
+}
@@ -0,0 +1,219 @@
+//! Moving between clients based on where they are on screen rather than their stack order.
+use crate::focus::warp_to_focus;
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, ClientSet, State},
+    pure::geometry::Rect,
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// How far `to` is from `from` along this direction and across it, or `None` if `to`
+    /// doesn't lie in this direction at all.
+    fn offsets(&self, from: Rect, to: Rect) -> Option<(u32, u32)> {
+        let (a, b) = (from.midpoint(), to.midpoint());
+        let (along, across) = match self {
+            Direction::Left if b.x < a.x => (a.x - b.x, a.y.abs_diff(b.y)),
+            Direction::Right if b.x > a.x => (b.x - a.x, a.y.abs_diff(b.y)),
+            Direction::Up if b.y < a.y => (a.y - b.y, a.x.abs_diff(b.x)),
+            Direction::Down if b.y > a.y => (b.y - a.y, a.x.abs_diff(b.x)),
+            _ => return None,
+        };
+
+        Some((along, across))
+    }
+}
+
+/// The candidate closest to `from` in direction `dir`. Being out of line is penalised more
+/// heavily than distance so that the neighbour directly alongside wins over a nearer one that
+/// is off at an angle.
+pub fn nearest_in_direction<T: Copy + Ord>(
+    from: Rect,
+    candidates: &[(T, Rect)],
+    dir: Direction,
+) -> Option<T> {
+    candidates
+        .iter()
+        .filter_map(|&(id, r)| {
+            let (along, across) = dir.offsets(from, r)?;
+            Some((along as u64 + 2 * across as u64, id))
+        })
+        .min()
+        .map(|(_, id)| id)
+}
+
+/// The focused client along with the geometry of every visible client.
+#[derive(Debug, Clone)]
+pub struct Neighbourhood {
+    pub focused: Xid,
+    pub r: Rect,
+    pub others: Vec<(Xid, Rect)>,
+}
+
+impl Neighbourhood {
+    pub fn of_focused<X: XConn>(state: &State<X>, x: &X) -> Result<Option<Self>> {
+        let focused = match state.client_set.current_client() {
+            Some(&id) => id,
+            None => return Ok(None),
+        };
+
+        let mut others = Vec::new();
+        for &id in state.client_set.on_screen_workspace_clients() {
+            if id != focused {
+                others.push((id, x.client_geometry(id)?));
+            }
+        }
+
+        Ok(Some(Self {
+            focused,
+            r: x.client_geometry(focused)?,
+            others,
+        }))
+    }
+
+    pub fn nearest(&self, dir: Direction) -> Option<Xid> {
+        nearest_in_direction(self.r, &self.others, dir)
+    }
+}
+
+/// Focus the nearest visible client in the given direction, across screens if needed.
+pub fn focus_direction<X: XConn>(dir: Direction) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let target = Neighbourhood::of_focused(state, x)?.and_then(|n| n.nearest(dir));
+
+        match target {
+            Some(id) => x.modify_and_refresh(state, |cs| cs.focus_client(&id)),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Focus the nearest screen in the given direction, going by where the outputs actually are
+/// rather than the order penrose has them in, and take the pointer along.
+pub fn focus_screen_direction<X: XConn>(dir: Direction) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let cs = &state.client_set;
+        let current = cs.current_screen();
+        let others: Vec<(usize, Rect)> = cs
+            .screens()
+            .filter(|s| s.index() != current.index())
+            .map(|s| (s.index(), s.geometry()))
+            .collect();
+
+        match nearest_in_direction(current.geometry(), &others, dir) {
+            Some(index) => {
+                x.modify_and_refresh(state, |cs| cs.focus_screen(index))?;
+                warp_to_focus(state, x)
+            }
+            None => Ok(()),
+        }
+    })
+}
+
+/// Exchange the stack positions of `a` and `b` if they share a workspace, or their tags if
+/// they don't, leaving focus with `a`.
+pub fn swap_tiled(cs: &mut ClientSet, a: Xid, b: Xid) {
+    let ta = cs.tag_for_client(&a).map(String::from);
+    let tb = cs.tag_for_client(&b).map(String::from);
+
+    match (ta, tb) {
+        (Some(ta), Some(tb)) if ta == tb => {
+            cs.focus_client(&a);
+            swap_in_stack(cs, a, b);
+        }
+        (Some(ta), Some(tb)) => {
+            cs.move_client_to_tag(&a, &tb);
+            cs.move_client_to_tag(&b, &ta);
+            cs.focus_client(&a);
+        }
+        _ => (),
+    }
+}
+
+/// Exchange the stack positions of the focused client `a` and `b` on the current workspace,
+/// leaving focus with `a`.
+fn swap_in_stack(cs: &mut ClientSet, a: Xid, b: Xid) {
+    let clients: Vec<Xid> = cs.current_workspace().clients().copied().collect();
+    let (i, j) = match (
+        clients.iter().position(|&c| c == a),
+        clients.iter().position(|&c| c == b),
+    ) {
+        (Some(i), Some(j)) => (i, j),
+        _ => return,
+    };
+
+    // Walk `a` over to where `b` was, which shifts `b` one place towards `a`'s old slot,
+    // then walk `b` the rest of the way back. Neither walk passes either end of the stack.
+    if i < j {
+        (i..j).for_each(|_| cs.swap_down());
+        cs.focus_client(&b);
+        (i..j - 1).for_each(|_| cs.swap_up());
+    } else {
+        (j..i).for_each(|_| cs.swap_up());
+        cs.focus_client(&b);
+        (j..i - 1).for_each(|_| cs.swap_down());
+    }
+    cs.focus_client(&a);
+}
+
+/// Swap the focused client with its nearest neighbour in the given direction. Tiled clients
+/// exchange stack positions (or tags, if the neighbour is on another screen) while floating
+/// clients exchange geometry.
+pub fn swap_direction<X: XConn>(dir: Direction) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let n = match Neighbourhood::of_focused(state, x)? {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        let (a, b) = match n.nearest(dir) {
+            Some(b) => (n.focused, b),
+            None => return Ok(()),
+        };
+        let rb = n.others.iter().find(|(id, _)| *id == b).map(|&(_, r)| r);
+
+        x.modify_and_refresh(state, |cs| match rb {
+            Some(rb) if cs.is_floating(&a) && cs.is_floating(&b) => {
+                let _ = cs.float(a, rb);
+                let _ = cs.float(b, n.r);
+            }
+            _ => swap_tiled(cs, a, b),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned_neighbours_beat_closer_diagonal_ones() {
+        let from = Rect::new(0, 0, 100, 100);
+        let candidates = [
+            (Xid::from(1), Rect::new(100, 100, 100, 100)),
+            (Xid::from(2), Rect::new(150, 0, 100, 100)),
+            (Xid::from(3), Rect::new(0, 100, 100, 100)),
+        ];
+
+        assert_eq!(
+            nearest_in_direction(from, &candidates, Direction::Right),
+            Some(Xid::from(2))
+        );
+        assert_eq!(
+            nearest_in_direction(from, &candidates, Direction::Down),
+            Some(Xid::from(3))
+        );
+        assert_eq!(
+            nearest_in_direction(from, &candidates, Direction::Left),
+            None
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Scaling sizes to suit the pixel density of each output.
+//!
+//! Every output gets a scale factor, either from the config or worked out from the physical
+//! size RandR reports for it relative to 96 DPI. Layouts have no access to the window manager
+//! state so the factors live in a process wide table which is refreshed at startup and
+//! whenever the RandR setup settles.
+use crate::{
+    bar,
+    monitor::{Randr, RANDR_SETTLED},
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        layout::{Layout, Message},
+        State,
+    },
+    pure::{geometry::Rect, Stack},
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+static SCALES: Mutex<Vec<(Rect, f64)>> = Mutex::new(Vec::new());
+static GAPS: AtomicBool = AtomicBool::new(true);
+
+/// Turn the gaps added by [ScaledGaps] on or off everywhere. The screens need refreshing for
+/// this to take effect.
+pub fn set_gaps(enabled: bool) {
+    GAPS.store(enabled, Ordering::Relaxed);
+}
+
+/// The scale factor of the output containing the middle of `r`.
+pub fn scale_for(r: Rect) -> f64 {
+    let p = r.midpoint();
+    let scales = match SCALES.lock() {
+        Ok(scales) => scales,
+        Err(_) => return 1.0,
+    };
+
+    scales
+        .iter()
+        .find(|(s, _)| s.contains_point(p))
+        .map_or(1.0, |&(_, scale)| scale)
+}
+
+pub fn scaled(px: u32, scale: f64) -> u32 {
+    (px as f64 * scale).round() as u32
+}
+
+/// The scale factor for an output `px` pixels across its longest side that is `mm`
+/// millimetres across the same way, in half steps and never below 1. Projectors and virtual
+/// machines often report no physical size at all.
+fn scale_from_size(px: u32, mm: u32) -> f64 {
+    if mm == 0 {
+        return 1.0;
+    }
+    let dpi = px as f64 * 25.4 / mm as f64;
+
+    ((dpi / 96.0) * 2.0).round().max(2.0) / 2.0
+}
+
+/// Keeps the table of scale factors up to date, re-laying out the screens when they change.
+/// The factors are also published to the bar as "scale" (e.g. "eDP-1=2 HDMI-1=1") for bars
+/// that size themselves per monitor.
+#[derive(Debug, Clone, Copy)]
+pub struct DpiHook {
+    /// Scale factors by output name, used in place of the one worked out from its size.
+    pub overrides: &'static [(&'static str, f64)],
+}
+
+impl DpiHook {
+    fn update<X: XConn>(&self, state: &mut State<X>, x: &X) -> Result<()> {
+        let outputs = Randr::connect()?.active_outputs()?;
+        let scales: Vec<(Rect, f64)> = outputs
+            .iter()
+            .map(|o| {
+                let configured = self.overrides.iter().find(|(name, _)| *name == o.name);
+                let scale = match configured {
+                    Some(&(_, scale)) => scale,
+                    None => scale_from_size(o.r.w.max(o.r.h), o.mm.0.max(o.mm.1)),
+                };
+                (o.r, scale)
+            })
+            .collect();
+
+        let summary: Vec<String> = outputs
+            .iter()
+            .zip(scales.iter())
+            .map(|(o, (_, scale))| format!("{}={scale}", o.name))
+            .collect();
+        bar::publish(state, "scale", summary.join(" "));
+
+        let changed = match SCALES.lock() {
+            Ok(mut current) if *current != scales => {
+                *current = scales;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            x.refresh(state)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for DpiHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        if let XEvent::ClientMessage(msg) = event {
+            if msg.dtype == RANDR_SETTLED {
+                self.update(state, x)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for DpiHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        self.update(state, x)
+    }
+}
+
+fn shrink(r: Rect, px: u32) -> Rect {
+    if r.w <= 2 * px || r.h <= 2 * px {
+        return r;
+    }
+
+    Rect::new(r.x + px, r.y + px, r.w - 2 * px, r.h - 2 * px)
+}
+
+/// Gaps around and between clients that grow with the scale factor of the screen being laid
+/// out. Scaled borders (see [ScaledBordersHook](crate::borders::ScaledBordersHook)) are
+/// drawn outside the area penrose allows for the configured border width, so clients are
+/// shrunk by the difference to keep them from overlapping.
+#[derive(Clone)]
+pub struct ScaledGaps {
+    inner: Box<dyn Layout>,
+    outer_px: u32,
+    inner_px: u32,
+    border_px: u32,
+}
+
+impl ScaledGaps {
+    pub fn wrap(
+        inner: Box<dyn Layout>,
+        outer_px: u32,
+        inner_px: u32,
+        border_px: u32,
+    ) -> Box<dyn Layout> {
+        Box::new(Self {
+            inner,
+            outer_px,
+            inner_px,
+            border_px,
+        })
+    }
+}
+
+impl Layout for ScaledGaps {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layout> {
+        Box::new(self.clone())
+    }
+
+    fn layout(&mut self, s: &Stack<Xid>, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        if !GAPS.load(Ordering::Relaxed) {
+            let (new, positions) = self.inner.layout(s, r);
+            if let Some(inner) = new {
+                self.inner = inner;
+            }
+            return (None, positions);
+        }
+
+        let scale = scale_for(r);
+        let (new, positions) = self
+            .inner
+            .layout(s, shrink(r, scaled(self.outer_px, scale)));
+        if let Some(inner) = new {
+            self.inner = inner;
+        }
+
+        let extra_border = scaled(self.border_px, scale).saturating_sub(self.border_px);
+        let per_client = scaled(self.inner_px, scale) + extra_border;
+        let positions = positions
+            .into_iter()
+            .map(|(id, r)| (id, shrink(r, per_client)))
+            .collect();
+
+        (None, positions)
+    }
+
+    fn layout_empty(&mut self, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        if let (Some(inner), _) = self.inner.layout_empty(r) {
+            self.inner = inner;
+        }
+
+        (None, vec![])
+    }
+
+    fn handle_message(&mut self, m: &Message) -> Option<Box<dyn Layout>> {
+        if let Some(inner) = self.inner.handle_message(m) {
+            self.inner = inner;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_come_in_half_steps_from_96_dpi() {
+        // A 15.6" 4K panel, a 24" 1080p monitor and a projector with no physical size.
+        assert_eq!(scale_from_size(3840, 344), 3.0);
+        assert_eq!(scale_from_size(1920, 531), 1.0);
+        assert_eq!(scale_from_size(1920, 0), 1.0);
+    }
+}
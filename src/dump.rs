@@ -0,0 +1,107 @@
+//! A readable dump of the window manager's state, for working out why a window is behaving
+//! oddly: the screens, each tag and its clients in stack order with their geometry, and the
+//! bound keys. The key binding writes it to the log, `wmcli dump` prints it.
+use crate::{client::wm_class, osd};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, State},
+    pure::geometry::Rect,
+    x::{XConn, XConnExt},
+    Result,
+};
+use std::{fmt::Write, sync::OnceLock};
+use tracing::info;
+
+static KEY_BINDINGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Record the keys that are bound, to include in dumps.
+pub fn set_key_bindings(mut keys: Vec<String>) {
+    keys.sort();
+    let _ = KEY_BINDINGS.set(keys);
+}
+
+/// `r` in X geometry notation, e.g. `1920x1080+0+0`.
+fn geometry(r: Rect) -> String {
+    format!("{}x{}+{}+{}", r.w, r.h, r.x, r.y)
+}
+
+fn dump<X: XConn>(state: &State<X>, x: &X) -> String {
+    let cs = &state.client_set;
+    let focused_screen = cs.current_screen().index();
+    let focused = cs.current_client().copied();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "screens:");
+    for s in cs.screens() {
+        let _ = writeln!(
+            out,
+            "  {}{} {}: tag {}",
+            s.index(),
+            if s.index() == focused_screen {
+                " (focused)"
+            } else {
+                ""
+            },
+            geometry(s.geometry()),
+            s.workspace.tag(),
+        );
+    }
+
+    let _ = writeln!(out, "tags:");
+    for ws in cs.workspaces() {
+        let shown = cs.screens().find(|s| s.workspace.tag() == ws.tag());
+        let _ = writeln!(
+            out,
+            "  {} [{}] {}",
+            ws.tag(),
+            ws.layout_name(),
+            shown.map_or("hidden".to_string(), |s| format!("on screen {}", s.index())),
+        );
+        for &id in ws.clients() {
+            let mut notes = Vec::new();
+            if cs.is_floating(&id) {
+                notes.push("floating");
+            }
+            if Some(id) == focused {
+                notes.push("focused");
+            }
+            let _ = writeln!(
+                out,
+                "    {id} {} {:?} {} {}",
+                wm_class(x, id).unwrap_or_else(|| "?".into()),
+                x.window_title(id).unwrap_or_default(),
+                x.client_geometry(id).map_or("?".to_string(), geometry),
+                notes.join(" "),
+            );
+        }
+    }
+
+    let keys = KEY_BINDINGS.get().map(Vec::as_slice).unwrap_or_default();
+    let _ = writeln!(out, "key bindings:\n  {}", keys.join(" "));
+
+    out
+}
+
+/// Write a dump of the state to the log.
+pub fn dump_state<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        info!("state dump:\n{}", dump(state, x));
+
+        osd::show(state, "State dumped to the log", None)
+    })
+}
+
+/// `wmcli dump`: print a dump of the state.
+pub fn dump_command<X: XConn>(_: &[String], state: &mut State<X>, x: &X) -> Result<String> {
+    Ok(dump(state, x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_is_written_the_x_way() {
+        assert_eq!(geometry(Rect::new(10, 20, 640, 480)), "640x480+10+20");
+    }
+}
@@ -0,0 +1,105 @@
+//! Running nested inside a Xephyr window, for trying out changes without logging out.
+//!
+//! `wm --embed 1280x720` starts Xephyr on the first free display and runs the window manager
+//! inside of it, optionally along with a few clients to arrange (`--with-clients`). The
+//! nested instance sets `$WM_INSTANCE`, which keeps its runtime files (such as the IPC
+//! socket) apart from those of the window manager running the outer session: use
+//! `WM_INSTANCE=embed1 wmcli ...` to talk to it. Xephyr exits as soon as the window manager
+//! does, restarting in place included.
+//!
+//! An embedded instance leaves everything outside of its own display to the outer session: it
+//! doesn't handle the lid or sleep, change the backlights or power profile, suspend on a low
+//! battery, serve the screensaver interface or talk to systemd.
+use crate::{modal::x_err, paths::INSTANCE_VAR, process::run, systemd::NOTIFY_VARS};
+use penrose::{Error, Result};
+use std::{
+    env,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Parse a screen size such as `1280x720`.
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once('x')?;
+    let size = (w.parse().ok()?, h.parse().ok()?);
+
+    Some(size).filter(|&(w, h)| w > 0 && h > 0)
+}
+
+fn socket(display: u32) -> String {
+    format!("/tmp/.X11-unix/X{display}")
+}
+
+/// Whether this is an instance running inside another session, such as one started by
+/// [embed].
+pub fn embedded() -> bool {
+    env::var_os(INSTANCE_VAR).is_some()
+}
+
+/// Start Xephyr at `size` (e.g. `1280x720`) and point everything started from here on
+/// (penrose included) at it, spawning each of `clients` once it is up. This does nothing if
+/// already running embedded, as after restarting in place.
+pub fn embed(size: &str, clients: &[&str]) -> Result<()> {
+    if embedded() {
+        return Ok(());
+    }
+    let (w, h) = parse_size(size)
+        .ok_or_else(|| Error::Custom(format!("invalid size for --embed: {size}")))?;
+    let display = (1..)
+        .find(|&n| !Path::new(&socket(n)).exists())
+        .unwrap_or(1);
+
+    info!("starting Xephyr on :{display} at {w}x{h}");
+    // With -reset and -terminate Xephyr exits once the last client has gone.
+    Command::new("Xephyr")
+        .arg(format!(":{display}"))
+        .args([
+            "-screen",
+            &format!("{w}x{h}"),
+            "-ac",
+            "-br",
+            "-reset",
+            "-terminate",
+        ])
+        .spawn()
+        .map_err(x_err)?;
+
+    let deadline = Instant::now() + START_TIMEOUT;
+    while !Path::new(&socket(display)).exists() {
+        if Instant::now() >= deadline {
+            return Err(Error::Custom(format!("Xephyr didn't start on :{display}")));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    env::set_var("DISPLAY", format!(":{display}"));
+    env::set_var(INSTANCE_VAR, format!("embed{display}"));
+    // The service systemd is watching is the outer window manager.
+    for var in NOTIFY_VARS {
+        env::remove_var(var);
+    }
+    for client in clients {
+        run(client)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_need_a_width_and_a_height() {
+        assert_eq!(parse_size("1280x720"), Some((1280, 720)));
+        assert_eq!(parse_size("1280"), None);
+        assert_eq!(parse_size("0x720"), None);
+        assert_eq!(parse_size("wide x tall"), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! The EWMH root and client properties that penrose's own EWMH support leaves out.
+//!
+//! Taskbars, pagers and tools such as `xdotool search` read these to find out what is going
+//! on: the list of clients in the order they were managed and in stacking order, the usable
+//! area of the screen once docks are out of the way, what can be done to each client, the
+//! desktop each client is on, and which parts of the spec are supported at all. The support
+//! list only names what the window manager actually handles, penrose's hooks included.
+use crate::{
+    modal::x_err,
+    sticky::{set_sticky, Sticky},
+    struts::usable_area,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    pure::geometry::Rect,
+    x::{Prop, XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::collections::{HashMap, HashSet};
+use x11rb::{protocol::xproto::ConnectionExt, rust_connection::RustConnection};
+
+/// Supported on top of the atoms penrose lists itself.
+const SUPPORTED: &[&str] = &[
+    "_NET_CLIENT_LIST",
+    "_NET_CLIENT_LIST_STACKING",
+    "_NET_WORKAREA",
+    "_NET_WM_ALLOWED_ACTIONS",
+    "_NET_WM_DESKTOP",
+    "_NET_WM_STATE",
+    "_NET_WM_STATE_ABOVE",
+    "_NET_WM_STATE_BELOW",
+    "_NET_WM_STATE_DEMANDS_ATTENTION",
+    "_NET_WM_STATE_FULLSCREEN",
+    "_NET_WM_STATE_HIDDEN",
+    "_NET_WM_STATE_MAXIMIZED_HORZ",
+    "_NET_WM_STATE_MAXIMIZED_VERT",
+    "_NET_WM_STATE_STICKY",
+    "_NET_WM_STRUT",
+    "_NET_WM_STRUT_PARTIAL",
+    "_NET_WM_WINDOW_OPACITY",
+    "_NET_STARTUP_ID",
+    "_NET_WM_ACTION_MOVE",
+    "_NET_WM_ACTION_RESIZE",
+    "_NET_WM_ACTION_MINIMIZE",
+    "_NET_WM_ACTION_FULLSCREEN",
+    "_NET_WM_ACTION_CHANGE_DESKTOP",
+    "_NET_WM_ACTION_CLOSE",
+    "_NET_WM_ACTION_ABOVE",
+    "_NET_WM_ACTION_BELOW",
+    "_NET_WM_ACTION_STICK",
+    "_NET_WM_ACTION_MAXIMIZE_HORZ",
+    "_NET_WM_ACTION_MAXIMIZE_VERT",
+];
+
+/// What can be done to any client.
+const ALLOWED_ACTIONS: &[&str] = &[
+    "_NET_WM_ACTION_MOVE",
+    "_NET_WM_ACTION_RESIZE",
+    "_NET_WM_ACTION_MINIMIZE",
+    "_NET_WM_ACTION_FULLSCREEN",
+    "_NET_WM_ACTION_CHANGE_DESKTOP",
+    "_NET_WM_ACTION_CLOSE",
+    "_NET_WM_ACTION_ABOVE",
+    "_NET_WM_ACTION_BELOW",
+    "_NET_WM_ACTION_STICK",
+    "_NET_WM_ACTION_MAXIMIZE_HORZ",
+    "_NET_WM_ACTION_MAXIMIZE_VERT",
+];
+
+/// The smallest rectangle covering every one of `rects`.
+fn bounding(rects: &[Rect]) -> Option<Rect> {
+    let first = rects.first()?;
+    let (mut x0, mut y0) = (first.x, first.y);
+    let (mut x1, mut y1) = (first.x + first.w, first.y + first.h);
+    for r in rects {
+        x0 = x0.min(r.x);
+        y0 = y0.min(r.y);
+        x1 = x1.max(r.x + r.w);
+        y1 = y1.max(r.y + r.h);
+    }
+
+    Some(Rect::new(x0, y0, x1 - x0, y1 - y0))
+}
+
+/// Sets the EWMH client lists and work area on every refresh, and the allowed actions for
+/// each client as it is managed. `_NET_CLIENT_LIST` is written every time, as penrose has a
+/// go at it itself, while the stacking order is only looked up again once the clients, their
+/// order or the focus have changed.
+#[derive(Debug, Default)]
+pub struct EwmhHook {
+    conn: Option<RustConnection>,
+    /// Clients in the order they were managed, oldest first.
+    clients: Vec<Xid>,
+    /// Every client in the client set in order, followed by the focused client, as of the
+    /// last time the stacking order was written.
+    stacked_for: Vec<Xid>,
+}
+
+impl EwmhHook {
+    /// Managed clients from bottom to top, as X stacks them.
+    fn stacking_order(&mut self, managed: &HashSet<Xid>, root: Xid) -> Result<Vec<Xid>> {
+        if self.conn.is_none() {
+            self.conn = Some(x11rb::connect(None).map_err(x_err)?.0);
+        }
+        let conn = match &self.conn {
+            Some(conn) => conn,
+            None => return Ok(vec![]),
+        };
+        let tree = conn
+            .query_tree(*root)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?;
+
+        Ok(tree
+            .children
+            .into_iter()
+            .map(Xid::from)
+            .filter(|id| managed.contains(id))
+            .collect())
+    }
+}
+
+impl<X: XConn> StateHook<X> for EwmhHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let root = x.root();
+        let cs = &state.client_set;
+        let managed: HashSet<Xid> = cs.clients().copied().collect();
+
+        self.clients.retain(|id| managed.contains(id));
+        let new: Vec<Xid> = cs
+            .clients()
+            .filter(|id| !self.clients.contains(id))
+            .copied()
+            .collect();
+        self.clients.extend(new);
+        x.set_prop(root, "_NET_CLIENT_LIST", Prop::Window(self.clients.clone()))?;
+        let stacked_for: Vec<Xid> = cs.clients().chain(cs.current_client()).copied().collect();
+        if stacked_for != self.stacked_for {
+            let stacking = self.stacking_order(&managed, root)?;
+            x.set_prop(root, "_NET_CLIENT_LIST_STACKING", Prop::Window(stacking))?;
+            self.stacked_for = stacked_for;
+        }
+
+        // The work area is a single rectangle per desktop, so with several screens the best
+        // that can be done is the area covering all of them.
+        let usable: Vec<Rect> = cs.screens().map(|s| usable_area(s.geometry())).collect();
+        let workarea = match bounding(&usable) {
+            Some(r) => [r.x, r.y, r.w, r.h].repeat(state.config.tags.len()),
+            None => vec![],
+        };
+
+        x.set_prop(root, "_NET_WORKAREA", Prop::Cardinal(workarea))
+    }
+}
+
+impl<X: XConn> ManageHook<X> for EwmhHook {
+    fn call(&mut self, client: Xid, _: &mut State<X>, x: &X) -> Result<()> {
+        let actions = ALLOWED_ACTIONS.iter().map(|a| a.to_string()).collect();
+
+        x.set_prop(client, "_NET_WM_ALLOWED_ACTIONS", Prop::Atom(actions))
+    }
+}
+
+/// The `_NET_WM_DESKTOP` value for a client on every desktop.
+const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+/// The desktop index of `tag`, if it is one of the tags in the config rather than one of the
+/// invisible workspaces used to park clients.
+fn desktop<X: XConn>(state: &State<X>, tag: &str) -> Option<u32> {
+    let ix = state.config.tags.iter().position(|t| t == tag)?;
+
+    u32::try_from(ix).ok()
+}
+
+/// Moves clients between desktops when pagers and scripts such as `wmctrl -t` ask, and keeps
+/// `_NET_WM_DESKTOP` up to date on every client however they are moved. Sticky clients are
+/// on all desktops, and asking for all desktops makes a client sticky.
+#[derive(Debug, Default, Clone)]
+pub struct WmDesktopHook {
+    /// The desktop last set on each client.
+    desktops: HashMap<Xid, u32>,
+}
+
+impl<X: XConn> EventHook<X> for WmDesktopHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == "_NET_WM_DESKTOP" => msg,
+            _ => return Ok(true),
+        };
+        let id = msg.id;
+        if !state.client_set.contains(&id) {
+            return Ok(true);
+        }
+
+        let requested = msg.data.as_usize()[0];
+        if requested == ALL_DESKTOPS as usize {
+            set_sticky(id, true, state);
+            x.refresh(state)?;
+            return Ok(false);
+        }
+        let tag = match state.config.tags.get(requested) {
+            Some(tag) => tag.clone(),
+            None => return Ok(false),
+        };
+
+        set_sticky(id, false, state);
+        x.modify_and_refresh(state, |cs| cs.move_client_to_tag(&id, &tag))?;
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for WmDesktopHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let sticky = state.extension_or_default::<Sticky>();
+        let sticky = sticky.borrow();
+        let cs = &state.client_set;
+        self.desktops.retain(|id, _| cs.contains(id));
+
+        for &id in cs.clients() {
+            let desktop = if sticky.contains(&id) {
+                Some(ALL_DESKTOPS)
+            } else {
+                cs.tag_for_client(&id).and_then(|tag| desktop(state, tag))
+            };
+            // Clients parked out of sight keep the desktop they were on.
+            let desktop = match desktop {
+                Some(d) if self.desktops.get(&id) != Some(&d) => d,
+                _ => continue,
+            };
+            x.set_prop(id, "_NET_WM_DESKTOP", Prop::Cardinal(vec![desktop]))?;
+            self.desktops.insert(id, desktop);
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds everything in [SUPPORTED] to the `_NET_SUPPORTED` list on the root window. This
+/// needs registering as a startup hook after penrose's EWMH hooks, which set the list to
+/// begin with.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EwmhSupportedHook;
+
+impl<X: XConn> StateHook<X> for EwmhSupportedHook {
+    fn call(&mut self, _: &mut State<X>, x: &X) -> Result<()> {
+        let root = x.root();
+        let mut supported = match x.get_prop(root, "_NET_SUPPORTED")? {
+            Some(Prop::Atom(atoms)) => atoms,
+            _ => vec![],
+        };
+        for atom in SUPPORTED {
+            if !supported.iter().any(|a| a == atom) {
+                supported.push(atom.to_string());
+            }
+        }
+
+        x.set_prop(root, "_NET_SUPPORTED", Prop::Atom(supported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockXConn;
+
+    #[test]
+    fn the_work_area_covers_every_screen() {
+        let screens = [Rect::new(0, 30, 1920, 1050), Rect::new(1920, 0, 2560, 1440)];
+
+        assert_eq!(bounding(&screens), Some(Rect::new(0, 0, 4480, 1440)));
+        assert_eq!(bounding(&[]), None);
+    }
+
+    #[test]
+    fn the_desktop_follows_clients_between_tags() {
+        let (x, mut state, id) = MockXConn::with_client();
+        let mut hook = WmDesktopHook::default();
+
+        StateHook::call(&mut hook, &mut state, &x).unwrap();
+        assert_eq!(x.prop(id, "_NET_WM_DESKTOP"), Some(Prop::Cardinal(vec![0])));
+
+        state.client_set.move_client_to_tag(&id, "3");
+        StateHook::call(&mut hook, &mut state, &x).unwrap();
+        assert_eq!(x.prop(id, "_NET_WM_DESKTOP"), Some(Prop::Cardinal(vec![2])));
+
+        set_sticky(id, true, &mut state);
+        StateHook::call(&mut hook, &mut state, &x).unwrap();
+        let all = Prop::Cardinal(vec![ALL_DESKTOPS]);
+        assert_eq!(x.prop(id, "_NET_WM_DESKTOP"), Some(all));
+    }
+}
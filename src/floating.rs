@@ -0,0 +1,777 @@
+//! Floating window helpers.
+use crate::{
+    client::wm_class,
+    modal::{Key, KeyboardGrab},
+    monitor::RANDR_SETTLED,
+    paths::cache_file,
+    timer::notify_after,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::{KeyEventHandler, MotionNotifyEvent, MouseEvent, MouseEventHandler},
+        hooks::{EventHook, ManageHook},
+        State,
+    },
+    pure::geometry::{Point, Rect},
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// The last floating geometry used by each WM_CLASS, persisted between sessions so that
+/// applications reopen where they were last placed.
+#[derive(Debug, Clone)]
+pub struct FloatingMemory {
+    path: PathBuf,
+    positions: HashMap<String, Rect>,
+    /// The WM_CLASS of each managed client, looked up once as it is managed.
+    classes: HashMap<Xid, String>,
+}
+
+/// Loads any previously saved positions from the cache directory.
+impl Default for FloatingMemory {
+    fn default() -> Self {
+        let path = cache_file("floating");
+        let positions = fs::read_to_string(&path)
+            .map(|s| s.lines().filter_map(parse_line).collect())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            positions,
+            classes: HashMap::new(),
+        }
+    }
+}
+
+impl FloatingMemory {
+    pub fn get(&self, class: &str) -> Option<Rect> {
+        self.positions.get(class).copied()
+    }
+
+    /// Record the geometry for `class`, writing the cache back out if it changed.
+    pub fn record(&mut self, class: String, r: Rect) {
+        if self.positions.get(&class) == Some(&r) {
+            return;
+        }
+        self.positions.insert(class, r);
+
+        if let Err(e) = self.save() {
+            warn!("unable to save floating positions: {e}");
+        }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut lines: Vec<String> = self
+            .positions
+            .iter()
+            .map(|(class, r)| format!("{class} {} {} {} {}", r.x, r.y, r.w, r.h))
+            .collect();
+        lines.sort();
+
+        fs::write(&self.path, lines.join("\n"))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(String, Rect)> {
+    let mut fields = line.rsplitn(5, ' ');
+    let mut next = || fields.next()?.parse().ok();
+    let (h, w, y, x) = (next()?, next()?, next()?, next()?);
+    let class = fields.next()?.to_string();
+
+    Some((class, Rect::new(x, y, w, h)))
+}
+
+/// Restores remembered positions for newly managed floating clients (or finds them a free
+/// spot with [smart_placement] if there isn't one) and records the positions of floating
+/// clients as they are moved or resized, going by the ConfigureNotify events X sends for them
+/// rather than asking after every client on every refresh.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FloatingMemoryHook;
+
+impl<X: XConn> ManageHook<X> for FloatingMemoryHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if let Some(class) = wm_class(x, client) {
+            let memory = state.extension_or_default::<FloatingMemory>();
+            memory.borrow_mut().classes.insert(client, class);
+        }
+        if !state.client_set.is_floating(&client) {
+            return Ok(());
+        }
+
+        let r = match remembered_geometry(client, state) {
+            Some(r) => r,
+            None => place_new(client, state, x)?,
+        };
+
+        state.client_set.float(client, r)
+    }
+}
+
+fn remembered_geometry<X: XConn>(id: Xid, state: &mut State<X>) -> Option<Rect> {
+    let memory = state.extension_or_default::<FloatingMemory>();
+    let memory = memory.borrow();
+
+    memory.get(memory.classes.get(&id)?)
+}
+
+impl<X: XConn> EventHook<X> for FloatingMemoryHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let (id, r) = match event {
+            XEvent::ConfigureNotify(e) if !e.is_root => (e.id, e.r),
+            XEvent::Destroy(id) => {
+                let memory = state.extension_or_default::<FloatingMemory>();
+                memory.borrow_mut().classes.remove(id);
+                return Ok(true);
+            }
+            _ => return Ok(true),
+        };
+        if !state.client_set.is_floating(&id) {
+            return Ok(true);
+        }
+
+        let memory = state.extension_or_default::<FloatingMemory>();
+        let class = memory.borrow().classes.get(&id).cloned();
+        if let Some(class) = class {
+            memory.borrow_mut().record(class, r);
+        }
+
+        Ok(true)
+    }
+}
+
+fn place_new<X: XConn>(id: Xid, state: &State<X>, x: &X) -> Result<Rect> {
+    let screen = match state.client_set.screen_for_client(&id) {
+        Some(s) => s.geometry(),
+        None => state.client_set.current_screen().geometry(),
+    };
+
+    let mut others = Vec::new();
+    for &c in state.client_set.on_screen_workspace_clients() {
+        if c != id && state.client_set.is_floating(&c) {
+            others.push(x.client_geometry(c)?);
+        }
+    }
+
+    let r = x.client_geometry(id)?;
+
+    Ok(smart_placement(
+        screen,
+        r.w,
+        r.h,
+        &others,
+        x.cursor_position()?,
+    ))
+}
+
+/// Somewhere on `screen` for a `w` x `h` window that doesn't overlap any of `others`, trying
+/// the screen corners and the spots alongside each of `others` and preferring the free spot
+/// closest to `pointer`. If nowhere is free the window is centred on the pointer when that is
+/// on this screen, and on the screen itself otherwise.
+///
+/// Tiled clients cover the whole screen so only other floating clients are worth avoiding.
+pub fn smart_placement(screen: Rect, w: u32, h: u32, others: &[Rect], pointer: Point) -> Rect {
+    let (w, h) = (w.min(screen.w), h.min(screen.h));
+    let at = |x: u32, y: u32| {
+        Rect::new(
+            x.clamp(screen.x, screen.x + screen.w - w),
+            y.clamp(screen.y, screen.y + screen.h - h),
+            w,
+            h,
+        )
+    };
+
+    let (right, bottom) = (screen.x + screen.w, screen.y + screen.h);
+    let mut candidates = vec![
+        at(screen.x, screen.y),
+        at(right, screen.y),
+        at(screen.x, bottom),
+        at(right, bottom),
+    ];
+    for o in others {
+        candidates.extend([
+            at(o.x + o.w, o.y),
+            at(o.x, o.y + o.h),
+            at(o.x.saturating_sub(w), o.y),
+            at(o.x, o.y.saturating_sub(h)),
+        ]);
+    }
+
+    let from_pointer = |r: &Rect| {
+        let m = r.midpoint();
+        (m.x.abs_diff(pointer.x) as u64).pow(2) + (m.y.abs_diff(pointer.y) as u64).pow(2)
+    };
+    let free = candidates
+        .into_iter()
+        .filter(|&c| others.iter().all(|&o| !intersects(c, o)))
+        .min_by_key(from_pointer);
+
+    match free {
+        Some(r) => r,
+        None if screen.contains_point(pointer) => at(
+            pointer.x.saturating_sub(w / 2),
+            pointer.y.saturating_sub(h / 2),
+        ),
+        None => at(screen.x + (screen.w - w) / 2, screen.y + (screen.h - h) / 2),
+    }
+}
+
+/// Toggle the focused client between tiled and floating, floating it at the position last
+/// used for its WM_CLASS when there is one.
+pub fn toggle_floating_remembered<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let id = match state.client_set.current_client() {
+            Some(&id) => id,
+            None => return Ok(()),
+        };
+
+        if state.client_set.is_floating(&id) {
+            return x.modify_and_refresh(state, |cs| {
+                cs.sink(&id);
+            });
+        }
+
+        let r = match remembered_geometry(id, state) {
+            Some(r) => r,
+            None => x.client_geometry(id)?,
+        };
+
+        x.modify_and_refresh(state, |cs| {
+            if let Err(e) = cs.float(id, r) {
+                warn!("unable to float client {id}: {e}");
+            }
+        })
+    })
+}
+
+/// The floating geometry clients had before [toggle_float_all] tiled them, by tag.
+#[derive(Debug, Default, Clone)]
+pub struct FloatAll {
+    geometries: HashMap<String, HashMap<Xid, Rect>>,
+}
+
+/// Float every client on the current tag where it currently sits, or if they are all already
+/// floating, tile them all. Floating a tag again puts clients back where they were the last
+/// time it was floated.
+pub fn toggle_float_all<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let tag = state.client_set.current_tag().to_string();
+        let clients: Vec<Xid> = state
+            .client_set
+            .current_workspace()
+            .clients()
+            .copied()
+            .collect();
+        if clients.is_empty() {
+            return Ok(());
+        }
+
+        let ext = state.extension_or_default::<FloatAll>();
+        let mut float_all = ext.borrow_mut();
+        let saved = float_all.geometries.entry(tag).or_default();
+
+        if clients.iter().all(|id| state.client_set.is_floating(id)) {
+            saved.clear();
+            for &id in clients.iter() {
+                saved.insert(id, x.client_geometry(id)?);
+            }
+
+            return x.modify_and_refresh(state, |cs| {
+                for id in clients.iter() {
+                    cs.sink(id);
+                }
+            });
+        }
+
+        let mut positions = Vec::with_capacity(clients.len());
+        for &id in clients.iter() {
+            if !state.client_set.is_floating(&id) {
+                let r = match saved.get(&id) {
+                    Some(&r) => r,
+                    None => x.client_geometry(id)?,
+                };
+                positions.push((id, r));
+            }
+        }
+
+        x.modify_and_refresh(state, |cs| {
+            for &(id, r) in positions.iter() {
+                if let Err(e) = cs.float(id, r) {
+                    warn!("unable to float client {id}: {e}");
+                }
+            }
+        })
+    })
+}
+
+/// Center the focused floating client on its screen, either at its current size or resized to
+/// the given (width, height) fraction of the screen.
+pub fn center_floating<X: XConn>(fraction: Option<(f64, f64)>) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let id = match state.client_set.current_client() {
+            Some(&id) if state.client_set.is_floating(&id) => id,
+            _ => return Ok(()),
+        };
+
+        let screen = match state.client_set.screen_for_client(&id) {
+            Some(s) => s.geometry(),
+            None => state.client_set.current_screen().geometry(),
+        };
+        let r = match fraction {
+            Some((w, h)) => screen.scale_w(w).scale_h(h),
+            None => x.client_geometry(id)?,
+        };
+        let r = r.centered_in(&screen).unwrap_or(r);
+
+        x.modify_and_refresh(state, |cs| {
+            if let Err(e) = cs.float(id, r) {
+                warn!("unable to center client {id}: {e}");
+            }
+        })
+    })
+}
+
+/// The smallest width or height that keyboard resizing will shrink a window to.
+const MIN_FLOAT_SIZE: u32 = 50;
+
+fn nudge(r: Rect, dx: i32, dy: i32) -> Rect {
+    Rect {
+        x: r.x.saturating_add_signed(dx),
+        y: r.y.saturating_add_signed(dy),
+        ..r
+    }
+}
+
+fn stretch(r: Rect, dw: i32, dh: i32) -> Rect {
+    Rect {
+        w: r.w.saturating_add_signed(dw).max(MIN_FLOAT_SIZE),
+        h: r.h.saturating_add_signed(dh).max(MIN_FLOAT_SIZE),
+        ..r
+    }
+}
+
+const FLOAT_MODE_KEY: &str = "_WM_FLOAT_MODE_KEY";
+/// How long float mode lasts before giving the keyboard back by itself.
+const FLOAT_MODE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the float mode grab is checked for key presses.
+const FLOAT_MODE_POLL: Duration = Duration::from_millis(10);
+
+/// A key press in float mode, passed to [FloatModeHook] as the data of a ClientMessage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FloatKey {
+    /// Move (or with shift held, resize) by a step in the given directions.
+    Step {
+        dx: i32,
+        dy: i32,
+        shift: bool,
+    },
+    Done,
+}
+
+impl FloatKey {
+    fn from_key(key: &Key) -> Option<Self> {
+        let (dx, dy) = match key.name.as_deref()? {
+            "Left" | "h" => (-1, 0),
+            "Right" | "l" => (1, 0),
+            "Up" | "k" => (0, -1),
+            "Down" | "j" => (0, 1),
+            "Escape" | "Return" => return Some(Self::Done),
+            _ => return None,
+        };
+
+        Some(Self::Step {
+            dx,
+            dy,
+            shift: key.shift,
+        })
+    }
+
+    fn to_data(self) -> [u32; 5] {
+        match self {
+            Self::Step { dx, dy, shift } => [0, dx as u32, dy as u32, shift as u32, 0],
+            Self::Done => [1, 0, 0, 0, 0],
+        }
+    }
+
+    fn from_data(data: &[usize]) -> Self {
+        match data {
+            [0, dx, dy, shift, ..] => Self::Step {
+                dx: *dx as u32 as i32,
+                dy: *dy as u32 as i32,
+                shift: *shift != 0,
+            },
+            _ => Self::Done,
+        }
+    }
+}
+
+/// The client being moved in float mode.
+#[derive(Debug)]
+struct FloatModeClient {
+    id: Xid,
+    r: Rect,
+    step: i32,
+    /// Set to have the thread holding the grab let go of it.
+    stop: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+pub struct FloatMode {
+    active: Option<FloatModeClient>,
+}
+
+/// Read keys from `grab` until float mode is done with, passing them on to [FloatModeHook].
+/// The grab is released once this returns, at the latest after [FLOAT_MODE_TIMEOUT].
+fn forward_float_keys(grab: KeyboardGrab, stop: Arc<AtomicBool>) {
+    let deadline = Instant::now() + FLOAT_MODE_TIMEOUT;
+    while !stop.load(Ordering::Relaxed) {
+        if Instant::now() >= deadline {
+            notify_after(Duration::ZERO, FLOAT_MODE_KEY, FloatKey::Done.to_data());
+            return;
+        }
+
+        match grab.poll_key() {
+            Ok(Some(key)) => {
+                if let Some(key) = FloatKey::from_key(&key) {
+                    notify_after(Duration::ZERO, FLOAT_MODE_KEY, key.to_data());
+                    if key == FloatKey::Done {
+                        return;
+                    }
+                }
+            }
+            Ok(None) => thread::sleep(FLOAT_MODE_POLL),
+            Err(e) => {
+                warn!("float mode lost its keyboard grab: {e}");
+                notify_after(Duration::ZERO, FLOAT_MODE_KEY, FloatKey::Done.to_data());
+                return;
+            }
+        }
+    }
+}
+
+/// Enter a modal "float mode" for the focused floating client: the arrow keys (or hjkl) move
+/// it by `step` pixels, holding shift resizes it instead and Escape or Return exits. Needs
+/// [FloatModeHook] registering to act on the keys.
+///
+/// The keyboard grab is read from a thread of its own so that the event loop carries on as
+/// normal (timers, IPC and the systemd watchdog included) while float mode is active, and it
+/// is given up after [FLOAT_MODE_TIMEOUT] in case float mode is forgotten about.
+pub fn float_mode<X: XConn>(step: u32) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let ext = state.extension_or_default::<FloatMode>();
+        if ext.borrow().active.is_some() {
+            return Ok(());
+        }
+        let id = match state.client_set.current_client() {
+            Some(&id) if state.client_set.is_floating(&id) => id,
+            _ => return Ok(()),
+        };
+
+        let r = x.client_geometry(id)?;
+        let grab = KeyboardGrab::new()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        thread::spawn(move || forward_float_keys(grab, thread_stop));
+
+        ext.borrow_mut().active = Some(FloatModeClient {
+            id,
+            r,
+            step: step as i32,
+            stop,
+        });
+
+        Ok(())
+    })
+}
+
+/// Moves and resizes the client in [float_mode] as keys are pressed, floating it where it
+/// was left once float mode is done.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FloatModeHook;
+
+impl<X: XConn> EventHook<X> for FloatModeHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let key = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == FLOAT_MODE_KEY => {
+                FloatKey::from_data(&msg.data.as_usize())
+            }
+            _ => return Ok(true),
+        };
+
+        let ext = state.extension_or_default::<FloatMode>();
+        let mut float_mode = ext.borrow_mut();
+        let (id, r) = match float_mode.active.as_mut() {
+            Some(c) if !state.client_set.contains(&c.id) => {
+                c.stop.store(true, Ordering::Relaxed);
+                float_mode.active = None;
+                return Ok(false);
+            }
+            Some(c) => match key {
+                FloatKey::Step { dx, dy, shift } => {
+                    let (dx, dy) = (dx * c.step, dy * c.step);
+                    let r = if shift {
+                        stretch(c.r, dx, dy)
+                    } else {
+                        nudge(c.r, dx, dy)
+                    };
+                    c.r = constrain(r, &screen_rects(state));
+                    x.position_client(c.id, c.r)?;
+                    x.flush();
+                    return Ok(false);
+                }
+                FloatKey::Done => (c.id, c.r),
+            },
+            None => return Ok(false),
+        };
+        float_mode.active = None;
+        drop(float_mode);
+
+        x.modify_and_refresh(state, |cs| {
+            if let Err(e) = cs.float(id, r) {
+                warn!("unable to float client {id}: {e}");
+            }
+        })?;
+
+        Ok(false)
+    }
+}
+
+/// How much of a floating window must always remain on screen horizontally.
+const MIN_VISIBLE: u32 = 64;
+
+fn screen_rects<X: XConn>(state: &State<X>) -> Vec<Rect> {
+    state.client_set.screens().map(|s| s.geometry()).collect()
+}
+
+fn distance_sq(a: Rect, b: Rect) -> u64 {
+    let (pa, pb) = (a.midpoint(), b.midpoint());
+    let dx = pa.x.abs_diff(pb.x) as u64;
+    let dy = pa.y.abs_diff(pb.y) as u64;
+
+    dx * dx + dy * dy
+}
+
+/// Clamp `r` so that it fits on the screen nearest to it, with its top edge (and so any
+/// titlebar) on screen and at least [MIN_VISIBLE] pixels of it visible horizontally.
+pub fn constrain(r: Rect, screens: &[Rect]) -> Rect {
+    let screen = match screens.iter().min_by_key(|s| distance_sq(r, **s)) {
+        Some(&s) => s,
+        None => return r,
+    };
+
+    let w = r.w.min(screen.w);
+    let h = r.h.min(screen.h);
+    let visible = MIN_VISIBLE.min(w);
+    let x = r.x.clamp(
+        screen.x.saturating_sub(w - visible),
+        screen.x + screen.w - visible,
+    );
+    let y =
+        r.y.clamp(screen.y, screen.y + screen.h - MIN_VISIBLE.min(h));
+
+    Rect::new(x, y, w, h)
+}
+
+/// Re-float `id` at `r` if its current geometry is outside of the permitted bounds.
+fn apply_constraint<X: XConn>(id: Xid, r: Rect, state: &mut State<X>, x: &X) -> Result<()> {
+    let constrained = constrain(r, &screen_rects(state));
+    if constrained != r {
+        x.position_client(id, constrained)?;
+        state.client_set.float(id, constrained)?;
+    }
+
+    Ok(())
+}
+
+/// Wraps a mouse handler (such as the builtin drag and resize handlers) so that the floating
+/// windows it moves are kept within the bounds of the screens.
+pub struct ConstrainedMouseHandler<X: XConn> {
+    inner: Box<dyn MouseEventHandler<X>>,
+}
+
+impl<X: XConn + 'static> ConstrainedMouseHandler<X> {
+    pub fn wrap(inner: Box<dyn MouseEventHandler<X>>) -> Box<dyn MouseEventHandler<X>> {
+        Box::new(Self { inner })
+    }
+}
+
+impl<X: XConn> MouseEventHandler<X> for ConstrainedMouseHandler<X> {
+    fn on_mouse_event(&mut self, evt: &MouseEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_mouse_event(evt, state, x)
+    }
+
+    fn on_motion(&mut self, evt: &MotionNotifyEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_motion(evt, state, x)?;
+
+        let id = evt.data.id;
+        if !state.client_set.is_floating(&id) {
+            return Ok(());
+        }
+
+        apply_constraint(id, x.client_geometry(id)?, state, x)
+    }
+}
+
+/// Clamps the geometry that floating clients request for themselves via ConfigureRequest.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConstrainFloatingHook;
+
+impl<X: XConn> EventHook<X> for ConstrainFloatingHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ConfigureRequest(e) if state.client_set.is_floating(&e.id) => {
+                let r = constrain(e.r, &screen_rects(state));
+                x.position_client(e.id, r)?;
+                state.client_set.float(e.id, r)?;
+
+                Ok(false)
+            }
+
+            _ => Ok(true),
+        }
+    }
+}
+
+fn intersects(a: Rect, b: Rect) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+/// Moves floating clients that no longer overlap any screen after a RandR change (such as
+/// unplugging the monitor they were on) back onto the nearest remaining screen.
+///
+/// The rescue waits for the monitor setup to settle so that penrose already knows the new
+/// screen layout and clients aren't shuffled around for each step of a hotplug.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OffscreenRescueHook;
+
+impl<X: XConn> EventHook<X> for OffscreenRescueHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == RANDR_SETTLED => (),
+            _ => return Ok(true),
+        }
+
+        let screens = screen_rects(state);
+        let floating: Vec<Xid> = state
+            .client_set
+            .on_screen_workspace_clients()
+            .filter(|&id| state.client_set.is_floating(id))
+            .copied()
+            .collect();
+
+        for id in floating {
+            let r = x.client_geometry(id)?;
+            if !screens.iter().any(|&s| intersects(r, s)) {
+                apply_constraint(id, r, state, x)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_mode_keys_survive_the_trip_through_a_client_message() {
+        let key = |name: &str, shift| Key {
+            name: Some(name.to_string()),
+            shift,
+        };
+        let to_usize = |data: [u32; 5]| data.map(|d| d as usize);
+
+        for (k, expected) in [
+            (
+                key("h", false),
+                FloatKey::Step {
+                    dx: -1,
+                    dy: 0,
+                    shift: false,
+                },
+            ),
+            (
+                key("Up", true),
+                FloatKey::Step {
+                    dx: 0,
+                    dy: -1,
+                    shift: true,
+                },
+            ),
+            (key("Return", false), FloatKey::Done),
+        ] {
+            let parsed = FloatKey::from_key(&k).unwrap();
+            assert_eq!(parsed, expected);
+            assert_eq!(FloatKey::from_data(&to_usize(parsed.to_data())), expected);
+        }
+        assert_eq!(FloatKey::from_key(&key("a", false)), None);
+    }
+
+    #[test]
+    fn cache_lines_allow_spaces_in_class_names() {
+        let parsed = parse_line("Some App 10 20 300 400");
+
+        assert_eq!(
+            parsed,
+            Some(("Some App".to_string(), Rect::new(10, 20, 300, 400)))
+        );
+    }
+
+    #[test]
+    fn keyboard_adjustments_saturate() {
+        let r = Rect::new(10, 10, 60, 60);
+
+        assert_eq!(nudge(r, -20, 5), Rect::new(0, 15, 60, 60));
+        assert_eq!(stretch(r, -20, 20), Rect::new(10, 10, MIN_FLOAT_SIZE, 80));
+    }
+
+    #[test]
+    fn constrain_keeps_windows_reachable_on_the_nearest_screen() {
+        let screens = [Rect::new(0, 0, 1000, 800), Rect::new(1000, 0, 1000, 800)];
+
+        assert_eq!(
+            constrain(Rect::new(1900, 790, 400, 300), &screens),
+            Rect::new(1900, 736, 400, 300)
+        );
+        assert_eq!(
+            constrain(Rect::new(10, 900, 1200, 300), &screens),
+            Rect::new(10, 736, 1000, 300)
+        );
+    }
+
+    #[test]
+    fn smart_placement_avoids_other_windows() {
+        let screen = Rect::new(0, 0, 1000, 800);
+        let others = [Rect::new(0, 0, 500, 400)];
+
+        assert_eq!(
+            smart_placement(screen, 300, 200, &others, Point::new(0, 0)),
+            Rect::new(0, 400, 300, 200)
+        );
+        let full = [screen];
+        assert_eq!(
+            smart_placement(screen, 300, 200, &full, Point::new(100, 100)),
+            Rect::new(0, 0, 300, 200)
+        );
+        assert_eq!(
+            smart_placement(screen, 300, 200, &full, Point::new(2000, 100)),
+            Rect::new(350, 300, 300, 200)
+        );
+    }
+}
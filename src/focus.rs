@@ -0,0 +1,372 @@
+//! Tracking and restoring client focus.
+use crate::{
+    client::wm_class, hidden::on_regular_tag, menu, spans::traced, startup::start_command,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        ClientSet, Config, State,
+    },
+    pure::geometry::Point,
+    x::{ClientMessageKind, Prop, XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+/// How many previously focused clients are remembered.
+const HISTORY_LEN: usize = 32;
+
+/// Recently focused clients, most recent first, along with the last client that was focused
+/// on each tag.
+#[derive(Debug, Default, Clone)]
+pub struct FocusHistory {
+    clients: Vec<Xid>,
+    per_tag: HashMap<String, Xid>,
+}
+
+impl FocusHistory {
+    /// The client that was focused as of the last refresh.
+    pub fn most_recent(&self) -> Option<Xid> {
+        self.clients.first().copied()
+    }
+
+    fn record(&mut self, id: Xid) {
+        if self.clients.first() == Some(&id) {
+            return;
+        }
+        self.clients.retain(|&c| c != id);
+        self.clients.insert(0, id);
+        self.clients.truncate(HISTORY_LEN);
+    }
+}
+
+/// Records focus changes after each refresh, for [focus_previous] and for [focus_tag] and
+/// [toggle_tag] to restore the client that was last focused on a tag.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FocusHistoryHook;
+
+impl<X: XConn> StateHook<X> for FocusHistoryHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let ext = state.extension_or_default::<FocusHistory>();
+        let mut history = ext.borrow_mut();
+        let cs = &state.client_set;
+        history.clients.retain(|id| cs.contains(id));
+        history
+            .per_tag
+            .retain(|t, id| cs.tag_for_client(id) == Some(t.as_str()));
+
+        if let Some(&id) = cs.current_client() {
+            history.record(id);
+            history.per_tag.insert(cs.current_tag().to_string(), id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `f` to change tag, then focus whichever client was last focused on the new tag.
+///
+/// This is only done for switching tag as such: actions that jump to a particular client
+/// on another tag want that client focused, not the one that was there before.
+fn switch_tag<X, F>(state: &mut State<X>, x: &X, f: F) -> Result<()>
+where
+    X: XConn,
+    F: FnOnce(&mut ClientSet),
+{
+    f(&mut state.client_set);
+    let tag = state.client_set.current_tag().to_string();
+    let restore = state
+        .extension_or_default::<FocusHistory>()
+        .borrow()
+        .per_tag
+        .get(&tag)
+        .copied();
+
+    if let Some(id) = restore {
+        if state.client_set.tag_for_client(&id) == Some(tag.as_str()) {
+            state.client_set.focus_client(&id);
+        }
+    }
+
+    x.refresh(state)
+}
+
+/// Switch to `tag`, focusing the client that was last focused there.
+pub fn focus_tag<X: XConn>(tag: &'static str) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| switch_tag(state, x, |cs| cs.focus_tag(tag)))
+}
+
+/// Switch back to the previous tag, focusing the client that was last focused there.
+pub fn toggle_tag<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| switch_tag(state, x, |cs| cs.toggle_tag()))
+}
+
+/// Jump back to the previously focused client, wherever it is (short of being minimized or
+/// otherwise parked).
+pub fn focus_previous<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let current = state.client_set.current_client().copied();
+        let previous = state
+            .extension_or_default::<FocusHistory>()
+            .borrow()
+            .clients
+            .iter()
+            .find(|&&id| Some(id) != current && on_regular_tag(state, &id))
+            .copied();
+
+        match previous {
+            Some(id) => x.modify_and_refresh(state, |cs| cs.focus_client(&id)),
+            None => Ok(()),
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusMode {
+    /// Focus only changes on click or from key bindings.
+    Click,
+    /// Focus whichever window the pointer enters.
+    FollowMouse,
+    /// Like `FollowMouse`, but windows moving under a stationary pointer (tag switches, layout
+    /// changes, new clients...) don't steal focus.
+    Sloppy,
+}
+
+impl FocusMode {
+    pub fn apply<X: XConn + 'static>(self, config: &mut Config<X>) {
+        config.focus_follow_mouse = self != FocusMode::Click;
+
+        if self == FocusMode::Sloppy {
+            let hook = SloppyFocusHook::default();
+            config.compose_or_set_event_hook(traced(hook.clone()));
+            config.compose_or_set_refresh_hook(traced(hook));
+        }
+    }
+}
+
+/// Remembers where the pointer was at the end of each refresh and drops any EnterNotify events
+/// that arrive without it having moved since, as those come from windows being rearranged
+/// rather than from the user reaching for a new window.
+#[derive(Debug, Default, Clone)]
+pub struct SloppyFocusHook {
+    settled_at: Rc<Cell<Option<Point>>>,
+}
+
+impl<X: XConn> EventHook<X> for SloppyFocusHook {
+    fn call(&mut self, event: &XEvent, _: &mut State<X>, _: &X) -> Result<bool> {
+        let p = match event {
+            XEvent::Enter(p) => p.abs,
+            _ => return Ok(true),
+        };
+
+        if self.settled_at.get() == Some(p) {
+            return Ok(false);
+        }
+        self.settled_at.set(None);
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for SloppyFocusHook {
+    fn call(&mut self, _: &mut State<X>, x: &X) -> Result<()> {
+        self.settled_at.set(Some(x.cursor_position()?));
+
+        Ok(())
+    }
+}
+
+/// Pick any client on a regular tag from a menu of "tag: class — title" entries and focus
+/// it, switching tag and screen as needed.
+pub fn focus_from_menu<X: XConn + 'static>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let mut clients = Vec::new();
+        let mut entries = Vec::new();
+        for tag in state.config.tags.iter() {
+            let ws = match state.client_set.workspace(tag) {
+                Some(ws) => ws,
+                None => continue,
+            };
+            for &id in ws.clients() {
+                let class = wm_class(x, id).unwrap_or_default();
+                let title = x.window_title(id).unwrap_or_default();
+                clients.push(id);
+                entries.push(format!("{tag}: {class} — {title}"));
+            }
+        }
+
+        menu::select("window", entries, state, move |ix, state, x| {
+            x.modify_and_refresh(state, |cs| cs.focus_client(&clients[ix]))
+        })
+    })
+}
+
+/// Focus a client with the given WM_CLASS (compared ignoring case), moving on to the next
+/// matching client each time this is repeated, or launch `command` if there aren't any.
+pub fn cycle_or_spawn<X: XConn>(
+    class: &'static str,
+    command: &'static str,
+) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let matching: Vec<Xid> = state
+            .config
+            .tags
+            .iter()
+            .filter_map(|tag| state.client_set.workspace(tag))
+            .flat_map(|ws| ws.clients())
+            .filter(|&&id| wm_class(x, id).is_some_and(|c| c.eq_ignore_ascii_case(class)))
+            .copied()
+            .collect();
+
+        let focused = state.client_set.current_client();
+        let next = match matching.iter().position(|id| Some(id) == focused) {
+            Some(ix) => matching[(ix + 1) % matching.len()],
+            None => match matching.first() {
+                Some(&id) => id,
+                None => return start_command(state, command),
+            },
+        };
+
+        x.modify_and_refresh(state, |cs| cs.focus_client(&next))
+    })
+}
+
+/// Set for as long as something the keyboard asked for is being carried out.
+#[derive(Debug, Default, Clone, Copy)]
+struct KeyDriven {
+    active: bool,
+}
+
+/// Run `f` as something the keyboard asked for, so that any focus change it makes takes the
+/// pointer along with it (see [WarpPointerHook]). Key bindings are all run this way by
+/// [key_driven_bindings], and anything acting later on keyboard input read in the background
+/// should be as well.
+pub fn key_driven<X, F>(state: &mut State<X>, f: F) -> Result<()>
+where
+    X: XConn,
+    F: FnOnce(&mut State<X>) -> Result<()>,
+{
+    let ext = state.extension_or_default::<KeyDriven>();
+    let was_active = std::mem::replace(&mut ext.borrow_mut().active, true);
+    let res = f(state);
+    ext.borrow_mut().active = was_active;
+
+    res
+}
+
+struct KeyDrivenBinding<X: XConn> {
+    inner: Box<dyn KeyEventHandler<X>>,
+}
+
+impl<X: XConn> KeyEventHandler<X> for KeyDrivenBinding<X> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        key_driven(state, |state| self.inner.call(state, x))
+    }
+}
+
+/// Run each key binding with [key_driven].
+pub fn key_driven_bindings<X: XConn + 'static>(
+    bindings: HashMap<String, Box<dyn KeyEventHandler<X>>>,
+) -> HashMap<String, Box<dyn KeyEventHandler<X>>> {
+    bindings
+        .into_iter()
+        .map(|(keys, inner)| (keys, Box::new(KeyDrivenBinding { inner }) as _))
+        .collect()
+}
+
+/// Warps the pointer to the middle of the focused client, or of the focused screen if it has
+/// no clients, whenever focus is moved from the keyboard (see [key_driven]) somewhere the
+/// pointer isn't. Focus changing for any other reason, such as a client being closed or a new
+/// one being mapped, leaves the pointer be.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WarpPointerHook {
+    last_client: Option<Xid>,
+    last_screen: usize,
+}
+
+impl<X: XConn> StateHook<X> for WarpPointerHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let client = state.client_set.current_client().copied();
+        let screen = state.client_set.current_screen();
+        if client == self.last_client && screen.index() == self.last_screen {
+            return Ok(());
+        }
+        self.last_client = client;
+        self.last_screen = screen.index();
+
+        if state.extension_or_default::<KeyDriven>().borrow().active {
+            warp_to_focus(state, x)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Warp the pointer to the middle of the focused client, or of the focused screen if it has no
+/// clients, unless it is already there.
+///
+/// Warping to the middle of a screen that has clients would hand focus to whichever of them
+/// happens to be there when focus follows the mouse, so the focused client is aimed for
+/// instead.
+pub fn warp_to_focus<X: XConn>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = state.client_set.current_client().copied();
+    let screen = state.client_set.current_screen();
+    let (index, r) = (screen.index(), screen.geometry());
+
+    let p = x.cursor_position()?;
+    match client {
+        Some(id) if !x.client_geometry(id)?.contains_point(p) => x.warp_pointer_to_window(id),
+        None if !r.contains_point(p) => x.warp_pointer_to_screen(state, index),
+        _ => Ok(()),
+    }
+}
+
+fn wants_take_focus<X: XConn>(x: &X, id: Xid) -> bool {
+    match x.get_prop(id, "WM_PROTOCOLS") {
+        Ok(Some(Prop::Atom(protocols))) => protocols.iter().any(|p| p == "WM_TAKE_FOCUS"),
+        _ => false,
+    }
+}
+
+/// Sends WM_TAKE_FOCUS to clients that list it in WM_PROTOCOLS whenever they are focused.
+/// Penrose sets the input focus itself, but clients following the locally or globally active
+/// input models from the ICCCM (Java applications and some games) also wait to be sent the
+/// message, and may otherwise never take keyboard input.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TakeFocusHook {
+    last_client: Option<Xid>,
+}
+
+impl<X: XConn> StateHook<X> for TakeFocusHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let client = state.client_set.current_client().copied();
+        if client == self.last_client {
+            return Ok(());
+        }
+        self.last_client = client;
+
+        match client {
+            Some(id) if wants_take_focus(x, id) => {
+                x.send_client_message(ClientMessageKind::TakeFocus(id).as_message(x)?)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refocusing_moves_a_client_to_the_front() {
+        let mut history = FocusHistory::default();
+        for id in [1, 2, 3, 1] {
+            history.record(Xid::from(id));
+        }
+
+        assert_eq!(history.clients, [1, 3, 2].map(Xid::from));
+    }
+}
@@ -0,0 +1,105 @@
+//! A single toggle that gets everything out of the way of a game.
+//!
+//! Gaming mode stops the compositor, drops gaps and borders, stops focus following the mouse,
+//! holds off idle locking and hands every key binding over to the game through
+//! [Passthrough]. Toggling it off again puts each of them back as it was.
+use crate::{
+    bar, compositor,
+    dpi::set_gaps,
+    idle::{inhibit, uninhibit},
+    passthrough::Passthrough,
+};
+use penrose::{
+    core::{bindings::KeyEventHandler, State},
+    x::{ClientConfig, XConn, XConnExt},
+    x11rb::RustConn,
+    Result, Xid,
+};
+use std::{cell::Cell, rc::Rc};
+
+const GAMING_REASON: &str = "gaming";
+
+#[derive(Debug, Clone, Copy)]
+pub struct GamingMode {
+    /// Whether to pause the [compositor] while gaming mode is on.
+    pub pause_compositor: bool,
+}
+
+/// What gaming mode changed, to be put back when it is switched off.
+#[derive(Debug, Clone, Copy)]
+struct Saved {
+    border_width: u32,
+    focus_follow_mouse: bool,
+}
+
+#[derive(Debug, Clone)]
+struct GamingToggle {
+    config: GamingMode,
+    passthrough: Passthrough,
+    saved: Rc<Cell<Option<Saved>>>,
+}
+
+/// Turn gaming mode on or off. This must be bound alongside the passthrough toggle rather
+/// than with the other bindings, as those are released while gaming mode is on.
+pub fn toggle_gaming_mode(
+    config: GamingMode,
+    passthrough: &Passthrough,
+) -> Box<dyn KeyEventHandler<RustConn>> {
+    Box::new(GamingToggle {
+        config,
+        passthrough: passthrough.clone(),
+        saved: Rc::default(),
+    })
+}
+
+impl GamingToggle {
+    fn set_borders(state: &State<RustConn>, x: &RustConn, px: u32) -> Result<()> {
+        let clients: Vec<Xid> = state.client_set.clients().copied().collect();
+        for id in clients {
+            x.set_client_config(id, &[ClientConfig::BorderPx(px)])?;
+        }
+
+        Ok(())
+    }
+
+    fn enable(&self, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+        if self.config.pause_compositor {
+            compositor::pause(state, GAMING_REASON)?;
+        }
+        set_gaps(false);
+        self.saved.set(Some(Saved {
+            border_width: state.config.border_width,
+            focus_follow_mouse: state.config.focus_follow_mouse,
+        }));
+        state.config.border_width = 0;
+        state.config.focus_follow_mouse = false;
+        Self::set_borders(state, x, 0)?;
+        inhibit(state, GAMING_REASON)?;
+        self.passthrough.set(true, state, x)?;
+        bar::publish(state, "gaming", "on");
+
+        x.refresh(state)
+    }
+
+    fn disable(&self, saved: Saved, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+        self.passthrough.set(false, state, x)?;
+        uninhibit(state, GAMING_REASON)?;
+        state.config.border_width = saved.border_width;
+        state.config.focus_follow_mouse = saved.focus_follow_mouse;
+        Self::set_borders(state, x, saved.border_width)?;
+        set_gaps(true);
+        compositor::resume(state, GAMING_REASON)?;
+        bar::publish(state, "gaming", "");
+
+        x.refresh(state)
+    }
+}
+
+impl KeyEventHandler<RustConn> for GamingToggle {
+    fn call(&mut self, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+        match self.saved.take() {
+            Some(saved) => self.disable(saved, state, x),
+            None => self.enable(state, x),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Invisible workspaces used to park clients that should be kept out of the layout.
+use crate::{
+    client::{requested_state, set_net_wm_state, wm_class},
+    menu,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::EventHook, ClientSet, State},
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+
+/// The invisible workspace that minimized clients are parked on.
+const MINIMIZED_TAG: &str = "minimized";
+const NET_WM_STATE_HIDDEN: &str = "_NET_WM_STATE_HIDDEN";
+/// The WM_CHANGE_STATE value asking for a client to be iconified, from the ICCCM.
+const ICONIC_STATE: usize = 3;
+
+/// Move `id` onto the invisible workspace `tag`, creating it if needed.
+pub fn park_client(cs: &mut ClientSet, id: Xid, tag: &str) -> Result<()> {
+    if cs.workspace(tag).is_none() {
+        cs.add_invisible_workspace(tag)?;
+    }
+    cs.move_client_to_tag(&id, tag);
+
+    Ok(())
+}
+
+/// Whether `id` is on one of the tags in the config, rather than parked out of sight on an
+/// invisible workspace.
+pub fn on_regular_tag<X: XConn>(state: &State<X>, id: &Xid) -> bool {
+    state
+        .client_set
+        .tag_for_client(id)
+        .is_some_and(|tag| state.config.tags.iter().any(|t| t == tag))
+}
+
+/// Minimized clients, most recently minimized last.
+#[derive(Debug, Default, Clone)]
+pub struct Minimized {
+    clients: Vec<Xid>,
+}
+
+fn is_minimized<X: XConn>(state: &State<X>, id: Xid) -> bool {
+    state.client_set.tag_for_client(&id) == Some(MINIMIZED_TAG)
+}
+
+fn minimize<X: XConn>(id: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    park_client(&mut state.client_set, id, MINIMIZED_TAG)?;
+    state
+        .extension_or_default::<Minimized>()
+        .borrow_mut()
+        .clients
+        .push(id);
+    set_net_wm_state(x, id, NET_WM_STATE_HIDDEN, true)?;
+
+    x.refresh(state)
+}
+
+/// Bring a minimized client back onto the current tag and focus it.
+fn restore<X: XConn>(id: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    let ext = state.extension_or_default::<Minimized>();
+    ext.borrow_mut().clients.retain(|&c| c != id);
+    set_net_wm_state(x, id, NET_WM_STATE_HIDDEN, false)?;
+
+    x.modify_and_refresh(state, |cs| {
+        cs.move_client_to_current_tag(&id);
+        cs.focus_client(&id);
+    })
+}
+
+/// Hide the focused client from the layout until it is restored with [restore_minimized].
+pub fn minimize_focused<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(
+        |state: &mut State<X>, x: &X| match state.client_set.current_client() {
+            Some(&id) => minimize(id, state, x),
+            None => Ok(()),
+        },
+    )
+}
+
+/// Pick a minimized client from a menu and bring it back onto the current tag.
+pub fn restore_minimized<X: XConn + 'static>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let ext = state.extension_or_default::<Minimized>();
+        {
+            let mut minimized = ext.borrow_mut();
+            minimized.clients.retain(|&id| is_minimized(state, id));
+            // Clients parked by the window manager before a restart are the oldest.
+            if let Some(ws) = state.client_set.workspace(MINIMIZED_TAG) {
+                let earlier: Vec<Xid> = ws
+                    .clients()
+                    .filter(|id| !minimized.clients.contains(id))
+                    .copied()
+                    .collect();
+                minimized.clients.splice(0..0, earlier);
+            }
+        }
+
+        let clients: Vec<Xid> = ext.borrow().clients.iter().rev().copied().collect();
+        let titles: Vec<String> = clients
+            .iter()
+            .map(|&id| {
+                let class = wm_class(x, id).unwrap_or_default();
+                let title = x.window_title(id).unwrap_or_default();
+                format!("{class}: {title}")
+            })
+            .collect();
+
+        menu::select("restore", titles, state, move |ix, state, x| {
+            let id = clients[ix];
+            // The client may have been restored some other way (or closed) in the meantime.
+            if !is_minimized(state, id) {
+                return Ok(());
+            }
+
+            restore(id, state, x)
+        })
+    })
+}
+
+/// Minimizes clients that ask to be iconified with WM_CHANGE_STATE, and minimizes or restores
+/// clients when pagers and taskbars ask for _NET_WM_STATE_HIDDEN to be set or cleared.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MinimizeHook;
+
+impl<X: XConn> EventHook<X> for MinimizeHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if state.client_set.contains(&msg.id) => msg,
+            _ => return Ok(true),
+        };
+        let id = msg.id;
+        let minimized = is_minimized(state, id);
+
+        if msg.dtype == "WM_CHANGE_STATE" {
+            if msg.data.as_usize()[0] == ICONIC_STATE && !minimized {
+                minimize(id, state, x)?;
+            }
+            return Ok(false);
+        }
+
+        match requested_state(state, x, msg, NET_WM_STATE_HIDDEN, minimized)? {
+            Some(true) if !minimized => minimize(id, state, x)?,
+            Some(false) if minimized => restore(id, state, x)?,
+            _ => (),
+        }
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,59 @@
+//! Every hook the window manager is made of, gathered in one place.
+//!
+//! Each is a self contained piece of behaviour that can be added to any penrose config with
+//! the `compose_or_set_*_hook` methods, as [config](crate::config) does. Some settings, such as
+//! [NightLight] and [Dpms], are hooks themselves. Hooks that read or change the window
+//! layout during a refresh should be put together with [batch_refresh_hooks], and every hook
+//! can be wrapped with [traced] to show up when profiling and to keep a panic in it from
+//! taking down the window manager, with [catch_hook_panics] covering hooks added otherwise.
+pub use crate::{
+    activation::FocusStealingHook,
+    audio::MicMuteHook,
+    autostart::AutostartHook,
+    batch::batch_refresh_hooks,
+    battery::BatteryHook,
+    borders::{BorderColorHook, BorderlessHook, ScaledBordersHook},
+    compositor::{CompositorHook, FullscreenPauseHook},
+    corners::RoundedCornersHook,
+    daemons::DaemonsHook,
+    dpi::DpiHook,
+    ewmh::{EwmhHook, EwmhSupportedHook, WmDesktopHook},
+    floating::{ConstrainFloatingHook, FloatModeHook, FloatingMemoryHook, OffscreenRescueHook},
+    focus::{FocusHistoryHook, SloppyFocusHook, TakeFocusHook, WarpPointerHook},
+    hidden::MinimizeHook,
+    idle::{CaffeineHook, Dpms, FullscreenInhibitHook, IdleLockHook},
+    ipc::IpcHook,
+    kbd_backlight::KbdBacklightHook,
+    kill::{ForceKillHook, KillModeHook},
+    layouts::OutputLayoutsHook,
+    lid::LidHook,
+    marks::MarksHook,
+    maximize::MaximizeHook,
+    menu::MenuHook,
+    migration::WorkspaceMigrationHook,
+    monitor::{MonitorHook, OutputScriptHook},
+    network::{NetworkHook, NetworkScriptHook},
+    nightlight::NightLight,
+    opacity::OpacityHook,
+    osd::OsdHook,
+    panics::{catch_hook_panics, non_essential},
+    pip::PictureInPictureHook,
+    power::PowerProfiles,
+    presentation::PresentationHook,
+    process::ReaperHook,
+    profiles::ProfileHook,
+    restart::RestoreHook,
+    schedule::ScheduleHook,
+    screens::PrimaryHook,
+    screensaver::ScreenSaverHook,
+    selection::ReplacedHook,
+    spans::traced,
+    stacking::AlwaysOnTopHook,
+    startup::StartupNotificationHook,
+    sticky::StickyHook,
+    struts::ReservedSpaceHook,
+    swallow::SwallowHook,
+    systemd::SystemdHook,
+    titlebar::TitlebarHook,
+    urgency::UrgencyHook,
+};
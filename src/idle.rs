@@ -0,0 +1,557 @@
+//! Locking and blanking the screens when idle, and keeping them on while something needs them.
+//!
+//! Lockers started through `xss-lock` (and the X server's own blanking) are driven by the X
+//! screen saver, and monitors are powered down through DPMS. While anything holds an
+//! inhibitor the screen saver timeout is set to zero and DPMS is disabled, and both are put
+//! back as they were once the last one is released. The [IdleLockHook] holds off in the same
+//! way, and dims the screens for a little while before locking to give a chance to stop it.
+use crate::{
+    bar, batch::Batch, client::wm_class, modal::x_err, nightlight::set_brightness,
+    timer::notify_after,
+};
+use chrono::{Local, Timelike};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result, Xid,
+};
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    process::{Child, Command},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        dpms::{ConnectionExt as _, DPMSMode},
+        screensaver::ConnectionExt as _,
+        xproto::{Blanking, ConnectionExt as _, Exposures},
+    },
+    rust_connection::RustConnection,
+};
+
+const MANUAL_REASON: &str = "manual";
+const CAFFEINE_REASON: &str = "caffeine";
+const FULLSCREEN_REASON: &str = "fullscreen";
+
+/// Sent once the key used to blank the screens has been released, as releasing it would
+/// otherwise wake them straight back up.
+const DPMS_BLANK: &str = "_WM_DPMS_BLANK";
+const BLANK_DELAY: Duration = Duration::from_millis(500);
+
+/// Sent when the [IdleLockHook] should next check how long the user has been idle.
+const IDLE_CHECK: &str = "_WM_IDLE_CHECK";
+/// The shortest time between idle checks.
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// How often to check while dimming the screens before locking, which sets how smooth the
+/// dimming is and how quickly input brings the screens back.
+const DIM_STEP: Duration = Duration::from_millis(200);
+/// How bright the screens are once dimmed right down, just before locking.
+const DIMMEST: f64 = 0.3;
+
+/// Sent once caffeine mode has been on for as long as was asked for.
+const CAFFEINE_EXPIRE: &str = "_WM_CAFFEINE_EXPIRE";
+
+/// The screen saver and DPMS settings from before the first inhibitor was taken.
+#[derive(Debug, Clone, Copy)]
+struct Saved {
+    timeout: i16,
+    interval: i16,
+    blanking: Blanking,
+    exposures: Exposures,
+    dpms: bool,
+}
+
+/// The connection used for the screen saver and DPMS, made the first time either is needed.
+struct Side {
+    conn: RustConnection,
+    root: u32,
+}
+
+#[derive(Default)]
+struct SideConnection {
+    side: Option<Rc<Side>>,
+}
+
+fn side<X: XConn>(state: &mut State<X>) -> Result<Rc<Side>> {
+    let ext = state.extension_or_default::<SideConnection>();
+    if let Some(side) = ext.borrow().side.clone() {
+        return Ok(side);
+    }
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+    let side = Rc::new(Side { conn, root });
+    ext.borrow_mut().side = Some(side.clone());
+
+    Ok(side)
+}
+
+#[derive(Debug, Default)]
+pub struct Inhibitors {
+    reasons: BTreeSet<String>,
+    saved: Option<Saved>,
+}
+
+/// Keep the screen on until [uninhibit] is called with the same `reason`. Active reasons are
+/// published to the bar as "inhibit".
+pub fn inhibit<X: XConn>(state: &mut State<X>, reason: &str) -> Result<()> {
+    let ext = state.extension_or_default::<Inhibitors>();
+    if ext.borrow().reasons.contains(reason) {
+        return Ok(());
+    }
+    let side = side(state)?;
+    let conn = &side.conn;
+    let summary = {
+        let mut inhibitors = ext.borrow_mut();
+        // The reason is only counted once the screen saver and DPMS are actually held off.
+        if inhibitors.saved.is_none() {
+            let current = conn
+                .get_screen_saver()
+                .map_err(x_err)?
+                .reply()
+                .map_err(x_err)?;
+            let dpms = conn
+                .dpms_info()
+                .map_err(x_err)?
+                .reply()
+                .map_err(x_err)?
+                .state;
+            let saved = Saved {
+                timeout: current.timeout as i16,
+                interval: current.interval as i16,
+                blanking: current.prefer_blanking,
+                exposures: current.allow_exposures,
+                dpms,
+            };
+
+            conn.set_screen_saver(0, saved.interval, saved.blanking, saved.exposures)
+                .map_err(x_err)?;
+            if dpms {
+                conn.dpms_disable().map_err(x_err)?;
+            }
+            conn.flush().map_err(x_err)?;
+            inhibitors.saved = Some(saved);
+        }
+        inhibitors.reasons.insert(reason.to_string());
+
+        summary(&inhibitors.reasons)
+    };
+    bar::publish(state, "inhibit", summary);
+
+    Ok(())
+}
+
+/// Release an inhibitor taken with [inhibit].
+pub fn uninhibit<X: XConn>(state: &mut State<X>, reason: &str) -> Result<()> {
+    let ext = state.extension_or_default::<Inhibitors>();
+    if !ext.borrow().reasons.contains(reason) {
+        return Ok(());
+    }
+    let side = side(state)?;
+    let conn = &side.conn;
+    let summary = {
+        let mut inhibitors = ext.borrow_mut();
+        inhibitors.reasons.remove(reason);
+        if inhibitors.reasons.is_empty() {
+            if let Some(saved) = inhibitors.saved.take() {
+                conn.set_screen_saver(
+                    saved.timeout,
+                    saved.interval,
+                    saved.blanking,
+                    saved.exposures,
+                )
+                .map_err(x_err)?;
+                if saved.dpms {
+                    conn.dpms_enable().map_err(x_err)?;
+                }
+                conn.flush().map_err(x_err)?;
+            }
+        }
+
+        summary(&inhibitors.reasons)
+    };
+    bar::publish(state, "inhibit", summary);
+
+    Ok(())
+}
+
+/// Whether anything is currently keeping the screen on.
+pub fn is_inhibited<X: XConn>(state: &mut State<X>) -> bool {
+    !inhibited_by(state).is_empty()
+}
+
+fn inhibited_by<X: XConn>(state: &mut State<X>) -> BTreeSet<String> {
+    state
+        .extension_or_default::<Inhibitors>()
+        .borrow()
+        .reasons
+        .clone()
+}
+
+/// Release every inhibitor, putting the screen saver and DPMS back as they were.
+pub fn release_all<X: XConn>(state: &mut State<X>) -> Result<()> {
+    for reason in inhibited_by(state) {
+        uninhibit(state, &reason)?;
+    }
+
+    Ok(())
+}
+
+fn summary(reasons: &BTreeSet<String>) -> String {
+    reasons.iter().cloned().collect::<Vec<_>>().join(",")
+}
+
+/// Keep the screens from blanking until this is run again.
+pub fn toggle_blanking<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        if inhibited_by(state).contains(MANUAL_REASON) {
+            uninhibit(state, MANUAL_REASON)
+        } else {
+            inhibit(state, MANUAL_REASON)
+        }
+    })
+}
+
+#[derive(Debug, Default)]
+struct Caffeine {
+    on: bool,
+    /// Bumped every time caffeine mode is turned on, so that only the last expiry counts.
+    generation: u32,
+}
+
+fn set_caffeine<X: XConn>(state: &mut State<X>, on: bool, expire: Option<Duration>) -> Result<()> {
+    let generation = {
+        let ext = state.extension_or_default::<Caffeine>();
+        let mut caffeine = ext.borrow_mut();
+        caffeine.on = on;
+        caffeine.generation = caffeine.generation.wrapping_add(1);
+        caffeine.generation
+    };
+
+    if !on {
+        bar::publish(state, "caffeine", "");
+        return uninhibit(state, CAFFEINE_REASON);
+    }
+
+    let label = match expire {
+        Some(after) => {
+            notify_after(after, CAFFEINE_EXPIRE, [generation, 0, 0, 0, 0]);
+            let until = Local::now() + after;
+            format!("until {:02}:{:02}", until.hour(), until.minute())
+        }
+        None => "on".to_string(),
+    };
+    bar::publish(state, "caffeine", label);
+
+    inhibit(state, CAFFEINE_REASON)
+}
+
+/// Keep the screens on and hold off the idle lock until this is run again or, with `expire`
+/// set, until that long has passed. Caffeine mode is published to the bar as "caffeine".
+pub fn toggle_caffeine<X: XConn>(expire: Option<Duration>) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let on = state.extension_or_default::<Caffeine>().borrow().on;
+        set_caffeine(state, !on, expire)
+    })
+}
+
+/// Turns caffeine mode off again once it expires.
+#[derive(Debug, Clone, Copy)]
+pub struct CaffeineHook;
+
+impl<X: XConn> EventHook<X> for CaffeineHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let generation = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == CAFFEINE_EXPIRE => {
+                msg.data.as_usize().first().copied()
+            }
+            _ => return Ok(true),
+        };
+
+        let current = state.extension_or_default::<Caffeine>().borrow().generation;
+        if generation == Some(current as usize) {
+            info!("caffeine mode expired");
+            set_caffeine(state, false, None)?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// Turn the monitors off now. Any input turns them back on.
+pub fn blank_screens<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|_: &mut State<X>, _: &X| {
+        notify_after(BLANK_DELAY, DPMS_BLANK, [0; 5]);
+        Ok(())
+    })
+}
+
+/// DPMS timeouts in seconds for each power saving level, applied at startup. A timeout of
+/// zero skips that level.
+#[derive(Debug, Clone, Copy)]
+pub struct Dpms {
+    pub standby: u16,
+    pub suspend: u16,
+    pub off: u16,
+}
+
+impl<X: XConn> StateHook<X> for Dpms {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let side = side(state)?;
+        let conn = &side.conn;
+        conn.dpms_set_timeouts(self.standby, self.suspend, self.off)
+            .map_err(x_err)?;
+        conn.dpms_enable().map_err(x_err)?;
+        conn.flush().map_err(x_err)?;
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for Dpms {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == DPMS_BLANK => (),
+            _ => return Ok(true),
+        }
+
+        // The server refuses to force a power level while DPMS is disabled.
+        let reasons = inhibited_by(state);
+        if !reasons.is_empty() {
+            info!(
+                "not blanking the screens: inhibited by {}",
+                summary(&reasons)
+            );
+            return Ok(false);
+        }
+
+        let side = side(state)?;
+        side.conn.dpms_force_level(DPMSMode::OFF).map_err(x_err)?;
+        side.conn.flush().map_err(x_err)?;
+
+        Ok(false)
+    }
+}
+
+/// Keeps the screens on while a fullscreen client is showing on any of them, so that media
+/// playing on one screen isn't interrupted for lack of input on another. With `classes` set,
+/// only fullscreen clients with one of those WM_CLASSes count.
+#[derive(Debug, Clone, Copy)]
+pub struct FullscreenInhibitHook {
+    pub classes: Option<&'static [&'static str]>,
+}
+
+impl FullscreenInhibitHook {
+    fn counts<X: XConn>(&self, batch: &mut Batch, x: &X, id: Xid) -> Result<bool> {
+        let fullscreen = batch.fullscreen(x, id)?;
+        let class_matches = match self.classes {
+            None => true,
+            Some(classes) => {
+                wm_class(x, id).is_some_and(|c| classes.iter().any(|e| c.eq_ignore_ascii_case(e)))
+            }
+        };
+
+        Ok(fullscreen && class_matches)
+    }
+}
+
+impl<X: XConn> StateHook<X> for FullscreenInhibitHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let batch = state.extension_or_default::<Batch>();
+        let mut showing = false;
+        for &id in state.client_set.on_screen_workspace_clients() {
+            if self.counts(&mut batch.borrow_mut(), x, id)? {
+                showing = true;
+                break;
+            }
+        }
+
+        if showing {
+            inhibit(state, FULLSCREEN_REASON)
+        } else {
+            uninhibit(state, FULLSCREEN_REASON)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Locking {
+    /// The running locker, if any.
+    locker: Option<Child>,
+    /// When locking was last held off, as time spent idle before then doesn't count.
+    allowed_since: Instant,
+    /// Whether the screens have been dimmed as a warning.
+    dimmed: bool,
+}
+
+/// How bright the screens should be after `idle` of the `dim` period before locking.
+fn dim_brightness(idle: Duration, dim: Duration) -> f64 {
+    let progress = if dim.is_zero() {
+        1.0
+    } else {
+        (idle.as_secs_f64() / dim.as_secs_f64()).min(1.0)
+    };
+
+    1.0 - (1.0 - DIMMEST) * progress
+}
+
+/// Runs `locker` once there has been no keyboard or mouse input for `timeout`. Locking is
+/// held off while the screen is inhibited, while one of `tags` is focused and while a client
+/// with one of `classes` has focus. For the last `dim` before locking the screens are dimmed
+/// as a warning, and put back straight away on any input.
+#[derive(Debug, Clone)]
+pub struct IdleLockHook {
+    timeout: Duration,
+    dim: Duration,
+    locker: &'static str,
+    tags: &'static [&'static str],
+    classes: &'static [&'static str],
+    locking: Rc<RefCell<Locking>>,
+}
+
+impl IdleLockHook {
+    pub fn new(
+        timeout: Duration,
+        dim: Duration,
+        locker: &'static str,
+        tags: &'static [&'static str],
+        classes: &'static [&'static str],
+    ) -> Self {
+        Self {
+            timeout,
+            dim: dim.min(timeout),
+            locker,
+            tags,
+            classes,
+            locking: Rc::new(RefCell::new(Locking {
+                locker: None,
+                allowed_since: Instant::now(),
+                dimmed: false,
+            })),
+        }
+    }
+
+    fn undim<X: XConn>(locking: &mut Locking, state: &mut State<X>) -> Result<()> {
+        if !locking.dimmed {
+            return Ok(());
+        }
+        locking.dimmed = false;
+
+        set_brightness(state, 1.0)
+    }
+
+    fn held_off<X: XConn>(&self, state: &mut State<X>, x: &X) -> bool {
+        let cs = &state.client_set;
+        let class = cs.current_client().and_then(|&id| wm_class(x, id));
+
+        self.tags.contains(&cs.current_tag())
+            || class.is_some_and(|c| self.classes.iter().any(|e| c.eq_ignore_ascii_case(e)))
+            || is_inhibited(state)
+    }
+
+    /// Lock if the user has been idle long enough, returning how long to wait before
+    /// checking again.
+    fn check<X: XConn>(&self, state: &mut State<X>, x: &X) -> Result<Duration> {
+        let mut locking = self.locking.borrow_mut();
+        let running = match locking.locker.as_mut().map(|c| c.try_wait()) {
+            Some(Ok(None)) => true,
+            Some(Err(e)) => {
+                warn!("unable to check on the locker: {e}");
+                false
+            }
+            _ => false,
+        };
+        if !running {
+            locking.locker = None;
+        }
+
+        if running || self.held_off(state, x) {
+            locking.allowed_since = Instant::now();
+            Self::undim(&mut locking, state)?;
+            return Ok(self.timeout - self.dim);
+        }
+
+        let side = side(state)?;
+        let since_input = side
+            .conn
+            .screensaver_query_info(side.root)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?
+            .ms_since_user_input;
+        let idle = Duration::from_millis(since_input as u64).min(locking.allowed_since.elapsed());
+
+        let dim_from = self.timeout - self.dim;
+        if idle < dim_from {
+            Self::undim(&mut locking, state)?;
+            return Ok(dim_from - idle);
+        } else if idle < self.timeout {
+            locking.dimmed = true;
+            set_brightness(state, dim_brightness(idle - dim_from, self.dim))?;
+            return Ok(DIM_STEP);
+        }
+
+        info!("idle for {}s: locking the screen", idle.as_secs());
+        locking.locker = Some(Command::new(self.locker).spawn().map_err(x_err)?);
+        Self::undim(&mut locking, state)?;
+
+        Ok(self.timeout - self.dim)
+    }
+}
+
+impl<X: XConn> EventHook<X> for IdleLockHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == IDLE_CHECK => (),
+            _ => return Ok(true),
+        }
+
+        // Keep checking even if this one failed.
+        let next = self.check(state, x);
+        notify_after(
+            next.as_ref()
+                .map_or(self.timeout, |d| *d)
+                .max(MIN_CHECK_INTERVAL),
+            IDLE_CHECK,
+            [0; 5],
+        );
+        next?;
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for IdleLockHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        notify_after(self.timeout - self.dim, IDLE_CHECK, [0; 5]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screens_dim_steadily_to_the_dimmest_level() {
+        let dim = Duration::from_secs(10);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        assert!(close(dim_brightness(Duration::ZERO, dim), 1.0));
+        assert!(close(dim_brightness(Duration::from_secs(5), dim), 0.65));
+        assert!(close(dim_brightness(Duration::from_secs(20), dim), DIMMEST));
+        assert!(close(
+            dim_brightness(Duration::ZERO, Duration::ZERO),
+            DIMMEST
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! Commands sent to the running window manager by `wmcli`.
+//!
+//! A background thread accepts connections on `$XDG_RUNTIME_DIR/wm/ipc.sock`. Each request is
+//! the command and its arguments, one per line, and is answered with `ok` or `error` on the
+//! first line followed by any output. Requests are handed to the event loop by waking it with
+//! a ClientMessage, as only it may touch the window manager state.
+use crate::{modal::x_err, paths::runtime_file, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{XConn, XEvent},
+    Error, Result,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use tracing::{error, warn};
+
+const IPC_REQUEST: &str = "_WM_IPC_REQUEST";
+/// How long to wait on `wmcli` when reading a request or writing a reply.
+const IO_TIMEOUT: Duration = Duration::from_secs(1);
+
+static REQUESTS: Mutex<Vec<(Vec<String>, UnixStream)>> = Mutex::new(Vec::new());
+
+/// Something `wmcli` can ask the window manager to do. The returned string is printed by
+/// `wmcli`, and an error makes it exit unsuccessfully.
+pub trait IpcCommand<X: XConn> {
+    fn call(&mut self, args: &[String], state: &mut State<X>, x: &X) -> Result<String>;
+}
+
+impl<F, X> IpcCommand<X> for F
+where
+    F: FnMut(&[String], &mut State<X>, &X) -> Result<String>,
+    X: XConn,
+{
+    fn call(&mut self, args: &[String], state: &mut State<X>, x: &X) -> Result<String> {
+        (self)(args, state, x)
+    }
+}
+
+/// Start accepting requests from `wmcli`.
+pub fn listen() -> Result<()> {
+    let path = runtime_file("ipc.sock");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(x_err)?;
+    }
+    // A previous instance won't have removed its socket if it crashed.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).map_err(x_err)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("unable to accept IPC connection: {e}");
+                    continue;
+                }
+            };
+            let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+            let mut request = String::new();
+            if let Err(e) = stream.read_to_string(&mut request) {
+                warn!("unable to read IPC request: {e}");
+                continue;
+            }
+            let args = request.lines().map(str::to_string).collect();
+
+            if let Ok(mut requests) = REQUESTS.lock() {
+                requests.push((args, stream));
+            }
+            notify_after(Duration::ZERO, IPC_REQUEST, [0; 5]);
+        }
+    });
+
+    Ok(())
+}
+
+/// Runs the commands requested through [listen].
+pub struct IpcHook<X: XConn> {
+    commands: HashMap<&'static str, Box<dyn IpcCommand<X>>>,
+}
+
+impl<X: XConn> IpcHook<X> {
+    pub fn new(commands: HashMap<&'static str, Box<dyn IpcCommand<X>>>) -> Self {
+        Self { commands }
+    }
+
+    fn run(&mut self, args: &[String], state: &mut State<X>, x: &X) -> Result<String> {
+        let (name, rest) = match args.split_first() {
+            Some((name, rest)) => (name.as_str(), rest),
+            None => return Err(Error::Custom("no command given".into())),
+        };
+
+        match self.commands.get_mut(name) {
+            Some(command) => command.call(rest, state, x),
+            None => Err(Error::Custom(format!("unknown command: {name}"))),
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for IpcHook<X> {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == IPC_REQUEST => (),
+            _ => return Ok(true),
+        }
+
+        let requests = match REQUESTS.lock() {
+            Ok(mut requests) => std::mem::take(&mut *requests),
+            Err(_) => return Ok(false),
+        };
+        for (args, mut stream) in requests {
+            let reply = match self.run(&args, state, x) {
+                Ok(output) => format!("ok\n{output}"),
+                Err(e) => format!("error\n{e}"),
+            };
+            let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+            if let Err(e) = stream.write_all(reply.as_bytes()) {
+                warn!("unable to reply to IPC request: {e}");
+            }
+        }
+
+        Ok(false)
+    }
+}
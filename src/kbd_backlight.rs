@@ -0,0 +1,258 @@
+//! The keyboard backlight, through `/sys/class/leds/*kbd_backlight*`.
+//!
+//! Besides being stepped up and down from the keyboard, the backlight is turned off once the
+//! keyboard has been left alone for a while and put back as it was on the next key press.
+//! Key presses are picked up as XInput raw events on a connection of their own, so they are
+//! seen whichever client has focus.
+use crate::{
+    backlight::{read_u32, to_percent},
+    modal::x_err,
+    osd,
+    timer::notify_after,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Error, Result,
+};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xinput::{ConnectionExt, Device, EventMask, XIEventMask},
+        Event,
+    },
+};
+
+const LEDS: &str = "/sys/class/leds";
+const KBD_IDLE_CHECK: &str = "_WM_KBD_IDLE_CHECK";
+const KBD_WAKE: &str = "_WM_KBD_WAKE";
+
+/// When the key press watcher started, as [LAST_KEY] is counted from here.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+/// Milliseconds from [STARTED] to the last key press.
+static LAST_KEY: AtomicU64 = AtomicU64::new(0);
+/// Whether the backlight has been turned off for being idle, so the next key press should
+/// turn it back on.
+static DIMMED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy)]
+pub struct KbdBacklight {
+    /// How long the keyboard is left alone before the backlight goes off, or zero to leave it
+    /// on.
+    pub idle_timeout: Duration,
+}
+
+struct Led {
+    path: PathBuf,
+    max: u32,
+}
+
+impl Led {
+    fn all() -> Vec<Led> {
+        let entries = match fs::read_dir(LEDS) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut leds: Vec<Led> = entries
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains("kbd_backlight"))
+            .filter_map(|e| {
+                let path = e.path();
+                let max = read_u32(&path.join("max_brightness")).filter(|&m| m > 0)?;
+                Some(Led { path, max })
+            })
+            .collect();
+        leds.sort_by(|a, b| a.path.cmp(&b.path));
+
+        leds
+    }
+
+    fn brightness(&self) -> Option<u32> {
+        read_u32(&self.path.join("brightness"))
+    }
+
+    fn set(&self, raw: u32) {
+        if let Err(e) = fs::write(self.path.join("brightness"), raw.min(self.max).to_string()) {
+            warn!("unable to set {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// The level `delta` steps on from `current`, staying within `0..=max`.
+fn step(current: u32, delta: i8, max: u32) -> u32 {
+    (current as i64 + delta as i64).clamp(0, max as i64) as u32
+}
+
+fn since_last_key() -> Duration {
+    let started = match STARTED.get() {
+        Some(started) => started,
+        None => return Duration::ZERO,
+    };
+    let last = Duration::from_millis(LAST_KEY.load(Ordering::SeqCst));
+
+    started.elapsed().saturating_sub(last)
+}
+
+fn watch_keys() -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+    conn.xinput_xi_query_version(2, 0)
+        .map_err(x_err)?
+        .reply()
+        .map_err(x_err)?;
+    let mask = EventMask {
+        deviceid: Device::ALL_MASTER.into(),
+        mask: vec![XIEventMask::RAW_KEY_PRESS.into()],
+    };
+    conn.xinput_xi_select_events(root, &[mask])
+        .map_err(x_err)?
+        .check()
+        .map_err(x_err)?;
+    let started = STARTED.get_or_init(Instant::now);
+
+    loop {
+        if let Event::XinputRawKeyPress(_) = conn.wait_for_event().map_err(x_err)? {
+            LAST_KEY.store(started.elapsed().as_millis() as u64, Ordering::SeqCst);
+            if DIMMED.swap(false, Ordering::SeqCst) {
+                notify_after(Duration::ZERO, KBD_WAKE, [0; 5]);
+            }
+        }
+    }
+}
+
+/// The level to put back once the keyboard is used again, if the backlight has been turned
+/// off for being idle.
+#[derive(Debug, Default, Clone, Copy)]
+struct Dimmed {
+    from: Option<u32>,
+}
+
+/// Turns the keyboard backlight off once the keyboard has been idle for long enough, and back
+/// on at the next key press.
+#[derive(Debug, Clone, Copy)]
+pub struct KbdBacklightHook {
+    config: KbdBacklight,
+}
+
+impl KbdBacklightHook {
+    pub fn new(config: KbdBacklight) -> Self {
+        Self { config }
+    }
+
+    /// Turn the backlight off if the keyboard has been idle long enough, returning how long to
+    /// wait before checking again.
+    fn check<X: XConn>(&mut self, state: &mut State<X>) -> Duration {
+        let timeout = self.config.idle_timeout;
+        let idle = since_last_key();
+        let dimmed = state.extension_or_default::<Dimmed>();
+        if dimmed.borrow().from.is_some() {
+            return timeout;
+        } else if idle < timeout {
+            return timeout - idle;
+        }
+
+        let leds = Led::all();
+        let level = leds.first().and_then(Led::brightness).unwrap_or(0);
+        if level > 0 {
+            dimmed.borrow_mut().from = Some(level);
+            DIMMED.store(true, Ordering::SeqCst);
+            for led in leds.iter() {
+                led.set(0);
+            }
+        }
+
+        timeout
+    }
+}
+
+impl<X: XConn> EventHook<X> for KbdBacklightHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == KBD_IDLE_CHECK => {
+                notify_after(self.check(state), KBD_IDLE_CHECK, [0; 5]);
+            }
+            XEvent::ClientMessage(msg) if msg.dtype == KBD_WAKE => {
+                let from = state
+                    .extension_or_default::<Dimmed>()
+                    .borrow_mut()
+                    .from
+                    .take();
+                if let Some(level) = from {
+                    for led in Led::all() {
+                        led.set(level);
+                    }
+                }
+            }
+            _ => return Ok(true),
+        }
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for KbdBacklightHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        if self.config.idle_timeout.is_zero() || Led::all().is_empty() {
+            return Ok(());
+        }
+
+        thread::spawn(|| {
+            if let Err(e) = watch_keys() {
+                error!("unable to watch for key presses: {e}");
+            }
+        });
+        notify_after(self.config.idle_timeout, KBD_IDLE_CHECK, [0; 5]);
+
+        Ok(())
+    }
+}
+
+/// Step the keyboard backlight up or down by `delta` levels.
+pub fn adjust_kbd_backlight<X: XConn>(delta: i8) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let leds = Led::all();
+        let first = match leds.first() {
+            Some(led) => led,
+            None => return Err(Error::Custom("no keyboard backlight found".into())),
+        };
+        let level = step(first.brightness().unwrap_or(0), delta, first.max);
+        // The key press waking the backlight up would otherwise undo this straight after.
+        state.extension_or_default::<Dimmed>().borrow_mut().from = None;
+        DIMMED.store(false, Ordering::SeqCst);
+        for led in leds.iter() {
+            led.set(level);
+        }
+        let percent = to_percent(level, first.max);
+
+        osd::show(state, &format!("Keyboard {percent}%"), Some(percent))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_stay_within_the_device_range() {
+        assert_eq!(step(1, 1, 3), 2);
+        assert_eq!(step(3, 1, 3), 3);
+        assert_eq!(step(0, -1, 3), 0);
+        assert_eq!(step(2, -1, 3), 1);
+    }
+}
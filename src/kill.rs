@@ -0,0 +1,139 @@
+//! Closing clients, politely at first.
+use crate::{
+    modal::{input_from_data, read_in_background, x_err, Input, KeyboardGrab},
+    timer::notify_after,
+    titlebar::TitlebarOwners,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::EventHook, State},
+    x::{ClientMessageKind, Prop, XConn, XEvent},
+    Result, Xid,
+};
+use std::time::Duration;
+use tracing::info;
+use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+const FORCE_KILL: &str = "_WM_FORCE_KILL";
+
+const KILL_MODE_INPUT: &str = "_WM_KILL_MODE_INPUT";
+/// How long kill mode waits for a click before giving up.
+const KILL_MODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// XC_pirate from the X cursor font.
+const KILL_CURSOR: u16 = 88;
+
+fn supports_delete<X: XConn>(x: &X, id: Xid) -> bool {
+    match x.get_prop(id, "WM_PROTOCOLS") {
+        Ok(Some(Prop::Atom(protocols))) => protocols.iter().any(|p| p == "WM_DELETE_WINDOW"),
+        _ => false,
+    }
+}
+
+/// Forcibly disconnect the X client owning `id` via XKillClient.
+pub fn force_kill(id: Xid) -> Result<()> {
+    let (conn, _) = x11rb::connect(None).map_err(x_err)?;
+    conn.kill_client(*id).map_err(x_err)?;
+    conn.flush().map_err(x_err)
+}
+
+/// Ask `id` to close via WM_DELETE_WINDOW, escalating to XKillClient if it is still around
+/// after `timeout` (or straight away if it doesn't support the protocol).
+pub fn close_client<X: XConn>(id: Xid, timeout: Duration, x: &X) -> Result<()> {
+    if !supports_delete(x, id) {
+        return force_kill(id);
+    }
+
+    x.send_client_message(ClientMessageKind::DeleteWindow(id).as_message(x)?)?;
+    notify_after(timeout, FORCE_KILL, [*id, 0, 0, 0, 0]);
+
+    Ok(())
+}
+
+/// Close the focused client as per [close_client].
+pub fn kill_focused<X: XConn>(timeout: Duration) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(
+        move |state: &mut State<X>, x: &X| match state.client_set.current_client() {
+            Some(&id) => close_client(id, timeout, x),
+            None => Ok(()),
+        },
+    )
+}
+
+/// Whether kill mode is waiting for a click.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KillMode {
+    active: bool,
+}
+
+/// Turn the pointer into a kill cursor and force kill whichever client is clicked next, as
+/// with xkill, via [KillModeHook]. Clicking a titlebar kills the client it belongs to.
+/// Clicking anything other than a client, pressing a key or leaving it for
+/// [KILL_MODE_TIMEOUT] cancels.
+pub fn kill_mode<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let ext = state.extension_or_default::<KillMode>();
+        if ext.borrow().active {
+            return Ok(());
+        }
+
+        let grab = KeyboardGrab::with_pointer(KILL_CURSOR)?;
+        read_in_background(grab, KILL_MODE_INPUT, KILL_MODE_TIMEOUT);
+        ext.borrow_mut().active = true;
+
+        Ok(())
+    })
+}
+
+/// Kills the client clicked on in [kill_mode].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KillModeHook;
+
+impl<X: XConn> EventHook<X> for KillModeHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == KILL_MODE_INPUT => msg,
+            _ => return Ok(true),
+        };
+        state.extension_or_default::<KillMode>().borrow_mut().active = false;
+
+        let win = match input_from_data(&msg.data.as_usize()) {
+            Some(Input::Click(win)) => Xid::from(win),
+            _ => return Ok(false),
+        };
+        let id = state
+            .extension_or_default::<TitlebarOwners>()
+            .borrow()
+            .client_for(win);
+        if state.client_set.contains(&id) {
+            info!("killing client {id} from kill mode");
+            force_kill(id)?;
+        }
+
+        Ok(false)
+    }
+}
+
+/// Kills clients that ignored a [close_client] request once their timeout expires.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ForceKillHook;
+
+impl<X: XConn> EventHook<X> for ForceKillHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == FORCE_KILL => msg,
+            _ => return Ok(true),
+        };
+
+        let id = match msg.data.as_usize().first() {
+            Some(&id) => Xid::from(id as u32),
+            None => return Ok(false),
+        };
+        if state.client_set.contains(&id) {
+            info!("client {id} ignored WM_DELETE_WINDOW: killing it");
+            force_kill(id)?;
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,90 @@
+//! Choosing layouts to suit the output a tag is shown on.
+//!
+//! Whenever a tag is shown on a different output from the one it was last laid out on, its
+//! available layouts are replaced with those configured for the new output, starting from the
+//! first. Showing a tag again on the same output leaves whichever layout was picked there
+//! alone.
+use crate::monitor::{active_outputs, Output, RANDR_SETTLED};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        layout::LayoutStack,
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Result,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+#[derive(Debug, Default)]
+struct Placed {
+    /// The active outputs as of the last time the RandR setup settled.
+    outputs: Vec<Output>,
+    /// The output each tag was last laid out on, by tag.
+    tags: HashMap<String, String>,
+}
+
+/// Gives tags the layouts returned by `layouts(output, tag)` for the output they are shown on.
+#[derive(Clone)]
+pub struct OutputLayoutsHook {
+    layouts: fn(&str, &str) -> LayoutStack,
+    placed: Rc<RefCell<Placed>>,
+}
+
+impl OutputLayoutsHook {
+    pub fn new(layouts: fn(&str, &str) -> LayoutStack) -> Self {
+        Self {
+            layouts,
+            placed: Rc::default(),
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for OutputLayoutsHook {
+    fn call(&mut self, event: &XEvent, _: &mut State<X>, _: &X) -> Result<bool> {
+        if let XEvent::ClientMessage(msg) = event {
+            if msg.dtype == RANDR_SETTLED {
+                self.placed.borrow_mut().outputs = active_outputs()?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for OutputLayoutsHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let mut placed = self.placed.borrow_mut();
+        if placed.outputs.is_empty() {
+            placed.outputs = active_outputs()?;
+        }
+
+        let cs = &state.client_set;
+        let mut moved = Vec::new();
+        for s in cs.screens() {
+            let r = s.geometry();
+            let output = match placed.outputs.iter().find(|o| (o.r.x, o.r.y) == (r.x, r.y)) {
+                Some(o) => &o.name,
+                None => continue,
+            };
+            let tag = s.workspace.tag();
+            if placed.tags.get(tag) != Some(output) {
+                moved.push((tag.to_string(), output.clone()));
+            }
+        }
+
+        if moved.is_empty() {
+            return Ok(());
+        }
+        for (tag, output) in moved {
+            if let Some(ws) = state.client_set.workspace_mut(&tag) {
+                ws.set_available_layouts((self.layouts)(&output, &tag));
+            }
+            placed.tags.insert(tag, output);
+        }
+        drop(placed);
+
+        // The screens have already been laid out with the old layouts by this point.
+        x.refresh(state)
+    }
+}
@@ -0,0 +1,79 @@
+//! A window manager built on penrose, as a library of hooks, layouts and actions that the
+//! `wm` binary puts together.
+//!
+//! [config] holds the settings and puts the hooks together into a penrose config, and
+//! [bindings] the key and mouse bindings. [hooks] gathers every hook in one place for
+//! putting together a different config out of the same parts.
+pub mod activation;
+pub mod audio;
+pub mod autostart;
+pub mod backlight;
+pub mod bar;
+pub mod batch;
+pub mod battery;
+pub mod bindings;
+pub mod bluetooth;
+pub mod borders;
+pub mod client;
+pub mod compositor;
+pub mod config;
+pub mod conflicts;
+pub mod corners;
+pub mod daemons;
+pub mod direction;
+pub mod dpi;
+pub mod dump;
+pub mod embed;
+pub mod ewmh;
+pub mod floating;
+pub mod focus;
+pub mod gaming;
+pub mod hidden;
+pub mod hooks;
+pub mod idle;
+pub mod ipc;
+pub mod kbd_backlight;
+pub mod kill;
+pub mod layouts;
+pub mod lid;
+pub mod logging;
+pub mod logind;
+pub mod marks;
+pub mod maximize;
+pub mod menu;
+pub mod migration;
+#[cfg(test)]
+pub mod mock;
+pub mod modal;
+pub mod monitor;
+pub mod mouse;
+pub mod mpris;
+pub mod network;
+pub mod nightlight;
+pub mod opacity;
+pub mod osd;
+pub mod panics;
+pub mod passthrough;
+pub mod paths;
+pub mod pip;
+pub mod power;
+pub mod presentation;
+pub mod process;
+pub mod profiles;
+pub mod restart;
+pub mod schedule;
+pub mod screens;
+pub mod screensaver;
+pub mod selection;
+pub mod sleep;
+pub mod spans;
+pub mod stacking;
+pub mod startup;
+pub mod sticky;
+pub mod struts;
+pub mod swallow;
+pub mod systemd;
+pub mod timer;
+pub mod titlebar;
+pub mod urgency;
+pub mod wallpaper;
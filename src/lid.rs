@@ -0,0 +1,221 @@
+//! Deciding what closing the laptop lid should do.
+//!
+//! logind can only be told one thing to do for each of a few fixed situations, so instead the
+//! window manager takes logind's `handle-lid-switch` inhibitor and acts on the lid itself. It
+//! knows whether any other monitors are in use and can turn off just the built in panel
+//! rather than suspending.
+use crate::{
+    battery::on_battery,
+    logind::{self, suspend},
+    monitor::{close_gaps, OutputSetup, Randr},
+    process::run,
+    sleep,
+    timer::notify_after,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{cell::RefCell, rc::Rc, thread, time::Duration};
+use tracing::{error, info};
+use zbus::{blocking::Connection, zvariant::OwnedFd};
+
+/// Sent with 1 in the first data field when the lid is closed and 0 when it is opened.
+const LID_SWITCH: &str = "_WM_LID_SWITCH";
+
+/// Something to do when the lid is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LidAction {
+    /// Leave everything as it is.
+    Nothing,
+    /// Turn off the built in panel until the lid is opened again.
+    TurnOffPanel,
+    /// Run the locker.
+    Lock,
+    /// Suspend without locking.
+    Suspend,
+    /// Run the locker and suspend once it has grabbed the keyboard.
+    LockAndSuspend,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LidSwitch {
+    /// The name of the laptop's built in output.
+    pub panel: &'static str,
+    /// The command to lock the screen with.
+    pub locker: &'static str,
+    /// What to do while other monitors are in use.
+    pub docked: LidAction,
+    /// What to do when there are no other monitors and the laptop is plugged in.
+    pub on_ac: LidAction,
+    /// What to do when there are no other monitors and the laptop is running on battery.
+    pub on_battery: LidAction,
+}
+
+impl LidSwitch {
+    fn action(&self, docked: bool, on_battery: bool) -> LidAction {
+        match (docked, on_battery) {
+            (true, _) => self.docked,
+            (false, true) => self.on_battery,
+            (false, false) => self.on_ac,
+        }
+    }
+}
+
+fn watch_lid() -> zbus::Result<()> {
+    let conn = Connection::system()?;
+    let logind = logind::manager(&conn)?;
+    // logind leaves the lid alone for as long as this is held.
+    let _inhibitor: OwnedFd = logind.call(
+        "Inhibit",
+        &(
+            "handle-lid-switch",
+            "wm",
+            "The window manager handles the lid",
+            "block",
+        ),
+    )?;
+
+    for change in logind.receive_property_changed::<bool>("LidClosed") {
+        let closed = change.get()?;
+        notify_after(Duration::ZERO, LID_SWITCH, [closed as u32, 0, 0, 0, 0]);
+    }
+
+    Ok(())
+}
+
+/// Acts on the lid being closed and opened according to a [LidSwitch].
+#[derive(Debug, Clone)]
+pub struct LidHook {
+    config: LidSwitch,
+    /// The outputs as they were before the panel was turned off.
+    restore: Rc<RefCell<Option<Vec<OutputSetup>>>>,
+}
+
+impl LidHook {
+    pub fn new(config: LidSwitch) -> Self {
+        Self {
+            config,
+            restore: Rc::default(),
+        }
+    }
+
+    fn closed(&self) -> Result<()> {
+        let randr = Randr::connect()?;
+        let docked = randr
+            .active_outputs()?
+            .iter()
+            .any(|o| o.name != self.config.panel);
+        let action = self.config.action(docked, on_battery());
+        info!("lid closed: {action:?}");
+
+        match action {
+            LidAction::Nothing => Ok(()),
+            LidAction::TurnOffPanel => self.turn_off_panel(&randr),
+            LidAction::Lock => run(self.config.locker),
+            LidAction::Suspend => suspend(),
+            LidAction::LockAndSuspend => {
+                // Waiting on the locker blocks, so it happens away from the event loop.
+                let locker = self.config.locker;
+                thread::spawn(move || {
+                    if let Err(e) = sleep::lock(locker).and_then(|_| suspend()) {
+                        error!("unable to lock and suspend: {e}");
+                    }
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn turn_off_panel(&self, randr: &Randr) -> Result<()> {
+        let current = randr.current_setups()?;
+        let mut setups = current.clone();
+        for s in setups.iter_mut().filter(|s| s.name == self.config.panel) {
+            s.mode = None;
+        }
+        if setups.iter().all(|s| s.mode.is_none()) {
+            return Ok(());
+        }
+        close_gaps(&mut setups);
+
+        if randr.apply(&setups)? {
+            *self.restore.borrow_mut() = Some(current);
+        }
+
+        Ok(())
+    }
+
+    fn opened(&self) -> Result<()> {
+        let restore = match self.restore.borrow_mut().take() {
+            Some(restore) => restore,
+            None => return Ok(()),
+        };
+        let randr = Randr::connect()?;
+        // Unplugging a monitor while the lid was closed will already have brought the panel
+        // back.
+        if randr
+            .active_outputs()?
+            .iter()
+            .any(|o| o.name == self.config.panel)
+        {
+            return Ok(());
+        }
+
+        info!("lid opened: restoring {}", self.config.panel);
+        randr.apply(&restore)?;
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for LidHook {
+    fn call(&mut self, event: &XEvent, _: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == LID_SWITCH => {
+                if msg.data.as_usize().first() == Some(&1) {
+                    self.closed()?;
+                } else {
+                    self.opened()?;
+                }
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for LidHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        thread::spawn(|| {
+            if let Err(e) = watch_lid() {
+                error!("unable to watch the lid switch: {e}");
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_monitors_take_precedence_over_power() {
+        let lid = LidSwitch {
+            panel: "eDP-1",
+            locker: "xsecurelock",
+            docked: LidAction::TurnOffPanel,
+            on_ac: LidAction::Lock,
+            on_battery: LidAction::LockAndSuspend,
+        };
+
+        assert_eq!(lid.action(true, true), LidAction::TurnOffPanel);
+        assert_eq!(lid.action(false, false), LidAction::Lock);
+        assert_eq!(lid.action(false, true), LidAction::LockAndSuspend);
+    }
+}
@@ -0,0 +1,231 @@
+//! Logging to stderr and to `$XDG_STATE_HOME/wm/wm.log`.
+//!
+//! The log file is rotated once it reaches [MAX_LOG_SIZE], keeping the last few as `wm.log.1`
+//! and so on, so that there is something to look at after the fact without it growing
+//! forever. Debug logging for the window manager itself can be turned on and off while it
+//! runs, from a key binding or with `wmcli log-level debug`.
+//!
+//! `wm --profile` also turns on debug logging, and writes the [spans](crate::spans)
+//! to `$XDG_STATE_HOME/wm/profile-<time>.json` as a Chrome trace. The trace is finished off
+//! when the window manager exits or restarts, and a restart starts a new one.
+use crate::{modal::x_err, osd, paths::state_file};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, State},
+    x::XConn,
+    Error, Result,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+/// How many rotated logs to keep alongside the current one.
+const KEEP_LOGS: usize = 3;
+const INFO: &str = "info";
+/// Debug logging for the window manager, leaving its dependencies at info.
+const DEBUG: &str = "info,wm=debug";
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static DEBUGGING: AtomicBool = AtomicBool::new(false);
+/// Finishes writing the profile when dropped.
+static PROFILE: Mutex<Option<FlushGuard>> = Mutex::new(None);
+
+/// The `n`th most recent rotated log for `path`, e.g. `wm.log.1`.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{n}"));
+
+    path.with_file_name(name)
+}
+
+/// A log file that is rotated once writing to it would take it past `max_size`.
+struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+}
+
+impl RotatingLog {
+    fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..KEEP_LOGS).rev() {
+            // Older logs may not exist yet.
+            let _ = fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1));
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Start logging at info, to stderr and to the log file if it can be opened. With `profile`
+/// set, log at debug and write a profile as well.
+pub fn init(profile: bool) {
+    let level = if profile { DEBUG } else { INFO };
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(level));
+    let _ = FILTER.set(handle);
+    DEBUGGING.store(profile, Ordering::Relaxed);
+    let mut errors = Vec::new();
+
+    let path = state_file("wm.log");
+    let file_layer = match RotatingLog::open(path.clone(), MAX_LOG_SIZE) {
+        Ok(log) => Some(fmt::layer().with_ansi(false).with_writer(Mutex::new(log))),
+        Err(e) => {
+            errors.push(format!("unable to open {}: {e}", path.display()));
+            None
+        }
+    };
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let profile_path = state_file(&format!("profile-{secs}.json"));
+    let profile_layer = match profile.then(|| File::create(&profile_path)) {
+        Some(Ok(file)) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .writer(file)
+                .include_args(true)
+                .build();
+            if let Ok(mut profile) = PROFILE.lock() {
+                *profile = Some(guard);
+            }
+            Some(layer)
+        }
+        Some(Err(e)) => {
+            errors.push(format!("unable to open {}: {e}", profile_path.display()));
+            None
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(file_layer)
+        .with(profile_layer)
+        .init();
+
+    for e in errors {
+        warn!("{e}");
+    }
+    if profile {
+        info!("writing a profile to {}", profile_path.display());
+    }
+}
+
+/// Finish writing the profile, if there is one, as it isn't otherwise finished off when
+/// exiting.
+pub fn finish_profile() {
+    if let Some(guard) = PROFILE.lock().ok().and_then(|mut p| p.take()) {
+        drop(guard);
+    }
+}
+
+fn set_debug(on: bool) -> Result<()> {
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| Error::Custom("logging isn't set up".into()))?;
+    handle
+        .reload(EnvFilter::new(if on { DEBUG } else { INFO }))
+        .map_err(x_err)?;
+    DEBUGGING.store(on, Ordering::Relaxed);
+    info!("debug logging {}", if on { "on" } else { "off" });
+
+    Ok(())
+}
+
+/// Turn debug logging on or off.
+pub fn toggle_debug_logging<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let on = !DEBUGGING.load(Ordering::Relaxed);
+        set_debug(on)?;
+        let label = if on {
+            "Debug logging on"
+        } else {
+            "Debug logging off"
+        };
+
+        osd::show(state, label, None)
+    })
+}
+
+/// `wmcli log-level [debug | info]`: set the log level, or show it with no arguments.
+pub fn log_level_command<X: XConn>(args: &[String], _: &mut State<X>, _: &X) -> Result<String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] if DEBUGGING.load(Ordering::Relaxed) => Ok("debug".into()),
+        [] => Ok("info".into()),
+        ["debug"] => set_debug(true).map(|_| "debug".into()),
+        ["info"] => set_debug(false).map(|_| "info".into()),
+        _ => Err(Error::Custom("usage: log-level [debug | info]".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_logs_are_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("wm-logging-{}", std::process::id()));
+        let path = dir.join("wm.log");
+        let mut log = RotatingLog::open(path.clone(), 8).unwrap();
+
+        log.write_all(b"first\n").unwrap();
+        log.write_all(b"second\n").unwrap();
+        log.write_all(b"third\n").unwrap();
+
+        let read = |p: &Path| fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "third\n");
+        assert_eq!(read(&rotated(&path, 1)), "second\n");
+        assert_eq!(read(&rotated(&path, 2)), "first\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+//! Talking to systemd-logind on the system bus.
+use crate::modal::x_err;
+use penrose::Result;
+use zbus::blocking::{Connection, Proxy};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+/// The `org.freedesktop.login1.Manager` object.
+pub fn manager(conn: &Connection) -> zbus::Result<Proxy<'_>> {
+    Proxy::new(conn, LOGIND, LOGIND_PATH, LOGIND_MANAGER)
+}
+
+/// Ask logind to suspend the machine.
+pub fn suspend() -> Result<()> {
+    let conn = Connection::system().map_err(x_err)?;
+    manager(&conn)
+        .and_then(|logind| logind.call::<_, ()>("Suspend", &(false,)))
+        .map_err(x_err)
+}
@@ -0,0 +1,144 @@
+//! Vim style marks for jumping back to specific clients.
+use crate::{
+    bar,
+    focus::key_driven,
+    hidden::on_regular_tag,
+    modal::{input_from_data, read_in_background, Input, Key, KeyboardGrab},
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::{collections::HashMap, time::Duration};
+
+const MARK_KEY: &str = "_WM_MARK_KEY";
+/// How long to wait for a mark letter before giving up on it.
+const MARK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do with the mark letter being waited for.
+#[derive(Debug, Clone, Copy)]
+enum PendingMark {
+    Set(Xid),
+    Jump,
+}
+
+/// Clients that have been marked, keyed by the letter used for the mark.
+#[derive(Debug, Default, Clone)]
+pub struct Marks {
+    marks: HashMap<char, Xid>,
+    pending: Option<PendingMark>,
+}
+
+impl Marks {
+    /// The mark currently set on `id`, if any.
+    pub fn mark_for(&self, id: &Xid) -> Option<char> {
+        self.marks
+            .iter()
+            .find_map(|(&c, client)| (client == id).then_some(c))
+    }
+}
+
+/// The mark letter for `key`, or `None` for anything other than a letter.
+fn mark_letter(key: Key) -> Option<char> {
+    let name = key.name?;
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// Start reading a mark letter for [MarksHook] to act on, unless one is already being read.
+fn read_mark<X: XConn>(pending: PendingMark, state: &mut State<X>) -> Result<()> {
+    let ext = state.extension_or_default::<Marks>();
+    if ext.borrow().pending.is_some() {
+        return Ok(());
+    }
+
+    read_in_background(KeyboardGrab::new()?, MARK_KEY, MARK_TIMEOUT);
+    ext.borrow_mut().pending = Some(pending);
+
+    Ok(())
+}
+
+/// Wait for a letter and mark the focused client with it, replacing any existing mark that
+/// used the same letter or was set on the same client.
+pub fn set_mark<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(
+        |state: &mut State<X>, _: &X| match state.client_set.current_client() {
+            Some(&id) => read_mark(PendingMark::Set(id), state),
+            None => Ok(()),
+        },
+    )
+}
+
+/// Wait for a letter and focus the client marked with it, switching tag and screen as needed.
+pub fn jump_to_mark<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| read_mark(PendingMark::Jump, state))
+}
+
+/// Drops marks for clients that have gone away and publishes the focused client's mark, and
+/// acts on the letters read for [set_mark] and [jump_to_mark].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MarksHook;
+
+impl<X: XConn> EventHook<X> for MarksHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == MARK_KEY => msg,
+            _ => return Ok(true),
+        };
+
+        let ext = state.extension_or_default::<Marks>();
+        let pending = ext.borrow_mut().pending.take();
+        let c = match input_from_data(&msg.data.as_usize()) {
+            Some(Input::Key(key)) => mark_letter(key),
+            _ => None,
+        };
+
+        match (pending, c) {
+            (Some(PendingMark::Set(id)), Some(c)) if state.client_set.contains(&id) => {
+                {
+                    let mut marks = ext.borrow_mut();
+                    marks.marks.retain(|_, client| *client != id);
+                    marks.marks.insert(c, id);
+                }
+                x.refresh(state)?;
+            }
+            (Some(PendingMark::Jump), Some(c)) => {
+                let target = ext.borrow().marks.get(&c).copied();
+                if let Some(id) = target.filter(|id| on_regular_tag(state, id)) {
+                    key_driven(state, |state| {
+                        x.modify_and_refresh(state, |cs| cs.focus_client(&id))
+                    })?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for MarksHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Marks>();
+        ext.borrow_mut()
+            .marks
+            .retain(|_, id| state.client_set.contains(id));
+
+        let mark = match state.client_set.current_client() {
+            Some(id) => ext.borrow().mark_for(id),
+            None => None,
+        };
+        bar::publish(state, "mark", mark.map(String::from).unwrap_or_default());
+
+        Ok(())
+    }
+}
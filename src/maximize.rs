@@ -0,0 +1,228 @@
+//! Maximizing clients to fill their screen vertically, horizontally or both.
+//!
+//! Clients ask for this with _NET_WM_STATE_MAXIMIZED_VERT and _NET_WM_STATE_MAXIMIZED_HORZ,
+//! usually from a double click on a client side title bar. A maximized client is floated over
+//! the space its screen has left once docks are accounted for, and put back where it was,
+//! tiled or floating, when it is no longer maximized in either direction. Tiling a maximized
+//! client by hand drops it from being maximized.
+use crate::{
+    client::{net_wm_state, requested_state, set_net_wm_state},
+    struts::usable_area,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    pure::geometry::Rect,
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::collections::HashMap;
+use tracing::warn;
+
+const NET_WM_STATE_MAXIMIZED_VERT: &str = "_NET_WM_STATE_MAXIMIZED_VERT";
+const NET_WM_STATE_MAXIMIZED_HORZ: &str = "_NET_WM_STATE_MAXIMIZED_HORZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Maximize {
+    /// Where the client was floating before being maximized, or `None` if it was tiled.
+    restore: Option<Rect>,
+    vert: bool,
+    horz: bool,
+}
+
+/// Clients currently maximized in at least one direction.
+#[derive(Debug, Default, Clone)]
+pub struct Maximized {
+    clients: HashMap<Xid, Maximize>,
+}
+
+impl Maximized {
+    /// Whether `id` is maximized vertically and horizontally.
+    fn directions(&self, id: &Xid) -> (bool, bool) {
+        self.clients
+            .get(id)
+            .map_or((false, false), |m| (m.vert, m.horz))
+    }
+}
+
+/// `base` stretched across `area` in the maximized directions.
+fn maximized(base: Rect, area: Rect, vert: bool, horz: bool) -> Rect {
+    let (y, h) = if vert {
+        (area.y, area.h)
+    } else {
+        (base.y, base.h)
+    };
+    let (x, w) = if horz {
+        (area.x, area.w)
+    } else {
+        (base.x, base.w)
+    };
+
+    Rect::new(x, y, w, h)
+}
+
+/// Maximize `id` vertically and horizontally or not, keeping its _NET_WM_STATE in sync.
+pub fn set_maximized<X: XConn>(
+    id: Xid,
+    vert: bool,
+    horz: bool,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let ext = state.extension_or_default::<Maximized>();
+    let current = ext.borrow().clients.get(&id).copied();
+    if ext.borrow().directions(&id) == (vert, horz) {
+        return Ok(());
+    }
+    set_net_wm_state(x, id, NET_WM_STATE_MAXIMIZED_VERT, vert)?;
+    set_net_wm_state(x, id, NET_WM_STATE_MAXIMIZED_HORZ, horz)?;
+
+    if !vert && !horz {
+        ext.borrow_mut().clients.remove(&id);
+        let restore = current.and_then(|m| m.restore);
+        return x.modify_and_refresh(state, |cs| match restore {
+            Some(r) => {
+                if let Err(e) = cs.float(id, r) {
+                    warn!("unable to restore client {id}: {e}");
+                }
+            }
+            None => {
+                cs.sink(&id);
+            }
+        });
+    }
+
+    let geometry = x.client_geometry(id)?;
+    let restore = match current {
+        Some(m) => m.restore,
+        None if state.client_set.is_floating(&id) => Some(geometry),
+        None => None,
+    };
+    ext.borrow_mut().clients.insert(
+        id,
+        Maximize {
+            restore,
+            vert,
+            horz,
+        },
+    );
+
+    let screen = match state.client_set.screen_for_client(&id) {
+        Some(s) => s.geometry(),
+        None => state.client_set.current_screen().geometry(),
+    };
+    let r = maximized(restore.unwrap_or(geometry), usable_area(screen), vert, horz);
+
+    x.modify_and_refresh(state, |cs| {
+        if let Err(e) = cs.float(id, r) {
+            warn!("unable to maximize client {id}: {e}");
+        }
+    })
+}
+
+/// Maximizes clients that ask for it, both at map time and via client messages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MaximizeHook;
+
+impl<X: XConn> EventHook<X> for MaximizeHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if state.client_set.contains(&msg.id) => msg,
+            _ => return Ok(true),
+        };
+
+        let (vert, horz) = state
+            .extension_or_default::<Maximized>()
+            .borrow()
+            .directions(&msg.id);
+        let new_vert = requested_state(state, x, msg, NET_WM_STATE_MAXIMIZED_VERT, vert)?;
+        let new_horz = requested_state(state, x, msg, NET_WM_STATE_MAXIMIZED_HORZ, horz)?;
+        if new_vert.is_some() || new_horz.is_some() {
+            set_maximized(
+                msg.id,
+                new_vert.unwrap_or(vert),
+                new_horz.unwrap_or(horz),
+                state,
+                x,
+            )?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for MaximizeHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let atoms = net_wm_state(x, client)?;
+        let vert = atoms.iter().any(|a| a == NET_WM_STATE_MAXIMIZED_VERT);
+        let horz = atoms.iter().any(|a| a == NET_WM_STATE_MAXIMIZED_HORZ);
+        if !vert && !horz {
+            return Ok(());
+        }
+
+        let screen = state.client_set.current_screen().geometry();
+        let r = maximized(x.client_geometry(client)?, usable_area(screen), vert, horz);
+        state.client_set.float(client, r)?;
+        state
+            .extension_or_default::<Maximized>()
+            .borrow_mut()
+            .clients
+            .insert(
+                client,
+                Maximize {
+                    restore: None,
+                    vert,
+                    horz,
+                },
+            );
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for MaximizeHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Maximized>();
+        let cs = &state.client_set;
+        let tiled: Vec<Xid> = ext
+            .borrow()
+            .clients
+            .keys()
+            .filter(|id| cs.contains(id) && !cs.is_floating(id))
+            .copied()
+            .collect();
+        ext.borrow_mut()
+            .clients
+            .retain(|id, _| cs.contains(id) && cs.is_floating(id));
+
+        for id in tiled {
+            set_net_wm_state(x, id, NET_WM_STATE_MAXIMIZED_VERT, false)?;
+            set_net_wm_state(x, id, NET_WM_STATE_MAXIMIZED_HORZ, false)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_maximized_directions_fill_the_area() {
+        let base = Rect::new(100, 200, 800, 600);
+        let area = Rect::new(0, 30, 1920, 1050);
+
+        assert_eq!(
+            maximized(base, area, true, false),
+            Rect::new(100, 30, 800, 1050)
+        );
+        assert_eq!(
+            maximized(base, area, false, true),
+            Rect::new(0, 200, 1920, 600)
+        );
+        assert_eq!(maximized(base, area, true, true), area);
+    }
+}
@@ -0,0 +1,144 @@
+//! Picking from a list of choices using dmenu.
+//!
+//! dmenu is waited on from a thread of its own so that the event loop carries on as normal
+//! while it is open, with the choice handed back to it as a ClientMessage for [MenuHook] to
+//! act on.
+use crate::{focus::key_driven, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    process::{Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+use tracing::warn;
+
+const MENU_CHOICE: &str = "_WM_MENU_CHOICE";
+
+/// What to do with the index of the entry picked from a menu.
+type OnSelect<X> = Box<dyn FnOnce(usize, &mut State<X>, &X) -> Result<()>>;
+
+/// Menus that are open, by the id sent back along with their choice.
+pub struct Menus<X: XConn> {
+    open: HashMap<u32, OnSelect<X>>,
+    next_id: u32,
+}
+
+impl<X: XConn> Default for Menus<X> {
+    fn default() -> Self {
+        Self {
+            open: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+/// The index of the chosen entry given what dmenu printed, if it matched one.
+fn chosen(choices: &[String], output: &[u8]) -> Option<usize> {
+    let selected = String::from_utf8_lossy(output);
+    let selected = selected.trim_end_matches('\n');
+
+    choices.iter().position(|c| c == selected)
+}
+
+fn wait_for_choice(mut child: Child, choices: &[String]) -> io::Result<Option<usize>> {
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(choices.join("\n").as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+
+    Ok(chosen(choices, &output.stdout))
+}
+
+/// Show `choices` in dmenu and, once an entry has been picked, run `on_select` with its index
+/// from [MenuHook]. Nothing is run if the menu is dismissed (or the input doesn't match any
+/// entry).
+pub fn select<X, F>(
+    prompt: &str,
+    choices: Vec<String>,
+    state: &mut State<X>,
+    on_select: F,
+) -> Result<()>
+where
+    X: XConn + 'static,
+    F: FnOnce(usize, &mut State<X>, &X) -> Result<()> + 'static,
+{
+    if choices.is_empty() {
+        return Ok(());
+    }
+
+    let child = Command::new("dmenu")
+        .args(["-i", "-l", "10", "-p", prompt])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let ext = state.extension_or_default::<Menus<X>>();
+    let id = {
+        let mut menus = ext.borrow_mut();
+        let id = menus.next_id;
+        menus.next_id = id.wrapping_add(1);
+        menus.open.insert(id, Box::new(on_select));
+        id
+    };
+
+    thread::spawn(move || {
+        let choice = match wait_for_choice(child, &choices) {
+            Ok(choice) => choice,
+            Err(e) => {
+                warn!("unable to read the choice from dmenu: {e}");
+                None
+            }
+        };
+        // 0 is no choice, so the index is sent one up.
+        let choice = choice.map_or(0, |ix| ix as u32 + 1);
+        notify_after(Duration::ZERO, MENU_CHOICE, [id, choice, 0, 0, 0]);
+    });
+
+    Ok(())
+}
+
+/// Runs whatever was waiting on the choice made in a menu opened with [select].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MenuHook;
+
+impl<X: XConn + 'static> EventHook<X> for MenuHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == MENU_CHOICE => msg,
+            _ => return Ok(true),
+        };
+
+        let data = msg.data.as_usize();
+        let on_select = state
+            .extension_or_default::<Menus<X>>()
+            .borrow_mut()
+            .open
+            .remove(&(data[0] as u32));
+        if let (Some(on_select), Some(ix)) = (on_select, data[1].checked_sub(1)) {
+            // Menus are only ever driven from the keyboard.
+            key_driven(state, |state| on_select(ix, state, x))?;
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_whole_entries_are_chosen() {
+        let choices = vec!["one".to_string(), "two".to_string()];
+
+        assert_eq!(chosen(&choices, b"two\n"), Some(1));
+        assert_eq!(chosen(&choices, b"tw\n"), None);
+        assert_eq!(chosen(&choices, b""), None);
+    }
+}
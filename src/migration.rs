@@ -0,0 +1,150 @@
+//! Keeping tags where they belong as monitors come and go.
+//!
+//! Penrose simply drops the screens of outputs that disappear, hiding whatever they were
+//! showing. Instead, the tag that had focus follows the user onto a remaining screen, and the
+//! tag each lost output was showing is remembered so that it is put back there if the output
+//! returns.
+use crate::monitor::{active_outputs, screen_for_output, Output, RANDR_SETTLED};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Result,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+#[derive(Debug, Default)]
+struct Arrangement {
+    /// The active outputs as of the last time the RandR setup settled.
+    outputs: Vec<Output>,
+    /// The tag shown on each of those outputs, by output name.
+    shown: HashMap<String, String>,
+    /// The output of the focused screen.
+    focused: Option<String>,
+    /// The tags that outputs which have since disappeared were showing.
+    away: HashMap<String, String>,
+}
+
+impl Arrangement {
+    /// Record which tag each output is showing, provided that penrose's screens line up with
+    /// the known outputs. They won't in between a monitor change and it settling.
+    fn record<X: XConn>(&mut self, state: &State<X>) {
+        let cs = &state.client_set;
+        let mut shown = HashMap::new();
+        let mut focused = None;
+
+        for s in cs.screens() {
+            let r = s.geometry();
+            let output = match self.outputs.iter().find(|o| (o.r.x, o.r.y) == (r.x, r.y)) {
+                Some(o) => o.name.clone(),
+                None => return,
+            };
+            if s.index() == cs.current_screen().index() {
+                focused = Some(output.clone());
+            }
+            if let Some(tag) = cs.tag_for_screen(s.index()) {
+                shown.insert(output, tag.to_string());
+            }
+        }
+
+        if shown.len() == self.outputs.len() {
+            self.shown = shown;
+            self.focused = focused;
+        }
+    }
+}
+
+/// Moves tags between screens as outputs disappear and reappear.
+#[derive(Debug, Default, Clone)]
+pub struct WorkspaceMigrationHook {
+    arrangement: Rc<RefCell<Arrangement>>,
+}
+
+impl<X: XConn> EventHook<X> for WorkspaceMigrationHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == RANDR_SETTLED => (),
+            _ => return Ok(true),
+        }
+
+        let outputs = active_outputs()?;
+        let (follow, restores) = {
+            let mut a = self.arrangement.borrow_mut();
+            let lost: Vec<String> = a
+                .outputs
+                .iter()
+                .filter(|o| !outputs.iter().any(|n| n.name == o.name))
+                .map(|o| o.name.clone())
+                .collect();
+            for name in lost.iter() {
+                if let Some(tag) = a.shown.remove(name) {
+                    a.away.insert(name.clone(), tag);
+                }
+            }
+
+            let follow = match &a.focused {
+                Some(f) if lost.contains(f) => a.away.get(f).cloned(),
+                _ => None,
+            };
+
+            let mut restores = Vec::new();
+            for o in outputs.iter() {
+                if a.outputs.iter().any(|known| known.name == o.name) {
+                    continue;
+                }
+                let screen = screen_for_output(state, o.r);
+                if let (Some(screen), Some(tag)) = (screen, a.away.remove(&o.name)) {
+                    restores.push((screen, tag));
+                }
+            }
+
+            a.outputs = outputs;
+            (follow, restores)
+        };
+
+        if follow.is_none() && restores.is_empty() {
+            return Ok(true);
+        }
+
+        x.modify_and_refresh(state, |cs| {
+            let current = cs.current_screen().index();
+            let focused_tag = follow
+                .clone()
+                .unwrap_or_else(|| cs.current_tag().to_string());
+
+            for (screen, tag) in restores.iter() {
+                cs.focus_screen(*screen);
+                cs.focus_tag(tag);
+            }
+
+            // Focus stays with the tag that had it, wherever that now is.
+            let showing = cs
+                .screens()
+                .map(|s| s.index())
+                .find(|&i| cs.tag_for_screen(i) == Some(focused_tag.as_str()));
+            match showing {
+                Some(i) => cs.focus_screen(i),
+                None => {
+                    cs.focus_screen(current);
+                    cs.focus_tag(&focused_tag);
+                }
+            }
+        })?;
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for WorkspaceMigrationHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let mut a = self.arrangement.borrow_mut();
+        if a.outputs.is_empty() {
+            a.outputs = active_outputs()?;
+        }
+        a.record(state);
+
+        Ok(())
+    }
+}
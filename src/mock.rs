@@ -0,0 +1,301 @@
+//! A scripted stand in for the X server, for exercising hooks and actions in tests.
+//!
+//! [MockXConn] keeps window properties and geometry in memory and records the changes made
+//! to clients, so a test can set up a client, run a hook or key binding against a [State]
+//! built with [MockXConn::state] and check what it asked X to do. Events to hand out from
+//! `next_event` can be queued up with [MockXConn::push_event]. Clones share everything, so
+//! one clone can be given to penrose and another kept to inspect afterwards.
+use penrose::{
+    core::{
+        bindings::{KeyCode, MouseState},
+        Config, State, WindowManager,
+    },
+    pure::geometry::{Point, Rect},
+    x::{
+        property::{MapState, WindowAttributes, WindowClass, WmState},
+        ClientAttr, ClientConfig, ClientMessage, Prop, XConn, XEvent,
+    },
+    Error, Result, Xid,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
+
+#[derive(Debug, Default)]
+struct Server {
+    atoms: Vec<String>,
+    props: HashMap<(Xid, String), Prop>,
+    geometry: HashMap<Xid, Rect>,
+    wm_state: HashMap<Xid, WmState>,
+    mapped: HashSet<Xid>,
+    configs: Vec<(Xid, ClientConfig)>,
+    messages: Vec<ClientMessage>,
+    events: VecDeque<XEvent>,
+    focused: Option<Xid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockXConn {
+    screens: Vec<Rect>,
+    server: Rc<RefCell<Server>>,
+}
+
+impl Default for MockXConn {
+    fn default() -> Self {
+        Self::new(vec![Rect::new(0, 0, 1920, 1080)])
+    }
+}
+
+impl MockXConn {
+    pub fn new(screens: Vec<Rect>) -> Self {
+        Self {
+            screens,
+            server: Rc::default(),
+        }
+    }
+
+    /// Window manager state with the default config, running against this connection.
+    pub fn state(&self) -> State<Self> {
+        let wm = WindowManager::new(
+            Config::default(),
+            HashMap::new(),
+            HashMap::new(),
+            self.clone(),
+        )
+        .expect("the mock connection to set up the window manager");
+
+        wm.state
+    }
+
+    /// A connection with a single 800x600 client window and state that is already managing
+    /// it on the focused tag: the starting point for most tests of per-client hooks.
+    pub fn with_client() -> (Self, State<Self>, Xid) {
+        let x = Self::default();
+        let mut state = x.state();
+        let id = Xid::from(10);
+        x.add_window(id, Rect::new(0, 0, 800, 600));
+        state.client_set.insert(id);
+
+        (x, state, id)
+    }
+
+    /// Set up a client window at `r`, as if a client had created it.
+    pub fn add_window(&self, id: Xid, r: Rect) {
+        self.server.borrow_mut().geometry.insert(id, r);
+    }
+
+    pub fn prop(&self, id: Xid, name: &str) -> Option<Prop> {
+        let server = self.server.borrow();
+
+        server.props.get(&(id, name.to_string())).cloned()
+    }
+
+    /// Every client config change sent for `id`, oldest first.
+    pub fn configs(&self, id: Xid) -> Vec<ClientConfig> {
+        let server = self.server.borrow();
+
+        server
+            .configs
+            .iter()
+            .filter(|(c, _)| *c == id)
+            .map(|&(_, config)| config)
+            .collect()
+    }
+
+    /// Every client message sent, oldest first.
+    pub fn messages(&self) -> Vec<ClientMessage> {
+        self.server.borrow().messages.clone()
+    }
+
+    pub fn is_mapped(&self, id: Xid) -> bool {
+        self.server.borrow().mapped.contains(&id)
+    }
+
+    pub fn focused(&self) -> Option<Xid> {
+        self.server.borrow().focused
+    }
+
+    /// Queue `event` to be returned from `next_event`.
+    pub fn push_event(&self, event: XEvent) {
+        self.server.borrow_mut().events.push_back(event);
+    }
+}
+
+impl XConn for MockXConn {
+    fn root(&self) -> Xid {
+        Xid::from(1)
+    }
+
+    fn screen_details(&self) -> Result<Vec<Rect>> {
+        Ok(self.screens.clone())
+    }
+
+    fn cursor_position(&self) -> Result<Point> {
+        Ok(Point::new(0, 0))
+    }
+
+    fn grab(&self, _: &[KeyCode], _: &[MouseState]) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_event(&self) -> Result<XEvent> {
+        let event = self.server.borrow_mut().events.pop_front();
+
+        event.ok_or_else(|| Error::Custom("no more scripted events".into()))
+    }
+
+    fn flush(&self) {}
+
+    fn intern_atom(&self, atom: &str) -> Result<Xid> {
+        let mut server = self.server.borrow_mut();
+        let ix = match server.atoms.iter().position(|a| a == atom) {
+            Some(ix) => ix,
+            None => {
+                server.atoms.push(atom.to_string());
+                server.atoms.len() - 1
+            }
+        };
+
+        // Atom ids start from 1, as 0 is None.
+        Ok(Xid::from(ix as u32 + 1))
+    }
+
+    fn atom_name(&self, xid: Xid) -> Result<String> {
+        let server = self.server.borrow();
+        let ix = (*xid as usize).checked_sub(1);
+
+        ix.and_then(|ix| server.atoms.get(ix))
+            .cloned()
+            .ok_or_else(|| Error::Custom(format!("{xid} isn't an atom")))
+    }
+
+    fn client_geometry(&self, client: Xid) -> Result<Rect> {
+        if client == self.root() {
+            let (w, h) = self
+                .screens
+                .iter()
+                .fold((0, 0), |(w, h), r| (w.max(r.x + r.w), h.max(r.y + r.h)));
+            return Ok(Rect::new(0, 0, w, h));
+        }
+        let server = self.server.borrow();
+
+        server
+            .geometry
+            .get(&client)
+            .copied()
+            .ok_or(Error::UnknownClient(client))
+    }
+
+    fn existing_clients(&self) -> Result<Vec<Xid>> {
+        let server = self.server.borrow();
+
+        Ok(server.mapped.iter().copied().collect())
+    }
+
+    fn map(&self, client: Xid) -> Result<()> {
+        self.server.borrow_mut().mapped.insert(client);
+
+        Ok(())
+    }
+
+    fn unmap(&self, client: Xid) -> Result<()> {
+        self.server.borrow_mut().mapped.remove(&client);
+
+        Ok(())
+    }
+
+    fn kill(&self, client: Xid) -> Result<()> {
+        let mut server = self.server.borrow_mut();
+        server.mapped.remove(&client);
+        server.geometry.remove(&client);
+
+        Ok(())
+    }
+
+    fn focus(&self, client: Xid) -> Result<()> {
+        self.server.borrow_mut().focused = Some(client);
+
+        Ok(())
+    }
+
+    fn get_prop(&self, client: Xid, prop_name: &str) -> Result<Option<Prop>> {
+        Ok(self.prop(client, prop_name))
+    }
+
+    fn list_props(&self, client: Xid) -> Result<Vec<String>> {
+        let server = self.server.borrow();
+
+        Ok(server
+            .props
+            .keys()
+            .filter(|(id, _)| *id == client)
+            .map(|(_, name)| name.clone())
+            .collect())
+    }
+
+    fn get_wm_state(&self, client: Xid) -> Result<Option<WmState>> {
+        Ok(self.server.borrow().wm_state.get(&client).copied())
+    }
+
+    fn get_window_attributes(&self, client: Xid) -> Result<WindowAttributes> {
+        let map_state = if self.is_mapped(client) {
+            MapState::Viewable
+        } else {
+            MapState::Unmapped
+        };
+
+        Ok(WindowAttributes {
+            override_redirect: false,
+            map_state,
+            window_class: WindowClass::InputOutput,
+        })
+    }
+
+    fn set_wm_state(&self, client: Xid, wm_state: WmState) -> Result<()> {
+        self.server.borrow_mut().wm_state.insert(client, wm_state);
+
+        Ok(())
+    }
+
+    fn set_prop(&self, client: Xid, name: &str, val: Prop) -> Result<()> {
+        let mut server = self.server.borrow_mut();
+        server.props.insert((client, name.to_string()), val);
+
+        Ok(())
+    }
+
+    fn delete_prop(&self, client: Xid, prop_name: &str) -> Result<()> {
+        let mut server = self.server.borrow_mut();
+        server.props.remove(&(client, prop_name.to_string()));
+
+        Ok(())
+    }
+
+    fn set_client_attributes(&self, _: Xid, _: &[ClientAttr]) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_client_config(&self, client: Xid, data: &[ClientConfig]) -> Result<()> {
+        let mut server = self.server.borrow_mut();
+        for &config in data {
+            if let ClientConfig::Position(r) = config {
+                server.geometry.insert(client, r);
+            }
+            server.configs.push((client, config));
+        }
+
+        Ok(())
+    }
+
+    fn send_client_message(&self, msg: ClientMessage) -> Result<()> {
+        self.server.borrow_mut().messages.push(msg);
+
+        Ok(())
+    }
+
+    fn warp_pointer(&self, _: Xid, _: i16, _: i16) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+//! Modal keyboard input that bypasses the normal key bindings.
+//!
+//! Penrose only ever sees the key combinations it has bound, so anything that needs to read
+//! arbitrary keys (float mode, mark names...) or clicks (kill mode) takes a full keyboard
+//! grab on a connection of its own for the duration of the interaction. The grab is read from
+//! a thread of its own so that the event loop carries on as normal in the meantime, with what
+//! was read handed back to it as a ClientMessage.
+use crate::timer::notify_after;
+use penrose::{util::spawn_for_output_with_args, Error, Result};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{ConnectionExt, EventMask, GrabMode, GrabStatus, KeyButMask},
+        Event,
+    },
+    rust_connection::RustConnection,
+    CURRENT_TIME, NONE,
+};
+
+/// How often a grab being read in the background is checked for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const INPUT_NONE: u32 = 0;
+const INPUT_KEY: u32 = 1;
+const INPUT_CLICK: u32 = 2;
+
+static KEYSYM_NAMES: OnceLock<HashMap<u8, String>> = OnceLock::new();
+/// How many [KeyboardGrab]s are currently held.
+static GRABS_HELD: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn x_err(e: impl std::fmt::Display) -> Error {
+    Error::Custom(e.to_string())
+}
+
+/// Create a cursor showing `glyph` from the X cursor font, black on white.
+pub fn glyph_cursor(conn: &RustConnection, glyph: u16) -> Result<u32> {
+    let font = conn.generate_id().map_err(x_err)?;
+    conn.open_font(font, b"cursor").map_err(x_err)?;
+    let cursor = conn.generate_id().map_err(x_err)?;
+    conn.create_glyph_cursor(
+        cursor,
+        font,
+        font,
+        glyph,
+        glyph + 1,
+        0,
+        0,
+        0,
+        0xffff,
+        0xffff,
+        0xffff,
+    )
+    .map_err(x_err)?;
+    // The cursor keeps what it needs from the font.
+    conn.close_font(font).map_err(x_err)?;
+
+    Ok(cursor)
+}
+
+/// A key pressed while the keyboard was grabbed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    /// The primary keysym name for the key as reported by xmodmap (e.g. "Left", "a").
+    pub name: Option<String>,
+    pub shift: bool,
+}
+
+/// Input read while both the keyboard and pointer were grabbed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    /// A click on the given top level window (which may be the root window).
+    Click(u32),
+}
+
+/// An active grab of the keyboard (and optionally the pointer), released when dropped.
+pub struct KeyboardGrab {
+    conn: RustConnection,
+    root: u32,
+}
+
+impl KeyboardGrab {
+    pub fn new() -> Result<Self> {
+        // Make sure keys can be named before anything is read.
+        keysym_names()?;
+        let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let reply = conn
+            .grab_keyboard(false, root, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?;
+
+        if reply.status != GrabStatus::SUCCESS {
+            return Err(Error::Custom("unable to grab the keyboard".to_string()));
+        }
+        GRABS_HELD.fetch_add(1, Ordering::SeqCst);
+
+        Ok(Self { conn, root })
+    }
+
+    /// Whether the window manager itself is holding a grab of the keyboard right now.
+    pub fn held() -> bool {
+        GRABS_HELD.load(Ordering::SeqCst) > 0
+    }
+
+    /// Grab the pointer as well, showing the given glyph from the X cursor font while the
+    /// grab is held.
+    pub fn with_pointer(cursor_glyph: u16) -> Result<Self> {
+        let grab = Self::new()?;
+        let conn = &grab.conn;
+
+        let cursor = glyph_cursor(conn, cursor_glyph)?;
+        let reply = conn
+            .grab_pointer(
+                false,
+                grab.root,
+                EventMask::BUTTON_PRESS,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                NONE,
+                cursor,
+                CURRENT_TIME,
+            )
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?;
+        conn.free_cursor(cursor).map_err(x_err)?;
+
+        if reply.status != GrabStatus::SUCCESS {
+            return Err(Error::Custom("unable to grab the pointer".to_string()));
+        }
+
+        Ok(grab)
+    }
+
+    /// The next key press if there has been one, without blocking.
+    pub fn poll_key(&self) -> Result<Option<Key>> {
+        while let Some(event) = self.conn.poll_for_event().map_err(x_err)? {
+            if let Some(Input::Key(key)) = self.input_data(&event).and_then(input_from) {
+                return Ok(Some(key));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The next key press or, if the pointer is grabbed, click, if there has been one.
+    fn poll_input_data(&self) -> Result<Option<[u32; 5]>> {
+        while let Some(event) = self.conn.poll_for_event().map_err(x_err)? {
+            if let Some(data) = self.input_data(&event) {
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// `event` as ClientMessage data for [input_from_data], if it is input at all.
+    fn input_data(&self, event: &Event) -> Option<[u32; 5]> {
+        match event {
+            Event::KeyPress(e) => {
+                let shift = e.state.contains(KeyButMask::SHIFT) as u32;
+                Some([INPUT_KEY, e.detail as u32, shift, 0, 0])
+            }
+            Event::ButtonPress(e) => {
+                let win = if e.child == NONE { self.root } else { e.child };
+                Some([INPUT_CLICK, win, 0, 0, 0])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for KeyboardGrab {
+    fn drop(&mut self) {
+        let _ = self.conn.ungrab_pointer(CURRENT_TIME);
+        let _ = self.conn.ungrab_keyboard(CURRENT_TIME);
+        let _ = self.conn.flush();
+        GRABS_HELD.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wait on a thread of its own for the next key press on `grab` (or click, if it has the
+/// pointer as well), then let go of the grab and hand what was read to the event loop as a
+/// ClientMessage of type `dtype` for [input_from_data]. Nothing having been read within
+/// `timeout` is handed over in the same way, so whatever is waiting on it can give up too.
+pub fn read_in_background(grab: KeyboardGrab, dtype: &'static str, timeout: Duration) {
+    thread::spawn(move || {
+        let deadline = Instant::now() + timeout;
+        let data = loop {
+            if Instant::now() >= deadline {
+                break [INPUT_NONE; 5];
+            }
+            match grab.poll_input_data() {
+                Ok(Some(data)) => break data,
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    warn!("lost the keyboard grab: {e}");
+                    break [INPUT_NONE; 5];
+                }
+            }
+        };
+        drop(grab);
+
+        notify_after(Duration::ZERO, dtype, data);
+    });
+}
+
+/// The input sent by [read_in_background], or `None` if it timed out.
+pub fn input_from_data(data: &[usize]) -> Option<Input> {
+    let data: Vec<u32> = data.iter().map(|&d| d as u32).collect();
+
+    input_from(data.try_into().ok()?)
+}
+
+fn input_from(data: [u32; 5]) -> Option<Input> {
+    match data {
+        [INPUT_KEY, code, shift, ..] => Some(Input::Key(Key {
+            name: KEYSYM_NAMES.get()?.get(&(code as u8)).cloned(),
+            shift: shift != 0,
+        })),
+        [INPUT_CLICK, win, ..] => Some(Input::Click(win)),
+        _ => None,
+    }
+}
+
+/// Keycodes to keysym names, read from xmodmap the first time a grab is taken.
+fn keysym_names() -> Result<&'static HashMap<u8, String>> {
+    if let Some(names) = KEYSYM_NAMES.get() {
+        return Ok(names);
+    }
+    let output = spawn_for_output_with_args("xmodmap", &["-pke"])?;
+
+    Ok(KEYSYM_NAMES.get_or_init(|| parse_xmodmap(&output)))
+}
+
+// Lines are of the form "keycode  38 = a A a A": we only care about the unshifted keysym.
+fn parse_xmodmap(output: &str) -> HashMap<u8, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (lhs, rhs) = line.split_once('=')?;
+            let code = lhs.trim().strip_prefix("keycode")?.trim().parse().ok()?;
+            let name = rhs.split_whitespace().next()?;
+
+            Some((code, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xmodmap_output_uses_the_unshifted_keysym() {
+        let names =
+            parse_xmodmap("keycode  38 = a A a A\nkeycode 113 = Left NoSymbol Left\nkeycode 255 =");
+
+        assert_eq!(names.get(&38).map(String::as_str), Some("a"));
+        assert_eq!(names.get(&113).map(String::as_str), Some("Left"));
+        assert_eq!(names.get(&255), None);
+    }
+}
@@ -0,0 +1,878 @@
+//! Reacting to changes in the connected monitors.
+use crate::{
+    modal::x_err,
+    process::run_with_args,
+    timer::notify_after,
+    wallpaper::{set_wallpapers, Scaling},
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    pure::geometry::Rect,
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{collections::HashMap, time::Duration};
+use tracing::{info, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::{
+            self, ConnectionExt as _, GetCrtcInfoReply, GetOutputInfoReply,
+            GetScreenResourcesCurrentReply, ModeInfo, Rotation,
+        },
+        xproto::{AtomEnum, ConnectionExt as _},
+    },
+    rust_connection::RustConnection,
+    CURRENT_TIME, NONE,
+};
+
+/// Sent once the monitor setup has stopped changing. Hotplugging tends to produce a burst of
+/// RandR events so anything expensive should wait for this rather than reacting to each one.
+pub const RANDR_SETTLED: &str = "_WM_RANDR_SETTLED";
+
+/// How long the monitor setup must go without changing before it counts as settled.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// A connected RandR output that is currently driving a CRTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub name: String,
+    pub r: Rect,
+    /// The physical width and height reported for the monitor, which may be zero.
+    pub mm: (u32, u32),
+}
+
+impl Output {
+    /// The geometry in the form xrandr uses, e.g. "2560x1440+1920+0".
+    pub fn geometry(&self) -> String {
+        format!("{}x{}+{}+{}", self.r.w, self.r.h, self.r.x, self.r.y)
+    }
+}
+
+/// A change to a single output, found by comparing the active outputs from before and after
+/// the monitor setup settles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    /// An output was plugged in or turned on.
+    Connected(Output),
+    /// An output was unplugged or turned off.
+    Disconnected(Output),
+    /// An output moved, changed resolution or was rotated.
+    Changed { from: Output, to: Output },
+}
+
+impl OutputEvent {
+    /// The event as arguments for a script: its kind ("connected", "disconnected" or
+    /// "changed"), the output name and its geometry, followed by the previous geometry for
+    /// changes.
+    fn args(&self) -> Vec<String> {
+        match self {
+            Self::Connected(o) => vec!["connected".into(), o.name.clone(), o.geometry()],
+            Self::Disconnected(o) => vec!["disconnected".into(), o.name.clone(), o.geometry()],
+            Self::Changed { from, to } => vec![
+                "changed".into(),
+                to.name.clone(),
+                to.geometry(),
+                from.geometry(),
+            ],
+        }
+    }
+}
+
+/// What changed between the active outputs `before` and `after`.
+pub fn output_events(before: &[Output], after: &[Output]) -> Vec<OutputEvent> {
+    let mut events: Vec<OutputEvent> = before
+        .iter()
+        .filter(|o| !after.iter().any(|a| a.name == o.name))
+        .map(|o| OutputEvent::Disconnected(o.clone()))
+        .collect();
+
+    for o in after {
+        match before.iter().find(|b| b.name == o.name) {
+            None => events.push(OutputEvent::Connected(o.clone())),
+            Some(b) if b.r != o.r => events.push(OutputEvent::Changed {
+                from: b.clone(),
+                to: o.clone(),
+            }),
+            Some(_) => (),
+        }
+    }
+
+    events
+}
+
+/// The changes found the last time the monitor setup settled, for hooks that follow
+/// [MonitorHook] to act on.
+#[derive(Debug, Default, Clone)]
+pub struct OutputChanges {
+    pub events: Vec<OutputEvent>,
+}
+
+/// An output with a monitor plugged into it, whether or not it is in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connected {
+    pub name: String,
+    /// See [edid_fingerprint].
+    pub edid: Option<String>,
+}
+
+/// How an output should be configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSetup {
+    pub name: String,
+    /// The resolution to use, or `None` to turn the output off.
+    pub mode: Option<(u16, u16)>,
+    pub pos: (i16, i16),
+    pub rotation: Rotation,
+    pub primary: bool,
+}
+
+/// A CRTC as it should be configured once a set of [OutputSetup]s has been applied.
+#[derive(Debug, Clone)]
+struct Planned {
+    crtc: u32,
+    mode: u32,
+    x: i16,
+    y: i16,
+    /// The size on screen, after rotation.
+    w: u16,
+    h: u16,
+    rotation: Rotation,
+    outputs: Vec<u32>,
+}
+
+impl Planned {
+    fn matches(&self, info: &GetCrtcInfoReply) -> bool {
+        let mut outputs = info.outputs.clone();
+        outputs.sort_unstable();
+        let mut wanted = self.outputs.clone();
+        wanted.sort_unstable();
+
+        info.mode == self.mode
+            && (info.x, info.y) == (self.x, self.y)
+            && info.rotation == self.rotation
+            && outputs == wanted
+    }
+}
+
+/// A connection for querying and changing the RandR configuration, along with the screen
+/// resources as of when it was opened.
+pub struct Randr {
+    conn: RustConnection,
+    root: u32,
+    res: GetScreenResourcesCurrentReply,
+}
+
+impl Randr {
+    pub fn connect() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+        let root = conn.setup().roots[screen_num].root;
+        let res = conn
+            .randr_get_screen_resources_current(root)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?;
+
+        Ok(Self { conn, root, res })
+    }
+
+    fn output_info(&self, id: u32) -> Result<GetOutputInfoReply> {
+        self.conn
+            .randr_get_output_info(id, self.res.config_timestamp)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)
+    }
+
+    fn crtc_info(&self, id: u32) -> Result<GetCrtcInfoReply> {
+        self.conn
+            .randr_get_crtc_info(id, self.res.config_timestamp)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)
+    }
+
+    /// Every output by name along with its id and current state.
+    fn outputs(&self) -> Result<HashMap<String, (u32, GetOutputInfoReply)>> {
+        let mut outputs = HashMap::new();
+        for &id in self.res.outputs.iter() {
+            let info = self.output_info(id)?;
+            outputs.insert(String::from_utf8_lossy(&info.name).into_owned(), (id, info));
+        }
+
+        Ok(outputs)
+    }
+
+    /// The active outputs in CRTC order, which is the order Xinerama numbers the screens in.
+    pub fn active_outputs(&self) -> Result<Vec<Output>> {
+        let mut outputs = Vec::new();
+        for (name, (_, info)) in self.outputs()? {
+            if info.connection != randr::Connection::CONNECTED || info.crtc == NONE {
+                continue;
+            }
+
+            let crtc = self.crtc_info(info.crtc)?;
+            let r = Rect::new(
+                crtc.x.max(0) as u32,
+                crtc.y.max(0) as u32,
+                crtc.width as u32,
+                crtc.height as u32,
+            );
+            let ix = self.res.crtcs.iter().position(|&c| c == info.crtc);
+            let mm = (info.mm_width, info.mm_height);
+            outputs.push((ix, Output { name, r, mm }));
+        }
+        outputs.sort_by_key(|(ix, _)| *ix);
+
+        Ok(outputs.into_iter().map(|(_, o)| o).collect())
+    }
+
+    /// The output RandR has marked as primary, if any.
+    pub fn primary(&self) -> Result<Option<String>> {
+        let id = self
+            .conn
+            .randr_get_output_primary(self.root)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?
+            .output;
+        if id == NONE {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            String::from_utf8_lossy(&self.output_info(id)?.name).into_owned(),
+        ))
+    }
+
+    /// Every output with a monitor plugged in, sorted by name.
+    pub fn connected_outputs(&self) -> Result<Vec<Connected>> {
+        let edid = self
+            .conn
+            .intern_atom(false, b"EDID")
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?
+            .atom;
+
+        let mut connected = Vec::new();
+        for (name, (id, info)) in self.outputs()? {
+            if info.connection != randr::Connection::CONNECTED {
+                continue;
+            }
+            let data = self
+                .conn
+                .randr_get_output_property(id, edid, AtomEnum::ANY, 0, 64, false, false)
+                .map_err(x_err)?
+                .reply()
+                .map_err(x_err)?
+                .data;
+
+            connected.push(Connected {
+                name,
+                edid: edid_fingerprint(&data),
+            });
+        }
+        connected.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(connected)
+    }
+
+    /// The current configuration of every active output, in a form that can be tweaked and
+    /// handed back to [Randr::apply].
+    pub fn current_setups(&self) -> Result<Vec<OutputSetup>> {
+        let primary = self.primary()?;
+        let mut setups = Vec::new();
+        for (name, (_, info)) in self.outputs()? {
+            if info.crtc == NONE {
+                continue;
+            }
+            let crtc = self.crtc_info(info.crtc)?;
+            let mode = self.res.modes.iter().find(|m| m.id == crtc.mode);
+
+            setups.push(OutputSetup {
+                primary: primary.as_ref() == Some(&name),
+                name,
+                mode: mode.map(|m| (m.width, m.height)),
+                pos: (crtc.x, crtc.y),
+                rotation: crtc.rotation,
+            });
+        }
+        setups.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(setups)
+    }
+
+    /// Scale the red, green and blue gamma ramps of every active CRTC by the given factors,
+    /// where `(1.0, 1.0, 1.0)` is the unaltered ramp.
+    pub fn set_gamma(&self, (r, g, b): (f64, f64, f64)) -> Result<()> {
+        for &c in self.res.crtcs.iter() {
+            if self.crtc_info(c)?.mode == NONE {
+                continue;
+            }
+            let size = self
+                .conn
+                .randr_get_crtc_gamma_size(c)
+                .map_err(x_err)?
+                .reply()
+                .map_err(x_err)?
+                .size as usize;
+            let ramp = |factor: f64| -> Vec<u16> {
+                (0..size)
+                    .map(|i| (i as f64 / (size.max(2) - 1) as f64 * factor * 65535.0) as u16)
+                    .collect()
+            };
+
+            self.conn
+                .randr_set_crtc_gamma(c, &ramp(r), &ramp(g), &ramp(b))
+                .map_err(x_err)?;
+        }
+        self.conn.flush().map_err(x_err)?;
+
+        Ok(())
+    }
+
+    /// The resolution the monitor on the named output prefers, if it is connected.
+    pub fn preferred_size(&self, name: &str) -> Result<Option<(u16, u16)>> {
+        let outputs = self.outputs()?;
+        let info = match outputs.get(name) {
+            Some((_, info)) if info.connection == randr::Connection::CONNECTED => info,
+            _ => return Ok(None),
+        };
+        // RandR lists an output's preferred modes ahead of the rest.
+        let id = match info.modes.first() {
+            Some(&id) => id,
+            None => return Ok(None),
+        };
+
+        Ok(self
+            .res
+            .modes
+            .iter()
+            .find(|m| m.id == id)
+            .map(|m| (m.width, m.height)))
+    }
+
+    /// Every connected output showing the same picture from the top left corner, at the
+    /// largest resolution they all support. The current primary output stays primary.
+    pub fn mirrored_setups(&self) -> Result<Vec<OutputSetup>> {
+        let primary = self.primary()?;
+        let mut names = Vec::new();
+        let mut sizes = Vec::new();
+        for (name, (_, info)) in self.outputs()? {
+            if info.connection != randr::Connection::CONNECTED {
+                continue;
+            }
+            let supported: Vec<(u16, u16)> = self
+                .res
+                .modes
+                .iter()
+                .filter(|m| info.modes.contains(&m.id))
+                .map(|m| (m.width, m.height))
+                .collect();
+            names.push(name);
+            sizes.push(supported);
+        }
+
+        let mode = largest_common_size(&sizes);
+        if mode.is_none() {
+            warn!("no resolution is shared by every connected output");
+        }
+
+        Ok(names
+            .into_iter()
+            .map(|name| OutputSetup {
+                primary: primary.as_ref() == Some(&name),
+                name,
+                mode,
+                pos: (0, 0),
+                rotation: Rotation::ROTATE0,
+            })
+            .collect())
+    }
+
+    /// Configure the given outputs, turning off any that aren't mentioned, and return whether
+    /// anything needed changing. CRTCs that are already set up as requested are left alone.
+    ///
+    /// Outputs given the same mode, position and rotation share a CRTC (mirroring each other)
+    /// where the hardware allows it.
+    pub fn apply(&self, setups: &[OutputSetup]) -> Result<bool> {
+        let outputs = self.outputs()?;
+        let mut crtcs = HashMap::new();
+        for &c in self.res.crtcs.iter() {
+            crtcs.insert(c, self.crtc_info(c)?);
+        }
+
+        // Outputs keep the CRTC they already have where possible so that anything which
+        // isn't changing doesn't flicker.
+        let mut wanted = Vec::new();
+        for s in setups {
+            let (w, h) = match s.mode {
+                Some(size) => size,
+                None => continue,
+            };
+            let (id, info) = match outputs.get(&s.name) {
+                Some((id, info)) => (*id, info),
+                None => {
+                    warn!("unknown output {}", s.name);
+                    continue;
+                }
+            };
+            match best_mode(&self.res.modes, &info.modes, w, h) {
+                Some(mode) => wanted.push((s, id, info, mode)),
+                None => warn!("output {} has no {w}x{h} mode", s.name),
+            }
+        }
+
+        let mut plan: Vec<Planned> = Vec::new();
+        for &(s, id, info, mode) in wanted.iter() {
+            let shared = plan.iter_mut().find(|p| {
+                (p.mode, p.x, p.y, p.rotation) == (mode, s.pos.0, s.pos.1, s.rotation)
+                    && info.crtcs.contains(&p.crtc)
+            });
+            if let Some(p) = shared {
+                p.outputs.push(id);
+                continue;
+            }
+
+            let held = |c: u32| {
+                plan.iter().any(|p| p.crtc == c)
+                    || wanted
+                        .iter()
+                        .any(|(_, other, o, _)| *other != id && o.crtc == c)
+            };
+            let crtc = match Some(info.crtc).filter(|&c| c != NONE && !held(c)) {
+                Some(c) => c,
+                None => match info.crtcs.iter().copied().find(|&c| !held(c)) {
+                    Some(c) => c,
+                    None => {
+                        warn!("no free CRTC for output {}", s.name);
+                        continue;
+                    }
+                },
+            };
+
+            let (w, h) = rotated_size(s);
+            plan.push(Planned {
+                crtc,
+                mode,
+                x: s.pos.0,
+                y: s.pos.1,
+                w,
+                h,
+                rotation: s.rotation,
+                outputs: vec![id],
+            });
+        }
+
+        if plan.is_empty() {
+            warn!("refusing to turn off every output");
+            return Ok(false);
+        }
+
+        let ts = self.res.config_timestamp;
+        let mut changed = false;
+
+        // Anything that is changing is switched off first as no CRTC may extend past the edge
+        // of the screen while it is being resized.
+        for (&c, info) in crtcs.iter() {
+            if info.mode != NONE && !plan.iter().any(|p| p.crtc == c && p.matches(info)) {
+                self.conn
+                    .randr_set_crtc_config(c, CURRENT_TIME, ts, 0, 0, NONE, Rotation::ROTATE0, &[])
+                    .map_err(x_err)?
+                    .reply()
+                    .map_err(x_err)?;
+                changed = true;
+            }
+        }
+
+        let w = plan
+            .iter()
+            .map(|p| p.x.max(0) as u16 + p.w)
+            .max()
+            .unwrap_or(0);
+        let h = plan
+            .iter()
+            .map(|p| p.y.max(0) as u16 + p.h)
+            .max()
+            .unwrap_or(0);
+        let current = self
+            .conn
+            .get_geometry(self.root)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?;
+        if (current.width, current.height) != (w, h) {
+            // RandR wants a physical size as well: assume the traditional 96 DPI.
+            let mm = |px: u16| px as u32 * 254 / 960;
+            self.conn
+                .randr_set_screen_size(self.root, w, h, mm(w), mm(h))
+                .map_err(x_err)?;
+            changed = true;
+        }
+
+        for p in plan.iter() {
+            if crtcs.get(&p.crtc).is_some_and(|info| p.matches(info)) {
+                continue;
+            }
+            self.conn
+                .randr_set_crtc_config(
+                    p.crtc,
+                    CURRENT_TIME,
+                    ts,
+                    p.x,
+                    p.y,
+                    p.mode,
+                    p.rotation,
+                    &p.outputs,
+                )
+                .map_err(x_err)?
+                .reply()
+                .map_err(x_err)?;
+            changed = true;
+        }
+
+        let primary = setups
+            .iter()
+            .filter(|s| s.primary && s.mode.is_some())
+            .find_map(|s| outputs.get(&s.name).map(|(id, _)| *id));
+        if let Some(id) = primary {
+            self.conn
+                .randr_set_output_primary(self.root, id)
+                .map_err(x_err)?;
+        }
+        self.conn.flush().map_err(x_err)?;
+
+        Ok(changed)
+    }
+}
+
+/// The size an output takes up on screen, which is its mode's size turned on its side for
+/// rotations of 90 and 270 degrees.
+pub fn rotated_size(s: &OutputSetup) -> (u16, u16) {
+    let (w, h) = s.mode.unwrap_or_default();
+    if s.rotation == Rotation::ROTATE90 || s.rotation == Rotation::ROTATE270 {
+        (h, w)
+    } else {
+        (w, h)
+    }
+}
+
+/// Change the rotation of the named output, moving the outputs entirely to the right of or
+/// below it so that they stay edge to edge with it once its size on screen changes.
+pub fn rotate(setups: &mut [OutputSetup], name: &str, rotation: Rotation) {
+    let ix = match setups.iter().position(|s| s.name == name) {
+        Some(ix) => ix,
+        None => return,
+    };
+    let (x, y) = setups[ix].pos;
+    let (old_w, old_h) = rotated_size(&setups[ix]);
+    setups[ix].rotation = rotation;
+    let (new_w, new_h) = rotated_size(&setups[ix]);
+
+    let dx = new_w as i16 - old_w as i16;
+    let dy = new_h as i16 - old_h as i16;
+    for (i, s) in setups.iter_mut().enumerate() {
+        if i == ix || s.mode.is_none() {
+            continue;
+        }
+        if s.pos.0 >= x + old_w as i16 {
+            s.pos.0 += dx;
+        }
+        if s.pos.1 >= y + old_h as i16 {
+            s.pos.1 += dy;
+        }
+    }
+}
+
+/// Shift the active outputs so that the top left of the screen is at the origin again, as it
+/// may not be once an output there has been turned off.
+pub fn close_gaps(setups: &mut [OutputSetup]) {
+    let active = setups.iter().filter(|s| s.mode.is_some());
+    let x0 = active.clone().map(|s| s.pos.0).min().unwrap_or(0);
+    let y0 = active.map(|s| s.pos.1).min().unwrap_or(0);
+    for s in setups.iter_mut() {
+        s.pos = (s.pos.0 - x0, s.pos.1 - y0);
+    }
+}
+
+/// The active outputs in CRTC order.
+pub fn active_outputs() -> Result<Vec<Output>> {
+    Randr::connect()?.active_outputs()
+}
+
+/// The largest resolution (by area) found in every one of the given lists.
+fn largest_common_size(sizes: &[Vec<(u16, u16)>]) -> Option<(u16, u16)> {
+    let (first, rest) = sizes.split_first()?;
+
+    first
+        .iter()
+        .filter(|size| rest.iter().all(|other| other.contains(size)))
+        .max_by_key(|&&(w, h)| w as u32 * h as u32)
+        .copied()
+}
+
+/// The id of the `w`x`h` mode out of those supported by an output with the highest refresh
+/// rate.
+fn best_mode(modes: &[ModeInfo], supported: &[u32], w: u16, h: u16) -> Option<u32> {
+    let refresh = |m: &ModeInfo| {
+        let dots = m.htotal as u64 * m.vtotal as u64;
+        (m.dot_clock as u64 * 1000).checked_div(dots).unwrap_or(0)
+    };
+
+    modes
+        .iter()
+        .filter(|m| supported.contains(&m.id) && (m.width, m.height) == (w, h))
+        .max_by_key(|m| refresh(m))
+        .map(|m| m.id)
+}
+
+/// A short identifier for a monitor made up of the manufacturer, product code and serial
+/// number from its EDID (e.g. "DEL-a0b1-0000cafe"). This stays the same whichever port the
+/// monitor is plugged into.
+pub fn edid_fingerprint(edid: &[u8]) -> Option<String> {
+    const HEADER: [u8; 8] = [0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+    if edid.len() < 16 || edid[..8] != HEADER {
+        return None;
+    }
+
+    // The manufacturer is three letters packed five bits apiece, with 1 being 'A'.
+    let id = u16::from_be_bytes([edid[8], edid[9]]);
+    let letter = |shift: u16| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char;
+    let product = u16::from_le_bytes([edid[10], edid[11]]);
+    let serial = u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]);
+
+    Some(format!(
+        "{}{}{}-{product:04x}-{serial:08x}",
+        letter(10),
+        letter(5),
+        letter(0)
+    ))
+}
+
+/// The index of the screen penrose has for an output with the given geometry.
+pub fn screen_for_output<X: XConn>(state: &State<X>, r: Rect) -> Option<usize> {
+    state
+        .client_set
+        .screens()
+        .find(|s| (s.geometry().x, s.geometry().y) == (r.x, r.y))
+        .map(|s| s.index())
+}
+
+/// Sends [RANDR_SETTLED] to the hooks that follow it once a burst of RandR changes is over,
+/// working out what changed as [OutputChanges] and setting the wallpaper for each output if
+/// anything did.
+#[derive(Debug, Clone)]
+pub struct MonitorHook {
+    /// Bumped on each RandR event so that only the last wakeup of a burst is acted on.
+    generation: u32,
+    /// The active outputs as of the last time the setup settled.
+    outputs: Vec<Output>,
+    /// Images keyed by output name (as shown by `xrandr`).
+    wallpapers: HashMap<String, String>,
+    /// The image used for any output without one of its own.
+    fallback: String,
+    scaling: Scaling,
+}
+
+impl MonitorHook {
+    pub fn new(wallpapers: &[(&str, &str)], fallback: &str, scaling: Scaling) -> Self {
+        Self {
+            generation: 0,
+            outputs: Vec::new(),
+            wallpapers: wallpapers
+                .iter()
+                .map(|&(o, p)| (o.to_string(), p.to_string()))
+                .collect(),
+            fallback: fallback.to_string(),
+            scaling,
+        }
+    }
+
+    /// The image for each output, in the same order as `outputs`.
+    fn images<'a>(&'a self, outputs: &[Output]) -> Vec<&'a str> {
+        outputs
+            .iter()
+            .map(|o| {
+                self.wallpapers
+                    .get(&o.name)
+                    .unwrap_or(&self.fallback)
+                    .as_str()
+            })
+            .collect()
+    }
+
+    fn set_wallpapers(&self) -> Result<()> {
+        let placed: Vec<(Rect, &str)> = self
+            .outputs
+            .iter()
+            .map(|o| o.r)
+            .zip(self.images(&self.outputs))
+            .collect();
+
+        set_wallpapers(&placed, self.scaling)
+    }
+}
+
+impl<X: XConn> EventHook<X> for MonitorHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::RandrNotify | XEvent::ScreenChange => {
+                self.generation = self.generation.wrapping_add(1);
+                notify_after(SETTLE_DELAY, RANDR_SETTLED, [self.generation, 0, 0, 0, 0]);
+
+                Ok(true)
+            }
+
+            XEvent::ClientMessage(msg) if msg.dtype == RANDR_SETTLED => {
+                let generation = msg.data.as_usize().first().map(|&g| g as u32);
+                if generation != Some(self.generation) {
+                    return Ok(false);
+                }
+
+                let outputs = active_outputs()?;
+                let events = output_events(&self.outputs, &outputs);
+                for e in events.iter() {
+                    info!("output change: {}", e.args().join(" "));
+                }
+                self.outputs = outputs;
+                if !events.is_empty() {
+                    self.set_wallpapers()?;
+                }
+                *state.extension_or_default::<OutputChanges>().borrow_mut() =
+                    OutputChanges { events };
+
+                Ok(true)
+            }
+
+            _ => Ok(true),
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for MonitorHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        self.outputs = active_outputs()?;
+        self.set_wallpapers()
+    }
+}
+
+/// Runs `script` for each [OutputEvent] with the arguments described in
+/// [OutputEvent::args], e.g. `script connected HDMI-1 2560x1440+1920+0`.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputScriptHook {
+    pub script: &'static str,
+}
+
+impl<X: XConn> EventHook<X> for OutputScriptHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == RANDR_SETTLED => (),
+            _ => return Ok(true),
+        }
+
+        let changes = state
+            .extension_or_default::<OutputChanges>()
+            .borrow()
+            .clone();
+        for e in changes.events.iter() {
+            let args = e.args();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run_with_args(self.script, &args)?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_without_a_wallpaper_use_the_fallback() {
+        let hook = MonitorHook::new(&[("HDMI-1", "hdmi.jpg")], "default.jpg", Scaling::Fill);
+        let outputs = ["eDP-1", "HDMI-1"].map(|name| Output {
+            name: name.to_string(),
+            r: Rect::new(0, 0, 1920, 1080),
+            mm: (0, 0),
+        });
+
+        assert_eq!(hook.images(&outputs), ["default.jpg", "hdmi.jpg"]);
+    }
+
+    #[test]
+    fn output_events_describe_each_change() {
+        let output = |name: &str, x: u32| Output {
+            name: name.to_string(),
+            r: Rect::new(x, 0, 1920, 1080),
+            mm: (0, 0),
+        };
+        let before = [output("eDP-1", 0), output("HDMI-1", 1920)];
+        let after = [output("eDP-1", 2560), output("DP-1", 0)];
+
+        assert_eq!(
+            output_events(&before, &after),
+            [
+                OutputEvent::Disconnected(output("HDMI-1", 1920)),
+                OutputEvent::Changed {
+                    from: output("eDP-1", 0),
+                    to: output("eDP-1", 2560),
+                },
+                OutputEvent::Connected(output("DP-1", 0)),
+            ]
+        );
+        assert_eq!(
+            OutputEvent::Connected(output("DP-1", 0)).args(),
+            ["connected", "DP-1", "1920x1080+0+0"]
+        );
+    }
+
+    #[test]
+    fn rotating_moves_the_outputs_beyond_it() {
+        let setup = |name: &str, x: i16| OutputSetup {
+            name: name.to_string(),
+            mode: Some((1920, 1080)),
+            pos: (x, 0),
+            rotation: Rotation::ROTATE0,
+            primary: false,
+        };
+        let mut setups = vec![setup("DP-1", 0), setup("DP-2", 1920), setup("DP-3", 3840)];
+
+        rotate(&mut setups, "DP-2", Rotation::ROTATE90);
+
+        let positions: Vec<_> = setups.iter().map(|s| s.pos).collect();
+        assert_eq!(positions, [(0, 0), (1920, 0), (3000, 0)]);
+        assert_eq!(rotated_size(&setups[1]), (1080, 1920));
+    }
+
+    #[test]
+    fn mirroring_uses_the_largest_shared_resolution() {
+        let laptop = vec![(2560, 1600), (1920, 1080), (1280, 720)];
+        let projector = vec![(1024, 768), (1280, 720), (1920, 1080)];
+
+        assert_eq!(
+            largest_common_size(&[laptop, projector]),
+            Some((1920, 1080))
+        );
+        assert_eq!(
+            largest_common_size(&[vec![(800, 600)], vec![(640, 480)]]),
+            None
+        );
+    }
+
+    #[test]
+    fn edid_fingerprints_decode_the_manufacturer() {
+        let mut edid = vec![0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+        edid.extend([0x10, 0xac, 0xb1, 0xa0, 0xfe, 0xca, 0, 0]);
+
+        assert_eq!(
+            edid_fingerprint(&edid).as_deref(),
+            Some("DEL-a0b1-0000cafe")
+        );
+        assert_eq!(edid_fingerprint(&edid[1..]), None);
+    }
+}
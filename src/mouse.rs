@@ -0,0 +1,370 @@
+//! Additional mouse bindings.
+use crate::{direction::swap_tiled, modal::x_err};
+use penrose::{
+    core::{
+        bindings::{MotionNotifyEvent, MouseEvent, MouseEventHandler, MouseEventKind},
+        State,
+    },
+    pure::geometry::{Point, Rect},
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::{hash_map::Entry, HashMap};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{
+        ConfigureWindowAux, ConnectionExt, CreateWindowAux, StackMode, WindowClass,
+    },
+    rust_connection::RustConnection,
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT,
+};
+
+/// Drag a tiled client and drop it onto another tile to swap the two of them around.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SwapDragHandler {
+    dragging: Option<Xid>,
+}
+
+impl SwapDragHandler {
+    pub fn boxed_default<X: XConn>() -> Box<dyn MouseEventHandler<X>> {
+        Box::<Self>::default()
+    }
+}
+
+impl<X: XConn> MouseEventHandler<X> for SwapDragHandler {
+    fn on_mouse_event(&mut self, evt: &MouseEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        let id = evt.data.id;
+
+        match evt.kind {
+            MouseEventKind::Press => {
+                let tiled = state.client_set.contains(&id) && !state.client_set.is_floating(&id);
+                self.dragging = tiled.then_some(id);
+                Ok(())
+            }
+
+            MouseEventKind::Release => {
+                let dragged = match self.dragging.take() {
+                    Some(id) => id,
+                    None => return Ok(()),
+                };
+
+                let mut target = None;
+                for &c in state.client_set.on_screen_workspace_clients() {
+                    if c != dragged
+                        && !state.client_set.is_floating(&c)
+                        && x.client_geometry(c)?.contains_point(evt.data.rpt)
+                    {
+                        target = Some(c);
+                        break;
+                    }
+                }
+
+                match target {
+                    Some(target) => {
+                        x.modify_and_refresh(state, |cs| swap_tiled(cs, dragged, target))
+                    }
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn on_motion(&mut self, _: &MotionNotifyEvent, _: &mut State<X>, _: &X) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Wraps a drag handler so that a window dropped on another screen moves to the tag visible
+/// there rather than staying on its old workspace.
+pub struct RetagOnDropHandler<X: XConn> {
+    inner: Box<dyn MouseEventHandler<X>>,
+    dragging: Option<Xid>,
+}
+
+impl<X: XConn + 'static> RetagOnDropHandler<X> {
+    pub fn wrap(inner: Box<dyn MouseEventHandler<X>>) -> Box<dyn MouseEventHandler<X>> {
+        Box::new(Self {
+            inner,
+            dragging: None,
+        })
+    }
+}
+
+impl<X: XConn> MouseEventHandler<X> for RetagOnDropHandler<X> {
+    fn on_mouse_event(&mut self, evt: &MouseEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_mouse_event(evt, state, x)?;
+
+        let id = match evt.kind {
+            MouseEventKind::Press => {
+                self.dragging = Some(evt.data.id).filter(|id| state.client_set.contains(id));
+                return Ok(());
+            }
+            MouseEventKind::Release => match self.dragging.take() {
+                Some(id) if state.client_set.is_floating(&id) => id,
+                _ => return Ok(()),
+            },
+        };
+
+        let p = evt.data.rpt;
+        let tag = match state
+            .client_set
+            .screens()
+            .find(|s| s.geometry().contains_point(p))
+        {
+            Some(s) => s.workspace.tag().to_string(),
+            None => return Ok(()),
+        };
+        if state.client_set.tag_for_client(&id) == Some(tag.as_str()) {
+            return Ok(());
+        }
+
+        let r = x.client_geometry(id)?;
+        x.modify_and_refresh(state, |cs| {
+            cs.move_client_to_tag(&id, &tag);
+            let _ = cs.float(id, r);
+            cs.focus_client(&id);
+        })
+    }
+
+    fn on_motion(&mut self, evt: &MotionNotifyEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_motion(evt, state, x)
+    }
+}
+
+/// How close to a screen edge the pointer needs to be for a dragged window to snap to it.
+const SNAP_EDGE: u32 = 8;
+
+/// Along an edge, how close to the adjacent edge counts as the corner.
+const SNAP_CORNER: u32 = 64;
+
+const SNAP_PREVIEW_COLOR: u32 = 0x458588;
+
+/// The half or quarter of `screen` a window dropped at `p` should snap to, if any.
+fn snap_zone(screen: Rect, p: Point) -> Option<Rect> {
+    let (dl, dr) = (
+        p.x.saturating_sub(screen.x),
+        (screen.x + screen.w).saturating_sub(p.x + 1),
+    );
+    let (dt, db) = (
+        p.y.saturating_sub(screen.y),
+        (screen.y + screen.h).saturating_sub(p.y + 1),
+    );
+    let at_x = dl.min(dr) < SNAP_EDGE;
+    let at_y = dt.min(db) < SNAP_EDGE;
+    if !at_x && !at_y {
+        return None;
+    }
+
+    // Once against one edge the other gets a wider reach so that corners are easy to hit.
+    let reach = |at_other: bool| if at_other { SNAP_CORNER } else { SNAP_EDGE };
+    let (x, w) = span(screen.x, screen.w, dl, dr, reach(at_y));
+    let (y, h) = span(screen.y, screen.h, dt, db, reach(at_x));
+
+    Some(Rect::new(x, y, w, h))
+}
+
+// The first or second half of a span depending on which end we are within `reach` of.
+fn span(start: u32, len: u32, before: u32, after: u32, reach: u32) -> (u32, u32) {
+    let half = len / 2;
+    if before < reach {
+        (start, half)
+    } else if after < reach {
+        (start + half, len - half)
+    } else {
+        (start, len)
+    }
+}
+
+/// A plain override-redirect window showing where a dragged window will snap to.
+struct SnapPreview {
+    conn: RustConnection,
+    win: u32,
+}
+
+impl SnapPreview {
+    fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+        let root = conn.setup().roots[screen_num].root;
+        let win = conn.generate_id().map_err(x_err)?;
+        let aux = CreateWindowAux::new()
+            .override_redirect(1)
+            .background_pixel(SNAP_PREVIEW_COLOR);
+
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            win,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &aux,
+        )
+        .map_err(x_err)?;
+
+        Ok(Self { conn, win })
+    }
+
+    /// Show the preview at `r`, stacked just below the window being dragged.
+    fn show(&self, r: Rect, below: Xid) -> Result<()> {
+        let aux = ConfigureWindowAux::new()
+            .x(r.x as i32)
+            .y(r.y as i32)
+            .width(r.w)
+            .height(r.h)
+            .sibling(*below)
+            .stack_mode(StackMode::BELOW);
+
+        self.conn.configure_window(self.win, &aux).map_err(x_err)?;
+        self.conn.map_window(self.win).map_err(x_err)?;
+        self.conn.flush().map_err(x_err)
+    }
+
+    fn hide(&self) -> Result<()> {
+        self.conn.unmap_window(self.win).map_err(x_err)?;
+        self.conn.flush().map_err(x_err)
+    }
+}
+
+impl Drop for SnapPreview {
+    fn drop(&mut self) {
+        let _ = self.conn.destroy_window(self.win);
+        let _ = self.conn.flush();
+    }
+}
+
+/// Wraps a drag handler so that floating windows dropped against a screen edge or corner snap
+/// to that half or quarter of the screen. Dragging a snapped window away restores the size it
+/// had before it was snapped.
+pub struct SnapDragHandler<X: XConn> {
+    inner: Box<dyn MouseEventHandler<X>>,
+    preview: Option<SnapPreview>,
+    dragging: Option<Xid>,
+    zone: Option<Rect>,
+    unsnapped: HashMap<Xid, Rect>,
+}
+
+impl<X: XConn + 'static> SnapDragHandler<X> {
+    pub fn wrap(inner: Box<dyn MouseEventHandler<X>>) -> Box<dyn MouseEventHandler<X>> {
+        Box::new(Self {
+            inner,
+            preview: None,
+            dragging: None,
+            zone: None,
+            unsnapped: HashMap::new(),
+        })
+    }
+}
+
+impl<X: XConn> SnapDragHandler<X> {
+    fn update_preview(&mut self, zone: Option<Rect>, id: Xid) -> Result<()> {
+        if zone == self.zone {
+            return Ok(());
+        }
+        self.zone = zone;
+
+        match zone {
+            Some(r) => {
+                if self.preview.is_none() {
+                    self.preview = Some(SnapPreview::new()?);
+                }
+                self.preview.as_ref().map_or(Ok(()), |p| p.show(r, id))
+            }
+            None => self.preview.as_ref().map_or(Ok(()), |p| p.hide()),
+        }
+    }
+}
+
+impl<X: XConn> MouseEventHandler<X> for SnapDragHandler<X> {
+    fn on_mouse_event(&mut self, evt: &MouseEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_mouse_event(evt, state, x)?;
+        self.unsnapped.retain(|id, _| state.client_set.contains(id));
+
+        match evt.kind {
+            MouseEventKind::Press => {
+                self.dragging = Some(evt.data.id).filter(|id| state.client_set.contains(id));
+                Ok(())
+            }
+
+            MouseEventKind::Release => {
+                let id = match self.dragging.take() {
+                    Some(id) => id,
+                    None => return Ok(()),
+                };
+                let zone = match self.zone {
+                    Some(zone) => zone,
+                    None => {
+                        self.unsnapped.remove(&id);
+                        return Ok(());
+                    }
+                };
+                self.update_preview(None, id)?;
+
+                if !state.client_set.is_floating(&id) {
+                    return Ok(());
+                }
+                if let Entry::Vacant(e) = self.unsnapped.entry(id) {
+                    e.insert(x.client_geometry(id)?);
+                }
+
+                state.client_set.float(id, zone)?;
+                x.refresh(state)
+            }
+        }
+    }
+
+    fn on_motion(&mut self, evt: &MotionNotifyEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        self.inner.on_motion(evt, state, x)?;
+
+        let id = match self.dragging {
+            Some(id) if state.client_set.is_floating(&id) => id,
+            _ => return Ok(()),
+        };
+
+        if let Some(prev) = self.unsnapped.get(&id) {
+            let mut r = x.client_geometry(id)?;
+            if (r.w, r.h) != (prev.w, prev.h) {
+                (r.w, r.h) = (prev.w, prev.h);
+                state.client_set.float(id, r)?;
+                x.position_client(id, r)?;
+            }
+        }
+
+        let p = evt.data.rpt;
+        let zone = state
+            .client_set
+            .screens()
+            .map(|s| s.geometry())
+            .find(|r| r.contains_point(p))
+            .and_then(|r| snap_zone(r, p));
+
+        self.update_preview(zone, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_snap_to_halves_and_corners_to_quarters() {
+        let screen = Rect::new(0, 0, 1000, 800);
+
+        assert_eq!(
+            snap_zone(screen, Point::new(0, 400)),
+            Some(Rect::new(0, 0, 500, 800))
+        );
+        assert_eq!(
+            snap_zone(screen, Point::new(999, 30)),
+            Some(Rect::new(500, 0, 500, 400))
+        );
+        assert_eq!(
+            snap_zone(screen, Point::new(500, 799)),
+            Some(Rect::new(0, 400, 1000, 400))
+        );
+        assert_eq!(snap_zone(screen, Point::new(500, 400)), None);
+    }
+}
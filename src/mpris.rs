@@ -0,0 +1,211 @@
+//! Controlling media players over MPRIS.
+//!
+//! Every player on the session bus is tracked in the order it was last active, the way
+//! `playerctld` does it: a player moves to the front when it appears on the bus or starts
+//! playing. The media keys then act on whichever player is at the front, so the same keys
+//! work for mpv, Firefox and Spotify alike.
+use crate::modal::x_err;
+use penrose::{builtin::actions::key_handler, core::bindings::KeyEventHandler, x::XConn};
+use std::{collections::HashMap, sync::Mutex, thread};
+use tracing::{debug, error};
+use zbus::{
+    blocking::{fdo::DBusProxy, Connection, MessageIterator, Proxy},
+    message::Type,
+    names::BusName,
+    zvariant::OwnedValue,
+    MatchRule,
+};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const MPRIS_PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+static PLAYERS: Mutex<Players> = Mutex::new(Players::new());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Player {
+    /// The well known name, e.g. "org.mpris.MediaPlayer2.mpv".
+    name: String,
+    /// The unique bus name of the connection owning `name`, which signals are sent from.
+    owner: String,
+}
+
+/// The players on the bus, most recently active first.
+#[derive(Debug)]
+struct Players {
+    recent: Vec<Player>,
+}
+
+impl Players {
+    const fn new() -> Self {
+        Self { recent: Vec::new() }
+    }
+
+    fn appeared(&mut self, name: &str, owner: &str) {
+        self.left(name);
+        self.recent.insert(
+            0,
+            Player {
+                name: name.to_string(),
+                owner: owner.to_string(),
+            },
+        );
+    }
+
+    fn left(&mut self, name: &str) {
+        self.recent.retain(|p| p.name != name);
+    }
+
+    /// Move the player owned by `owner` to the front.
+    fn playing(&mut self, owner: &str) {
+        if let Some(ix) = self.recent.iter().position(|p| p.owner == owner) {
+            let player = self.recent.remove(ix);
+            self.recent.insert(0, player);
+        }
+    }
+
+    fn active(&self) -> Option<&str> {
+        self.recent.first().map(|p| p.name.as_str())
+    }
+}
+
+fn with_players(f: impl FnOnce(&mut Players)) {
+    if let Ok(mut players) = PLAYERS.lock() {
+        f(&mut players);
+    }
+}
+
+fn find_players(conn: &Connection) -> zbus::Result<()> {
+    let dbus = DBusProxy::new(conn)?;
+    let mut playing = Vec::new();
+    for name in dbus.list_names()? {
+        let name = name.as_str();
+        if !name.starts_with(MPRIS_PREFIX) {
+            continue;
+        }
+        let owner = dbus.get_name_owner(BusName::try_from(name)?)?;
+        with_players(|players| players.appeared(name, owner.as_str()));
+
+        let status: String = Proxy::new(conn, name, MPRIS_PATH, MPRIS_PLAYER)?
+            .get_property("PlaybackStatus")
+            .unwrap_or_default();
+        if status == "Playing" {
+            playing.push(owner);
+        }
+    }
+    for owner in playing {
+        with_players(|players| players.playing(owner.as_str()));
+    }
+
+    Ok(())
+}
+
+fn watch_names(conn: &Connection) -> zbus::Result<()> {
+    for signal in DBusProxy::new(conn)?.receive_name_owner_changed()? {
+        let args = signal.args()?;
+        let name = args.name().as_str();
+        if !name.starts_with(MPRIS_PREFIX) {
+            continue;
+        }
+        match args.new_owner().as_ref() {
+            Some(owner) => {
+                debug!("{name} appeared on the bus");
+                with_players(|players| players.appeared(name, owner.as_str()));
+            }
+            None => with_players(|players| players.left(name)),
+        }
+    }
+
+    Ok(())
+}
+
+fn watch_playback(conn: &Connection) -> zbus::Result<()> {
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(PROPERTIES)?
+        .member("PropertiesChanged")?
+        .path(MPRIS_PATH)?
+        .arg(0, MPRIS_PLAYER)?
+        .build();
+
+    for msg in MessageIterator::for_match_rule(rule, conn, None)? {
+        let msg = msg?;
+        let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+            msg.body().deserialize()?;
+        let status = changed
+            .get("PlaybackStatus")
+            .and_then(|v| <&str>::try_from(v).ok());
+        if status != Some("Playing") {
+            continue;
+        }
+        if let Some(sender) = msg.header().sender() {
+            with_players(|players| players.playing(sender.as_str()));
+        }
+    }
+
+    Ok(())
+}
+
+fn track() -> zbus::Result<()> {
+    let conn = Connection::session()?;
+    find_players(&conn)?;
+
+    let names = conn.clone();
+    thread::spawn(move || {
+        if let Err(e) = watch_names(&names) {
+            error!("stopped watching for media players: {e}");
+        }
+    });
+
+    watch_playback(&conn)
+}
+
+/// Start keeping track of which media player was most recently active.
+pub fn track_players() {
+    thread::spawn(|| {
+        if let Err(e) = track() {
+            error!("unable to track media players: {e}");
+        }
+    });
+}
+
+/// Call `method` (e.g. "PlayPause" or "Next") on the most recently active media player.
+pub fn media<X: XConn>(method: &'static str) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |_, _| {
+        let name = match PLAYERS
+            .lock()
+            .ok()
+            .and_then(|p| p.active().map(String::from))
+        {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let conn = Connection::session().map_err(x_err)?;
+
+        Proxy::new(&conn, &name, MPRIS_PATH, MPRIS_PLAYER)
+            .and_then(|player| player.call::<_, ()>(method, &()))
+            .map_err(x_err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_player_to_start_playing_is_active() {
+        let mut players = Players::new();
+        players.appeared("org.mpris.MediaPlayer2.spotify", ":1.10");
+        players.appeared("org.mpris.MediaPlayer2.mpv", ":1.20");
+        assert_eq!(players.active(), Some("org.mpris.MediaPlayer2.mpv"));
+
+        players.playing(":1.10");
+        assert_eq!(players.active(), Some("org.mpris.MediaPlayer2.spotify"));
+
+        players.left("org.mpris.MediaPlayer2.spotify");
+        assert_eq!(players.active(), Some("org.mpris.MediaPlayer2.mpv"));
+        players.left("org.mpris.MediaPlayer2.mpv");
+        assert_eq!(players.active(), None);
+    }
+}
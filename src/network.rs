@@ -0,0 +1,259 @@
+//! Following network changes through NetworkManager on the system bus.
+//!
+//! A background thread watches NetworkManager's property changes and, whenever the set of
+//! active connections or VPNs changes, wakes the event loop. The [NetworkHook] then works out
+//! what changed as a list of [NetworkEvent]s for the hooks that follow it to act on (such as
+//! [NetworkScriptHook]), and publishes the active connections to the bar as "network" and
+//! any VPNs as "vpn".
+use crate::{bar, process::run_with_args, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{collections::BTreeSet, sync::Mutex, thread, time::Duration};
+use tracing::error;
+use zbus::{
+    blocking::{Connection, MessageIterator, Proxy},
+    message::Type,
+    zvariant::OwnedObjectPath,
+    MatchRule,
+};
+
+const NETWORK_MANAGER: &str = "org.freedesktop.NetworkManager";
+const NETWORK_MANAGER_PATH: &str = "/org/freedesktop/NetworkManager";
+const ACTIVE_CONNECTION: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+/// `NM_ACTIVE_CONNECTION_STATE_ACTIVATED`
+const ACTIVATED: u32 = 2;
+const NETWORK_CHANGED: &str = "_WM_NETWORK_CHANGED";
+
+static CURRENT: Mutex<Network> = Mutex::new(Network::new());
+
+/// The connections that are up, by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Network {
+    connections: BTreeSet<String>,
+    vpns: BTreeSet<String>,
+}
+
+impl Network {
+    const fn new() -> Self {
+        Self {
+            connections: BTreeSet::new(),
+            vpns: BTreeSet::new(),
+        }
+    }
+
+    fn read(conn: &Connection) -> zbus::Result<Self> {
+        let manager = Proxy::new(conn, NETWORK_MANAGER, NETWORK_MANAGER_PATH, NETWORK_MANAGER)?;
+        let mut network = Self::new();
+        for path in manager.get_property::<Vec<OwnedObjectPath>>("ActiveConnections")? {
+            let active = Proxy::new(conn, NETWORK_MANAGER, path.as_str(), ACTIVE_CONNECTION)?;
+            // Connections can go away between listing them and reading them.
+            if active.get_property::<u32>("State").ok() != Some(ACTIVATED) {
+                continue;
+            }
+            let id: String = active.get_property("Id")?;
+            let kind: String = active.get_property("Type")?;
+            if active.get_property::<bool>("Vpn")? || kind == "wireguard" {
+                network.vpns.insert(id);
+            } else if kind != "loopback" {
+                network.connections.insert(id);
+            }
+        }
+
+        Ok(network)
+    }
+}
+
+/// A change in the network, as found by the [NetworkHook].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkEvent {
+    /// A connection (e.g. a wifi network) came up.
+    Connected(String),
+    /// A connection went down.
+    Disconnected(String),
+    /// A VPN (including WireGuard) came up.
+    VpnUp(String),
+    /// A VPN went down.
+    VpnDown(String),
+}
+
+impl NetworkEvent {
+    /// The event as arguments for a script: its kind ("connected", "disconnected", "vpn-up"
+    /// or "vpn-down") followed by the name of the connection.
+    fn args(&self) -> [&str; 2] {
+        match self {
+            Self::Connected(id) => ["connected", id],
+            Self::Disconnected(id) => ["disconnected", id],
+            Self::VpnUp(id) => ["vpn-up", id],
+            Self::VpnDown(id) => ["vpn-down", id],
+        }
+    }
+}
+
+/// What changed between the network `before` and `after`, with anything going down first.
+fn network_events(before: &Network, after: &Network) -> Vec<NetworkEvent> {
+    let gone = |b: &BTreeSet<String>, a: &BTreeSet<String>| -> Vec<String> {
+        b.difference(a).cloned().collect()
+    };
+    let mut events = Vec::new();
+    events.extend(
+        gone(&before.vpns, &after.vpns)
+            .into_iter()
+            .map(NetworkEvent::VpnDown),
+    );
+    events.extend(
+        gone(&before.connections, &after.connections)
+            .into_iter()
+            .map(NetworkEvent::Disconnected),
+    );
+    events.extend(
+        gone(&after.connections, &before.connections)
+            .into_iter()
+            .map(NetworkEvent::Connected),
+    );
+    events.extend(
+        gone(&after.vpns, &before.vpns)
+            .into_iter()
+            .map(NetworkEvent::VpnUp),
+    );
+
+    events
+}
+
+fn watch() -> zbus::Result<()> {
+    let conn = Connection::system()?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(NETWORK_MANAGER)?
+        .interface(PROPERTIES)?
+        .member("PropertiesChanged")?
+        .build();
+    let mut changes = MessageIterator::for_match_rule(rule, &conn, None)?;
+
+    // Pick up whatever is connected already before waiting for changes.
+    loop {
+        let network = Network::read(&conn)?;
+        let changed = match CURRENT.lock() {
+            Ok(mut current) if *current != network => {
+                *current = network;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            notify_after(Duration::ZERO, NETWORK_CHANGED, [0; 5]);
+        }
+
+        match changes.next() {
+            Some(msg) => {
+                msg?;
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Start watching NetworkManager for changes. This fails quietly if NetworkManager isn't
+/// running.
+pub fn watch_network() {
+    thread::spawn(|| {
+        if let Err(e) = watch() {
+            error!("unable to watch the network: {e}");
+        }
+    });
+}
+
+/// The changes found the last time the network changed, for hooks that follow [NetworkHook]
+/// to act on.
+#[derive(Debug, Default, Clone)]
+pub struct NetworkChanges {
+    pub events: Vec<NetworkEvent>,
+}
+
+/// Works out what changed each time the network does. This needs [watch_network] running.
+#[derive(Debug, Default, Clone)]
+pub struct NetworkHook {
+    network: Network,
+}
+
+impl<X: XConn> EventHook<X> for NetworkHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == NETWORK_CHANGED => (),
+            _ => return Ok(true),
+        }
+
+        let network = match CURRENT.lock() {
+            Ok(current) => current.clone(),
+            Err(_) => return Ok(true),
+        };
+        let events = network_events(&self.network, &network);
+        state
+            .extension_or_default::<NetworkChanges>()
+            .borrow_mut()
+            .events = events;
+
+        let join = |ids: &BTreeSet<String>| ids.iter().cloned().collect::<Vec<_>>().join(",");
+        bar::publish(state, "network", join(&network.connections));
+        bar::publish(state, "vpn", join(&network.vpns));
+        self.network = network;
+
+        Ok(true)
+    }
+}
+
+/// Runs `script` for each [NetworkEvent] with the arguments described in
+/// [NetworkEvent::args], e.g. `script disconnected HomeWifi`.
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkScriptHook {
+    pub script: &'static str,
+}
+
+impl<X: XConn> EventHook<X> for NetworkScriptHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == NETWORK_CHANGED => (),
+            _ => return Ok(true),
+        }
+
+        let changes = state
+            .extension_or_default::<NetworkChanges>()
+            .borrow()
+            .clone();
+        for e in changes.events.iter() {
+            run_with_args(self.script, &e.args())?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(connections: &[&str], vpns: &[&str]) -> Network {
+        Network {
+            connections: connections.iter().map(|s| s.to_string()).collect(),
+            vpns: vpns.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn changes_take_things_down_before_bringing_them_up() {
+        let events = network_events(&network(&["HomeWifi"], &["work"]), &network(&["Cafe"], &[]));
+
+        assert_eq!(
+            events,
+            vec![
+                NetworkEvent::VpnDown("work".into()),
+                NetworkEvent::Disconnected("HomeWifi".into()),
+                NetworkEvent::Connected("Cafe".into()),
+            ]
+        );
+        assert_eq!(events[1].args(), ["disconnected", "HomeWifi"]);
+    }
+}
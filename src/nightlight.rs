@@ -0,0 +1,243 @@
+//! Warming the colour of the screens in the evening, in the style of redshift.
+//!
+//! The night light is applied through the RandR gamma ramps of every output. It can follow a
+//! daily schedule, be toggled by hand, or both: whichever changed it last wins until the next
+//! scheduled change. Its state is saved so that it survives restarting the window manager.
+//! The same ramps are used to dim the screens ahead of the idle lock.
+use crate::{
+    bar,
+    monitor::{Randr, RANDR_SETTLED},
+    paths::cache_file,
+    timer::notify_after,
+};
+use chrono::{Local, Timelike};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{fs, time::Duration};
+use tracing::warn;
+
+/// Sent when the night light is next due to turn on or off.
+const NIGHT_LIGHT_TICK: &str = "_WM_NIGHT_LIGHT_TICK";
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// The red, green and blue gamma factors for a colour temperature in Kelvin, using Tanner
+/// Helland's approximation of the black body curve. 6600K and above leave colours unchanged.
+fn temperature_rgb(kelvin: u32) -> (f64, f64, f64) {
+    let t = kelvin.clamp(1000, 6600) as f64 / 100.0;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    let unit = |c: f64| (c / 255.0).clamp(0.0, 1.0);
+
+    (unit(r), unit(g), unit(b))
+}
+
+/// Whether `now` falls between `start` and `end`, all in minutes since midnight. The period
+/// may run past midnight.
+fn within(now: u32, start: u32, end: u32) -> bool {
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+/// How many minutes there are from `now` until the next of `start` and `end`.
+fn minutes_until_next(now: u32, start: u32, end: u32) -> u32 {
+    let until = |t: u32| match (t + MINUTES_PER_DAY - now) % MINUTES_PER_DAY {
+        0 => MINUTES_PER_DAY,
+        m => m,
+    };
+
+    until(start).min(until(end))
+}
+
+fn save(on: bool) -> std::io::Result<()> {
+    let path = cache_file("nightlight");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, if on { "on" } else { "off" })
+}
+
+/// Whether the night light is currently on.
+#[derive(Debug, Default, Clone, Copy)]
+struct Enabled(bool);
+
+/// The gamma factors the night light last asked for, and how far the screens are dimmed on
+/// top of that.
+#[derive(Debug, Clone, Copy)]
+struct Gamma {
+    rgb: (f64, f64, f64),
+    brightness: f64,
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Self {
+            rgb: (1.0, 1.0, 1.0),
+            brightness: 1.0,
+        }
+    }
+}
+
+fn apply_gamma<X: XConn>(state: &mut State<X>) -> Result<()> {
+    let Gamma {
+        rgb: (r, g, b),
+        brightness,
+    } = *state.extension_or_default::<Gamma>().borrow();
+
+    Randr::connect()?.set_gamma((r * brightness, g * brightness, b * brightness))
+}
+
+/// Dim every screen to `brightness` (from 0.0 to 1.0) through its gamma ramps, on top of
+/// whatever the night light is doing.
+pub fn set_brightness<X: XConn>(state: &mut State<X>, brightness: f64) -> Result<()> {
+    state
+        .extension_or_default::<Gamma>()
+        .borrow_mut()
+        .brightness = brightness.clamp(0.0, 1.0);
+
+    apply_gamma(state)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NightLight {
+    /// The colour temperature in Kelvin while the night light is off.
+    pub day: u32,
+    /// The colour temperature in Kelvin while the night light is on.
+    pub night: u32,
+    /// The (hour, minute) to turn on and off at, or `None` to only change it by hand.
+    pub schedule: Option<((u32, u32), (u32, u32))>,
+}
+
+impl NightLight {
+    fn scheduled(&self) -> Option<(u32, u32, u32)> {
+        let ((sh, sm), (eh, em)) = self.schedule?;
+        let now = Local::now();
+
+        Some((now.hour() * 60 + now.minute(), sh * 60 + sm, eh * 60 + em))
+    }
+
+    /// Wake up when the schedule next calls for a change.
+    fn schedule_tick(&self) {
+        if let Some((now, start, end)) = self.scheduled() {
+            let secs = minutes_until_next(now, start, end) as u64 * 60;
+            let since_minute = Local::now().second() as u64;
+            let delay = Duration::from_secs(secs.saturating_sub(since_minute));
+            notify_after(delay, NIGHT_LIGHT_TICK, [0; 5]);
+        }
+    }
+
+    fn set<X: XConn>(&self, state: &mut State<X>, on: bool) -> Result<()> {
+        *state.extension_or_default::<Enabled>().borrow_mut() = Enabled(on);
+        if let Err(e) = save(on) {
+            warn!("unable to save the night light state: {e}");
+        }
+        bar::publish(state, "nightlight", if on { "on" } else { "" });
+
+        self.apply(state)
+    }
+
+    fn apply<X: XConn>(&self, state: &mut State<X>) -> Result<()> {
+        let on = state.extension_or_default::<Enabled>().borrow().0;
+        let kelvin = if on { self.night } else { self.day };
+        state.extension_or_default::<Gamma>().borrow_mut().rgb = temperature_rgb(kelvin);
+
+        apply_gamma(state)
+    }
+}
+
+impl<X: XConn> EventHook<X> for NightLight {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) => msg,
+            _ => return Ok(true),
+        };
+
+        if msg.dtype == NIGHT_LIGHT_TICK {
+            if let Some((now, start, end)) = self.scheduled() {
+                self.set(state, within(now, start, end))?;
+            }
+            self.schedule_tick();
+            return Ok(false);
+        } else if msg.dtype == RANDR_SETTLED {
+            // Outputs that have just been turned on start out with the default gamma ramps.
+            self.apply(state)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for NightLight {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let saved = fs::read_to_string(cache_file("nightlight")).ok();
+        let on = match saved.as_deref().map(str::trim) {
+            Some("on") => true,
+            Some("off") => false,
+            _ => self
+                .scheduled()
+                .is_some_and(|(now, start, end)| within(now, start, end)),
+        };
+
+        self.schedule_tick();
+        self.set(state, on)
+    }
+}
+
+/// Turn the night light on or off until the schedule next changes it.
+pub fn toggle_night_light<X: XConn>(config: NightLight) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let on = state.extension_or_default::<Enabled>().borrow().0;
+        config.set(state, !on)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warmer_temperatures_cut_blue_first() {
+        assert_eq!(temperature_rgb(6600), (1.0, 1.0, 1.0));
+
+        let (r, g, b) = temperature_rgb(3400);
+        assert_eq!(r, 1.0);
+        assert!(b < g && g < 1.0);
+    }
+
+    #[test]
+    fn schedules_can_run_past_midnight() {
+        let (start, end) = (21 * 60, 7 * 60);
+
+        assert!(within(23 * 60, start, end));
+        assert!(within(6 * 60, start, end));
+        assert!(!within(12 * 60, start, end));
+        assert_eq!(minutes_until_next(20 * 60, start, end), 60);
+        assert_eq!(minutes_until_next(end, start, end), 14 * 60);
+    }
+}
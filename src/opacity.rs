@@ -0,0 +1,116 @@
+//! Window transparency via _NET_WM_WINDOW_OPACITY, for compositors such as picom to apply.
+use crate::client::wm_class;
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::StateHook, State},
+    x::{Prop, XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::HashMap;
+
+const NET_WM_WINDOW_OPACITY: &str = "_NET_WM_WINDOW_OPACITY";
+
+/// The most transparent a client can be made from the key bindings.
+const MIN_OPACITY: f64 = 0.1;
+
+/// Opacity set by hand for individual clients along with what is currently applied to each
+/// client, so the property is only rewritten when it changes.
+#[derive(Debug, Default, Clone)]
+pub struct Opacity {
+    manual: HashMap<Xid, f64>,
+    applied: HashMap<Xid, u32>,
+    classes: HashMap<Xid, Option<String>>,
+}
+
+/// Opacity as the cardinal value the property expects, where u32::MAX is fully opaque.
+fn as_cardinal(opacity: f64) -> u32 {
+    (opacity.clamp(0.0, 1.0) * u32::MAX as f64).round() as u32
+}
+
+/// Make the focused client more (positive `delta`) or less (negative `delta`) opaque. This
+/// takes precedence over any rule matching the client.
+pub fn adjust_opacity<X: XConn>(delta: f64) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let id = match state.client_set.current_client() {
+            Some(&id) => id,
+            None => return Ok(()),
+        };
+
+        {
+            let ext = state.extension_or_default::<Opacity>();
+            let mut opacity = ext.borrow_mut();
+            let current = opacity.manual.get(&id).copied().unwrap_or(1.0);
+            opacity
+                .manual
+                .insert(id, (current + delta).clamp(MIN_OPACITY, 1.0));
+        }
+
+        x.refresh(state)
+    })
+}
+
+/// Applies opacity rules after each refresh: clients with a matching WM_CLASS are drawn at
+/// the given opacity while unfocused and fully opaque when focused.
+#[derive(Debug, Clone)]
+pub struct OpacityHook {
+    unfocused: HashMap<String, f64>,
+}
+
+impl OpacityHook {
+    pub fn new(unfocused: &[(&str, f64)]) -> Self {
+        Self {
+            unfocused: unfocused
+                .iter()
+                .map(|&(class, o)| (class.to_string(), o))
+                .collect(),
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for OpacityHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Opacity>();
+        let mut opacity = ext.borrow_mut();
+        let cs = &state.client_set;
+        opacity.manual.retain(|id, _| cs.contains(id));
+        opacity.applied.retain(|id, _| cs.contains(id));
+        opacity.classes.retain(|id, _| cs.contains(id));
+
+        let focused = cs.current_client().copied();
+        for &id in cs.clients() {
+            let class = opacity
+                .classes
+                .entry(id)
+                .or_insert_with(|| wm_class(x, id))
+                .clone();
+            let target = match opacity.manual.get(&id) {
+                Some(&o) => o,
+                None if focused == Some(id) => 1.0,
+                None => class
+                    .and_then(|c| self.unfocused.get(&c).copied())
+                    .unwrap_or(1.0),
+            };
+
+            let value = as_cardinal(target);
+            if opacity.applied.get(&id) != Some(&value) {
+                x.set_prop(id, NET_WM_WINDOW_OPACITY, Prop::Cardinal(vec![value]))?;
+                opacity.applied.insert(id, value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opacity_is_clamped_to_the_cardinal_range() {
+        assert_eq!(as_cardinal(1.0), u32::MAX);
+        assert_eq!(as_cardinal(1.5), u32::MAX);
+        assert_eq!(as_cardinal(0.0), 0);
+        assert_eq!(as_cardinal(0.5), u32::MAX / 2 + 1);
+    }
+}
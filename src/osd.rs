@@ -0,0 +1,193 @@
+//! A brief on screen display of levels such as volume and brightness.
+//!
+//! The display is a single override-redirect window on a connection of its own, shown near the
+//! bottom of the focused screen and hidden again shortly after the last time it was updated.
+use crate::{modal::x_err, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    pure::geometry::Rect,
+    x::{XConn, XEvent},
+    Result,
+};
+use std::time::Duration;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{
+        ConfigureWindowAux, ConnectionExt, CreateGCAux, CreateWindowAux, Rectangle, StackMode,
+        WindowClass,
+    },
+    rust_connection::RustConnection,
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT,
+};
+
+const OSD_HIDE: &str = "_WM_OSD_HIDE";
+const TIMEOUT: Duration = Duration::from_millis(1500);
+
+const WIDTH: u32 = 240;
+const HEIGHT: u32 = 44;
+const PADDING: u32 = 10;
+const BAR_HEIGHT: u32 = 6;
+const BACKGROUND: u32 = 0x282828;
+const FOREGROUND: u32 = 0xebdbb2;
+
+// Metrics of the core "fixed" font (6x13).
+const FONT: &[u8] = b"fixed";
+const TEXT_BASELINE: i16 = 20;
+
+struct Window {
+    conn: RustConnection,
+    win: u32,
+    gc: u32,
+}
+
+impl Window {
+    fn new() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let font = conn.generate_id().map_err(x_err)?;
+        conn.open_font(font, FONT).map_err(x_err)?;
+        let gc = conn.generate_id().map_err(x_err)?;
+        let aux = CreateGCAux::new()
+            .foreground(FOREGROUND)
+            .background(BACKGROUND)
+            .font(font);
+        conn.create_gc(gc, root, &aux).map_err(x_err)?;
+
+        let win = conn.generate_id().map_err(x_err)?;
+        let aux = CreateWindowAux::new()
+            .override_redirect(1)
+            .background_pixel(BACKGROUND);
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            win,
+            root,
+            0,
+            0,
+            WIDTH as u16,
+            HEIGHT as u16,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &aux,
+        )
+        .map_err(x_err)?;
+
+        Ok(Self { conn, win, gc })
+    }
+
+    fn show(&self, screen: Rect, label: &str, level: Option<u8>) -> Result<()> {
+        let (x, y) = placement(screen);
+        let aux = ConfigureWindowAux::new()
+            .x(x)
+            .y(y)
+            .stack_mode(StackMode::ABOVE);
+        self.conn.configure_window(self.win, &aux).map_err(x_err)?;
+        self.conn.map_window(self.win).map_err(x_err)?;
+
+        self.conn
+            .clear_area(false, self.win, 0, 0, 0, 0)
+            .map_err(x_err)?;
+        self.conn
+            .image_text8(
+                self.win,
+                self.gc,
+                PADDING as i16,
+                TEXT_BASELINE,
+                label.as_bytes(),
+            )
+            .map_err(x_err)?;
+        if let Some(level) = level {
+            let full = WIDTH - 2 * PADDING;
+            let bar = Rectangle {
+                x: PADDING as i16,
+                y: (HEIGHT - PADDING - BAR_HEIGHT) as i16,
+                width: (full * level.min(100) as u32 / 100) as u16,
+                height: BAR_HEIGHT as u16,
+            };
+            self.conn
+                .poly_fill_rectangle(self.win, self.gc, &[bar])
+                .map_err(x_err)?;
+        }
+        self.conn.flush().map_err(x_err)?;
+
+        Ok(())
+    }
+
+    fn hide(&self) -> Result<()> {
+        self.conn.unmap_window(self.win).map_err(x_err)?;
+        self.conn.flush().map_err(x_err)?;
+
+        Ok(())
+    }
+}
+
+/// Where to put the display on `screen`: centred, a sixth of the way up from the bottom.
+fn placement(screen: Rect) -> (i32, i32) {
+    let x = screen.x as i32 + (screen.w as i32 - WIDTH as i32) / 2;
+    let y = screen.y as i32 + screen.h as i32 * 5 / 6 - HEIGHT as i32 / 2;
+
+    (x, y)
+}
+
+#[derive(Default)]
+struct Osd {
+    window: Option<Window>,
+    /// Bumped every time the display is shown, so that only the last timeout hides it.
+    shown: u32,
+}
+
+/// Show `label` (and `level` as a percentage bar, if given) on the focused screen for a moment.
+pub fn show<X: XConn>(state: &mut State<X>, label: &str, level: Option<u8>) -> Result<()> {
+    let screen = state.client_set.current_screen().geometry();
+    let ext = state.extension_or_default::<Osd>();
+    let mut osd = ext.borrow_mut();
+    if osd.window.is_none() {
+        osd.window = Some(Window::new()?);
+    }
+    if let Some(window) = osd.window.as_ref() {
+        window.show(screen, label, level)?;
+    }
+
+    osd.shown = osd.shown.wrapping_add(1);
+    notify_after(TIMEOUT, OSD_HIDE, [osd.shown, 0, 0, 0, 0]);
+
+    Ok(())
+}
+
+/// Hides the display once it has been left alone for long enough.
+#[derive(Debug, Clone, Copy)]
+pub struct OsdHook;
+
+impl<X: XConn> EventHook<X> for OsdHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let shown = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == OSD_HIDE => {
+                msg.data.as_usize().first().copied()
+            }
+            _ => return Ok(true),
+        };
+
+        let ext = state.extension_or_default::<Osd>();
+        let osd = ext.borrow();
+        if let Some(window) = osd
+            .window
+            .as_ref()
+            .filter(|_| shown == Some(osd.shown as usize))
+        {
+            window.hide()?;
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_display_sits_low_in_the_middle_of_the_screen() {
+        assert_eq!(placement(Rect::new(1920, 0, 2560, 1440)), (3080, 1178));
+    }
+}
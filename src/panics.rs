@@ -0,0 +1,226 @@
+//! Recovering from panics in hooks and bindings.
+//!
+//! Every binding, and every hook added with [traced](crate::spans::traced), is wrapped so that
+//! a panic inside it unwinds no further than the wrapper: it is logged, reported with
+//! notify-send and the window manager carries on, rather than dying and taking the whole X
+//! session with it. Hooks are wrapped one by one as they are added, so the hooks composed
+//! after one that panics still run, and [catch_hook_panics] catches anything left over from
+//! hooks added some other way (such as penrose's own EWMH hooks). Whatever the panicking
+//! code was part way through doing is left as it was. Repeats of the same panic (e.g. from a
+//! refresh hook that panics on every refresh) are only reported once every [RENOTIFY_AFTER].
+//!
+//! Startup hooks are composed into a single chain that stops at the first one to return an
+//! error, so those that the rest of startup can do without are wrapped with [non_essential]
+//! to have their errors logged instead.
+use crate::{process::run_with_args, spans::Traced};
+use penrose::{
+    core::{
+        bindings::{KeyEventHandler, MotionNotifyEvent, MouseEvent, MouseEventHandler},
+        hooks::{EventHook, ManageHook, StateHook},
+        Config, State,
+    },
+    x::{XConn, XEvent},
+    Result, Xid,
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    hash::Hash,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{error, warn};
+
+const RENOTIFY_AFTER: Duration = Duration::from_secs(60);
+
+/// The last panic reported, and when.
+static LAST_REPORTED: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+/// Log panics through tracing, with where they happened, rather than to stderr.
+pub fn log_panics() {
+    panic::set_hook(Box::new(|info| error!("{info}")));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Whether to report `message` given the last panic reported.
+fn should_report(last: Option<&(String, Instant)>, message: &str, now: Instant) -> bool {
+    match last {
+        Some((m, at)) => m != message || now.duration_since(*at) >= RENOTIFY_AFTER,
+        None => true,
+    }
+}
+
+fn report(what: &str, message: &str) {
+    warn!("recovered from a panic in {what}");
+    let message = format!("{what} panicked: {message}");
+    let now = Instant::now();
+    if let Ok(mut last) = LAST_REPORTED.lock() {
+        if !should_report(last.as_ref(), &message, now) {
+            return;
+        }
+        *last = Some((message.clone(), now));
+    }
+
+    let args = ["-u", "critical", "Window manager error", message.as_str()];
+    if let Err(e) = run_with_args("notify-send", &args) {
+        error!("unable to report a panic: {e}");
+    }
+}
+
+/// Run `f`, returning `None` if it panicked.
+pub(crate) fn catch<T>(what: &str, f: impl FnOnce() -> T) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => Some(res),
+        Err(payload) => {
+            report(what, panic_message(payload.as_ref()));
+            None
+        }
+    }
+}
+
+/// A hook or binding with any panics inside of it caught.
+struct Caught<H> {
+    what: String,
+    inner: H,
+}
+
+impl<H> Caught<H> {
+    fn new(what: impl Into<String>, inner: H) -> Self {
+        Self {
+            what: what.into(),
+            inner,
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for Caught<Box<dyn EventHook<X>>> {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        // Let penrose handle the event as normal if a hook panicked part way through it.
+        catch(&self.what, || self.inner.call(event, state, x)).unwrap_or(Ok(true))
+    }
+}
+
+impl<X: XConn> ManageHook<X> for Caught<Box<dyn ManageHook<X>>> {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        catch(&self.what, || self.inner.call(client, state, x)).unwrap_or(Ok(()))
+    }
+}
+
+impl<X: XConn> StateHook<X> for Caught<Box<dyn StateHook<X>>> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        catch(&self.what, || self.inner.call(state, x)).unwrap_or(Ok(()))
+    }
+}
+
+impl<X: XConn> KeyEventHandler<X> for Caught<Box<dyn KeyEventHandler<X>>> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        catch(&self.what, || self.inner.call(state, x)).unwrap_or(Ok(()))
+    }
+}
+
+impl<X: XConn> MouseEventHandler<X> for Caught<Box<dyn MouseEventHandler<X>>> {
+    fn on_mouse_event(&mut self, evt: &MouseEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        catch(&self.what, || self.inner.on_mouse_event(evt, state, x)).unwrap_or(Ok(()))
+    }
+
+    fn on_motion(&mut self, evt: &MotionNotifyEvent, state: &mut State<X>, x: &X) -> Result<()> {
+        catch(&self.what, || self.inner.on_motion(evt, state, x)).unwrap_or(Ok(()))
+    }
+}
+
+/// A startup hook whose errors are logged rather than stopping the startup hooks after it.
+#[derive(Debug, Clone)]
+pub struct NonEssential<H> {
+    inner: Traced<H>,
+}
+
+/// Log `hook` failing at startup and carry on with the rest of startup.
+pub fn non_essential<H>(hook: Traced<H>) -> NonEssential<H> {
+    NonEssential { inner: hook }
+}
+
+impl<X: XConn, H: StateHook<X>> StateHook<X> for NonEssential<H> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        if let Err(e) = self.inner.call(state, x) {
+            warn!("{} failed at startup: {e}", self.inner.name());
+        }
+
+        Ok(())
+    }
+}
+
+/// Catch panics in every hook set on `config`. This needs to come after all of the hooks
+/// have been added.
+pub fn catch_hook_panics<X: XConn + 'static>(mut config: Config<X>) -> Config<X> {
+    config.event_hook = config
+        .event_hook
+        .take()
+        .map(|h| Box::new(Caught::new("an event hook", h)) as _);
+    config.manage_hook = config
+        .manage_hook
+        .take()
+        .map(|h| Box::new(Caught::new("a manage hook", h)) as _);
+    config.startup_hook = config
+        .startup_hook
+        .take()
+        .map(|h| Box::new(Caught::new("a startup hook", h)) as _);
+    config.refresh_hook = config
+        .refresh_hook
+        .take()
+        .map(|h| Box::new(Caught::new("a refresh hook", h)) as _);
+
+    config
+}
+
+/// Catch panics in key bindings, reporting them with the keys they are bound to.
+pub fn catch_key_panics<X: XConn + 'static>(
+    bindings: HashMap<String, Box<dyn KeyEventHandler<X>>>,
+) -> HashMap<String, Box<dyn KeyEventHandler<X>>> {
+    bindings
+        .into_iter()
+        .map(|(keys, h)| {
+            let what = format!("the {keys} binding");
+            (keys, Box::new(Caught::new(what, h)) as _)
+        })
+        .collect()
+}
+
+/// Catch panics in mouse bindings.
+pub fn catch_mouse_panics<K: Eq + Hash, X: XConn + 'static>(
+    bindings: HashMap<K, Box<dyn MouseEventHandler<X>>>,
+) -> HashMap<K, Box<dyn MouseEventHandler<X>>> {
+    bindings
+        .into_iter()
+        .map(|(k, h)| (k, Box::new(Caught::new("a mouse binding", h)) as _))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_panics_are_only_reported_once_a_minute() {
+        let now = Instant::now();
+        let last = ("a refresh hook panicked: oops".to_string(), now);
+
+        assert!(should_report(None, &last.0, now));
+        assert!(!should_report(Some(&last), &last.0, now));
+        assert!(should_report(
+            Some(&last),
+            "a startup hook panicked: oops",
+            now
+        ));
+        assert!(should_report(Some(&last), &last.0, now + RENOTIFY_AFTER));
+    }
+}
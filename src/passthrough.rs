@@ -0,0 +1,83 @@
+//! Temporarily handing every key binding bar one over to the focused application.
+//!
+//! VMs, remote desktops and games often want Super based shortcuts of their own, which never
+//! reach them while the root window holds passive grabs for our bindings. Passthrough mode
+//! releases those grabs (keeping only the toggle) until it is switched off again.
+use crate::{bar, modal::x_err};
+use penrose::{
+    core::{bindings::KeyCode, bindings::KeyEventHandler, State},
+    x::XConn,
+    x11rb::RustConn,
+    Result,
+};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ConnectionExt, GrabMode, ModMask},
+};
+
+/// The key handler for the passthrough toggle. `release` must be given every other binding
+/// once the bindings have been parsed.
+#[derive(Debug, Default, Clone)]
+pub struct Passthrough {
+    released: Rc<RefCell<Vec<KeyCode>>>,
+    active: Rc<Cell<bool>>,
+}
+
+impl Passthrough {
+    /// The bindings to release while passthrough is active.
+    pub fn release(&self, codes: impl IntoIterator<Item = KeyCode>) {
+        *self.released.borrow_mut() = codes.into_iter().collect();
+    }
+
+    pub fn handler(&self) -> Box<dyn KeyEventHandler<RustConn>> {
+        Box::new(self.clone())
+    }
+
+    /// Switch passthrough on or off, doing nothing if it is already that way.
+    pub fn set(&self, active: bool, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+        if active == self.active.get() {
+            return Ok(());
+        }
+        let released = self.released.borrow();
+
+        let conn = x.connection();
+        // Penrose grabs each binding with and without num lock held, so both of those
+        // variants need releasing and grabbing again.
+        let locks = [ModMask::from(0u16), ModMask::M2];
+        for k in released.iter() {
+            for m in locks {
+                let mods = ModMask::from(k.mask) | m;
+                if active {
+                    conn.ungrab_key(k.code, *x.root(), mods).map_err(x_err)?;
+                } else {
+                    // Not XConn::grab, which would drop every other key and button grab.
+                    conn.grab_key(
+                        false,
+                        *x.root(),
+                        mods,
+                        k.code,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )
+                    .map_err(x_err)?;
+                }
+            }
+        }
+        conn.flush().map_err(x_err)?;
+
+        self.active.set(active);
+        bar::publish(state, "passthrough", if active { "on" } else { "" });
+
+        Ok(())
+    }
+}
+
+impl KeyEventHandler<RustConn> for Passthrough {
+    fn call(&mut self, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+        self.set(!self.active.get(), state, x)
+    }
+}
@@ -0,0 +1,57 @@
+//! Locations of the files the window manager reads and writes outside of its config.
+use std::{env, path::PathBuf};
+
+/// Names an instance of the window manager other than the one running the session, keeping
+/// its runtime files apart.
+pub const INSTANCE_VAR: &str = "WM_INSTANCE";
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(fallback),
+    }
+}
+
+/// The directories to look for config in, most important first: `$XDG_CONFIG_HOME` and then
+/// each of `$XDG_CONFIG_DIRS`.
+pub fn config_dirs() -> Vec<PathBuf> {
+    let system = env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+
+    std::iter::once(xdg_dir("XDG_CONFIG_HOME", ".config"))
+        .chain(
+            system
+                .split(':')
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from),
+        )
+        .collect()
+}
+
+/// A file under `$XDG_CACHE_HOME/wm`, for state that can safely be lost.
+pub fn cache_file(name: &str) -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache").join("wm").join(name)
+}
+
+/// A file under `$XDG_STATE_HOME/wm`, for state that should be kept.
+pub fn state_file(name: &str) -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+        .join("wm")
+        .join(name)
+}
+
+/// A file under `$XDG_RUNTIME_DIR/wm`, for state that only makes sense for this session.
+/// Other instances use `wm-$WM_INSTANCE` instead.
+pub fn runtime_file(name: &str) -> PathBuf {
+    let dir = match env::var(INSTANCE_VAR) {
+        Ok(instance) if !instance.is_empty() => format!("wm-{instance}"),
+        _ => "wm".to_string(),
+    };
+
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join(dir).join(name),
+        _ => xdg_dir("XDG_CACHE_HOME", ".cache").join(dir).join(name),
+    }
+}
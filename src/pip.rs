@@ -0,0 +1,119 @@
+//! Automatic handling of browser picture-in-picture windows.
+use crate::{stacking::mark_above, sticky::set_sticky};
+use penrose::{
+    core::{hooks::ManageHook, State},
+    pure::geometry::Rect,
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+
+/// Window titles used by Firefox and Chromium for their picture-in-picture players.
+const PIP_TITLES: [&str; 2] = ["picture-in-picture", "picture in picture"];
+
+/// Gap left between an anchored window and the edges of its screen.
+const PIP_MARGIN: u32 = 10;
+
+/// The smallest width a picture-in-picture window will be clamped to.
+const PIP_MIN_WIDTH: u32 = 240;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    /// Position a window of the given size in this corner of `screen`.
+    fn anchor(&self, screen: Rect, w: u32, h: u32) -> Rect {
+        let left = screen.x + PIP_MARGIN;
+        let right = (screen.x + screen.w).saturating_sub(w + PIP_MARGIN);
+        let top = screen.y + PIP_MARGIN;
+        let bottom = (screen.y + screen.h).saturating_sub(h + PIP_MARGIN);
+
+        match self {
+            Corner::TopLeft => Rect::new(left, top, w, h),
+            Corner::TopRight => Rect::new(right, top, w, h),
+            Corner::BottomLeft => Rect::new(left, bottom, w, h),
+            Corner::BottomRight => Rect::new(right, bottom, w, h),
+        }
+    }
+}
+
+/// Floats picture-in-picture windows in a corner of their screen, keeping them above other
+/// windows and sticky across tags.
+#[derive(Debug, Clone, Copy)]
+pub struct PictureInPictureHook {
+    pub corner: Corner,
+    /// The maximum fraction of the screen width and height the window may take up.
+    pub max_size: f64,
+}
+
+impl Default for PictureInPictureHook {
+    fn default() -> Self {
+        Self {
+            corner: Corner::default(),
+            max_size: 0.3,
+        }
+    }
+}
+
+impl PictureInPictureHook {
+    fn geometry(&self, screen: Rect, current: Rect) -> Rect {
+        let max_w = ((screen.w as f64 * self.max_size) as u32).max(PIP_MIN_WIDTH);
+        let max_h = (screen.h as f64 * self.max_size) as u32;
+        let aspect = current.h.max(1) as f64 / current.w.max(1) as f64;
+
+        let mut w = current.w.clamp(PIP_MIN_WIDTH, max_w);
+        let mut h = (w as f64 * aspect) as u32;
+        if h > max_h {
+            h = max_h;
+            w = (h as f64 / aspect) as u32;
+        }
+
+        self.corner.anchor(screen, w, h)
+    }
+}
+
+fn is_pip<X: XConn>(x: &X, id: Xid) -> bool {
+    let title = x.window_title(id).unwrap_or_default().to_lowercase();
+
+    PIP_TITLES.iter().any(|t| title == *t)
+}
+
+impl<X: XConn> ManageHook<X> for PictureInPictureHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if !is_pip(x, client) {
+            return Ok(());
+        }
+
+        let screen = match state.client_set.screen_for_client(&client) {
+            Some(s) => s.geometry(),
+            None => state.client_set.current_screen().geometry(),
+        };
+        let r = self.geometry(screen, x.client_geometry(client)?);
+
+        state.client_set.float(client, r)?;
+        set_sticky(client, true, state);
+        mark_above(client, true, state, x)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_windows_are_clamped_and_anchored() {
+        let hook = PictureInPictureHook::default();
+        let screen = Rect::new(0, 0, 1000, 1000);
+
+        let r = hook.geometry(screen, Rect::new(0, 0, 800, 400));
+
+        assert_eq!(r, Rect::new(690, 840, 300, 150));
+    }
+}
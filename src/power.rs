@@ -0,0 +1,165 @@
+//! Switching power profiles through power-profiles-daemon.
+//!
+//! The profile is picked automatically: performance while plugged in with one of the
+//! configured tags focused, power saving on battery and balanced otherwise. Cycling through
+//! the profiles by hand overrides that until the automatic choice next changes. The active
+//! profile is published to the bar as "power".
+use crate::{bar, battery::on_battery, modal::x_err, timer::notify_after};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{thread, time::Duration};
+use tracing::{info, warn};
+use zbus::blocking::{Connection, Proxy};
+
+const POWER_PROFILES: &str = "net.hadess.PowerProfiles";
+const POWER_PROFILES_PATH: &str = "/net/hadess/PowerProfiles";
+/// Sent to check whether the machine has been plugged in or unplugged.
+const POWER_CHECK: &str = "_WM_POWER_CHECK";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Profile {
+    PowerSaver,
+    Balanced,
+    Performance,
+}
+
+impl Profile {
+    fn name(&self) -> &'static str {
+        match self {
+            Profile::PowerSaver => "power-saver",
+            Profile::Balanced => "balanced",
+            Profile::Performance => "performance",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            Profile::PowerSaver => Profile::Balanced,
+            Profile::Balanced => Profile::Performance,
+            Profile::Performance => Profile::PowerSaver,
+        }
+    }
+
+    /// The profile to use given how the machine is powered and what is focused.
+    fn wanted(on_battery: bool, performance_tag: bool) -> Self {
+        match (on_battery, performance_tag) {
+            (true, _) => Profile::PowerSaver,
+            (false, true) => Profile::Performance,
+            (false, false) => Profile::Balanced,
+        }
+    }
+}
+
+fn set(profile: Profile) -> Result<()> {
+    let conn = Connection::system().map_err(x_err)?;
+    let daemon =
+        Proxy::new(&conn, POWER_PROFILES, POWER_PROFILES_PATH, POWER_PROFILES).map_err(x_err)?;
+
+    daemon
+        .set_property("ActiveProfile", profile.name())
+        .map_err(x_err)
+}
+
+#[derive(Debug, Default)]
+struct Profiles {
+    /// The profile last picked automatically.
+    auto: Option<Profile>,
+    /// The profile last switched to, whether automatically or by hand.
+    active: Option<Profile>,
+    /// Whether the periodic [POWER_CHECK] has been started.
+    checking: bool,
+}
+
+/// Switch to `profile` on a thread of its own, so that the event loop isn't kept waiting on
+/// the system bus. A missing power-profiles-daemon is only logged.
+fn switch<X: XConn>(state: &mut State<X>, profile: Profile) {
+    thread::spawn(move || {
+        info!("switching to the {} power profile", profile.name());
+        if let Err(e) = set(profile) {
+            warn!(
+                "unable to switch to the {} power profile: {e}",
+                profile.name()
+            );
+        }
+    });
+    state.extension_or_default::<Profiles>().borrow_mut().active = Some(profile);
+    bar::publish(state, "power", profile.name());
+}
+
+/// Picks the power profile automatically. This needs registering as an event hook and as a
+/// refresh hook.
+#[derive(Debug, Clone, Copy)]
+pub struct PowerProfiles {
+    /// Tags to switch to the performance profile for while plugged in.
+    pub performance_tags: &'static [&'static str],
+}
+
+impl PowerProfiles {
+    fn update<X: XConn>(&self, state: &mut State<X>) {
+        let tag = state.client_set.current_tag().to_string();
+        let wanted = Profile::wanted(on_battery(), self.performance_tags.contains(&tag.as_str()));
+        let ext = state.extension_or_default::<Profiles>();
+        if ext.borrow_mut().auto.replace(wanted) != Some(wanted) {
+            switch(state, wanted);
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for PowerProfiles {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == POWER_CHECK => (),
+            _ => return Ok(true),
+        }
+
+        notify_after(CHECK_INTERVAL, POWER_CHECK, [0; 5]);
+        self.update(state);
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for PowerProfiles {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Profiles>();
+        if !std::mem::replace(&mut ext.borrow_mut().checking, true) {
+            notify_after(CHECK_INTERVAL, POWER_CHECK, [0; 5]);
+        }
+
+        self.update(state);
+
+        Ok(())
+    }
+}
+
+/// Switch to the next power profile, until the automatic choice next changes.
+pub fn cycle_power_profile<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let active = state.extension_or_default::<Profiles>().borrow().active;
+
+        switch(state, active.unwrap_or(Profile::Balanced).next());
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batteries_save_power_even_when_gaming() {
+        assert_eq!(Profile::wanted(true, true), Profile::PowerSaver);
+        assert_eq!(Profile::wanted(false, true), Profile::Performance);
+        assert_eq!(Profile::wanted(false, false), Profile::Balanced);
+    }
+}
@@ -0,0 +1,215 @@
+//! Setting up for a presentation as soon as a projector is plugged in.
+//!
+//! When one of the configured projectors connects it is either mirrored with the other
+//! outputs or added to the right of them, a chosen tag is shown on it and the screen is kept
+//! from blanking or locking. Unplugging it puts the previous arrangement back.
+use crate::{
+    bar,
+    idle::{inhibit, uninhibit},
+    monitor::{rotated_size, screen_for_output, OutputSetup, Randr, RANDR_SETTLED},
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Result,
+};
+use std::{cell::RefCell, rc::Rc};
+use tracing::info;
+use x11rb::protocol::randr::Rotation;
+
+const INHIBIT_REASON: &str = "presentation";
+
+#[derive(Debug, Clone, Copy)]
+pub struct Presentation {
+    /// Output names or EDID fingerprints (see
+    /// [edid_fingerprint](crate::monitor::edid_fingerprint)) that count as projectors.
+    pub projectors: &'static [&'static str],
+    /// Mirror the projector with the other outputs rather than extending onto it.
+    pub mirror: bool,
+    /// A tag to show on the projector.
+    pub tag: Option<&'static str>,
+}
+
+#[derive(Debug, Default)]
+struct Presenting {
+    /// The connected projector.
+    output: Option<String>,
+    /// The arrangement from before the projector was connected.
+    restore: Vec<OutputSetup>,
+    /// Whether the tag is waiting on penrose to see the projector's screen.
+    pending_tag: bool,
+}
+
+/// Where an output should go to extend the screen to the right of those in `setups`.
+fn beside(setups: &[OutputSetup]) -> (i16, i16) {
+    let x = setups
+        .iter()
+        .filter(|s| s.mode.is_some())
+        .map(|s| s.pos.0 + rotated_size(s).0 as i16)
+        .max()
+        .unwrap_or(0);
+
+    (x, 0)
+}
+
+/// Applies the [Presentation] settings when a projector is connected and undoes them when it
+/// is disconnected. This should be registered after the
+/// [ProfileHook](crate::profiles::ProfileHook) so that it has the final say.
+#[derive(Debug, Clone)]
+pub struct PresentationHook {
+    config: Presentation,
+    presenting: Rc<RefCell<Presenting>>,
+}
+
+impl PresentationHook {
+    pub fn new(config: Presentation) -> Self {
+        Self {
+            config,
+            presenting: Rc::default(),
+        }
+    }
+
+    fn check<X: XConn>(&self, state: &mut State<X>, x: &X) -> Result<()> {
+        let randr = Randr::connect()?;
+        let connected = randr.connected_outputs()?;
+        let projector = connected
+            .iter()
+            .find(|c| {
+                self.config
+                    .projectors
+                    .iter()
+                    .any(|&p| c.name == p || c.edid.as_deref() == Some(p))
+            })
+            .map(|c| c.name.clone());
+
+        let current = self.presenting.borrow().output.clone();
+        match (current, projector) {
+            (None, Some(name)) => {
+                info!("projector connected on {name}");
+                // The projector may already be on (when it was plugged in before the window
+                // manager started), in which case it is set up afresh rather than twice over.
+                let before: Vec<OutputSetup> = randr
+                    .current_setups()?
+                    .into_iter()
+                    .filter(|s| s.name != name)
+                    .collect();
+                let setups = if self.config.mirror {
+                    randr.mirrored_setups()?
+                } else {
+                    let mut setups = before.clone();
+                    setups.push(OutputSetup {
+                        name: name.clone(),
+                        mode: randr.preferred_size(&name)?,
+                        pos: beside(&before),
+                        rotation: Rotation::ROTATE0,
+                        primary: false,
+                    });
+                    setups
+                };
+                let changed = randr.apply(&setups)?;
+
+                bar::publish(state, "presenting", name.as_str());
+                inhibit(state, INHIBIT_REASON)?;
+                *self.presenting.borrow_mut() = Presenting {
+                    output: Some(name),
+                    restore: before,
+                    pending_tag: self.config.tag.is_some(),
+                };
+                if !changed {
+                    self.show_tag(&randr, state, x)?;
+                }
+            }
+
+            (Some(name), None) => {
+                info!("projector disconnected from {name}");
+                let restore = std::mem::take(&mut *self.presenting.borrow_mut()).restore;
+                let setups: Vec<OutputSetup> = restore
+                    .into_iter()
+                    .filter(|s| connected.iter().any(|c| c.name == s.name))
+                    .collect();
+                randr.apply(&setups)?;
+
+                bar::publish(state, "presenting", "");
+                uninhibit(state, INHIBIT_REASON)?;
+            }
+
+            _ => self.show_tag(&randr, state, x)?,
+        }
+
+        Ok(())
+    }
+
+    /// Show the configured tag on the projector's screen if it is still waiting to be shown,
+    /// leaving focus on the current screen.
+    fn show_tag<X: XConn>(&self, randr: &Randr, state: &mut State<X>, x: &X) -> Result<()> {
+        let (tag, name) = {
+            let p = self.presenting.borrow();
+            match (self.config.tag, &p.output) {
+                (Some(tag), Some(name)) if p.pending_tag => (tag, name.clone()),
+                _ => return Ok(()),
+            }
+        };
+        let outputs = randr.active_outputs()?;
+        let screen = outputs
+            .iter()
+            .find(|o| o.name == name)
+            .and_then(|o| screen_for_output(state, o.r));
+        let screen = match screen {
+            Some(screen) => screen,
+            None => return Ok(()),
+        };
+
+        self.presenting.borrow_mut().pending_tag = false;
+        x.modify_and_refresh(state, |cs| {
+            let current = cs.current_screen().index();
+            cs.focus_screen(screen);
+            cs.focus_tag(tag);
+            cs.focus_screen(current);
+        })
+    }
+}
+
+impl<X: XConn> EventHook<X> for PresentationHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        if let XEvent::ClientMessage(msg) = event {
+            if msg.dtype == RANDR_SETTLED {
+                self.check(state, x)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for PresentationHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        self.check(state, x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projectors_extend_to_the_right() {
+        let setup = |mode, x, rotation| OutputSetup {
+            name: "DP-1".to_string(),
+            mode,
+            pos: (x, 0),
+            rotation,
+            primary: false,
+        };
+        let setups = [
+            setup(Some((1920, 1080)), 0, Rotation::ROTATE0),
+            setup(Some((1920, 1080)), 1920, Rotation::ROTATE90),
+            setup(None, 5000, Rotation::ROTATE0),
+        ];
+
+        assert_eq!(beside(&setups), (3000, 0));
+        assert_eq!(beside(&[]), (0, 0));
+    }
+}
@@ -0,0 +1,338 @@
+//! Starting processes, and looking up client processes via /proc.
+use crate::{client::pid, modal::x_err, startup::start};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, hooks::StateHook, State},
+    x::XConn,
+    Error, Result,
+};
+use signal_hook::{consts::SIGCHLD, iterator::Signals};
+use std::{
+    fs, iter,
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{channel, Sender},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// A command exiting unsuccessfully sooner than this after starting counts as failing to
+/// start, rather than as something that ran and was closed.
+const QUICK_EXIT: Duration = Duration::from_secs(2);
+
+/// The fields of /proc/<pid>/stat that follow the command name. The name is wrapped in parens
+/// and may itself contain spaces or parens so everything up to the last paren is skipped.
+fn stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+
+    Some(rest.split_whitespace().map(String::from).collect())
+}
+
+// Indices into the fields returned by stat_fields (see proc(5)).
+const PPID: usize = 1;
+const TPGID: usize = 5;
+
+fn stat_field(pid: u32, field: usize) -> Option<u32> {
+    stat_fields(pid)?.get(field)?.parse().ok()
+}
+
+/// The parent process id of `pid`.
+pub fn parent_pid(pid: u32) -> Option<u32> {
+    stat_field(pid, PPID)
+}
+
+/// The parents of `pid` all the way up to (but not including) init.
+pub fn ancestors(pid: u32) -> impl Iterator<Item = u32> {
+    std::iter::successors(parent_pid(pid), |&p| parent_pid(p)).take_while(|&p| p > 1)
+}
+
+/// The direct children of `pid`, oldest first.
+pub fn children(pid: u32) -> Vec<u32> {
+    let mut children: Vec<u32> = fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.parse().ok())
+        .filter(|&p| parent_pid(p) == Some(pid))
+        .collect();
+    children.sort_unstable();
+
+    children
+}
+
+/// The longest command name the kernel keeps for a process, which is truncated to fit.
+const COMM_LEN: usize = 15;
+
+/// Whether any process is running `program`, going by the command names in /proc.
+pub fn is_running(program: &str) -> bool {
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let name = &name.as_bytes()[..name.len().min(COMM_LEN)];
+
+    fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|f| f.parse::<u32>().is_ok())
+        })
+        .filter_map(|e| fs::read(e.path().join("comm")).ok())
+        .any(|comm| comm.strip_suffix(b"\n").unwrap_or(&comm) == name)
+}
+
+fn cwd(pid: u32) -> Option<PathBuf> {
+    fs::read_link(format!("/proc/{pid}/cwd")).ok()
+}
+
+/// The working directory of whatever is in the foreground of the terminal running as `pid`:
+/// the foreground process group of its shell if that can be found, or the shell itself.
+pub fn foreground_cwd(pid: u32) -> Option<PathBuf> {
+    let shell = *children(pid).first()?;
+
+    stat_field(shell, TPGID)
+        .filter(|&pg| pg > 0)
+        .and_then(cwd)
+        .or_else(|| cwd(shell))
+}
+
+/// Launch `terminal`, passing it the foreground working directory of the focused client via
+/// `cwd_flag` when there is one.
+pub fn spawn_terminal_here<X: XConn>(
+    terminal: &'static str,
+    cwd_flag: &'static str,
+) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let dir = state
+            .client_set
+            .current_client()
+            .and_then(|&id| pid(x, id))
+            .and_then(foreground_cwd);
+
+        match dir.as_ref().and_then(|d| d.to_str()) {
+            Some(dir) => start(state, terminal, &[cwd_flag, dir]),
+            None => start(state, terminal, &[]),
+        }
+    })
+}
+
+/// Why a command exited, for a notification.
+fn failure_reason(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(127), _) => "command not found".to_string(),
+        (Some(code), _) => format!("exited with status {code}"),
+        (None, Some(signal)) => format!("killed by signal {signal}"),
+        (None, None) => "exited".to_string(),
+    }
+}
+
+/// A command for the spawner thread to start.
+#[derive(Debug)]
+struct Job {
+    program: String,
+    args: Vec<String>,
+    /// Extra environment variables to set.
+    env: Vec<(String, String)>,
+    /// Whether to send a notification if it fails.
+    notify: bool,
+}
+
+impl Job {
+    fn command_line(&self) -> String {
+        iter::once(&self.program)
+            .chain(self.args.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// A child started by the spawner thread, for the reaper to wait on.
+#[derive(Debug)]
+struct Running {
+    child: Child,
+    command: String,
+    started: Instant,
+    notify: bool,
+}
+
+static SPAWNER: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+static RUNNING: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+fn queue(job: Job) -> Result<()> {
+    let tx = SPAWNER.get_or_init(|| Mutex::new(spawn_worker()));
+    let sent = tx.lock().map(|tx| tx.send(job).is_ok()).unwrap_or(false);
+    if !sent {
+        return Err(Error::Custom("the spawner thread is not running".into()));
+    }
+
+    Ok(())
+}
+
+fn spawn_worker() -> Sender<Job> {
+    let (tx, rx) = channel::<Job>();
+    thread::spawn(move || {
+        for job in rx {
+            let command = job.command_line();
+            let spawned = Command::new(&job.program)
+                .args(&job.args)
+                .envs(job.env)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .spawn();
+            match spawned {
+                Ok(child) => {
+                    if let Ok(mut running) = RUNNING.lock() {
+                        running.push(Running {
+                            child,
+                            command,
+                            started: Instant::now(),
+                            notify: job.notify,
+                        });
+                    }
+                }
+                Err(e) => failed(&command, &e.to_string(), job.notify),
+            }
+        }
+    });
+
+    tx
+}
+
+fn failed(command: &str, reason: &str, notify: bool) {
+    warn!("{command} failed: {reason}");
+    if notify {
+        let _ = queue(Job {
+            program: "notify-send".into(),
+            args: vec![format!("{command} failed"), reason.to_string()],
+            env: vec![],
+            notify: false,
+        });
+    }
+}
+
+/// Wait on every child that has exited, reporting any that failed straight away.
+fn reap() {
+    let mut exited = Vec::new();
+    if let Ok(mut running) = RUNNING.lock() {
+        for ix in (0..running.len()).rev() {
+            match running[ix].child.try_wait() {
+                Ok(None) => continue,
+                Ok(Some(status)) => exited.push((running.swap_remove(ix), Some(status))),
+                Err(e) => {
+                    warn!("unable to wait on {}: {e}", running[ix].command);
+                    exited.push((running.swap_remove(ix), None));
+                }
+            }
+        }
+    }
+
+    for (r, status) in exited {
+        match status {
+            Some(status) if !status.success() && r.started.elapsed() < QUICK_EXIT => {
+                failed(&r.command, &failure_reason(status), r.notify)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Start `program` with `args`, with a notification if it can't be started or exits with an
+/// error straight away. Starting it happens on a thread of its own, so a slow fork never
+/// holds up the event loop, and it is reaped once it exits by the [ReaperHook].
+pub fn run_with_args(program: &str, args: &[&str]) -> Result<()> {
+    run_with_env(program, args, &[])
+}
+
+/// Start `program` with `args` and the extra environment variables in `env`. See
+/// [run_with_args].
+pub fn run_with_env(program: &str, args: &[&str], env: &[(&str, &str)]) -> Result<()> {
+    queue(Job {
+        program: program.to_string(),
+        args: args.iter().map(|a| a.to_string()).collect(),
+        env: env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        notify: true,
+    })
+}
+
+/// Run `command`, split on whitespace like penrose's `spawn`. See [run_with_args].
+pub fn run(command: &str) -> Result<()> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.split_first() {
+        Some((program, args)) => run_with_args(program, args),
+        None => Err(Error::Custom("empty command".into())),
+    }
+}
+
+/// Spawn `command`, with a notification if it fails to start. See [run_with_args].
+pub fn spawn<X: XConn>(command: &'static str) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |_: &mut State<X>, _: &X| run(command))
+}
+
+/// Reaps the children started by [run_with_args] as they exit. Penrose ignores SIGCHLD,
+/// which would have the kernel reap children before their exit status could be read, so
+/// this needs registering as a startup hook to come after it. Children started elsewhere
+/// (such as the compositor) are left for whatever started them to wait on.
+#[derive(Debug, Clone, Copy)]
+pub struct ReaperHook;
+
+impl<X: XConn> StateHook<X> for ReaperHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        let mut signals = Signals::new([SIGCHLD]).map_err(x_err)?;
+        thread::spawn(move || {
+            // Pick up anything that exited before now.
+            reap();
+            for _ in signals.forever() {
+                reap();
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn our_own_process_is_a_child_of_its_parent() {
+        let me = std::process::id();
+        let parent = parent_pid(me).expect("parent pid");
+
+        assert!(children(parent).contains(&me));
+    }
+
+    #[test]
+    fn running_programs_are_found_by_name() {
+        let me = std::env::current_exe().expect("current exe");
+
+        assert!(is_running(me.to_str().expect("utf-8 path")));
+        assert!(!is_running("/no/such/program-anywhere"));
+    }
+
+    #[test]
+    fn missing_commands_are_reported_as_such() {
+        assert_eq!(
+            failure_reason(ExitStatus::from_raw(127 << 8)),
+            "command not found"
+        );
+        assert_eq!(
+            failure_reason(ExitStatus::from_raw(1 << 8)),
+            "exited with status 1"
+        );
+        assert_eq!(
+            failure_reason(ExitStatus::from_raw(9)),
+            "killed by signal 9"
+        );
+    }
+}
@@ -0,0 +1,472 @@
+//! Named monitor arrangements applied automatically when their monitors are plugged in, in
+//! the style of autorandr.
+//!
+//! Each time the RandR setup settles the connected monitors are compared against the
+//! configured profiles. When the set changes to one matching a profile, its modes, positions
+//! and primary output are applied and, once penrose has picked up the new screens, each of
+//! its tags is shown on the output it names.
+//!
+//! Profiles can be marked as docked, in which case the laptop's own panel can be turned off
+//! while they apply. Whatever tag the panel was showing moves to the profile's primary output,
+//! and comes back to the panel (by way of the
+//! [WorkspaceMigrationHook](crate::migration::WorkspaceMigrationHook)) once undocked.
+//!
+//! The current arrangement can also be saved under a name with `wmcli output save <name>` and
+//! put back with `wmcli output apply <name>`. Saved arrangements are applied automatically
+//! like profiles, for monitors that none of the configured profiles match.
+use crate::{
+    modal::x_err,
+    monitor::{close_gaps, screen_for_output, Connected, OutputSetup, Randr, RANDR_SETTLED},
+    paths::state_file,
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Error, Result,
+};
+use std::{cell::RefCell, fs, path::PathBuf, rc::Rc};
+use tracing::{info, warn};
+use x11rb::protocol::randr::Rotation;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProfileOutput {
+    /// The output name as shown by `xrandr`.
+    pub name: &'static str,
+    /// The EDID fingerprint of the monitor (as logged when no profile matches), or `None` to
+    /// accept any monitor on this output.
+    pub edid: Option<&'static str>,
+    /// The resolution to use, or `None` to leave the output turned off.
+    pub mode: Option<(u16, u16)>,
+    pub pos: (i16, i16),
+    pub rotation: Rotation,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Profile {
+    pub name: &'static str,
+    /// Every output that must be connected for the profile to apply, and nothing more.
+    pub outputs: &'static [ProfileOutput],
+    /// Tags to show on particular outputs, as (tag, output name) pairs.
+    pub tags: &'static [(&'static str, &'static str)],
+    /// Whether this is a docked setup, which turns off the laptop panel if the
+    /// [ProfileHook] was given one.
+    pub docked: bool,
+}
+
+impl Profile {
+    fn matches(&self, connected: &[Connected]) -> bool {
+        self.outputs.len() == connected.len()
+            && self.outputs.iter().all(|o| {
+                connected
+                    .iter()
+                    .any(|c| c.name == o.name && (o.edid.is_none() || c.edid.as_deref() == o.edid))
+            })
+    }
+
+    fn setups(&self) -> Vec<OutputSetup> {
+        self.outputs
+            .iter()
+            .map(|o| OutputSetup {
+                name: o.name.to_string(),
+                mode: o.mode,
+                pos: o.pos,
+                rotation: o.rotation,
+                primary: o.primary,
+            })
+            .collect()
+    }
+}
+
+const ROTATIONS: [(u16, Rotation); 4] = [
+    (0, Rotation::ROTATE0),
+    (90, Rotation::ROTATE90),
+    (180, Rotation::ROTATE180),
+    (270, Rotation::ROTATE270),
+];
+
+/// An arrangement saved with `wmcli output save`, covering every connected output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Saved {
+    outputs: Vec<(Connected, OutputSetup)>,
+}
+
+impl Saved {
+    fn matches(&self, connected: &[Connected]) -> bool {
+        self.outputs.len() == connected.len()
+            && self.outputs.iter().all(|(c, _)| connected.contains(c))
+    }
+
+    fn setups(&self) -> Vec<OutputSetup> {
+        self.outputs.iter().map(|(_, s)| s.clone()).collect()
+    }
+
+    /// One line per output, e.g. `HDMI-1 DEL-a0b1-0000cafe 2560x1440 1920 0 0 primary`, with
+    /// `-` for a missing EDID and `off` in place of the mode and everything after it for an
+    /// output that is turned off.
+    fn to_lines(&self) -> String {
+        let lines: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|(c, s)| {
+                let edid = c.edid.as_deref().unwrap_or("-");
+                let (w, h) = match s.mode {
+                    Some(mode) => mode,
+                    None => return format!("{} {edid} off", c.name),
+                };
+                let degrees = ROTATIONS
+                    .iter()
+                    .find(|(_, r)| *r == s.rotation)
+                    .map_or(0, |(d, _)| *d);
+                let primary = if s.primary { " primary" } else { "" };
+
+                format!(
+                    "{} {edid} {w}x{h} {} {} {degrees}{primary}",
+                    c.name, s.pos.0, s.pos.1
+                )
+            })
+            .collect();
+
+        lines.join("\n") + "\n"
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut outputs = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let connected = Connected {
+                name: fields.first()?.to_string(),
+                edid: Some(*fields.get(1)?)
+                    .filter(|&e| e != "-")
+                    .map(str::to_string),
+            };
+            let mut setup = OutputSetup {
+                name: connected.name.clone(),
+                mode: None,
+                pos: (0, 0),
+                rotation: Rotation::ROTATE0,
+                primary: false,
+            };
+
+            if *fields.get(2)? != "off" {
+                let (w, h) = fields[2].split_once('x')?;
+                setup.mode = Some((w.parse().ok()?, h.parse().ok()?));
+                setup.pos = (fields.get(3)?.parse().ok()?, fields.get(4)?.parse().ok()?);
+                let degrees: u16 = fields.get(5)?.parse().ok()?;
+                setup.rotation = ROTATIONS.iter().find(|(d, _)| *d == degrees)?.1;
+                setup.primary = fields.get(6) == Some(&"primary");
+            }
+            outputs.push((connected, setup));
+        }
+
+        Some(Self { outputs })
+    }
+}
+
+fn saved_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(Error::Custom(format!("invalid layout name: {name:?}")));
+    }
+
+    Ok(state_file("outputs").join(name))
+}
+
+fn load_saved(name: &str) -> Result<Saved> {
+    let text = fs::read_to_string(saved_path(name)?)
+        .map_err(|e| Error::Custom(format!("unable to read layout {name}: {e}")))?;
+
+    Saved::parse(&text).ok_or_else(|| Error::Custom(format!("layout {name} is malformed")))
+}
+
+/// The names of every saved arrangement, sorted.
+fn saved_names() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(state_file("outputs"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+
+    names
+}
+
+fn save_current(randr: &Randr, name: &str) -> Result<()> {
+    let current = randr.current_setups()?;
+    let outputs = randr
+        .connected_outputs()?
+        .into_iter()
+        .map(|c| {
+            let setup = current.iter().find(|s| s.name == c.name).cloned();
+            let setup = setup.unwrap_or_else(|| OutputSetup {
+                name: c.name.clone(),
+                mode: None,
+                pos: (0, 0),
+                rotation: Rotation::ROTATE0,
+                primary: false,
+            });
+            (c, setup)
+        })
+        .collect();
+
+    let path = saved_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(x_err)?;
+    }
+
+    fs::write(path, Saved { outputs }.to_lines()).map_err(x_err)
+}
+
+/// `wmcli output save <name>`, `wmcli output apply <name>` and `wmcli output list`.
+pub fn output_command<X: XConn>(args: &[String], _: &mut State<X>, _: &X) -> Result<String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["save", name] => {
+            save_current(&Randr::connect()?, name)?;
+            Ok(format!("saved {name}"))
+        }
+        ["apply", name] => {
+            let saved = load_saved(name)?;
+            Randr::connect()?.apply(&saved.setups())?;
+            Ok(format!("applied {name}"))
+        }
+        ["list"] => Ok(saved_names().join("\n")),
+        _ => Err(Error::Custom(
+            "usage: output (save <name> | apply <name> | list)".into(),
+        )),
+    }
+}
+
+/// Tags to show on particular outputs, as (tag, output name) pairs.
+type TagPlacements = Vec<(String, String)>;
+
+/// Show each tag on its output, leaving focus on the current screen.
+fn show_tags<X: XConn>(
+    randr: &Randr,
+    tags: &[(String, String)],
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let outputs = randr.active_outputs()?;
+    let moves: Vec<(usize, &str)> = tags
+        .iter()
+        .filter_map(|(tag, name)| {
+            let o = outputs.iter().find(|o| &o.name == name)?;
+            Some((screen_for_output(state, o.r)?, tag.as_str()))
+        })
+        .collect();
+
+    if moves.is_empty() {
+        return Ok(());
+    }
+
+    x.modify_and_refresh(state, |cs| {
+        let current = cs.current_screen().index();
+        for &(screen, tag) in moves.iter() {
+            cs.focus_screen(screen);
+            cs.focus_tag(tag);
+        }
+        cs.focus_screen(current);
+    })
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    /// The monitors that were connected last time we looked.
+    connected: Vec<Connected>,
+    /// Tags from a profile that has been applied, waiting on penrose to see the new screens.
+    pending: TagPlacements,
+}
+
+/// Applies the matching [Profile] at startup and whenever the connected monitors change. If
+/// `panel` names the laptop's built in output it is turned off while docked.
+#[derive(Debug, Clone)]
+pub struct ProfileHook {
+    profiles: &'static [Profile],
+    panel: Option<&'static str>,
+    tracked: Rc<RefCell<Tracked>>,
+}
+
+impl ProfileHook {
+    pub fn new(profiles: &'static [Profile], panel: Option<&'static str>) -> Self {
+        Self {
+            profiles,
+            panel,
+            tracked: Rc::default(),
+        }
+    }
+
+    /// The setups for `profile` along with the tags it wants shown on particular outputs,
+    /// taking the panel's tag along to the primary output if docking turns it off.
+    fn plan<X: XConn>(
+        &self,
+        profile: &Profile,
+        randr: &Randr,
+        state: &State<X>,
+    ) -> Result<(Vec<OutputSetup>, TagPlacements)> {
+        let mut setups = profile.setups();
+        let mut tags = Vec::new();
+
+        if let Some(panel) = self.panel.filter(|_| profile.docked) {
+            for s in setups.iter_mut().filter(|s| s.name == panel) {
+                s.mode = None;
+            }
+            close_gaps(&mut setups);
+            let shown = randr
+                .active_outputs()?
+                .into_iter()
+                .find(|o| o.name == panel)
+                .and_then(|o| screen_for_output(state, o.r))
+                .and_then(|screen| state.client_set.tag_for_screen(screen));
+            let target = setups
+                .iter()
+                .filter(|s| s.mode.is_some())
+                .max_by_key(|s| s.primary)
+                .map(|s| s.name.clone());
+            if let (Some(tag), Some(target)) = (shown, target) {
+                tags.push((tag.to_string(), target));
+            }
+        }
+
+        // Tags the profile places itself take precedence over the panel's.
+        tags.extend(
+            profile
+                .tags
+                .iter()
+                .map(|&(tag, output)| (tag.to_string(), output.to_string())),
+        );
+
+        Ok((setups, tags))
+    }
+
+    fn check<X: XConn>(&self, state: &mut State<X>, x: &X) -> Result<()> {
+        let randr = Randr::connect()?;
+        let connected = randr.connected_outputs()?;
+        let mut tracked = self.tracked.borrow_mut();
+
+        if connected == tracked.connected {
+            let pending = std::mem::take(&mut tracked.pending);
+            return show_tags(&randr, &pending, state, x);
+        }
+        tracked.connected = connected.clone();
+        tracked.pending.clear();
+
+        let profile = match self.profiles.iter().find(|p| p.matches(&connected)) {
+            Some(p) => p,
+            None => {
+                let saved = saved_names()
+                    .into_iter()
+                    .find_map(|name| match load_saved(&name) {
+                        Ok(saved) if saved.matches(&connected) => Some((name, saved)),
+                        Ok(_) => None,
+                        Err(e) => {
+                            warn!("{e}");
+                            None
+                        }
+                    });
+                if let Some((name, saved)) = saved {
+                    info!("applying saved monitor layout {name}");
+                    randr.apply(&saved.setups())?;
+                    return Ok(());
+                }
+
+                let monitors: Vec<String> = connected
+                    .iter()
+                    .map(|c| format!("{} ({})", c.name, c.edid.as_deref().unwrap_or("no EDID")))
+                    .collect();
+                info!("no monitor profile matches {}", monitors.join(", "));
+                return Ok(());
+            }
+        };
+
+        info!("applying monitor profile {}", profile.name);
+        let (setups, tags) = self.plan(profile, &randr, state)?;
+        if randr.apply(&setups)? {
+            tracked.pending = tags;
+            Ok(())
+        } else {
+            show_tags(&randr, &tags, state, x)
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for ProfileHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        if let XEvent::ClientMessage(msg) = event {
+            if msg.dtype == RANDR_SETTLED {
+                self.check(state, x)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for ProfileHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        self.check(state, x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn output(name: &'static str, edid: Option<&'static str>) -> ProfileOutput {
+        ProfileOutput {
+            name,
+            edid,
+            mode: Some((1920, 1080)),
+            pos: (0, 0),
+            rotation: Rotation::ROTATE0,
+            primary: false,
+        }
+    }
+
+    #[test]
+    fn profiles_match_exactly_their_monitors() {
+        const DOCKED: Profile = Profile {
+            name: "docked",
+            outputs: &[
+                output("eDP-1", None),
+                output("DP-1", Some("DEL-a0b1-0000cafe")),
+            ],
+            tags: &[],
+            docked: true,
+        };
+        let connected = |monitors: &[(&str, &str)]| -> Vec<Connected> {
+            monitors
+                .iter()
+                .map(|&(name, edid)| Connected {
+                    name: name.to_string(),
+                    edid: Some(edid.to_string()),
+                })
+                .collect()
+        };
+
+        assert!(DOCKED.matches(&connected(&[
+            ("DP-1", "DEL-a0b1-0000cafe"),
+            ("eDP-1", "AUO-1234-00000000"),
+        ])));
+        assert!(!DOCKED.matches(&connected(&[
+            ("DP-1", "GSM-5b09-00000001"),
+            ("eDP-1", "AUO-1234-00000000"),
+        ])));
+        assert!(!DOCKED.matches(&connected(&[("eDP-1", "AUO-1234-00000000")])));
+    }
+
+    #[test]
+    fn saved_layouts_round_trip() {
+        let text = "HDMI-1 DEL-a0b1-0000cafe 2560x1440 1920 0 90 primary\neDP-1 - off\n";
+        let saved = Saved::parse(text).unwrap();
+
+        assert_eq!(saved.outputs[0].1.rotation, Rotation::ROTATE90);
+        assert!(saved.outputs[0].1.primary);
+        assert_eq!(saved.outputs[1].0.edid, None);
+        assert_eq!(saved.outputs[1].1.mode, None);
+        assert_eq!(saved.to_lines(), text);
+    }
+}
@@ -0,0 +1,412 @@
+//! Restarting the window manager in place without losing the session.
+//!
+//! Before restarting or exiting, the arrangement of every tag (its clients in order, its
+//! layout and which tags are on which screens), the geometry of floating clients and the
+//! focused client are written to a state file, and every client is mapped so that the next
+//! window manager to start picks up those on hidden tags along with the rest. Clients don't
+//! belong to the window manager, so they stay open across the exec. Once they have been
+//! managed again the [RestoreHook] puts them back as they were, including those parked on
+//! invisible workspaces (minimized clients and swallowed terminals) and which child each
+//! swallowed terminal belongs to.
+use crate::{
+    compositor, daemons,
+    hidden::park_client,
+    idle, logging,
+    modal::x_err,
+    paths::runtime_file,
+    startup,
+    swallow::{restore_swallowed, Swallows},
+    systemd::notify_env,
+};
+use penrose::{
+    core::{bindings::KeyEventHandler, hooks::StateHook, State},
+    pure::{geometry::Rect, Position},
+    x::{XConn, XConnExt},
+    Error, Result, Xid,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    os::unix::process::CommandExt,
+    process::{self, Command},
+};
+use tracing::{info, warn};
+
+/// The most layouts a tag is expected to cycle through when looking for its saved one.
+const MAX_LAYOUTS: usize = 16;
+/// Set for the window manager that is exec'd when restarting.
+const RESTARTED_VAR: &str = "WM_RESTARTED";
+
+/// The arrangement of a tag at the point of restarting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tag {
+    tag: String,
+    layout: String,
+    clients: Vec<u32>,
+}
+
+/// Everything needed to put the clients back as they were.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Session {
+    /// The tag shown on each screen, by screen index.
+    screens: Vec<String>,
+    /// The index of the focused screen.
+    focused_screen: usize,
+    focused: Option<u32>,
+    tags: Vec<Tag>,
+    floating: HashMap<u32, Rect>,
+    /// Each swallowing child along with the terminal it swallowed.
+    swallowed: Vec<(u32, u32)>,
+}
+
+impl Session {
+    /// One line per screen, tag and floating client, e.g. `tag 2 1a00003,2200007 Side`.
+    fn to_lines(&self) -> String {
+        let mut lines = vec![format!("focused {}", self.focused_screen)];
+        lines.extend(self.screens.iter().map(|tag| format!("screen {tag}")));
+        if let Some(id) = self.focused {
+            lines.push(format!("client {id:x}"));
+        }
+        for t in self.tags.iter() {
+            let clients: Vec<String> = t.clients.iter().map(|id| format!("{id:x}")).collect();
+            let clients = if clients.is_empty() {
+                "-".to_string()
+            } else {
+                clients.join(",")
+            };
+            lines.push(format!("tag {} {clients} {}", t.tag, t.layout));
+        }
+        let mut floating: Vec<_> = self.floating.iter().collect();
+        floating.sort_by_key(|(&id, _)| id);
+        for (id, r) in floating {
+            lines.push(format!("float {id:x} {} {} {} {}", r.x, r.y, r.w, r.h));
+        }
+        for (child, terminal) in self.swallowed.iter() {
+            lines.push(format!("swallow {child:x} {terminal:x}"));
+        }
+
+        lines.join("\n")
+    }
+
+    fn parse(s: &str) -> Self {
+        let hex = |s: &str| u32::from_str_radix(s, 16).ok();
+        let mut session = Self::default();
+        for line in s.lines() {
+            let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match kind {
+                "focused" => session.focused_screen = rest.parse().unwrap_or(0),
+                "screen" => session.screens.push(rest.to_string()),
+                "client" => session.focused = hex(rest),
+                "tag" => {
+                    let mut fields = rest.splitn(3, ' ');
+                    let (tag, clients) = match (fields.next(), fields.next()) {
+                        (Some(tag), Some(clients)) => (tag, clients),
+                        _ => continue,
+                    };
+                    session.tags.push(Tag {
+                        tag: tag.to_string(),
+                        layout: fields.next().unwrap_or_default().to_string(),
+                        clients: clients.split(',').filter_map(hex).collect(),
+                    });
+                }
+                "float" => {
+                    let fields: Vec<&str> = rest.split(' ').collect();
+                    if let [id, x, y, w, h] = fields[..] {
+                        let r = (x.parse(), y.parse(), w.parse(), h.parse());
+                        if let (Some(id), (Ok(x), Ok(y), Ok(w), Ok(h))) = (hex(id), r) {
+                            session.floating.insert(id, Rect::new(x, y, w, h));
+                        }
+                    }
+                }
+                "swallow" => {
+                    if let Some((child, terminal)) = rest.split_once(' ') {
+                        if let (Some(child), Some(terminal)) = (hex(child), hex(terminal)) {
+                            session.swallowed.push((child, terminal));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        session
+    }
+
+    fn read<X: XConn>(state: &mut State<X>, x: &X) -> Self {
+        let mut swallowed: Vec<(u32, u32)> = state
+            .extension_or_default::<Swallows>()
+            .borrow()
+            .pairs()
+            .into_iter()
+            .map(|(child, terminal)| (*child, *terminal))
+            .collect();
+        swallowed.sort();
+        let cs = &state.client_set;
+        let floating = cs
+            .clients()
+            .filter(|&id| cs.is_floating(id))
+            .filter_map(|&id| Some((*id, x.client_geometry(id).ok()?)))
+            .collect();
+
+        Self {
+            screens: cs
+                .screens()
+                .map(|s| s.workspace.tag().to_string())
+                .collect(),
+            focused_screen: cs.current_screen().index(),
+            focused: cs.current_client().map(|id| **id),
+            tags: cs
+                .workspaces()
+                .map(|ws| Tag {
+                    tag: ws.tag().to_string(),
+                    layout: ws.layout_name(),
+                    clients: ws.clients().map(|id| **id).collect(),
+                })
+                .collect(),
+            floating,
+            swallowed,
+        }
+    }
+
+    fn clients(&self) -> HashSet<u32> {
+        self.tags.iter().flat_map(|t| t.clients.clone()).collect()
+    }
+}
+
+/// Save the session and put back anything changed outside of the window manager, ahead of
+/// exiting or restarting.
+pub fn prepare_to_exit<X: XConn>(state: &mut State<X>, x: &X) -> Result<()> {
+    let path = runtime_file("session");
+    let saved = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, Session::read(state, x).to_lines()));
+    if let Err(e) = saved {
+        warn!("unable to save the session: {e}");
+    }
+
+    compositor::shut_down(state);
+    daemons::shut_down(state);
+    idle::release_all(state)?;
+    startup::clear(state)?;
+    let clients: Vec<Xid> = state.client_set.clients().copied().collect();
+    for id in clients {
+        x.map(id)?;
+    }
+    x.flush();
+    logging::finish_profile();
+
+    Ok(())
+}
+
+/// Whether this window manager was started by restarting in place, rather than at the start
+/// of the session.
+pub fn restarted() -> bool {
+    env::var_os(RESTARTED_VAR).is_some()
+}
+
+/// Restart the window manager in place, keeping every client where it is.
+pub fn restart<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    Box::new(Restart)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Restart;
+
+impl<X: XConn> KeyEventHandler<X> for Restart {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let exe = env::current_exe().map_err(x_err)?;
+        prepare_to_exit(state, x)?;
+
+        info!("restarting {}", exe.display());
+        let err = Command::new(&exe)
+            .args(env::args_os().skip(1))
+            .env(RESTARTED_VAR, "1")
+            // Still the same process as far as systemd is concerned.
+            .envs(notify_env())
+            .exec();
+
+        Err(Error::Custom(format!("unable to restart: {err}")))
+    }
+}
+
+/// Exit the window manager, saving the session for whichever starts next.
+pub fn quit<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    Box::new(Quit)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quit;
+
+impl<X: XConn> KeyEventHandler<X> for Quit {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        prepare_to_exit(state, x)?;
+
+        process::exit(0)
+    }
+}
+
+/// Puts clients back as they were before the window manager last exited, as they are
+/// managed again. This needs registering as a refresh hook.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreHook {
+    session: Session,
+    /// Clients from the session that haven't been managed yet.
+    pending: HashSet<u32>,
+    restoring: bool,
+}
+
+impl RestoreHook {
+    /// Load the session written by the last window manager to exit, if there is one.
+    pub fn load() -> Self {
+        let path = runtime_file("session");
+        let session = match fs::read_to_string(&path) {
+            Ok(s) => Session::parse(&s),
+            Err(_) => return Self::default(),
+        };
+        // Window ids are reused, so the session is only good for this one start.
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("unable to remove {}: {e}", path.display());
+        }
+
+        Self {
+            pending: session.clients(),
+            session,
+            restoring: false,
+        }
+    }
+
+    /// Arrange every client from the session that is managed now. Doing this again as more
+    /// clients turn up leaves those already in place alone.
+    fn arrange<X: XConn>(&self, state: &mut State<X>) -> Result<()> {
+        let tags = &state.config.tags;
+        let cs = &mut state.client_set;
+        for (ix, tag) in self.session.screens.iter().enumerate() {
+            cs.focus_screen(ix);
+            cs.focus_tag(tag);
+        }
+
+        for t in self.session.tags.iter() {
+            let present: Vec<Xid> = t
+                .clients
+                .iter()
+                .map(|&id| Xid::from(id))
+                .filter(|id| cs.contains(id))
+                .collect();
+            if present.is_empty() && t.layout.is_empty() {
+                continue;
+            }
+            // Invisible workspaces only exist while something is parked on them, so they
+            // are made again rather than focused.
+            if !tags.contains(&t.tag) {
+                for id in present {
+                    park_client(cs, id, &t.tag)?;
+                }
+                continue;
+            }
+            cs.focus_tag(&t.tag);
+            for id in present {
+                cs.remove_client(&id);
+                cs.insert_at(Position::Tail, id);
+                if let Some(&r) = self.session.floating.get(&*id) {
+                    if let Err(e) = cs.float(id, r) {
+                        warn!("unable to float {id}: {e}");
+                    }
+                }
+            }
+            let ws = cs.current_workspace_mut();
+            for _ in 0..MAX_LAYOUTS {
+                if ws.layout_name() == t.layout {
+                    break;
+                }
+                ws.next_layout();
+            }
+        }
+
+        for (ix, tag) in self.session.screens.iter().enumerate() {
+            cs.focus_screen(ix);
+            cs.focus_tag(tag);
+        }
+        cs.focus_screen(self.session.focused_screen);
+        if let Some(id) = self
+            .session
+            .focused
+            .map(Xid::from)
+            .filter(|id| cs.contains(id))
+        {
+            cs.focus_client(&id);
+        }
+
+        for &(child, terminal) in self.session.swallowed.iter() {
+            restore_swallowed(Xid::from(child), Xid::from(terminal), state)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for RestoreHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        if self.restoring || self.pending.is_empty() {
+            return Ok(());
+        }
+        let before = self.pending.len();
+        self.pending
+            .retain(|&id| !state.client_set.contains(&Xid::from(id)));
+        if self.pending.len() == before {
+            return Ok(());
+        }
+
+        info!("restoring the previous session");
+        self.arrange(state)?;
+        self.restoring = true;
+        let res = x.refresh(state);
+        self.restoring = false;
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_survive_being_written_out() {
+        let session = Session {
+            screens: vec!["1".into(), "4".into()],
+            focused_screen: 1,
+            focused: Some(0x2200007),
+            tags: vec![
+                Tag {
+                    tag: "1".into(),
+                    layout: "Centered Main".into(),
+                    clients: vec![0x1a00003, 0x2200007],
+                },
+                Tag {
+                    tag: "2".into(),
+                    layout: "Mono".into(),
+                    clients: vec![],
+                },
+            ],
+            floating: HashMap::from([(0x2200007, Rect::new(10, 20, 640, 480))]),
+            swallowed: vec![(0x2200007, 0x1a00003)],
+        };
+
+        assert_eq!(
+            session.to_lines(),
+            [
+                "focused 1",
+                "screen 1",
+                "screen 4",
+                "client 2200007",
+                "tag 1 1a00003,2200007 Centered Main",
+                "tag 2 - Mono",
+                "float 2200007 10 20 640 480",
+                "swallow 2200007 1a00003",
+            ]
+            .join("\n")
+        );
+        assert_eq!(Session::parse(&session.to_lines()), session);
+    }
+}
@@ -0,0 +1,262 @@
+//! Running actions at set times of day or at sunrise and sunset.
+//!
+//! Each scheduled action has a wakeup of its own, set for the next time it is due and set
+//! again for the following day once it has run. Wakeups are never more than [MAX_SLEEP] apart
+//! and the time an action is due is worked out afresh at each one, so that a suspend or the
+//! clock changing (for daylight saving, say) leaves actions late by at most that much rather
+//! than by however long the machine was asleep. Sunrise and sunset are worked out for the
+//! configured location with the NOAA approximation, which is good to a minute or two away
+//! from the poles. On days when the sun doesn't rise or set, those actions don't run.
+use crate::timer::notify_after;
+use chrono::{Datelike, Local, Timelike};
+use penrose::{
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{cell::RefCell, collections::HashMap, f64::consts::PI, rc::Rc, time::Duration};
+use tracing::{info, warn};
+
+/// Sent with the index of the action that is due in the first data field.
+const SCHEDULE_TICK: &str = "_WM_SCHEDULE_TICK";
+const MINUTES_PER_DAY: i64 = 24 * 60;
+/// The longest to go without checking the clock. Timers don't count time spent suspended.
+const MAX_SLEEP: Duration = Duration::from_secs(5 * 60);
+
+/// When a scheduled action runs each day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    /// At this (hour, minute), local time.
+    At(u32, u32),
+    /// This many minutes after sunrise (or before, if negative).
+    Sunrise(i32),
+    /// This many minutes after sunset (or before, if negative).
+    Sunset(i32),
+}
+
+/// Where to work out sunrise and sunset for, in degrees north and east.
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Sunrise and sunset on `day` of the year, in minutes after midnight UTC, or `None` if the
+/// sun stays up or down all day.
+fn sun_times(day: u32, location: Location) -> Option<(f64, f64)> {
+    let gamma = 2.0 * PI / 365.0 * (day as f64 - 1.0);
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    // 90.833 degrees allows for refraction and the size of the sun's disc.
+    let lat = location.latitude.to_radians();
+    let cos_ha = 90.833_f64.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+    if !(-1.0..=1.0).contains(&cos_ha) {
+        return None;
+    }
+    let ha = cos_ha.acos().to_degrees();
+    let noon = 720.0 - 4.0 * location.longitude - eqtime;
+
+    Some((noon - 4.0 * ha, noon + 4.0 * ha))
+}
+
+impl When {
+    /// When this falls on `day` of the year, in minutes after local midnight, for a clock
+    /// `utc_offset` minutes ahead of UTC.
+    fn minute_of_day(&self, day: u32, location: Location, utc_offset: i64) -> Option<i64> {
+        let (sun, offset) = match *self {
+            When::At(hour, minute) => return Some((hour * 60 + minute) as i64),
+            When::Sunrise(offset) => (sun_times(day, location)?.0, offset),
+            When::Sunset(offset) => (sun_times(day, location)?.1, offset),
+        };
+
+        Some(sun.round() as i64 + utc_offset + offset as i64)
+    }
+
+    /// How many minutes there are from `now` (minutes after local midnight on `day`) until
+    /// this is next due, not counting `now` itself.
+    fn minutes_until(
+        &self,
+        now: i64,
+        day: u32,
+        location: Location,
+        utc_offset: i64,
+    ) -> Option<i64> {
+        let today = self.minute_of_day(day, location, utc_offset);
+        if let Some(t) = today.filter(|&t| t > now) {
+            return Some(t - now);
+        }
+        let tomorrow = self.minute_of_day(day % 366 + 1, location, utc_offset)?;
+
+        Some(tomorrow + MINUTES_PER_DAY - now)
+    }
+}
+
+type Events<X> = Rc<RefCell<Vec<(When, Box<dyn KeyEventHandler<X>>)>>>;
+
+/// When an action is next due, as a UNIX timestamp.
+#[derive(Debug, Clone, Copy)]
+struct Due {
+    at: i64,
+    /// False when the sun doesn't rise or set for a while, and it is only time to look again.
+    run: bool,
+}
+
+/// Runs each action when it is due. This needs registering as both an event hook and a
+/// startup hook.
+pub struct ScheduleHook<X: XConn> {
+    location: Location,
+    events: Events<X>,
+    due: Rc<RefCell<HashMap<usize, Due>>>,
+}
+
+impl<X: XConn> Clone for ScheduleHook<X> {
+    fn clone(&self) -> Self {
+        Self {
+            location: self.location,
+            events: self.events.clone(),
+            due: self.due.clone(),
+        }
+    }
+}
+
+/// Wake up to look at the action at `ix` again in `secs` seconds, or sooner.
+fn wake_in(ix: usize, secs: i64) {
+    let delay = Duration::from_secs(secs.max(0) as u64).min(MAX_SLEEP);
+
+    notify_after(delay, SCHEDULE_TICK, [ix as u32, 0, 0, 0, 0]);
+}
+
+impl<X: XConn> ScheduleHook<X> {
+    pub fn new(location: Location, events: Vec<(When, Box<dyn KeyEventHandler<X>>)>) -> Self {
+        Self {
+            location,
+            events: Rc::new(RefCell::new(events)),
+            due: Rc::default(),
+        }
+    }
+
+    /// Work out when the action at `ix` is next due from the clock as it is now, and wake up
+    /// then (or in [MAX_SLEEP], whichever comes first).
+    fn schedule(&self, ix: usize) {
+        let when = match self.events.borrow().get(ix) {
+            Some((when, _)) => *when,
+            None => return,
+        };
+        let now = Local::now();
+        let utc_offset = now.offset().local_minus_utc() as i64 / 60;
+        let minute = (now.hour() * 60 + now.minute()) as i64;
+
+        let due = match when.minutes_until(minute, now.ordinal(), self.location, utc_offset) {
+            Some(minutes) => Due {
+                at: now.timestamp() + minutes * 60 - now.second() as i64,
+                run: true,
+            },
+            // The sun doesn't rise or set for a while: look again tomorrow.
+            None => Due {
+                at: now.timestamp() + 86400,
+                run: false,
+            },
+        };
+        self.due.borrow_mut().insert(ix, due);
+
+        wake_in(ix, due.at - now.timestamp());
+    }
+}
+
+impl<X: XConn> EventHook<X> for ScheduleHook<X> {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let ix = match event {
+            XEvent::ClientMessage(msg) if msg.dtype == SCHEDULE_TICK => msg.data.as_usize()[0],
+            _ => return Ok(true),
+        };
+        let due = match self.due.borrow().get(&ix).copied() {
+            Some(due) => due,
+            None => return Ok(false),
+        };
+
+        let now = Local::now().timestamp();
+        if now < due.at {
+            // Not due yet, but the clock may have changed since it was worked out.
+            if due.run {
+                self.schedule(ix);
+            } else {
+                wake_in(ix, due.at - now);
+            }
+            return Ok(false);
+        }
+
+        // Schedule the next run first, so that a failing action still runs tomorrow.
+        self.schedule(ix);
+        if !due.run {
+            return Ok(false);
+        }
+        if let Some((when, action)) = self.events.borrow_mut().get_mut(ix) {
+            info!("running the action scheduled for {when:?}");
+            if let Err(e) = action.call(state, x) {
+                warn!("scheduled action for {when:?} failed: {e}");
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for ScheduleHook<X> {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        for ix in 0..self.events.borrow().len() {
+            self.schedule(ix);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5,
+        longitude: -0.13,
+    };
+
+    #[test]
+    fn sun_times_match_the_almanac() {
+        // The summer solstice in London: sunrise at 03:43 and sunset at 20:21 UTC.
+        let (rise, set) = sun_times(172, LONDON).unwrap();
+        assert!((rise - 223.0).abs() < 5.0, "{rise}");
+        assert!((set - 1221.0).abs() < 5.0, "{set}");
+
+        let svalbard = Location {
+            latitude: 78.2,
+            longitude: 15.6,
+        };
+        assert_eq!(sun_times(172, svalbard), None);
+    }
+
+    #[test]
+    fn actions_already_run_today_wait_for_tomorrow() {
+        let at_nine = When::At(21, 0);
+        assert_eq!(at_nine.minutes_until(20 * 60, 1, LONDON, 0), Some(60));
+        assert_eq!(
+            at_nine.minutes_until(21 * 60, 1, LONDON, 0),
+            Some(MINUTES_PER_DAY)
+        );
+
+        let sunset = When::Sunset(0).minutes_until(0, 172, LONDON, 60).unwrap();
+        assert!((sunset - (1221 + 60)).abs() < 5, "{sunset}");
+    }
+}
@@ -0,0 +1,263 @@
+//! Moving focus and clients between screens, and keeping track of the primary one.
+use crate::{
+    bar,
+    client::window_types,
+    focus::warp_to_focus,
+    monitor::{rotate, screen_for_output, OutputSetup, Randr, RANDR_SETTLED},
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    pure::geometry::Rect,
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use x11rb::protocol::randr::Rotation;
+
+const DIALOG: &str = "_NET_WM_WINDOW_TYPE_DIALOG";
+
+/// Focus the screen `offset` places along from the focused one (wrapping around), taking the
+/// pointer along so that mouse actions land on the same screen.
+pub fn cycle_screen<X: XConn>(offset: isize) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let n = state.client_set.screens().count() as isize;
+        let current = state.client_set.current_screen().index() as isize;
+        let index = (current + offset).rem_euclid(n.max(1)) as usize;
+
+        x.modify_and_refresh(state, |cs| cs.focus_screen(index))?;
+        warp_to_focus(state, x)
+    })
+}
+
+/// Rotate the output showing the focused screen, matching the rotations `xrandr --rotate`
+/// calls normal, left, inverted and right to `ROTATE0`, `ROTATE90`, `ROTATE180` and
+/// `ROTATE270`. The wallpaper and layouts catch up once the change settles.
+pub fn rotate_focused_output<X: XConn>(rotation: Rotation) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| {
+        let randr = Randr::connect()?;
+        let r = state.client_set.current_screen().geometry();
+        let outputs = randr.active_outputs()?;
+        let name = match outputs.iter().find(|o| (o.r.x, o.r.y) == (r.x, r.y)) {
+            Some(o) => o.name.clone(),
+            None => return Ok(()),
+        };
+
+        let mut setups = randr.current_setups()?;
+        rotate(&mut setups, &name, rotation);
+        randr.apply(&setups)?;
+
+        Ok(())
+    })
+}
+
+/// The arrangement to go back to when mirroring is turned off, while it is on.
+#[derive(Debug, Default, Clone)]
+struct Mirrored {
+    extended: Option<Vec<OutputSetup>>,
+}
+
+/// Switch between showing the same thing on every connected output (say, a laptop and a
+/// projector) and whatever arrangement was in use before. Mirroring is published to the bar as
+/// "mirror"; the wallpaper and primary output follow once the change settles.
+pub fn toggle_mirror<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, _: &X| {
+        let randr = Randr::connect()?;
+        let mirrored = state.extension_or_default::<Mirrored>();
+        let extended = mirrored.borrow_mut().extended.take();
+
+        match extended {
+            Some(setups) => {
+                randr.apply(&setups)?;
+                bar::publish(state, "mirror", "");
+            }
+            None => {
+                let current = randr.current_setups()?;
+                if randr.apply(&randr.mirrored_setups()?)? {
+                    mirrored.borrow_mut().extended = Some(current);
+                    bar::publish(state, "mirror", "on");
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Where `r` ends up when moved from the screen `from` to the screen `to`, keeping the same
+/// offset from the top left corner but staying within `to`.
+fn translate(r: Rect, from: Rect, to: Rect) -> Rect {
+    let dx = r.x.saturating_sub(from.x);
+    let dy = r.y.saturating_sub(from.y);
+    let w = r.w.min(to.w);
+    let h = r.h.min(to.h);
+
+    Rect::new(to.x + dx.min(to.w - w), to.y + dy.min(to.h - h), w, h)
+}
+
+/// Move the focused client to whichever tag is showing on the screen `offset` places along
+/// from the focused one (wrapping around), optionally focusing it there. Floating clients keep
+/// their position relative to the screen.
+pub fn send_to_screen<X: XConn>(offset: isize, follow: bool) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let cs = &state.client_set;
+        let id = match cs.current_client() {
+            Some(&id) => id,
+            None => return Ok(()),
+        };
+
+        let n = cs.screens().count() as isize;
+        let current = cs.current_screen();
+        let index = (current.index() as isize + offset).rem_euclid(n) as usize;
+        if n < 2 || index == current.index() {
+            return Ok(());
+        }
+        let target = match cs.screens().find(|s| s.index() == index) {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        let tag = target.workspace.tag().to_string();
+
+        let floating = if cs.is_floating(&id) {
+            let r = x.client_geometry(id)?;
+            Some(translate(r, current.geometry(), target.geometry()))
+        } else {
+            None
+        };
+
+        x.modify_and_refresh(state, |cs| {
+            cs.move_client_to_tag(&id, &tag);
+            if let Some(r) = floating {
+                let _ = cs.float(id, r);
+            }
+            if follow {
+                cs.focus_client(&id);
+            }
+        })
+    })
+}
+
+/// Where the primary output is, as last seen by [PrimaryHook].
+#[derive(Debug, Default, Clone)]
+pub struct Primary {
+    r: Option<Rect>,
+}
+
+/// The index of the screen showing the primary output.
+pub fn primary_screen<X: XConn>(state: &mut State<X>) -> Option<usize> {
+    let r = state.extension_or_default::<Primary>().borrow().r?;
+
+    screen_for_output(state, r)
+}
+
+/// Focus the screen showing the primary output, taking the pointer along.
+pub fn focus_primary<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| match primary_screen(state) {
+        Some(index) => {
+            x.modify_and_refresh(state, |cs| cs.focus_screen(index))?;
+            warp_to_focus(state, x)
+        }
+        None => Ok(()),
+    })
+}
+
+/// Keeps track of the primary output, publishing its name to the bar as "primary" so that a
+/// bar launched per monitor can choose to only run there. The primary output is the one named
+/// in the config if it is connected, then the one RandR has marked as primary, then the first
+/// active output.
+///
+/// When `dialogs_on_primary` is set new dialogs are moved to the tag on the primary screen and
+/// centered on it, wherever they were opened from.
+#[derive(Debug, Clone, Copy)]
+pub struct PrimaryHook {
+    pub output: Option<&'static str>,
+    pub dialogs_on_primary: bool,
+}
+
+impl PrimaryHook {
+    fn update<X: XConn>(&self, state: &mut State<X>) -> Result<()> {
+        let randr = Randr::connect()?;
+        let outputs = randr.active_outputs()?;
+        let configured = self.output.filter(|&o| outputs.iter().any(|a| a.name == o));
+        let name = match configured {
+            Some(o) => Some(o.to_string()),
+            None => randr
+                .primary()?
+                .or_else(|| outputs.first().map(|o| o.name.clone())),
+        };
+        let r = name
+            .as_ref()
+            .and_then(|n| outputs.iter().find(|o| &o.name == n))
+            .map(|o| o.r);
+
+        bar::publish(state, "primary", name.unwrap_or_default());
+        *state.extension_or_default::<Primary>().borrow_mut() = Primary { r };
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for PrimaryHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        if let XEvent::ClientMessage(msg) = event {
+            if msg.dtype == RANDR_SETTLED {
+                self.update(state)?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for PrimaryHook {
+    fn call(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
+        self.update(state)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for PrimaryHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if !self.dialogs_on_primary || !window_types(x, client).iter().any(|t| t == DIALOG) {
+            return Ok(());
+        }
+        let screen = match primary_screen(state) {
+            Some(index) => state.client_set.screens().find(|s| s.index() == index),
+            None => None,
+        };
+        let (tag, s) = match screen {
+            Some(s) => (s.workspace.tag().to_string(), s.geometry()),
+            None => return Ok(()),
+        };
+
+        let r = x.client_geometry(client)?;
+        let (w, h) = (r.w.min(s.w), r.h.min(s.h));
+        let centered = Rect::new(s.x + (s.w - w) / 2, s.y + (s.h - h) / 2, w, h);
+
+        let cs = &mut state.client_set;
+        cs.move_client_to_tag(&client, &tag);
+        cs.float(client, centered)?;
+        cs.focus_client(&client);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_keep_their_offset_but_stay_on_screen() {
+        let from = Rect::new(0, 0, 1920, 1080);
+        let to = Rect::new(1920, 0, 1280, 720);
+
+        let r = translate(Rect::new(100, 50, 400, 300), from, to);
+        assert_eq!(r, Rect::new(2020, 50, 400, 300));
+
+        let r = translate(Rect::new(1500, 900, 400, 300), from, to);
+        assert_eq!(r, Rect::new(2800, 420, 400, 300));
+    }
+}
@@ -0,0 +1,207 @@
+//! The freedesktop `org.freedesktop.ScreenSaver` D-Bus service.
+//!
+//! Browsers, video players and games call `Inhibit` on the session bus while they are playing
+//! something and `UnInhibit` with the returned cookie once they stop. Each application holding
+//! a cookie becomes an [idle](crate::idle) inhibitor named after it, so it shows up in the
+//! bar. Cookies held by a client that leaves the bus without releasing them are dropped, as
+//! otherwise a crashed player would keep the screens on for good.
+use crate::{idle, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{XConn, XEvent},
+    Result,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use tracing::{error, info};
+use zbus::{
+    blocking::{fdo::DBusProxy, Connection, ConnectionBuilder},
+    interface,
+    message::Header,
+};
+
+const SERVICE: &str = "org.freedesktop.ScreenSaver";
+/// Older clients use the short path.
+const PATHS: [&str; 2] = ["/org/freedesktop/ScreenSaver", "/ScreenSaver"];
+const INHIBITORS_CHANGED: &str = "_WM_SCREENSAVER_INHIBITORS";
+
+static COOKIES: Mutex<Cookies> = Mutex::new(Cookies::new());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cookie {
+    app: String,
+    /// The unique bus name of the client that took the cookie.
+    sender: Option<String>,
+}
+
+#[derive(Debug)]
+struct Cookies {
+    last: u32,
+    held: BTreeMap<u32, Cookie>,
+}
+
+impl Cookies {
+    const fn new() -> Self {
+        Self {
+            last: 0,
+            held: BTreeMap::new(),
+        }
+    }
+
+    fn take(&mut self, app: &str, sender: Option<String>) -> u32 {
+        // Some clients treat a cookie of 0 as the call having failed.
+        self.last = self.last.checked_add(1).unwrap_or(1);
+        let app = match app.trim() {
+            "" => sender.clone().unwrap_or_else(|| "unknown".to_string()),
+            app => app.to_string(),
+        };
+        self.held.insert(self.last, Cookie { app, sender });
+
+        self.last
+    }
+
+    fn release(&mut self, cookie: u32) -> bool {
+        self.held.remove(&cookie).is_some()
+    }
+
+    fn release_sender(&mut self, sender: &str) -> bool {
+        let before = self.held.len();
+        self.held.retain(|_, c| c.sender.as_deref() != Some(sender));
+
+        self.held.len() != before
+    }
+
+    fn apps(&self) -> BTreeSet<String> {
+        self.held.values().map(|c| c.app.clone()).collect()
+    }
+}
+
+fn changed() {
+    notify_after(Duration::ZERO, INHIBITORS_CHANGED, [0; 5]);
+}
+
+struct ScreenSaver;
+
+#[interface(name = "org.freedesktop.ScreenSaver")]
+impl ScreenSaver {
+    fn inhibit(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        application_name: &str,
+        reason_for_inhibit: &str,
+    ) -> u32 {
+        let sender = header.sender().map(|s| s.to_string());
+        info!("{application_name} is inhibiting the screen saver: {reason_for_inhibit}");
+        let cookie = match COOKIES.lock() {
+            Ok(mut cookies) => cookies.take(application_name, sender),
+            Err(_) => return 0,
+        };
+        changed();
+
+        cookie
+    }
+
+    fn un_inhibit(&self, cookie: u32) {
+        if COOKIES
+            .lock()
+            .is_ok_and(|mut cookies| cookies.release(cookie))
+        {
+            changed();
+        }
+    }
+}
+
+/// Start serving the ScreenSaver interface on the session bus. This fails quietly if there is
+/// no session bus or something else already provides the service.
+pub fn serve() {
+    thread::spawn(|| {
+        if let Err(e) = run_service() {
+            error!("unable to serve {SERVICE}: {e}");
+        }
+    });
+}
+
+fn run_service() -> zbus::Result<()> {
+    let mut builder = ConnectionBuilder::session()?.name(SERVICE)?;
+    for path in PATHS {
+        builder = builder.serve_at(path, ScreenSaver)?;
+    }
+    let conn: Connection = builder.build()?;
+
+    // Requests are handled on the connection's own thread: this one only needs to notice
+    // clients leaving the bus.
+    for signal in DBusProxy::new(&conn)?.receive_name_owner_changed()? {
+        let args = signal.args()?;
+        if args.new_owner().is_some() {
+            continue;
+        }
+        let name = args.name().as_str();
+        if COOKIES
+            .lock()
+            .is_ok_and(|mut cookies| cookies.release_sender(name))
+        {
+            info!("{name} left the bus while inhibiting the screen saver");
+            changed();
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps the idle inhibitors in line with the applications holding cookies from [serve].
+#[derive(Debug, Default, Clone)]
+pub struct ScreenSaverHook {
+    apps: BTreeSet<String>,
+}
+
+impl<X: XConn> EventHook<X> for ScreenSaverHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == INHIBITORS_CHANGED => (),
+            _ => return Ok(true),
+        }
+
+        let apps = match COOKIES.lock() {
+            Ok(cookies) => cookies.apps(),
+            Err(_) => return Ok(false),
+        };
+        for app in apps.difference(&self.apps) {
+            idle::inhibit(state, app)?;
+        }
+        for app in self.apps.difference(&apps) {
+            idle::uninhibit(state, app)?;
+        }
+        self.apps = apps;
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apps_are_held_until_their_last_cookie_goes() {
+        let mut cookies = Cookies::new();
+        let a = cookies.take("firefox", Some(":1.20".into()));
+        let b = cookies.take("firefox", Some(":1.20".into()));
+        cookies.take("mpv", Some(":1.31".into()));
+        assert_ne!(a, 0);
+
+        assert!(cookies.release(a));
+        assert!(!cookies.release(a));
+        assert_eq!(
+            cookies.apps(),
+            BTreeSet::from(["firefox".into(), "mpv".into()])
+        );
+
+        assert!(cookies.release(b));
+        assert!(cookies.release_sender(":1.31"));
+        assert!(cookies.apps().is_empty());
+    }
+}
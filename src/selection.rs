@@ -0,0 +1,182 @@
+//! Owning the ICCCM window manager selection for the screen, `WM_S0`.
+//!
+//! Only one window manager can manage a screen at a time, so on startup the selection is
+//! taken to claim it. With `--replace` a running window manager is asked to give it up and
+//! waited for to exit first, which makes it easy to switch into this window manager from an
+//! existing session. The same works the other way around: when another window manager takes
+//! the selection, the [ReplacedHook] exits the way it would for SIGTERM.
+use crate::{modal::x_err, restart::prepare_to_exit, timer::notify_after};
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{XConn, XEvent},
+    Error, Result,
+};
+use std::{
+    process, thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt,
+            CreateWindowAux, EventMask, PropMode, WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, NONE,
+};
+
+const REPLACED: &str = "_WM_REPLACED";
+/// How long to wait for a window manager being replaced to exit.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn intern(conn: &RustConnection, name: &str) -> Result<u32> {
+    Ok(conn
+        .intern_atom(false, name.as_bytes())
+        .map_err(x_err)?
+        .reply()
+        .map_err(x_err)?
+        .atom)
+}
+
+/// Get a server timestamp to take the selection with, by appending nothing to a property of
+/// `win` and waiting for the PropertyNotify.
+fn timestamp(conn: &RustConnection, win: u32) -> Result<u32> {
+    conn.change_property8(
+        PropMode::APPEND,
+        win,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        &[],
+    )
+    .map_err(x_err)?;
+    conn.flush().map_err(x_err)?;
+
+    loop {
+        if let Event::PropertyNotify(e) = conn.wait_for_event().map_err(x_err)? {
+            if e.window == win {
+                return Ok(e.time);
+            }
+        }
+    }
+}
+
+/// Wait for the window that owned the selection before us to be destroyed, which is how a
+/// window manager says it has finished exiting.
+fn wait_for_exit(conn: &RustConnection, previous: u32) -> Result<()> {
+    let deadline = Instant::now() + REPLACE_TIMEOUT;
+    loop {
+        match conn.poll_for_event().map_err(x_err)? {
+            Some(Event::DestroyNotify(e)) if e.window == previous => return Ok(()),
+            Some(_) => (),
+            None if Instant::now() >= deadline => {
+                warn!("the running window manager didn't exit: carrying on regardless");
+                return Ok(());
+            }
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Take the window manager selection, replacing a running window manager if `replace` is
+/// set. This needs to happen before connecting penrose, which would otherwise fail to manage
+/// a screen that is already managed.
+pub fn acquire(replace: bool) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+    let selection = intern(&conn, &format!("WM_S{screen_num}"))?;
+    let get_owner = |conn: &RustConnection| -> Result<u32> {
+        Ok(conn
+            .get_selection_owner(selection)
+            .map_err(x_err)?
+            .reply()
+            .map_err(x_err)?
+            .owner)
+    };
+
+    let previous = get_owner(&conn)?;
+    if previous != NONE && !replace {
+        return Err(Error::Custom(
+            "another window manager is running: use --replace to take over from it".into(),
+        ));
+    }
+
+    let win = conn.generate_id().map_err(x_err)?;
+    let aux = CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        win,
+        root,
+        -1,
+        -1,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        COPY_FROM_PARENT,
+        &aux,
+    )
+    .map_err(x_err)?;
+    if previous != NONE {
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+        conn.change_window_attributes(previous, &aux)
+            .map_err(x_err)?;
+    }
+
+    let time = timestamp(&conn, win)?;
+    conn.set_selection_owner(win, selection, time)
+        .map_err(x_err)?;
+    if get_owner(&conn)? != win {
+        return Err(Error::Custom(format!(
+            "unable to take WM_S{screen_num} from the running window manager"
+        )));
+    }
+    if previous != NONE {
+        info!("waiting for the running window manager to exit");
+        wait_for_exit(&conn, previous)?;
+    }
+
+    let manager = intern(&conn, "MANAGER")?;
+    let event = ClientMessageEvent::new(32, root, manager, [time, selection, win, 0, 0]);
+    conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, event)
+        .map_err(x_err)?;
+    conn.flush().map_err(x_err)?;
+
+    // The connection owns the selection, so it stays open for as long as we're running.
+    thread::spawn(move || loop {
+        match conn.wait_for_event() {
+            Ok(Event::SelectionClear(e)) if e.owner == win => {
+                notify_after(Duration::ZERO, REPLACED, [0; 5]);
+            }
+            Ok(_) => (),
+            Err(e) => {
+                error!("lost the window manager selection connection: {e}");
+                return;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Exits when another window manager takes over the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct ReplacedHook;
+
+impl<X: XConn> EventHook<X> for ReplacedHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        match event {
+            XEvent::ClientMessage(msg) if msg.dtype == REPLACED => (),
+            _ => return Ok(true),
+        }
+
+        info!("replaced by another window manager: exiting");
+        prepare_to_exit(state, x)?;
+
+        process::exit(0)
+    }
+}
@@ -0,0 +1,143 @@
+//! Locking the screen before the machine goes to sleep.
+//!
+//! Starting the locker from a `PrepareForSleep` handler alone races the suspend, and the
+//! unlocked desktop can be on screen for a moment after resuming. Instead a logind delay
+//! inhibitor is held at all times, and is only released once the locker has grabbed the
+//! keyboard (or given up trying). It is taken again on resume, ready for the next time.
+use crate::{
+    logind,
+    modal::{x_err, KeyboardGrab},
+    process,
+};
+use penrose::Result;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ConnectionExt, GrabMode, GrabStatus},
+    rust_connection::RustConnection,
+    CURRENT_TIME,
+};
+use zbus::{
+    blocking::{self, Proxy},
+    zvariant::OwnedFd,
+};
+
+/// How long to give a locker that is already starting up (e.g. from closing the lid) before
+/// starting another.
+const ALREADY_LOCKING: Duration = Duration::from_millis(500);
+/// logind won't wait longer than `InhibitDelayMaxSec`, which defaults to five seconds.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn take_inhibitor(logind: &Proxy<'_>) -> Result<OwnedFd> {
+    logind
+        .call(
+            "Inhibit",
+            &("sleep", "wm", "Lock the screen before sleeping", "delay"),
+        )
+        .map_err(x_err)
+}
+
+/// Whether the keyboard is grabbed by `locker`. X won't say who holds a grab, so one only
+/// counts as the locker's while the locker is running and the window manager isn't holding a
+/// grab of its own (for float mode, mark names...).
+fn locker_grabbed(conn: &RustConnection, root: u32, locker: &str) -> Result<bool> {
+    let reply = conn
+        .grab_keyboard(false, root, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)
+        .map_err(x_err)?
+        .reply()
+        .map_err(x_err)?;
+
+    if reply.status == GrabStatus::SUCCESS {
+        conn.ungrab_keyboard(CURRENT_TIME).map_err(x_err)?;
+        conn.flush().map_err(x_err)?;
+    }
+
+    let program = locker.split_whitespace().next().unwrap_or(locker);
+
+    Ok(reply.status == GrabStatus::ALREADY_GRABBED
+        && !KeyboardGrab::held()
+        && process::is_running(program))
+}
+
+fn wait_for_grab(
+    conn: &RustConnection,
+    root: u32,
+    locker: &str,
+    timeout: Duration,
+) -> Result<bool> {
+    let start = Instant::now();
+    loop {
+        if locker_grabbed(conn, root, locker)? {
+            return Ok(true);
+        } else if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Start `locker` unless one is already grabbing the keyboard, and wait for it to grab.
+fn lock_with(conn: &RustConnection, root: u32, locker: &str) -> Result<()> {
+    if !wait_for_grab(conn, root, locker, ALREADY_LOCKING)? {
+        info!("locking the screen before sleeping");
+        process::run(locker)?;
+        if !wait_for_grab(conn, root, locker, LOCK_TIMEOUT)? {
+            warn!("{locker} didn't grab the keyboard before sleeping");
+        }
+    }
+
+    Ok(())
+}
+
+fn connect() -> Result<(RustConnection, u32)> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+
+    Ok((conn, root))
+}
+
+/// Start `locker` and block until it has grabbed the keyboard (or given up waiting on it),
+/// for suspending straight after.
+pub fn lock(locker: &str) -> Result<()> {
+    let (conn, root) = connect()?;
+
+    lock_with(&conn, root, locker)
+}
+
+fn run(locker: &str) -> Result<()> {
+    let (conn, root) = connect()?;
+    let bus = blocking::Connection::system().map_err(x_err)?;
+    let logind = logind::manager(&bus).map_err(x_err)?;
+
+    let mut inhibitor = Some(take_inhibitor(&logind)?);
+    for msg in logind.receive_signal("PrepareForSleep").map_err(x_err)? {
+        let sleeping: bool = msg.body().deserialize().map_err(x_err)?;
+        if !sleeping {
+            if inhibitor.is_none() {
+                inhibitor = Some(take_inhibitor(&logind)?);
+            }
+            continue;
+        }
+
+        lock_with(&conn, root, locker)?;
+        // Dropping the inhibitor lets the machine go to sleep.
+        inhibitor = None;
+    }
+
+    Ok(())
+}
+
+/// Run `locker` whenever the machine is about to sleep, holding off the sleep until it has
+/// locked the screen.
+pub fn lock_before_sleep(locker: &'static str) {
+    thread::spawn(move || {
+        if let Err(e) = run(locker) {
+            error!("unable to lock the screen before sleeping: {e}");
+        }
+    });
+}
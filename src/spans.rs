@@ -0,0 +1,156 @@
+//! Tracing spans for working out where the time goes.
+//!
+//! Hooks, key bindings and layouts are wrapped so that each opens a span while it runs, named
+//! for the hook's type, the keys bound or the layout. The spans are at debug level and cost
+//! next to nothing normally: `wm --profile` turns on debug logging and writes every span out
+//! as a Chrome trace (see [logging](crate::logging)), which chrome://tracing, Perfetto or
+//! speedscope will show as a flamegraph. The span for a binding covers everything it does,
+//! including penrose applying the layout and running the refresh hooks, so a slow tag switch
+//! shows up as a long `binding` span with the culprit somewhere underneath it.
+use crate::panics::catch;
+use penrose::{
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, ManageHook, StateHook},
+        layout::{Layout, Message},
+        State,
+    },
+    pure::{geometry::Rect, Stack},
+    x::{XConn, XEvent},
+    Result, Xid,
+};
+use std::{any::type_name, collections::HashMap};
+use tracing::debug_span;
+
+/// The name of `T` without its module path or type parameters, e.g. `BorderColorHook`.
+fn short_type_name<T>() -> &'static str {
+    let name = type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// A hook or binding that runs inside a span named for it.
+#[derive(Debug, Clone)]
+pub struct Traced<H> {
+    name: String,
+    inner: H,
+}
+
+/// Run `hook` inside a span named for its type.
+pub fn traced<H>(hook: H) -> Traced<H> {
+    Traced {
+        name: short_type_name::<H>().to_string(),
+        inner: hook,
+    }
+}
+
+impl<H> Traced<H> {
+    /// The name of the wrapped hook or binding.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Panics are caught here too, so that one hook panicking doesn't keep the rest of the hooks
+// it has been composed with from running.
+impl<X: XConn, H: EventHook<X>> EventHook<X> for Traced<H> {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let _span = debug_span!("event_hook", name = self.name.as_str()).entered();
+        // Let penrose handle the event as normal if the hook panicked part way through it.
+        catch(&self.name, || self.inner.call(event, state, x)).unwrap_or(Ok(true))
+    }
+}
+
+impl<X: XConn, H: ManageHook<X>> ManageHook<X> for Traced<H> {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let _span = debug_span!("manage_hook", name = self.name.as_str()).entered();
+        catch(&self.name, || self.inner.call(client, state, x)).unwrap_or(Ok(()))
+    }
+}
+
+impl<X: XConn, H: StateHook<X>> StateHook<X> for Traced<H> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let _span = debug_span!("state_hook", name = self.name.as_str()).entered();
+        catch(&self.name, || self.inner.call(state, x)).unwrap_or(Ok(()))
+    }
+}
+
+impl<X: XConn> KeyEventHandler<X> for Traced<Box<dyn KeyEventHandler<X>>> {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let _span = debug_span!("binding", keys = self.name.as_str()).entered();
+        self.inner.call(state, x)
+    }
+}
+
+/// Run each key binding inside a span named for the keys it is bound to.
+pub fn trace_key_bindings<X: XConn + 'static>(
+    bindings: HashMap<String, Box<dyn KeyEventHandler<X>>>,
+) -> HashMap<String, Box<dyn KeyEventHandler<X>>> {
+    bindings
+        .into_iter()
+        .map(|(keys, inner)| {
+            let name = keys.clone();
+            (keys, Box::new(Traced { name, inner }) as _)
+        })
+        .collect()
+}
+
+/// Lays out `inner` inside a span named for it.
+#[derive(Clone)]
+pub struct TracedLayout {
+    inner: Box<dyn Layout>,
+}
+
+impl TracedLayout {
+    pub fn wrap(inner: Box<dyn Layout>) -> Box<dyn Layout> {
+        Box::new(Self { inner })
+    }
+}
+
+impl Layout for TracedLayout {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layout> {
+        Box::new(self.clone())
+    }
+
+    fn layout(&mut self, s: &Stack<Xid>, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        let _span = debug_span!("layout", name = self.inner.name().as_str()).entered();
+        let (new, positions) = self.inner.layout(s, r);
+        if let Some(inner) = new {
+            self.inner = inner;
+        }
+
+        (None, positions)
+    }
+
+    fn layout_empty(&mut self, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        if let (Some(inner), _) = self.inner.layout_empty(r) {
+            self.inner = inner;
+        }
+
+        (None, vec![])
+    }
+
+    fn handle_message(&mut self, m: &Message) -> Option<Box<dyn Layout>> {
+        if let Some(inner) = self.inner.handle_message(m) {
+            self.inner = inner;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_named_without_the_module_path() {
+        assert_eq!(short_type_name::<Traced<Rect>>(), "Traced");
+        assert_eq!(short_type_name::<Rect>(), "Rect");
+    }
+}
@@ -0,0 +1,226 @@
+//! Keeping selected clients stacked above or below everything else.
+use crate::{
+    batch::Batch,
+    client::{net_wm_state, requested_state, set_net_wm_state},
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    x::{ClientConfig, XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::collections::HashSet;
+
+const NET_WM_STATE_ABOVE: &str = "_NET_WM_STATE_ABOVE";
+const NET_WM_STATE_BELOW: &str = "_NET_WM_STATE_BELOW";
+
+/// The clients that should be kept above all others.
+#[derive(Debug, Default, Clone)]
+pub struct AlwaysOnTop {
+    clients: HashSet<Xid>,
+}
+
+impl AlwaysOnTop {
+    pub fn contains(&self, id: &Xid) -> bool {
+        self.clients.contains(id)
+    }
+}
+
+/// The clients that should be kept below all others, such as desktop widgets.
+#[derive(Debug, Default, Clone)]
+pub struct KeptBelow {
+    clients: HashSet<Xid>,
+}
+
+/// Mark or unmark `id` as always on top, keeping its _NET_WM_STATE in sync. Returns whether
+/// anything changed: callers are responsible for refreshing.
+pub fn mark_above<X: XConn>(id: Xid, above: bool, state: &mut State<X>, x: &X) -> Result<bool> {
+    let ext = state.extension_or_default::<AlwaysOnTop>();
+    let changed = if above {
+        ext.borrow_mut().clients.insert(id)
+    } else {
+        ext.borrow_mut().clients.remove(&id)
+    };
+
+    if changed {
+        set_net_wm_state(x, id, NET_WM_STATE_ABOVE, above)?;
+    }
+    if above {
+        mark_below(id, false, state, x)?;
+    }
+
+    Ok(changed)
+}
+
+/// Mark or unmark `id` as kept below everything else, the same as [mark_above]. A client
+/// can't be kept both above and below, so marking one unmarks the other.
+pub fn mark_below<X: XConn>(id: Xid, below: bool, state: &mut State<X>, x: &X) -> Result<bool> {
+    let ext = state.extension_or_default::<KeptBelow>();
+    let changed = if below {
+        ext.borrow_mut().clients.insert(id)
+    } else {
+        ext.borrow_mut().clients.remove(&id)
+    };
+
+    if changed {
+        set_net_wm_state(x, id, NET_WM_STATE_BELOW, below)?;
+    }
+    if below {
+        mark_above(id, false, state, x)?;
+    }
+
+    Ok(changed)
+}
+
+/// Mark or unmark `id` as always on top and refresh if that changed anything.
+pub fn set_above<X: XConn>(id: Xid, above: bool, state: &mut State<X>, x: &X) -> Result<()> {
+    if mark_above(id, above, state, x)? {
+        x.refresh(state)?;
+    }
+
+    Ok(())
+}
+
+/// Toggle whether the focused client is kept above all other windows.
+pub fn toggle_above<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let id = match state.client_set.current_client() {
+            Some(&id) => id,
+            None => return Ok(()),
+        };
+        let above = state
+            .extension_or_default::<AlwaysOnTop>()
+            .borrow()
+            .contains(&id);
+
+        set_above(id, !above, state, x)
+    })
+}
+
+/// Restacks always-on-top clients after every refresh so that focus changes never bury them,
+/// and clients kept below everything else so that they never cover anything. Honours
+/// _NET_WM_STATE_ABOVE and _NET_WM_STATE_BELOW from clients both at map time and via client
+/// messages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AlwaysOnTopHook;
+
+impl<X: XConn> EventHook<X> for AlwaysOnTopHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if state.client_set.contains(&msg.id) => msg,
+            _ => return Ok(true),
+        };
+
+        let above = state
+            .extension_or_default::<AlwaysOnTop>()
+            .borrow()
+            .contains(&msg.id);
+        let below = state
+            .extension_or_default::<KeptBelow>()
+            .borrow()
+            .clients
+            .contains(&msg.id);
+        let mut changed = false;
+        if let Some(above) = requested_state(state, x, msg, NET_WM_STATE_ABOVE, above)? {
+            changed |= mark_above(msg.id, above, state, x)?;
+        }
+        if let Some(below) = requested_state(state, x, msg, NET_WM_STATE_BELOW, below)? {
+            changed |= mark_below(msg.id, below, state, x)?;
+        }
+        if changed {
+            x.refresh(state)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for AlwaysOnTopHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let atoms = net_wm_state(x, client)?;
+        if atoms.iter().any(|a| a == NET_WM_STATE_ABOVE) {
+            state
+                .extension_or_default::<AlwaysOnTop>()
+                .borrow_mut()
+                .clients
+                .insert(client);
+        } else if atoms.iter().any(|a| a == NET_WM_STATE_BELOW) {
+            state
+                .extension_or_default::<KeptBelow>()
+                .borrow_mut()
+                .clients
+                .insert(client);
+        }
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for AlwaysOnTopHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<AlwaysOnTop>();
+        ext.borrow_mut()
+            .clients
+            .retain(|id| state.client_set.contains(id));
+        let below = state.extension_or_default::<KeptBelow>();
+        below
+            .borrow_mut()
+            .clients
+            .retain(|id| state.client_set.contains(id));
+
+        let batch = state.extension_or_default::<Batch>();
+        let visible: HashSet<Xid> = state
+            .client_set
+            .on_screen_workspace_clients()
+            .copied()
+            .collect();
+        for id in ext
+            .borrow()
+            .clients
+            .iter()
+            .filter(|id| visible.contains(id))
+        {
+            batch
+                .borrow_mut()
+                .configure(x, *id, ClientConfig::StackTop)?;
+        }
+        for id in below
+            .borrow()
+            .clients
+            .iter()
+            .filter(|id| visible.contains(id))
+        {
+            batch
+                .borrow_mut()
+                .configure(x, *id, ClientConfig::StackBottom)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::net_wm_state, mock::MockXConn};
+
+    #[test]
+    fn toggling_above_marks_the_focused_client() {
+        let (x, mut state, id) = MockXConn::with_client();
+        let mut binding = toggle_above();
+
+        binding.call(&mut state, &x).unwrap();
+        assert!(state
+            .extension_or_default::<AlwaysOnTop>()
+            .borrow()
+            .contains(&id));
+        assert_eq!(net_wm_state(&x, id).unwrap(), vec![NET_WM_STATE_ABOVE]);
+
+        binding.call(&mut state, &x).unwrap();
+        assert!(net_wm_state(&x, id).unwrap().is_empty());
+    }
+}
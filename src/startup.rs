@@ -0,0 +1,312 @@
+//! Startup notification for launched applications, following the freedesktop
+//! startup-notification spec.
+//!
+//! Applications started with [launch] are given a `DESKTOP_STARTUP_ID`, and the root window
+//! shows a busy cursor until each of them has a window up, says that it has finished starting
+//! or takes longer than [LAUNCH_TIMEOUT]. The window is sent to the tag it was launched from,
+//! so switching tags while something slow starts up doesn't leave it on whichever tag happens
+//! to be showing when it appears. Not everything sets `_NET_STARTUP_ID` on its windows, so
+//! the id is also looked for in the environment of the window's process.
+use crate::{
+    client::pid,
+    modal::{glyph_cursor, x_err},
+    process::run_with_env,
+    timer::notify_after,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, ManageHook},
+        State,
+    },
+    x::{ClientMessageData, Prop, XConn, XEvent},
+    Error, Result, Xid,
+};
+use std::{
+    collections::HashMap,
+    fs, process,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ChangeWindowAttributesAux, ConnectionExt},
+};
+
+const STARTUP_ID_VAR: &str = "DESKTOP_STARTUP_ID";
+const INFO_BEGIN: &str = "_NET_STARTUP_INFO_BEGIN";
+const INFO: &str = "_NET_STARTUP_INFO";
+const LAUNCH_CHECK: &str = "_WM_LAUNCH_CHECK";
+/// How long to show the busy cursor for something that never shows a window.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(15);
+// Glyphs from the X cursor font.
+const XC_LEFT_PTR: u16 = 68;
+const XC_WATCH: u16 = 150;
+
+static NEXT_LAUNCH: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone)]
+struct Launch {
+    id: String,
+    tag: String,
+    started: Instant,
+}
+
+/// Applications launched that haven't shown a window yet.
+#[derive(Debug, Default, Clone)]
+pub struct Launches {
+    pending: Vec<Launch>,
+    busy: bool,
+}
+
+impl Launches {
+    fn finish(&mut self, id: &str) -> Option<Launch> {
+        let ix = self.pending.iter().position(|l| l.id == id)?;
+
+        Some(self.pending.remove(ix))
+    }
+
+    /// Show the busy cursor while anything is starting.
+    fn update_cursor(&mut self) -> Result<()> {
+        let busy = !self.pending.is_empty();
+        if busy == self.busy {
+            return Ok(());
+        }
+        self.busy = busy;
+
+        set_root_cursor(if busy { XC_WATCH } else { XC_LEFT_PTR })
+    }
+}
+
+fn set_root_cursor(glyph: u16) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+    let cursor = glyph_cursor(&conn, glyph)?;
+    let aux = ChangeWindowAttributesAux::new().cursor(cursor);
+    conn.change_window_attributes(root, &aux).map_err(x_err)?;
+    // The root window keeps using the cursor after it has been freed.
+    conn.free_cursor(cursor).map_err(x_err)?;
+    conn.flush().map_err(x_err)?;
+
+    Ok(())
+}
+
+/// A startup id unique to this launch.
+fn new_startup_id() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let n = NEXT_LAUNCH.fetch_add(1, Ordering::Relaxed);
+
+    format!("wm-{}-{secs}-{n}", process::id())
+}
+
+/// Start `program` with `args` with startup notification, to show up on the current tag.
+pub fn start<X: XConn>(state: &mut State<X>, program: &str, args: &[&str]) -> Result<()> {
+    let id = new_startup_id();
+    debug!("launching {program} as {id}");
+    run_with_env(program, args, &[(STARTUP_ID_VAR, &id)])?;
+
+    let ext = state.extension_or_default::<Launches>();
+    let mut launches = ext.borrow_mut();
+    launches.pending.push(Launch {
+        id,
+        tag: state.client_set.current_tag().to_string(),
+        started: Instant::now(),
+    });
+    notify_after(LAUNCH_TIMEOUT, LAUNCH_CHECK, [0; 5]);
+
+    launches.update_cursor()
+}
+
+/// [start] `command`, split on whitespace.
+pub fn start_command<X: XConn>(state: &mut State<X>, command: &str) -> Result<()> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.split_first() {
+        Some((program, args)) => start(state, program, args),
+        None => Err(Error::Custom("empty command".into())),
+    }
+}
+
+/// Launch the application run by `command` with startup notification.
+pub fn launch<X: XConn>(command: &'static str) -> Box<dyn KeyEventHandler<X>> {
+    key_handler(move |state: &mut State<X>, _: &X| start_command(state, command))
+}
+
+/// Stop showing the busy cursor, before exiting.
+pub fn clear<X: XConn>(state: &mut State<X>) -> Result<()> {
+    let ext = state.extension_or_default::<Launches>();
+    let mut launches = ext.borrow_mut();
+    launches.pending.clear();
+
+    launches.update_cursor()
+}
+
+/// The startup id of `id`, from its own property or the environment it was started with.
+fn startup_id<X: XConn>(x: &X, id: Xid) -> Option<String> {
+    if let Ok(Some(Prop::UTF8String(strs))) = x.get_prop(id, "_NET_STARTUP_ID") {
+        if let Some(s) = strs.into_iter().next() {
+            return Some(s);
+        }
+    }
+    // Changes to the environment made after starting don't show up here, so this still has
+    // the id even though toolkits unset it once they have read it.
+    let environ = fs::read(format!("/proc/{}/environ", pid(x, id)?)).ok()?;
+    let prefix = format!("{STARTUP_ID_VAR}=");
+
+    environ
+        .split(|&b| b == 0)
+        .filter_map(|var| std::str::from_utf8(var).ok())
+        .find_map(|var| var.strip_prefix(&prefix))
+        .map(String::from)
+}
+
+/// Parse a startup notification message, e.g. `remove: ID=foo`, into its type and fields.
+/// Values may be quoted, and may contain backslash escaped characters.
+fn parse_message(msg: &str) -> Option<(&str, HashMap<String, String>)> {
+    let (kind, rest) = msg.split_once(':')?;
+    let mut fields = HashMap::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if_eq(&' ').is_some() {}
+        let key: String = chars.by_ref().take_while(|&c| c != '=').collect();
+        if key.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next()),
+                '"' => quoted = !quoted,
+                ' ' if !quoted => break,
+                c => value.push(c),
+            }
+        }
+        fields.insert(key, value);
+    }
+
+    Some((kind, fields))
+}
+
+/// Tracks applications started with [launch]: moving their windows to the tag they were
+/// launched from as they are managed, and clearing the busy cursor once they have started.
+#[derive(Debug, Default, Clone)]
+pub struct StartupNotificationHook {
+    /// Messages being received from each window, as they arrive 20 bytes at a time.
+    partial: HashMap<Xid, Vec<u8>>,
+}
+
+impl StartupNotificationHook {
+    /// Add a chunk of a message from `from`, returning the message once it is complete.
+    fn receive(&mut self, from: Xid, begin: bool, chunk: &[u8]) -> Option<String> {
+        if begin {
+            self.partial.insert(from, Vec::new());
+        }
+        let buf = self.partial.get_mut(&from)?;
+        match chunk.iter().position(|&b| b == 0) {
+            Some(end) => {
+                buf.extend_from_slice(&chunk[..end]);
+                let buf = self.partial.remove(&from)?;
+                Some(String::from_utf8_lossy(&buf).into_owned())
+            }
+            None => {
+                buf.extend_from_slice(chunk);
+                None
+            }
+        }
+    }
+}
+
+impl<X: XConn> EventHook<X> for StartupNotificationHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, _: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) => msg,
+            _ => return Ok(true),
+        };
+        let ext = state.extension_or_default::<Launches>();
+        let mut launches = ext.borrow_mut();
+
+        if msg.dtype == LAUNCH_CHECK {
+            launches
+                .pending
+                .retain(|l| l.started.elapsed() < LAUNCH_TIMEOUT);
+            launches.update_cursor()?;
+            return Ok(false);
+        }
+
+        let begin = msg.dtype == INFO_BEGIN;
+        let chunk = match &msg.data {
+            ClientMessageData::U8(chunk) if begin || msg.dtype == INFO => chunk,
+            _ => return Ok(true),
+        };
+        let message = match self.receive(msg.id, begin, chunk) {
+            Some(message) => message,
+            None => return Ok(false),
+        };
+        if let Some(("remove", fields)) = parse_message(&message) {
+            if let Some(id) = fields.get("ID") {
+                launches.finish(id);
+                launches.update_cursor()?;
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for StartupNotificationHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        let id = match startup_id(x, client) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let ext = state.extension_or_default::<Launches>();
+        let mut launches = ext.borrow_mut();
+        let launch = match launches.finish(&id) {
+            Some(launch) => launch,
+            None => return Ok(()),
+        };
+
+        if state.client_set.workspace(&launch.tag).is_some() {
+            state.client_set.move_client_to_tag(&client, &launch.tag);
+        } else {
+            warn!("{} has gone: leaving {client} where it is", launch.tag);
+        }
+
+        launches.update_cursor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_split_into_fields() {
+        let (kind, fields) =
+            parse_message(r#"new: ID=wm-1 NAME="Text \"Editor\"" SCREEN=0"#).unwrap();
+
+        assert_eq!(kind, "new");
+        assert_eq!(fields["ID"], "wm-1");
+        assert_eq!(fields["NAME"], r#"Text "Editor""#);
+        assert_eq!(fields["SCREEN"], "0");
+    }
+
+    #[test]
+    fn messages_are_put_back_together_from_chunks() {
+        let mut hook = StartupNotificationHook::default();
+        let from = Xid::from(7);
+        let mut last = [0; 20];
+        last[..3].copy_from_slice(b"foo");
+
+        assert_eq!(hook.receive(from, true, b"remove: ID=wm-1-2-3"), None);
+        assert_eq!(
+            hook.receive(from, false, &last),
+            Some("remove: ID=wm-1-2-3foo".into())
+        );
+    }
+}
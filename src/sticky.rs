@@ -0,0 +1,143 @@
+//! Sticky clients that follow their screen across tag changes.
+use crate::client::{net_wm_state, requested_state, set_net_wm_state};
+use penrose::{
+    core::{
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::collections::{HashMap, HashSet};
+
+const NET_WM_STATE_STICKY: &str = "_NET_WM_STATE_STICKY";
+
+/// Sticky clients along with the index of the screen they were last shown on.
+#[derive(Debug, Default, Clone)]
+pub struct Sticky {
+    clients: HashMap<Xid, usize>,
+}
+
+impl Sticky {
+    pub fn contains(&self, id: &Xid) -> bool {
+        self.clients.contains_key(id)
+    }
+}
+
+/// Make `id` sticky (or not). The client is pinned to the screen it is currently on.
+pub fn set_sticky<X: XConn>(id: Xid, sticky: bool, state: &mut State<X>) {
+    let ext = state.extension_or_default::<Sticky>();
+    if !sticky {
+        ext.borrow_mut().clients.remove(&id);
+        return;
+    }
+
+    let screen = match state.client_set.screen_for_client(&id) {
+        Some(s) => s.index(),
+        None => state.client_set.current_screen().index(),
+    };
+    ext.borrow_mut().clients.insert(id, screen);
+}
+
+/// Moves sticky clients onto whichever tag is now visible on their screen whenever they
+/// would otherwise have been hidden. Clients can ask to be made sticky with
+/// _NET_WM_STATE_STICKY, which is kept in sync however they are made sticky.
+#[derive(Debug, Default, Clone)]
+pub struct StickyHook {
+    /// The clients last marked as sticky in their _NET_WM_STATE.
+    marked: HashSet<Xid>,
+}
+
+impl StickyHook {
+    fn sync_state<X: XConn>(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Sticky>();
+        let sticky: HashSet<Xid> = ext.borrow().clients.keys().copied().collect();
+        self.marked.retain(|id| state.client_set.contains(id));
+
+        for &id in sticky.difference(&self.marked) {
+            set_net_wm_state(x, id, NET_WM_STATE_STICKY, true)?;
+        }
+        for &id in self.marked.difference(&sticky) {
+            set_net_wm_state(x, id, NET_WM_STATE_STICKY, false)?;
+        }
+        self.marked = sticky;
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> EventHook<X> for StickyHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) if state.client_set.contains(&msg.id) => msg,
+            _ => return Ok(true),
+        };
+
+        let ext = state.extension_or_default::<Sticky>();
+        let current = ext.borrow().contains(&msg.id);
+        match requested_state(state, x, msg, NET_WM_STATE_STICKY, current)? {
+            Some(sticky) if sticky != current => {
+                set_sticky(msg.id, sticky, state);
+                x.refresh(state)?;
+            }
+            _ => (),
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for StickyHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if net_wm_state(x, client)?
+            .iter()
+            .any(|a| a == NET_WM_STATE_STICKY)
+        {
+            set_sticky(client, true, state);
+        }
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for StickyHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Sticky>();
+        ext.borrow_mut()
+            .clients
+            .retain(|id, _| state.client_set.contains(id));
+        self.sync_state(state, x)?;
+
+        let mut moves = Vec::new();
+        for (id, screen) in ext.borrow_mut().clients.iter_mut() {
+            // Clients parked on an invisible workspace (minimized or swallowed) stay there.
+            let parked = state
+                .client_set
+                .tag_for_client(id)
+                .is_some_and(|t| !state.config.tags.iter().any(|tag| tag == t));
+            match state.client_set.screen_for_client(id) {
+                Some(s) => *screen = s.index(),
+                None if parked => (),
+                None => {
+                    if let Some(tag) = state.client_set.tag_for_screen(*screen) {
+                        moves.push((*id, tag.to_string(), x.client_geometry(*id)?));
+                    }
+                }
+            }
+        }
+
+        if moves.is_empty() {
+            return Ok(());
+        }
+
+        x.modify_and_refresh(state, |cs| {
+            for (id, tag, r) in moves.iter() {
+                let floating = cs.is_floating(id);
+                cs.move_client_to_tag(id, tag);
+                if floating {
+                    let _ = cs.float(*id, *r);
+                }
+            }
+        })
+    }
+}
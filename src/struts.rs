@@ -0,0 +1,356 @@
+//! Space kept free at the edges of particular outputs.
+//!
+//! Docks such as polybar or trayer say how much space they need with `_NET_WM_STRUT` or
+//! `_NET_WM_STRUT_PARTIAL`, and that space is kept free on whichever outputs they cover,
+//! following them as they are mapped, unmapped, moved or change their struts. Desktop
+//! widgets such as conky or plank often don't set a strut at all, so space can also be
+//! reserved per output in the config. As with the scale factors in [dpi](crate::dpi), layouts
+//! can't see which output they are laying out so the reserved space is looked up by screen
+//! geometry from a process wide table.
+use crate::{
+    client::window_types,
+    monitor::{Randr, RANDR_SETTLED},
+};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        layout::{Layout, Message},
+        State,
+    },
+    pure::{geometry::Rect, Stack},
+    x::{ClientAttr, Prop, XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use std::{collections::HashMap, sync::Mutex};
+
+const STRUT: &str = "_NET_WM_STRUT";
+const STRUT_PARTIAL: &str = "_NET_WM_STRUT_PARTIAL";
+const DOCK: &str = "_NET_WM_WINDOW_TYPE_DOCK";
+
+static RESERVED: Mutex<Vec<(Rect, Reserve)>> = Mutex::new(Vec::new());
+
+/// Pixels to keep free along each edge of an output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Reserve {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Reserve {
+    /// What is left of `r` once the reserved space is taken out, or `r` itself if nothing
+    /// would be left.
+    fn apply(&self, r: Rect) -> Rect {
+        let (dw, dh) = (self.left + self.right, self.top + self.bottom);
+        if dw >= r.w || dh >= r.h {
+            return r;
+        }
+
+        Rect::new(r.x + self.left, r.y + self.top, r.w - dw, r.h - dh)
+    }
+
+    /// Enough space along each edge for both `self` and `other`.
+    fn combine(self, other: Reserve) -> Reserve {
+        Reserve {
+            top: self.top.max(other.top),
+            bottom: self.bottom.max(other.bottom),
+            left: self.left.max(other.left),
+            right: self.right.max(other.right),
+        }
+    }
+}
+
+/// A dock's strut in the form of `_NET_WM_STRUT_PARTIAL`: the space it needs along the left,
+/// right, top and bottom edges of the root window, then the first and last row or column of
+/// each of those edges that it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Strut([u32; 12]);
+
+impl Strut {
+    fn partial(vals: &[u32]) -> Option<Self> {
+        Some(Self(vals.get(..12)?.try_into().ok()?))
+    }
+
+    /// A plain `_NET_WM_STRUT` covers the whole of each edge, which would take space from
+    /// every output lined up along it, so it is only applied to the part of the edge that
+    /// the dock itself is on.
+    fn plain(vals: &[u32], dock: Rect) -> Option<Self> {
+        let &[left, right, top, bottom] = vals.get(..4)? else {
+            return None;
+        };
+        let (y0, y1) = (dock.y, dock.y + dock.h.saturating_sub(1));
+        let (x0, x1) = (dock.x, dock.x + dock.w.saturating_sub(1));
+
+        Some(Self([
+            left, right, top, bottom, y0, y1, y0, y1, x0, x1, x0, x1,
+        ]))
+    }
+
+    /// The space this takes from the screen at `r`, given the geometry of the root window.
+    fn reserve(&self, r: Rect, root: Rect) -> Reserve {
+        let [left, right, top, bottom, ly0, ly1, ry0, ry1, tx0, tx1, bx0, bx1] = self.0;
+        let covers =
+            |first: u32, last: u32, start: u32, len: u32| first < start + len && last >= start;
+        let edge = |covered: bool, space: u32, offset: u32| {
+            if covered {
+                space.saturating_sub(offset)
+            } else {
+                0
+            }
+        };
+
+        Reserve {
+            top: edge(covers(tx0, tx1, r.x, r.w), top, r.y.saturating_sub(root.y)),
+            bottom: edge(
+                covers(bx0, bx1, r.x, r.w),
+                bottom,
+                (root.y + root.h).saturating_sub(r.y + r.h),
+            ),
+            left: edge(covers(ly0, ly1, r.y, r.h), left, r.x.saturating_sub(root.x)),
+            right: edge(
+                covers(ry0, ry1, r.y, r.h),
+                right,
+                (root.x + root.w).saturating_sub(r.x + r.w),
+            ),
+        }
+    }
+}
+
+/// The struts of the docks currently mapped.
+#[derive(Debug, Default, Clone)]
+pub struct Docks {
+    struts: HashMap<Xid, Strut>,
+}
+
+fn is_dock<X: XConn>(x: &X, id: Xid) -> bool {
+    window_types(x, id).iter().any(|t| t == DOCK)
+}
+
+/// The strut set by `id`, preferring the partial form.
+fn strut<X: XConn>(x: &X, id: Xid) -> Result<Option<Strut>> {
+    if let Some(Prop::Cardinal(vals)) = x.get_prop(id, STRUT_PARTIAL)? {
+        return Ok(Strut::partial(&vals));
+    }
+    match x.get_prop(id, STRUT)? {
+        Some(Prop::Cardinal(vals)) => Ok(Strut::plain(&vals, x.client_geometry(id)?)),
+        _ => Ok(None),
+    }
+}
+
+fn reserve_for(r: Rect) -> Reserve {
+    let p = r.midpoint();
+    let reserved = match RESERVED.lock() {
+        Ok(reserved) => reserved,
+        Err(_) => return Reserve::default(),
+    };
+
+    reserved
+        .iter()
+        .find(|(s, _)| s.contains_point(p))
+        .map_or(Reserve::default(), |&(_, reserve)| reserve)
+}
+
+/// What is left of the screen at `r` once its reserved space is taken out.
+pub fn usable_area(r: Rect) -> Rect {
+    reserve_for(r).apply(r)
+}
+
+/// Keeps the table of reserved space up to date, re-laying out the screens when it changes.
+/// Docks aren't managed, so their struts are tracked from the events they generate.
+#[derive(Debug, Clone, Copy)]
+pub struct ReservedSpaceHook {
+    /// Space to reserve by output name.
+    pub outputs: &'static [(&'static str, Reserve)],
+}
+
+impl ReservedSpaceHook {
+    fn update<X: XConn>(&self, state: &mut State<X>, x: &X) -> Result<()> {
+        let root = x.client_geometry(x.root())?;
+        let ext = state.extension_or_default::<Docks>();
+        let struts: Vec<Strut> = ext.borrow().struts.values().copied().collect();
+        let reserved: Vec<(Rect, Reserve)> = Randr::connect()?
+            .active_outputs()?
+            .into_iter()
+            .map(|o| {
+                let configured = self
+                    .outputs
+                    .iter()
+                    .find(|(name, _)| *name == o.name)
+                    .map_or(Reserve::default(), |&(_, reserve)| reserve);
+                let reserve = struts
+                    .iter()
+                    .fold(configured, |acc, s| acc.combine(s.reserve(o.r, root)));
+                (o.r, reserve)
+            })
+            .filter(|(_, reserve)| *reserve != Reserve::default())
+            .collect();
+
+        let changed = match RESERVED.lock() {
+            Ok(mut current) if *current != reserved => {
+                *current = reserved;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            x.refresh(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Start tracking `id` if it is a dock, returning whether it is one.
+    fn track<X: XConn>(&self, state: &mut State<X>, x: &X, id: Xid) -> Result<bool> {
+        if !is_dock(x, id) {
+            return Ok(false);
+        }
+        // For the property changes and moves that follow.
+        x.set_client_attributes(id, &[ClientAttr::ClientEventMask])?;
+        self.read_strut(state, x, id)?;
+
+        Ok(true)
+    }
+
+    fn read_strut<X: XConn>(&self, state: &mut State<X>, x: &X, id: Xid) -> Result<()> {
+        let strut = strut(x, id)?;
+        let ext = state.extension_or_default::<Docks>();
+        let mut docks = ext.borrow_mut();
+        match strut {
+            Some(strut) => docks.struts.insert(id, strut),
+            None => docks.struts.remove(&id),
+        };
+
+        Ok(())
+    }
+
+    fn is_tracked<X: XConn>(state: &mut State<X>, id: Xid) -> bool {
+        let ext = state.extension_or_default::<Docks>();
+        let tracked = ext.borrow().struts.contains_key(&id);
+
+        tracked
+    }
+}
+
+impl<X: XConn> EventHook<X> for ReservedSpaceHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let changed = match event {
+            XEvent::ClientMessage(msg) => msg.dtype == RANDR_SETTLED,
+            XEvent::MapRequest(id) => self.track(state, x, *id)?,
+            XEvent::UnmapNotify(id) | XEvent::Destroy(id) => {
+                let ext = state.extension_or_default::<Docks>();
+                let removed = ext.borrow_mut().struts.remove(id).is_some();
+                removed
+            }
+            XEvent::PropertyNotify(p) if p.atom == STRUT || p.atom == STRUT_PARTIAL => {
+                self.track(state, x, p.id)?
+            }
+            // A plain strut depends on where the dock is.
+            XEvent::ConfigureNotify(e) if !e.is_root && Self::is_tracked(state, e.id) => {
+                self.read_strut(state, x, e.id)?;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            self.update(state, x)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> StateHook<X> for ReservedSpaceHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        // Docks already mapped when the window manager starts, e.g. after a restart.
+        for id in x.existing_clients()? {
+            self.track(state, x, id)?;
+        }
+
+        self.update(state, x)
+    }
+}
+
+/// Lays out `inner` in whatever space the screen's output doesn't reserve.
+#[derive(Clone)]
+pub struct Reserved {
+    inner: Box<dyn Layout>,
+}
+
+impl Reserved {
+    pub fn wrap(inner: Box<dyn Layout>) -> Box<dyn Layout> {
+        Box::new(Self { inner })
+    }
+}
+
+impl Layout for Reserved {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layout> {
+        Box::new(self.clone())
+    }
+
+    fn layout(&mut self, s: &Stack<Xid>, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        let (new, positions) = self.inner.layout(s, reserve_for(r).apply(r));
+        if let Some(inner) = new {
+            self.inner = inner;
+        }
+
+        (None, positions)
+    }
+
+    fn layout_empty(&mut self, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        if let (Some(inner), _) = self.inner.layout_empty(reserve_for(r).apply(r)) {
+            self.inner = inner;
+        }
+
+        (None, vec![])
+    }
+
+    fn handle_message(&mut self, m: &Message) -> Option<Box<dyn Layout>> {
+        if let Some(inner) = self.inner.handle_message(m) {
+            self.inner = inner;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_space_comes_off_each_edge() {
+        let reserve = Reserve {
+            top: 30,
+            right: 300,
+            ..Reserve::default()
+        };
+
+        let r = reserve.apply(Rect::new(1920, 0, 2560, 1440));
+        assert_eq!(r, Rect::new(1920, 30, 2260, 1410));
+
+        let r = Rect::new(0, 0, 200, 100);
+        assert_eq!(reserve.apply(r), r);
+    }
+
+    #[test]
+    fn struts_only_take_space_from_the_outputs_they_cover() {
+        // A 30 pixel bar along the top of the left output, which is shorter than the right.
+        let strut = Strut([0, 0, 420, 0, 0, 0, 0, 0, 0, 1919, 0, 0]);
+        let root = Rect::new(0, 0, 4480, 1440);
+        let left = Rect::new(0, 390, 1920, 1050);
+        let right = Rect::new(1920, 0, 2560, 1440);
+
+        assert_eq!(strut.reserve(left, root).top, 30);
+        assert_eq!(strut.reserve(right, root), Reserve::default());
+
+        let bar = Rect::new(1920, 1410, 2560, 30);
+        let strut = Strut::plain(&[0, 0, 0, 30], bar).unwrap();
+        assert_eq!(strut.reserve(right, root).bottom, 30);
+        assert_eq!(strut.reserve(left, root), Reserve::default());
+    }
+}
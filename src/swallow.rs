@@ -0,0 +1,150 @@
+//! dwm style window swallowing: GUI programs launched from a terminal take over the
+//! terminal's tile until they exit.
+use crate::{
+    client::{pid, wm_class},
+    hidden::park_client,
+    process::ancestors,
+};
+use penrose::{
+    core::{
+        hooks::{ManageHook, StateHook},
+        State,
+    },
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::HashMap;
+
+/// The invisible workspace that swallowed terminals are parked on.
+const SWALLOW_TAG: &str = "swallowed";
+
+/// A terminal that has been hidden in favour of a child client.
+#[derive(Debug, Clone)]
+struct Swallowed {
+    terminal: Xid,
+    tag: String,
+}
+
+/// Terminals currently swallowed, keyed by the child client that swallowed them.
+#[derive(Debug, Default, Clone)]
+pub struct Swallows {
+    swallowed: HashMap<Xid, Swallowed>,
+}
+
+impl Swallows {
+    /// Each swallowing child along with the terminal it swallowed.
+    pub fn pairs(&self) -> Vec<(Xid, Xid)> {
+        self.swallowed
+            .iter()
+            .map(|(&child, s)| (child, s.terminal))
+            .collect()
+    }
+}
+
+/// Record `child` as having swallowed `terminal`, as saved by an earlier window manager,
+/// parking the terminal if it isn't already. Does nothing unless both are managed.
+pub fn restore_swallowed<X: XConn>(child: Xid, terminal: Xid, state: &mut State<X>) -> Result<()> {
+    let cs = &mut state.client_set;
+    let tag = match cs.tag_for_client(&child) {
+        Some(tag) if cs.contains(&terminal) => tag.to_string(),
+        _ => return Ok(()),
+    };
+    if cs.tag_for_client(&terminal) != Some(SWALLOW_TAG) {
+        park_client(cs, terminal, SWALLOW_TAG)?;
+    }
+    state
+        .extension_or_default::<Swallows>()
+        .borrow_mut()
+        .swallowed
+        .insert(child, Swallowed { terminal, tag });
+
+    Ok(())
+}
+
+/// Hides the parent terminal of newly managed clients (found by walking the process tree up
+/// from their _NET_WM_PID) and restores it once the child is gone. The swallowed terminals
+/// are kept in [Swallows] so the hook can be registered for both manage and refresh.
+#[derive(Debug, Clone)]
+pub struct SwallowHook {
+    terminals: Vec<String>,
+}
+
+impl SwallowHook {
+    /// Swallow terminals with any of the given WM_CLASS values.
+    pub fn new(terminals: &[&str]) -> Self {
+        Self {
+            terminals: terminals.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn is_terminal<X: XConn>(&self, x: &X, id: Xid) -> bool {
+        wm_class(x, id).is_some_and(|c| self.terminals.contains(&c))
+    }
+
+    fn terminal_for<X: XConn>(&self, child_pid: u32, state: &State<X>, x: &X) -> Option<Xid> {
+        let terminals: HashMap<u32, Xid> = state
+            .client_set
+            .clients()
+            .filter(|&&id| self.is_terminal(x, id))
+            .filter_map(|&id| Some((pid(x, id)?, id)))
+            .collect();
+
+        ancestors(child_pid).find_map(|p| terminals.get(&p).copied())
+    }
+}
+
+impl<X: XConn> ManageHook<X> for SwallowHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if state.client_set.is_floating(&client) || self.is_terminal(x, client) {
+            return Ok(());
+        }
+
+        let terminal = match pid(x, client).and_then(|p| self.terminal_for(p, state, x)) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let tag = match state.client_set.tag_for_client(&terminal) {
+            Some(tag) if tag != SWALLOW_TAG => tag.to_string(),
+            _ => return Ok(()),
+        };
+
+        park_client(&mut state.client_set, terminal, SWALLOW_TAG)?;
+        state
+            .extension_or_default::<Swallows>()
+            .borrow_mut()
+            .swallowed
+            .insert(client, Swallowed { terminal, tag });
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for SwallowHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Swallows>();
+        let mut restore = Vec::new();
+        ext.borrow_mut().swallowed.retain(|child, s| {
+            match state.client_set.tag_for_client(child) {
+                Some(tag) => s.tag = tag.to_string(),
+                None => restore.push(s.clone()),
+            }
+
+            state.client_set.contains(child)
+        });
+
+        let restore: Vec<_> = restore
+            .into_iter()
+            .filter(|s| state.client_set.contains(&s.terminal))
+            .collect();
+        if restore.is_empty() {
+            return Ok(());
+        }
+
+        x.modify_and_refresh(state, |cs| {
+            for s in restore.iter() {
+                cs.move_client_to_tag(&s.terminal, &s.tag);
+                cs.focus_client(&s.terminal);
+            }
+        })
+    }
+}
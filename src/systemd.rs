@@ -0,0 +1,172 @@
+//! Running as a systemd user service.
+//!
+//! With `Type=notify` systemd is told the window manager is ready once it starts managing the
+//! screen. With `WatchdogSec=` set the watchdog is pet from the event loop itself, so a hung
+//! window manager is restarted rather than left holding the display. SIGTERM is handed to the
+//! event loop as a ClientMessage so that anything changed outside of the window manager is
+//! put back before it exits. The variables systemd passes along for all this are taken out of
+//! the environment so that children don't inherit them, and only handed on to the window
+//! manager exec'd when restarting in place.
+use crate::{modal::x_err, restart::prepare_to_exit, timer::notify_after};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        State,
+    },
+    x::{XConn, XEvent},
+    Result,
+};
+use signal_hook::{consts::SIGTERM, iterator::Signals};
+use std::{
+    collections::HashMap,
+    env,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    process,
+    sync::OnceLock,
+    thread,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+const WATCHDOG_TICK: &str = "_WM_WATCHDOG_TICK";
+const SHUTDOWN: &str = "_WM_SHUTDOWN";
+
+/// The environment systemd passes to services it expects to hear from.
+pub const NOTIFY_VARS: [&str; 3] = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+static NOTIFY_ENV: OnceLock<HashMap<&'static str, String>> = OnceLock::new();
+
+/// The [NOTIFY_VARS] the window manager was started with. They are taken out of the
+/// environment the first time this is called so that nothing started from here on thinks it
+/// is the one systemd is waiting to hear from.
+pub fn notify_env() -> &'static HashMap<&'static str, String> {
+    NOTIFY_ENV.get_or_init(|| {
+        NOTIFY_VARS
+            .into_iter()
+            .filter_map(|name| {
+                let value = env::var(name).ok();
+                env::remove_var(name);
+                Some((name, value?))
+            })
+            .collect()
+    })
+}
+
+/// Send a state change such as `READY=1` to systemd. This does nothing when not started by
+/// systemd.
+fn notify(message: &str) {
+    let path = match notify_env().get("NOTIFY_SOCKET") {
+        Some(path) if !path.is_empty() => path,
+        _ => return,
+    };
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        socket.send_to_addr(message.as_bytes(), &addr)
+    });
+
+    if let Err(e) = sent {
+        warn!("unable to notify systemd of {message}: {e}");
+    }
+}
+
+/// How often to pet the watchdog given `$WATCHDOG_USEC` and `$WATCHDOG_PID`: half of the
+/// timeout, so that a slow event doesn't trip it.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, our_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != our_pid {
+            return None;
+        }
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&usec| usec > 0)?;
+
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Hand SIGTERM to the event loop rather than dying where we stand.
+pub fn handle_sigterm() -> Result<()> {
+    let mut signals = Signals::new([SIGTERM]).map_err(x_err)?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            notify_after(Duration::ZERO, SHUTDOWN, [0; 5]);
+        }
+    });
+
+    Ok(())
+}
+
+/// Tells systemd when the window manager is ready, pets its watchdog and shuts down on
+/// SIGTERM.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemdHook {
+    watchdog: Option<Duration>,
+}
+
+impl Default for SystemdHook {
+    fn default() -> Self {
+        let env = notify_env();
+        let watchdog = watchdog_interval(
+            env.get("WATCHDOG_USEC").map(String::as_str),
+            env.get("WATCHDOG_PID").map(String::as_str),
+            process::id(),
+        );
+
+        Self { watchdog }
+    }
+}
+
+impl<X: XConn> EventHook<X> for SystemdHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg) => msg,
+            _ => return Ok(true),
+        };
+
+        if msg.dtype == WATCHDOG_TICK {
+            notify("WATCHDOG=1");
+            if let Some(interval) = self.watchdog {
+                notify_after(interval, WATCHDOG_TICK, [0; 5]);
+            }
+            Ok(false)
+        } else if msg.dtype == SHUTDOWN {
+            info!("shutting down on SIGTERM");
+            notify("STOPPING=1");
+            prepare_to_exit(state, x)?;
+            process::exit(0)
+        } else {
+            Ok(true)
+        }
+    }
+}
+
+impl<X: XConn> StateHook<X> for SystemdHook {
+    fn call(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        notify(&format!("READY=1\nMAINPID={}", process::id()));
+        if let Some(interval) = self.watchdog {
+            notify_after(interval, WATCHDOG_TICK, [0; 5]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_watchdog_is_only_pet_for_our_own_process() {
+        let half = Some(Duration::from_secs(15));
+
+        assert_eq!(watchdog_interval(Some("30000000"), None, 42), half);
+        assert_eq!(watchdog_interval(Some("30000000"), Some("42"), 42), half);
+        assert_eq!(watchdog_interval(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval(None, None, 42), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! Deferred wakeups for the event loop.
+//!
+//! The window manager only runs in response to X events, so anything that needs to happen
+//! after a delay asks a background thread to send a ClientMessage of a given type to the root
+//! window once the delay has passed. Hooks then pick these up like any other event.
+use crate::modal::x_err;
+use penrose::Result;
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{error, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ClientMessageEvent, ConnectionExt, EventMask},
+};
+
+static TIMER: OnceLock<Mutex<Sender<Wakeup>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+struct Wakeup {
+    at: Instant,
+    dtype: &'static str,
+    data: [u32; 5],
+}
+
+/// Deliver a ClientMessage of type `dtype` carrying `data` to the root window after `delay`.
+pub fn notify_after(delay: Duration, dtype: &'static str, data: [u32; 5]) {
+    let tx = TIMER.get_or_init(|| Mutex::new(spawn_worker()));
+    let wakeup = Wakeup {
+        at: Instant::now() + delay,
+        dtype,
+        data,
+    };
+
+    if tx.lock().map(|tx| tx.send(wakeup)).is_err() {
+        warn!("timer thread is not running: dropping {dtype} wakeup");
+    }
+}
+
+fn spawn_worker() -> Sender<Wakeup> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        if let Err(e) = run_worker(rx) {
+            error!("timer thread exited: {e}");
+        }
+    });
+
+    tx
+}
+
+fn run_worker(rx: Receiver<Wakeup>) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let root = conn.setup().roots[screen_num].root;
+    let mut atoms: HashMap<&'static str, u32> = HashMap::new();
+    let mut pending: Vec<Wakeup> = Vec::new();
+
+    loop {
+        let now = Instant::now();
+        let (due, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|w| w.at <= now);
+        pending = waiting;
+
+        for w in due {
+            let atom = match atoms.get(w.dtype) {
+                Some(&atom) => atom,
+                None => {
+                    let atom = conn
+                        .intern_atom(false, w.dtype.as_bytes())
+                        .map_err(x_err)?
+                        .reply()
+                        .map_err(x_err)?
+                        .atom;
+                    atoms.insert(w.dtype, atom);
+                    atom
+                }
+            };
+
+            let event = ClientMessageEvent::new(32, root, atom, w.data);
+            conn.send_event(false, root, EventMask::SUBSTRUCTURE_REDIRECT, event)
+                .map_err(x_err)?;
+        }
+        conn.flush().map_err(x_err)?;
+
+        let next = match pending.iter().map(|w| w.at).min() {
+            Some(at) => rx.recv_timeout(at.saturating_duration_since(now)),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match next {
+            Ok(w) => pending.push(w),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,527 @@
+//! Optional titlebars for tags that should feel like a conventional desktop.
+//!
+//! Wrapping a layout in [Titled] leaves room above each tiled client and [TitlebarHook] draws
+//! a bar showing the client's title and a close button into that space. Bars are plain
+//! override-redirect windows on a connection of their own, with a background thread turning
+//! clicks and drags on them into client messages for the hook to act on.
+use crate::{kill::close_client, modal::x_err, timer::notify_after};
+use penrose::{
+    core::{
+        hooks::{EventHook, StateHook},
+        layout::{Layout, Message},
+        State,
+    },
+    pure::{geometry::Rect, Stack},
+    x::{XConn, XConnExt, XEvent},
+    Color, Result, Xid,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tracing::warn;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            ChangeWindowAttributesAux, ConfigureWindowAux, ConnectionExt, CreateGCAux,
+            CreateWindowAux, EventMask, StackMode, WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT,
+};
+
+pub const TITLE_HEIGHT: u32 = 20;
+
+/// Layout names are prefixed with this when wrapped in [Titled] so that the hook can tell
+/// which workspaces want titlebars.
+const TITLED_PREFIX: &str = "Titled ";
+
+const CLOSE_WIDTH: u32 = 20;
+const TEXT_PADDING: u32 = 6;
+const TEXT_COLOR: u32 = 0xffffff;
+
+// Metrics of the core "fixed" font (6x13).
+const FONT: &[u8] = b"fixed";
+const CHAR_WIDTH: u32 = 6;
+const TEXT_BASELINE: i16 = 14;
+
+const TITLE_CLOSE: &str = "_WM_TITLEBAR_CLOSE";
+const TITLE_PRESS: &str = "_WM_TITLEBAR_PRESS";
+const TITLE_DRAG: &str = "_WM_TITLEBAR_DRAG";
+
+/// Shift every client down to leave room for its titlebar.
+#[derive(Clone)]
+pub struct Titled {
+    inner: Box<dyn Layout>,
+}
+
+impl Titled {
+    pub fn wrap(inner: Box<dyn Layout>) -> Box<dyn Layout> {
+        Box::new(Self { inner })
+    }
+}
+
+fn below_titlebar(r: Rect) -> Rect {
+    Rect::new(
+        r.x,
+        r.y + TITLE_HEIGHT,
+        r.w,
+        r.h.saturating_sub(TITLE_HEIGHT),
+    )
+}
+
+impl Layout for Titled {
+    fn name(&self) -> String {
+        format!("{TITLED_PREFIX}{}", self.inner.name())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layout> {
+        Box::new(self.clone())
+    }
+
+    fn layout(&mut self, s: &Stack<Xid>, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        let (new, positions) = self.inner.layout(s, r);
+        if let Some(inner) = new {
+            self.inner = inner;
+        }
+
+        let positions = positions
+            .into_iter()
+            .map(|(id, r)| (id, below_titlebar(r)))
+            .collect();
+
+        (None, positions)
+    }
+
+    fn layout_empty(&mut self, r: Rect) -> (Option<Box<dyn Layout>>, Vec<(Xid, Rect)>) {
+        if let (Some(inner), _) = self.inner.layout_empty(r) {
+            self.inner = inner;
+        }
+
+        (None, vec![])
+    }
+
+    fn handle_message(&mut self, m: &Message) -> Option<Box<dyn Layout>> {
+        if let Some(inner) = self.inner.handle_message(m) {
+            self.inner = inner;
+        }
+
+        None
+    }
+}
+
+/// As much of `title` as fits in `max_chars` of the (latin-1 only) core font.
+fn fit_title(title: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_control() {
+                c
+            } else {
+                '?'
+            }
+        })
+        .collect();
+
+    if chars.len() <= max_chars {
+        chars.into_iter().collect()
+    } else {
+        let mut s: String = chars[..max_chars.saturating_sub(2)].iter().collect();
+        s.push_str("..");
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bar {
+    client: Xid,
+    r: Rect,
+    title: String,
+    focused: bool,
+}
+
+/// The titlebar windows themselves, keyed by their window id.
+struct Titlebars {
+    conn: RustConnection,
+    root: u32,
+    focused_gc: u32,
+    unfocused_gc: u32,
+    focused_pixel: u32,
+    unfocused_pixel: u32,
+    bars: Mutex<HashMap<u32, Bar>>,
+}
+
+impl Titlebars {
+    fn new(focused: Color, unfocused: Color) -> Result<Arc<Self>> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let font = conn.generate_id().map_err(x_err)?;
+        conn.open_font(font, FONT).map_err(x_err)?;
+
+        let (focused_pixel, unfocused_pixel) = (focused.rgb_u32(), unfocused.rgb_u32());
+        let mut gcs = [0; 2];
+        for (gc, bg) in gcs.iter_mut().zip([focused_pixel, unfocused_pixel]) {
+            *gc = conn.generate_id().map_err(x_err)?;
+            let aux = CreateGCAux::new()
+                .foreground(TEXT_COLOR)
+                .background(bg)
+                .font(font);
+            conn.create_gc(*gc, root, &aux).map_err(x_err)?;
+        }
+
+        let bars = Arc::new(Self {
+            conn,
+            root,
+            focused_gc: gcs[0],
+            unfocused_gc: gcs[1],
+            focused_pixel,
+            unfocused_pixel,
+            bars: Mutex::default(),
+        });
+
+        let events = Arc::clone(&bars);
+        thread::spawn(move || {
+            if let Err(e) = events.run() {
+                warn!("titlebar event loop exited: {e}");
+            }
+        });
+
+        Ok(bars)
+    }
+
+    fn create(&self) -> Result<u32> {
+        let win = self.conn.generate_id().map_err(x_err)?;
+        let aux = CreateWindowAux::new().override_redirect(1).event_mask(
+            EventMask::EXPOSURE
+                | EventMask::BUTTON_PRESS
+                | EventMask::BUTTON_RELEASE
+                | EventMask::BUTTON_MOTION,
+        );
+
+        self.conn
+            .create_window(
+                COPY_DEPTH_FROM_PARENT,
+                win,
+                self.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                COPY_FROM_PARENT,
+                &aux,
+            )
+            .map_err(x_err)?;
+        self.conn.map_window(win).map_err(x_err)?;
+
+        Ok(win)
+    }
+
+    fn place(&self, win: u32, bar: &Bar) -> Result<()> {
+        let aux = ConfigureWindowAux::new()
+            .x(bar.r.x as i32)
+            .y(bar.r.y as i32)
+            .width(bar.r.w)
+            .height(bar.r.h)
+            .sibling(*bar.client)
+            .stack_mode(StackMode::ABOVE);
+
+        self.conn.configure_window(win, &aux).map_err(x_err)?;
+
+        Ok(())
+    }
+
+    fn draw(&self, win: u32, bar: &Bar) -> Result<()> {
+        let (gc, pixel) = if bar.focused {
+            (self.focused_gc, self.focused_pixel)
+        } else {
+            (self.unfocused_gc, self.unfocused_pixel)
+        };
+        let max_chars = bar.r.w.saturating_sub(CLOSE_WIDTH + 2 * TEXT_PADDING) / CHAR_WIDTH;
+        let title = fit_title(&bar.title, max_chars as usize);
+        let close_x = bar.r.w.saturating_sub(CLOSE_WIDTH) + (CLOSE_WIDTH - CHAR_WIDTH) / 2;
+
+        let aux = ChangeWindowAttributesAux::new().background_pixel(pixel);
+        self.conn
+            .change_window_attributes(win, &aux)
+            .map_err(x_err)?;
+        self.conn
+            .clear_area(false, win, 0, 0, 0, 0)
+            .map_err(x_err)?;
+        self.conn
+            .image_text8(
+                win,
+                gc,
+                TEXT_PADDING as i16,
+                TEXT_BASELINE,
+                title.as_bytes(),
+            )
+            .map_err(x_err)?;
+        self.conn
+            .image_text8(win, gc, close_x as i16, TEXT_BASELINE, b"x")
+            .map_err(x_err)?;
+
+        Ok(())
+    }
+
+    /// Bring the set of bars in line with `wanted`, destroying any that are no longer needed.
+    fn sync(&self, wanted: Vec<Bar>) -> Result<()> {
+        let mut bars = self.bars.lock().map_err(x_err)?;
+        let keep: HashSet<Xid> = wanted.iter().map(|b| b.client).collect();
+        bars.retain(|&win, bar| {
+            let keep = keep.contains(&bar.client);
+            if !keep {
+                let _ = self.conn.destroy_window(win);
+            }
+            keep
+        });
+
+        for bar in wanted {
+            let existing = bars
+                .iter()
+                .find(|(_, b)| b.client == bar.client)
+                .map(|(&win, _)| win);
+            let win = match existing {
+                Some(win) => win,
+                None => self.create()?,
+            };
+
+            self.place(win, &bar)?;
+            self.draw(win, &bar)?;
+            bars.insert(win, bar);
+        }
+
+        self.conn.flush().map_err(x_err)
+    }
+
+    /// Keep the bar for `client` attached to it while it is being dragged.
+    fn follow(&self, client: Xid, r: Rect) -> Result<()> {
+        let mut bars = self.bars.lock().map_err(x_err)?;
+        if let Some((&win, bar)) = bars.iter_mut().find(|(_, b)| b.client == client) {
+            bar.r = Rect::new(r.x, r.y.saturating_sub(TITLE_HEIGHT), bar.r.w, bar.r.h);
+            self.place(win, bar)?;
+        }
+
+        self.conn.flush().map_err(x_err)
+    }
+
+    /// The client each titlebar window belongs to.
+    fn owners(&self) -> HashMap<Xid, Xid> {
+        match self.bars.lock() {
+            Ok(bars) => bars
+                .iter()
+                .map(|(&win, bar)| (Xid::from(win), bar.client))
+                .collect(),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    fn bar(&self, win: u32) -> Option<Bar> {
+        self.bars.lock().ok()?.get(&win).cloned()
+    }
+
+    fn run(&self) -> Result<()> {
+        let mut dragging = None;
+
+        loop {
+            match self.conn.wait_for_event().map_err(x_err)? {
+                Event::Expose(e) if e.count == 0 => {
+                    if let Some(bar) = self.bar(e.window) {
+                        self.draw(e.window, &bar)?;
+                        self.conn.flush().map_err(x_err)?;
+                    }
+                }
+
+                Event::ButtonPress(e) => {
+                    let bar = match self.bar(e.event) {
+                        Some(bar) => bar,
+                        None => continue,
+                    };
+                    let (px, py) = (e.root_x.max(0) as u32, e.root_y.max(0) as u32);
+
+                    if e.event_x.max(0) as u32 >= bar.r.w.saturating_sub(CLOSE_WIDTH) {
+                        notify_after(Duration::ZERO, TITLE_CLOSE, [*bar.client, 0, 0, 0, 0]);
+                    } else {
+                        dragging = Some(bar.client);
+                        notify_after(Duration::ZERO, TITLE_PRESS, [*bar.client, px, py, 0, 0]);
+                    }
+                }
+
+                Event::MotionNotify(e) => {
+                    if let Some(client) = dragging {
+                        let (px, py) = (e.root_x.max(0) as u32, e.root_y.max(0) as u32);
+                        notify_after(Duration::ZERO, TITLE_DRAG, [*client, px, py, 0, 0]);
+                    }
+                }
+
+                Event::ButtonRelease(_) => dragging = None,
+
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Which client each titlebar window belongs to, as of the last refresh.
+#[derive(Debug, Default, Clone)]
+pub struct TitlebarOwners {
+    clients: HashMap<Xid, Xid>,
+}
+
+impl TitlebarOwners {
+    /// The client `win` is the titlebar of, or `win` itself if it isn't a titlebar.
+    pub fn client_for(&self, win: Xid) -> Xid {
+        self.clients.get(&win).copied().unwrap_or(win)
+    }
+}
+
+/// Where a titlebar drag started: the pointer position and the client's geometry at the time.
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    client: Xid,
+    from: (u32, u32),
+    r: Rect,
+}
+
+/// Draws titlebars for the visible clients of any workspace using a [Titled] layout, closes
+/// clients whose close button is clicked and floats and moves clients dragged by their bar.
+/// Clones share the same bars so the hook can be registered for both events and refresh.
+#[derive(Clone)]
+pub struct TitlebarHook {
+    bars: Arc<Titlebars>,
+    kill_timeout: Duration,
+    drag: Option<Drag>,
+}
+
+impl TitlebarHook {
+    pub fn new(focused: Color, unfocused: Color, kill_timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            bars: Titlebars::new(focused, unfocused)?,
+            kill_timeout,
+            drag: None,
+        })
+    }
+}
+
+impl<X: XConn> EventHook<X> for TitlebarHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let msg = match event {
+            XEvent::ClientMessage(msg)
+                if [TITLE_CLOSE, TITLE_PRESS, TITLE_DRAG].contains(&msg.dtype.as_str()) =>
+            {
+                msg
+            }
+            _ => return Ok(true),
+        };
+
+        let data = msg.data.as_usize();
+        let id = Xid::from(data[0] as u32);
+        let pointer = (data[1] as u32, data[2] as u32);
+        if !state.client_set.contains(&id) {
+            return Ok(true);
+        }
+
+        match msg.dtype.as_str() {
+            TITLE_CLOSE => close_client(id, self.kill_timeout, x)?,
+
+            TITLE_PRESS => {
+                let r = x.client_geometry(id)?;
+                self.drag = Some(Drag {
+                    client: id,
+                    from: pointer,
+                    r,
+                });
+                x.modify_and_refresh(state, |cs| {
+                    cs.focus_client(&id);
+                    if !cs.is_floating(&id) {
+                        let _ = cs.float(id, r);
+                    }
+                })?;
+            }
+
+            _ => match self.drag {
+                Some(d) if d.client == id => {
+                    let dx = pointer.0 as i64 - d.from.0 as i64;
+                    let dy = pointer.1 as i64 - d.from.1 as i64;
+                    let r = Rect::new(
+                        (d.r.x as i64 + dx).max(0) as u32,
+                        (d.r.y as i64 + dy).max(0) as u32,
+                        d.r.w,
+                        d.r.h,
+                    );
+
+                    state.client_set.float(id, r)?;
+                    x.position_client(id, r)?;
+                    self.bars.follow(id, r)?;
+                }
+                _ => (),
+            },
+        }
+
+        Ok(false)
+    }
+}
+
+impl<X: XConn> StateHook<X> for TitlebarHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let bw = state.config.border_width;
+        let focused = state.client_set.current_client().copied();
+        let titled: HashSet<String> = state
+            .client_set
+            .screens()
+            .filter(|s| s.workspace.layout_name().starts_with(TITLED_PREFIX))
+            .map(|s| s.workspace.tag().to_string())
+            .collect();
+
+        let mut wanted = Vec::new();
+        for &id in state.client_set.on_screen_workspace_clients() {
+            let on_titled = state
+                .client_set
+                .tag_for_client(&id)
+                .is_some_and(|t| titled.contains(t));
+            if !on_titled {
+                continue;
+            }
+
+            let r = x.client_geometry(id)?;
+            wanted.push(Bar {
+                client: id,
+                r: Rect::new(
+                    r.x,
+                    r.y.saturating_sub(TITLE_HEIGHT),
+                    r.w + 2 * bw,
+                    TITLE_HEIGHT,
+                ),
+                title: x.window_title(id).unwrap_or_default(),
+                focused: focused == Some(id),
+            });
+        }
+
+        self.bars.sync(wanted)?;
+        state
+            .extension_or_default::<TitlebarOwners>()
+            .borrow_mut()
+            .clients = self.bars.owners();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_titles_are_truncated_to_fit() {
+        assert_eq!(fit_title("short", 10), "short");
+        assert_eq!(fit_title("a much longer title", 10), "a much l..");
+        assert_eq!(fit_title("caf\u{e9}", 10), "caf?");
+    }
+}
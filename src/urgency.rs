@@ -0,0 +1,195 @@
+//! Tracking clients that want attention via ICCCM urgency or _NET_WM_STATE_DEMANDS_ATTENTION.
+use crate::{
+    bar,
+    client::{net_wm_state, set_net_wm_state},
+    hidden::on_regular_tag,
+};
+use penrose::{
+    builtin::actions::key_handler,
+    core::{
+        bindings::KeyEventHandler,
+        hooks::{EventHook, ManageHook, StateHook},
+        State,
+    },
+    x::{Prop, XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+
+pub const NET_WM_STATE_DEMANDS_ATTENTION: &str = "_NET_WM_STATE_DEMANDS_ATTENTION";
+
+/// Clients currently asking for attention, oldest first.
+#[derive(Debug, Default, Clone)]
+pub struct Urgent {
+    clients: Vec<Xid>,
+}
+
+impl Urgent {
+    pub fn contains(&self, id: &Xid) -> bool {
+        self.clients.contains(id)
+    }
+
+    /// Record whether `id` is urgent, returning whether that changed anything.
+    fn set(&mut self, id: Xid, urgent: bool) -> bool {
+        let present = self.clients.contains(&id);
+        if urgent && !present {
+            self.clients.push(id);
+        } else if !urgent && present {
+            self.clients.retain(|&c| c != id);
+        }
+
+        urgent != present
+    }
+}
+
+fn is_urgent<X: XConn>(x: &X, id: Xid) -> Result<bool> {
+    if let Some(Prop::WmHints(hints)) = x.get_prop(id, "WM_HINTS")? {
+        if hints.is_urgent() {
+            return Ok(true);
+        }
+    }
+
+    Ok(net_wm_state(x, id)?
+        .iter()
+        .any(|a| a == NET_WM_STATE_DEMANDS_ATTENTION))
+}
+
+/// Ask for attention on behalf of `id` as though it had set the hint itself.
+pub fn demand_attention<X: XConn>(x: &X, id: Xid) -> Result<()> {
+    set_net_wm_state(x, id, NET_WM_STATE_DEMANDS_ATTENTION, true)
+}
+
+/// Jump to the client that has been waiting for attention the longest, passing over any that
+/// are minimized or otherwise parked.
+pub fn focus_urgent<X: XConn>() -> Box<dyn KeyEventHandler<X>> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let oldest = state
+            .extension_or_default::<Urgent>()
+            .borrow()
+            .clients
+            .iter()
+            .find(|id| on_regular_tag(state, id))
+            .copied();
+
+        match oldest {
+            Some(id) => x.modify_and_refresh(state, |cs| cs.focus_client(&id)),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Watches for clients changing their urgency and publishes the tags urgent clients are on
+/// to the bar. Urgency is cleared once a client gets focus, and a focused client is never
+/// counted as urgent.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UrgencyHook;
+
+impl<X: XConn> EventHook<X> for UrgencyHook {
+    fn call(&mut self, event: &XEvent, state: &mut State<X>, x: &X) -> Result<bool> {
+        let id = match event {
+            XEvent::PropertyNotify(p) if p.atom == "WM_HINTS" || p.atom == "_NET_WM_STATE" => p.id,
+            _ => return Ok(true),
+        };
+        if !state.client_set.contains(&id) {
+            return Ok(true);
+        }
+
+        // The focused client has already been seen to, so a hint it still has set doesn't
+        // count. Otherwise clearing its urgency on refresh would bring it straight back.
+        let focused = state.client_set.current_client() == Some(&id);
+        let urgent = !focused && is_urgent(x, id)?;
+        let changed = state
+            .extension_or_default::<Urgent>()
+            .borrow_mut()
+            .set(id, urgent);
+        if changed {
+            x.refresh(state)?;
+        }
+
+        Ok(true)
+    }
+}
+
+impl<X: XConn> ManageHook<X> for UrgencyHook {
+    fn call(&mut self, client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+        if is_urgent(x, client)? {
+            state
+                .extension_or_default::<Urgent>()
+                .borrow_mut()
+                .set(client, true);
+        }
+
+        Ok(())
+    }
+}
+
+impl<X: XConn> StateHook<X> for UrgencyHook {
+    fn call(&mut self, state: &mut State<X>, x: &X) -> Result<()> {
+        let ext = state.extension_or_default::<Urgent>();
+        let focused = state.client_set.current_client().copied();
+
+        let tags = {
+            let mut urgent = ext.borrow_mut();
+            urgent.clients.retain(|id| state.client_set.contains(id));
+            if let Some(id) = focused.filter(|id| urgent.clients.contains(id)) {
+                urgent.set(id, false);
+                set_net_wm_state(x, id, NET_WM_STATE_DEMANDS_ATTENTION, false)?;
+            }
+
+            let mut tags: Vec<String> = Vec::new();
+            for id in urgent.clients.iter() {
+                if let Some(tag) = state.client_set.tag_for_client(id) {
+                    if !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+            }
+
+            tags
+        };
+
+        bar::publish(state, "urgent", tags.join(","));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockXConn;
+    use penrose::x::{event::PropertyEvent, property::WmHints};
+
+    #[test]
+    fn a_focused_client_with_the_urgency_hint_is_not_urgent() {
+        let (x, mut state, id) = MockXConn::with_client();
+        let mut hook = UrgencyHook;
+        // XUrgencyHint is bit 8 of the WM_HINTS flags.
+        let hints = WmHints::try_from_bytes(&[1 << 8, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        x.set_prop(id, "WM_HINTS", Prop::WmHints(hints)).unwrap();
+
+        StateHook::call(&mut hook, &mut state, &x).unwrap();
+        let event = XEvent::PropertyNotify(PropertyEvent {
+            id,
+            atom: "WM_HINTS".to_string(),
+            is_root: false,
+        });
+        assert!(EventHook::call(&mut hook, &event, &mut state, &x).unwrap());
+
+        let urgent = state.extension_or_default::<Urgent>();
+        assert!(!urgent.borrow().contains(&id));
+        assert_eq!(x.prop(id, "_NET_WM_STATE"), None);
+    }
+
+    #[test]
+    fn urgent_clients_are_kept_in_arrival_order() {
+        let mut urgent = Urgent::default();
+
+        assert!(urgent.set(Xid::from(1), true));
+        assert!(urgent.set(Xid::from(2), true));
+        assert!(!urgent.set(Xid::from(1), true));
+        assert!(urgent.set(Xid::from(1), false));
+        assert!(urgent.set(Xid::from(1), true));
+
+        assert_eq!(urgent.clients, [2, 1].map(Xid::from));
+    }
+}
@@ -0,0 +1,204 @@
+//! Drawing wallpapers straight onto the root window.
+//!
+//! Every output's image is scaled into a single pixmap the size of the root window which only
+//! replaces the old wallpaper once it is complete, so there is no flash of an empty root
+//! window on monitor changes. The pixmap is also published as `_XROOTPMAP_ID` (and
+//! `ESETROOT_PMAP_ID`) for terminals and compositors that fake transparency from it.
+use crate::modal::x_err;
+use penrose::{pure::geometry::Rect, Result};
+use tracing::warn;
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::xproto::{
+        AtomEnum, ChangeWindowAttributesAux, CloseDown, ConnectionExt, CreateGCAux, ImageFormat,
+        PropMode, Rectangle,
+    },
+    rust_connection::RustConnection,
+    NONE,
+};
+
+const XROOTPMAP_ID: &[u8] = b"_XROOTPMAP_ID";
+const ESETROOT_PMAP_ID: &[u8] = b"ESETROOT_PMAP_ID";
+
+/// How an image is fitted to an output whose size doesn't match its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// Scale to cover the whole output, cropping whatever overhangs.
+    Fill,
+    /// Scale to fit entirely within the output, leaving bars along two of the edges.
+    Fit,
+    /// Draw at the original size in the middle of the output.
+    Center,
+    /// Repeat at the original size from the top left corner.
+    Tile,
+}
+
+impl Scaling {
+    /// The pixel of an `src` sized image that should be drawn at `p` on a `dst` sized output,
+    /// or `None` if the background shows through there.
+    fn sample(&self, src: (u32, u32), dst: (u32, u32), p: (u32, u32)) -> Option<(u32, u32)> {
+        let (iw, ih) = (src.0.max(1) as f64, src.1.max(1) as f64);
+        let (ow, oh) = (dst.0 as f64, dst.1 as f64);
+        let scale = match self {
+            Scaling::Fill => (ow / iw).max(oh / ih),
+            Scaling::Fit => (ow / iw).min(oh / ih),
+            Scaling::Center => 1.0,
+            Scaling::Tile => return Some((p.0 % src.0.max(1), p.1 % src.1.max(1))),
+        };
+
+        let sx = (p.0 as f64 + 0.5 - (ow - iw * scale) / 2.0) / scale;
+        let sy = (p.1 as f64 + 0.5 - (oh - ih * scale) / 2.0) / scale;
+        if sx < 0.0 || sy < 0.0 || sx >= iw || sy >= ih {
+            return None;
+        }
+
+        Some((sx as u32, sy as u32))
+    }
+}
+
+/// Render the given image for each output into a new root pixmap and make it the wallpaper.
+/// Images that can't be loaded are logged and leave their output black.
+pub fn set_wallpapers(outputs: &[(Rect, &str)], scaling: Scaling) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None).map_err(x_err)?;
+    let screen = &conn.setup().roots[screen_num];
+    let (root, depth) = (screen.root, screen.root_depth);
+    let (w, h) = (screen.width_in_pixels, screen.height_in_pixels);
+
+    let pixmap = conn.generate_id().map_err(x_err)?;
+    conn.create_pixmap(depth, pixmap, root, w, h)
+        .map_err(x_err)?;
+    let gc = conn.generate_id().map_err(x_err)?;
+    conn.create_gc(
+        gc,
+        pixmap,
+        &CreateGCAux::new().foreground(screen.black_pixel),
+    )
+    .map_err(x_err)?;
+    let all = Rectangle {
+        x: 0,
+        y: 0,
+        width: w,
+        height: h,
+    };
+    conn.poly_fill_rectangle(pixmap, gc, &[all])
+        .map_err(x_err)?;
+
+    for &(r, path) in outputs {
+        match image::open(path) {
+            Ok(img) => draw(&conn, pixmap, gc, depth, r, &img.to_rgb8(), scaling)?,
+            Err(e) => warn!("unable to load wallpaper {path}: {e}"),
+        }
+    }
+    conn.free_gc(gc).map_err(x_err)?;
+
+    let xrootpmap = atom(&conn, XROOTPMAP_ID)?;
+    let esetroot = atom(&conn, ESETROOT_PMAP_ID)?;
+
+    // Whoever set the previous wallpaper left its pixmap behind when they exited, so it is
+    // only freed by killing the (long gone) client that still owns it.
+    let previous = conn
+        .get_property(false, root, esetroot, AtomEnum::PIXMAP, 0, 1)
+        .map_err(x_err)?
+        .reply()
+        .map_err(x_err)?
+        .value32()
+        .and_then(|mut v| v.next());
+    if let Some(old) = previous.filter(|&p| p != NONE) {
+        conn.kill_client(old).map_err(x_err)?;
+    }
+
+    for prop in [xrootpmap, esetroot] {
+        conn.change_property32(PropMode::REPLACE, root, prop, AtomEnum::PIXMAP, &[pixmap])
+            .map_err(x_err)?;
+    }
+    conn.change_window_attributes(
+        root,
+        &ChangeWindowAttributesAux::new().background_pixmap(pixmap),
+    )
+    .map_err(x_err)?;
+    conn.clear_area(false, root, 0, 0, 0, 0).map_err(x_err)?;
+
+    // Keep the pixmap alive once this connection is closed.
+    conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)
+        .map_err(x_err)?;
+    conn.flush().map_err(x_err)?;
+
+    Ok(())
+}
+
+fn atom(conn: &RustConnection, name: &[u8]) -> Result<u32> {
+    Ok(conn
+        .intern_atom(false, name)
+        .map_err(x_err)?
+        .reply()
+        .map_err(x_err)?
+        .atom)
+}
+
+/// Upload `img` scaled into `r` on the pixmap, in bands of rows small enough to fit in a
+/// single request. This assumes the usual 24 bit TrueColor visual with 32 bit BGRX pixels.
+fn draw(
+    conn: &RustConnection,
+    pixmap: u32,
+    gc: u32,
+    depth: u8,
+    r: Rect,
+    img: &image::RgbImage,
+    scaling: Scaling,
+) -> Result<()> {
+    let (iw, ih) = (img.width(), img.height());
+    let raw = img.as_raw();
+    let row_bytes = r.w as usize * 4;
+    let band = ((conn.maximum_request_bytes() - 32) / row_bytes.max(1)).max(1) as u32;
+
+    let mut y0 = 0;
+    while y0 < r.h {
+        let rows = band.min(r.h - y0);
+        let mut data = Vec::with_capacity(row_bytes * rows as usize);
+        for y in y0..y0 + rows {
+            for x in 0..r.w {
+                match scaling.sample((iw, ih), (r.w, r.h), (x, y)) {
+                    Some((sx, sy)) => {
+                        let i = (sy as usize * iw as usize + sx as usize) * 3;
+                        data.extend_from_slice(&[raw[i + 2], raw[i + 1], raw[i], 0]);
+                    }
+                    None => data.extend_from_slice(&[0; 4]),
+                }
+            }
+        }
+
+        conn.put_image(
+            ImageFormat::Z_PIXMAP,
+            pixmap,
+            gc,
+            r.w as u16,
+            rows as u16,
+            r.x as i16,
+            (r.y + y0) as i16,
+            0,
+            depth,
+            &data,
+        )
+        .map_err(x_err)?;
+        y0 += rows;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_crops_and_fit_letterboxes() {
+        // A square image on a 2:1 output.
+        let (src, dst) = ((100, 100), (200, 100));
+
+        assert_eq!(Scaling::Fill.sample(src, dst, (0, 0)), Some((0, 25)));
+        assert_eq!(Scaling::Fit.sample(src, dst, (0, 0)), None);
+        assert_eq!(Scaling::Fit.sample(src, dst, (50, 0)), Some((0, 0)));
+        assert_eq!(Scaling::Center.sample(src, dst, (149, 99)), Some((99, 99)));
+        assert_eq!(Scaling::Tile.sample(src, dst, (150, 50)), Some((50, 50)));
+    }
+}